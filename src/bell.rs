@@ -0,0 +1,152 @@
+//! What a pane does when its program rings the bell (BEL).
+//!
+//! Each pane has a [`BellPolicy`]: any mix of flashing the pane border, badging the pane
+//! while it is not being looked at and asking the window system for attention. Bells are
+//! rate limited per pane, so a program printing BELs in a loop flashes at a steady pace
+//! instead of strobing; bells that arrive in between are counted into the next one.
+
+use std::time::{Duration, Instant};
+
+/// Shortest time between two bells acted on in one pane.
+pub const MIN_INTERVAL: Duration = Duration::from_millis(500);
+/// How long the pane border stays lit after a bell.
+pub const FLASH_DURATION: Duration = Duration::from_millis(150);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BellPolicy {
+    pub flash: bool,
+    /// Mark the pane until it is focused, if the bell rang while it was not.
+    pub badge: bool,
+    /// Request window attention (taskbar flash, dock bounce) when the window is unfocused.
+    pub attention: bool,
+}
+
+impl Default for BellPolicy {
+    /// `ORCHESTRATERM_BELL` (see [`Self::parse`]), else flash and badge.
+    fn default() -> Self {
+        std::env::var("ORCHESTRATERM_BELL")
+            .ok()
+            .and_then(|v| Self::parse(&v))
+            .unwrap_or(Self {
+                flash: true,
+                badge: true,
+                attention: false,
+            })
+    }
+}
+
+impl BellPolicy {
+    pub const NONE: Self = Self {
+        flash: false,
+        badge: false,
+        attention: false,
+    };
+
+    /// A comma-separated list of `flash`, `badge` and `attention`, or `none`.
+    pub fn parse(value: &str) -> Option<Self> {
+        let mut policy = Self::NONE;
+        for item in value.split(',').map(str::trim).filter(|v| !v.is_empty()) {
+            match item {
+                "flash" => policy.flash = true,
+                "badge" => policy.badge = true,
+                "attention" => policy.attention = true,
+                "none" => {}
+                _ => return None,
+            }
+        }
+        Some(policy)
+    }
+
+    pub fn is_none(self) -> bool {
+        self == Self::NONE
+    }
+}
+
+/// A pane's bell policy and what its last bells left behind.
+#[derive(Debug, Clone)]
+pub struct BellState {
+    pub policy: BellPolicy,
+    last: Option<Instant>,
+    /// Bells swallowed by the rate limit since the last one acted on.
+    suppressed: usize,
+    flash_until: Option<Instant>,
+    pub badged: bool,
+}
+
+impl BellState {
+    pub fn new(policy: BellPolicy) -> Self {
+        Self {
+            policy,
+            last: None,
+            suppressed: 0,
+            flash_until: None,
+            badged: false,
+        }
+    }
+
+    /// Registers `count` bells at `now`. Returns how many bells the call stands for
+    /// (including ones suppressed earlier) if they should be acted on, or `None` while
+    /// rate limited. Bells are counted whatever the policy, so they can still be reported.
+    pub fn ring(&mut self, count: usize, now: Instant) -> Option<usize> {
+        if count == 0 {
+            return None;
+        }
+        if self
+            .last
+            .is_some_and(|last| now.duration_since(last) < MIN_INTERVAL)
+        {
+            self.suppressed += count;
+            return None;
+        }
+        self.last = Some(now);
+        let total = count + std::mem::take(&mut self.suppressed);
+        if self.policy.flash {
+            self.flash_until = Some(now + FLASH_DURATION);
+        }
+        Some(total)
+    }
+
+    /// Whether the border is lit at `now`.
+    pub fn flashing(&self, now: Instant) -> bool {
+        self.flash_until.is_some_and(|until| now < until)
+    }
+
+    /// Time left on the flash, to schedule the repaint that ends it.
+    pub fn flash_remaining(&self, now: Instant) -> Option<Duration> {
+        self.flash_until
+            .and_then(|until| until.checked_duration_since(now))
+            .filter(|d| !d.is_zero())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn policies_parse_from_lists() {
+        assert_eq!(
+            BellPolicy::parse("flash, attention"),
+            Some(BellPolicy {
+                flash: true,
+                badge: false,
+                attention: true,
+            })
+        );
+        assert_eq!(BellPolicy::parse("none"), Some(BellPolicy::NONE));
+        assert_eq!(BellPolicy::parse("beep"), None);
+    }
+
+    #[test]
+    fn bell_floods_are_rate_limited() {
+        let mut state = BellState::new(BellPolicy::parse("flash").unwrap());
+        let start = Instant::now();
+        assert_eq!(state.ring(1, start), Some(1));
+        assert!(state.flashing(start));
+        assert!(!state.flashing(start + FLASH_DURATION));
+        assert_eq!(state.ring(5, start + Duration::from_millis(100)), None);
+        assert_eq!(state.ring(1, start + Duration::from_millis(200)), None);
+        assert_eq!(state.ring(1, start + MIN_INTERVAL), Some(7));
+        assert_eq!(state.ring(0, start + MIN_INTERVAL * 3), None);
+    }
+}
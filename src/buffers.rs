@@ -0,0 +1,209 @@
+//! Paste buffers, like tmux's `set-buffer`/`choose-buffer`, and the OSC 52 access policy.
+//!
+//! Copy mode and OSC 52 writes push unnamed buffers (`buffer0`, `buffer1`, ...); named
+//! buffers are only replaced when set again. The newest buffer comes first.
+
+use std::collections::VecDeque;
+
+/// Unnamed buffers kept before the oldest is dropped; named buffers never expire.
+pub const MAX_AUTOMATIC: usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BufferSource {
+    CopyMode,
+    Osc52 { pane: usize },
+    Named,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PasteBuffer {
+    pub name: String,
+    pub text: String,
+    pub source: BufferSource,
+}
+
+impl PasteBuffer {
+    /// First line of the text, shortened to `max_chars`, for pickers.
+    pub fn preview(&self, max_chars: usize) -> String {
+        let line = self.text.lines().next().unwrap_or("");
+        let mut preview: String = line.chars().take(max_chars).collect();
+        if line.chars().count() > max_chars || self.text.trim_end().contains('\n') {
+            preview.push('…');
+        }
+        preview
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct PasteBuffers {
+    buffers: VecDeque<PasteBuffer>,
+    next_index: usize,
+}
+
+impl PasteBuffers {
+    /// Stores `text` as a new unnamed buffer and returns its name. Text identical to the
+    /// newest buffer is not stored twice.
+    pub fn push(&mut self, text: String, source: BufferSource) -> Option<&str> {
+        if text.is_empty() {
+            return None;
+        }
+        if self.buffers.front().is_none_or(|b| b.text != text) {
+            let name = format!("buffer{}", self.next_index);
+            self.next_index += 1;
+            self.buffers.push_front(PasteBuffer { name, text, source });
+            let automatic = self.buffers.iter().filter(|b| is_automatic(b)).count();
+            if automatic > MAX_AUTOMATIC
+                && let Some(oldest) = self.buffers.iter().rposition(is_automatic)
+            {
+                self.buffers.remove(oldest);
+            }
+        }
+        self.buffers.front().map(|b| b.name.as_str())
+    }
+
+    /// Creates or replaces the buffer `name` and moves it to the top.
+    pub fn set(&mut self, name: &str, text: String) {
+        let source = match self.remove(name) {
+            Some(old) => old.source,
+            None => BufferSource::Named,
+        };
+        self.buffers.push_front(PasteBuffer {
+            name: name.to_string(),
+            text,
+            source,
+        });
+    }
+
+    pub fn get(&self, name: &str) -> Option<&PasteBuffer> {
+        self.buffers.iter().find(|b| b.name == name)
+    }
+
+    pub fn latest(&self) -> Option<&PasteBuffer> {
+        self.buffers.front()
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<PasteBuffer> {
+        let index = self.buffers.iter().position(|b| b.name == name)?;
+        self.buffers.remove(index)
+    }
+
+    /// Buffers from newest to oldest.
+    pub fn iter(&self) -> impl Iterator<Item = &PasteBuffer> {
+        self.buffers.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.buffers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffers.is_empty()
+    }
+}
+
+fn is_automatic(buffer: &PasteBuffer) -> bool {
+    buffer.source != BufferSource::Named
+}
+
+/// What programs in panes may do with the clipboard through OSC 52.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClipboardAccess {
+    Off,
+    /// Programs may set the clipboard but reads are answered with an empty selection.
+    WriteOnly,
+    /// Reads are answered with the newest paste buffer.
+    ReadWrite,
+}
+
+impl Default for ClipboardAccess {
+    /// `ORCHESTRATERM_OSC52` (`off`, `write` or `read-write`), else write-only.
+    fn default() -> Self {
+        std::env::var("ORCHESTRATERM_OSC52")
+            .ok()
+            .and_then(|v| Self::parse(&v))
+            .unwrap_or(Self::WriteOnly)
+    }
+}
+
+impl ClipboardAccess {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim() {
+            "off" => Some(Self::Off),
+            "write" => Some(Self::WriteOnly),
+            "read-write" => Some(Self::ReadWrite),
+            _ => None,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Off => "Off",
+            Self::WriteOnly => "Write only",
+            Self::ReadWrite => "Read and write",
+        }
+    }
+
+    pub fn all() -> [Self; 3] {
+        [Self::Off, Self::WriteOnly, Self::ReadWrite]
+    }
+
+    pub fn allows_write(self) -> bool {
+        self != Self::Off
+    }
+
+    pub fn allows_read(self) -> bool {
+        self == Self::ReadWrite
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn push_names_buffers_and_skips_repeats() {
+        let mut buffers = PasteBuffers::default();
+        assert_eq!(
+            buffers.push("one".into(), BufferSource::CopyMode),
+            Some("buffer0")
+        );
+        assert_eq!(
+            buffers.push("two".into(), BufferSource::Osc52 { pane: 1 }),
+            Some("buffer1")
+        );
+        assert_eq!(
+            buffers.push("two".into(), BufferSource::CopyMode),
+            Some("buffer1")
+        );
+        assert_eq!(buffers.push(String::new(), BufferSource::CopyMode), None);
+        let names: Vec<_> = buffers.iter().map(|b| b.name.as_str()).collect();
+        assert_eq!(names, ["buffer1", "buffer0"]);
+    }
+
+    #[test]
+    fn automatic_buffers_expire_but_named_ones_stay() {
+        let mut buffers = PasteBuffers::default();
+        buffers.set("keep", "named".into());
+        for i in 0..=MAX_AUTOMATIC {
+            buffers.push(i.to_string(), BufferSource::CopyMode);
+        }
+        assert_eq!(buffers.len(), MAX_AUTOMATIC + 1);
+        assert!(buffers.get("buffer0").is_none());
+        assert_eq!(buffers.get("keep").map(|b| b.text.as_str()), Some("named"));
+
+        buffers.set("buffer1", "edited".into());
+        assert_eq!(buffers.latest().map(|b| b.text.as_str()), Some("edited"));
+        assert_eq!(buffers.len(), MAX_AUTOMATIC + 1);
+    }
+
+    #[test]
+    fn preview_marks_truncation() {
+        let buffer = PasteBuffer {
+            name: "b".into(),
+            text: "first line\nsecond".into(),
+            source: BufferSource::Named,
+        };
+        assert_eq!(buffer.preview(40), "first line…");
+        assert_eq!(buffer.preview(5), "first…");
+    }
+}
@@ -0,0 +1,1268 @@
+//! The tmux-like command language behind the `:` prompt, key bindings, the startup file
+//! and `orchestraterm command`.
+//!
+//! A line holds one or more commands separated by `;` (`\;` is a literal `;`, so a
+//! binding can run a sequence). Words are split on whitespace; single quotes are literal,
+//! double quotes allow `\"`, and an unquoted `#` starts a comment:
+//!
+//! ```text
+//! split-window -h -c ~/src ; send-keys 'cargo test' Enter
+//! select-layout tiled
+//! bind w split-window -v \; select-layout even-vertical
+//! ```
+
+use anyhow::{Context, Result, anyhow, bail};
+use std::path::PathBuf;
+
+use crate::core::{Layout, SplitAxis};
+use crate::engine::runtime_dir;
+use crate::keymap::{self, GLOBAL_TABLE, Mode};
+use crate::protocol::ServerRequest;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    EnterPrefix,
+    /// Read the next key from this key table.
+    SwitchTable(String),
+    /// Open the `:` prompt.
+    CommandPrompt,
+    /// Open the fuzzy palette.
+    CommandPalette,
+    EnterCopyMode,
+    ExitCopyMode,
+    /// Split the focused pane, starting the new shell in `dir` if given.
+    SplitWindow {
+        axis: SplitAxis,
+        dir: Option<String>,
+    },
+    /// Close a pane, the focused one by default.
+    KillPane(Option<usize>),
+    ToggleZoom,
+    FocusPrev,
+    FocusNext,
+    /// Focus a pane and/or set its title.
+    SelectPane {
+        target: Option<usize>,
+        title: Option<String>,
+    },
+    SelectLayout(Layout),
+    RenameWindow(String),
+    CopyMoveUp,
+    CopyMoveDown,
+    CopyMoveLeft,
+    CopyMoveRight,
+    CopyStartSelection,
+    CopyCopySelection,
+    CopySearchStart,
+    CopySearchApply,
+    SendEnter,
+    /// Send text to the focused pane as if typed.
+    SendText(String),
+    /// Send keys by name (`Enter`, `C-c`, ...) or, failing that, as text; `-l` sends
+    /// every word as text.
+    SendKeys {
+        target: Option<usize>,
+        literal: bool,
+        keys: Vec<String>,
+    },
+    OpenFolder,
+    /// Create or replace a paste buffer; unnamed ones are stored like copies.
+    SetBuffer {
+        name: Option<String>,
+        text: String,
+    },
+    /// Paste the named buffer, or the newest one.
+    PasteBuffer(Option<String>),
+    ChooseBuffer,
+    BufferPickerUp,
+    BufferPickerDown,
+    BufferPickerPaste,
+    BufferPickerDelete,
+    BufferPickerClose,
+    ZoomIn,
+    ZoomOut,
+    ZoomReset,
+    Team(TeamCommand),
+    Bind {
+        table: String,
+        repeat: bool,
+        keys: String,
+        command: Box<Command>,
+    },
+    Unbind {
+        table: String,
+        keys: String,
+    },
+    Set {
+        option: String,
+        value: String,
+    },
+    /// Commands run one after another, from `a \; b` in a binding.
+    Sequence(Vec<Command>),
+}
+
+/// Commands that take no arguments, by name.
+pub const SIMPLE_COMMANDS: &[(&str, Command)] = &[
+    ("enter-prefix", Command::EnterPrefix),
+    ("command-prompt", Command::CommandPrompt),
+    ("command-palette", Command::CommandPalette),
+    ("copy-mode", Command::EnterCopyMode),
+    ("cancel", Command::ExitCopyMode),
+    ("zoom-pane", Command::ToggleZoom),
+    ("select-pane-prev", Command::FocusPrev),
+    ("select-pane-next", Command::FocusNext),
+    ("copy-up", Command::CopyMoveUp),
+    ("copy-down", Command::CopyMoveDown),
+    ("copy-left", Command::CopyMoveLeft),
+    ("copy-right", Command::CopyMoveRight),
+    ("begin-selection", Command::CopyStartSelection),
+    ("copy-selection", Command::CopyCopySelection),
+    ("search", Command::CopySearchStart),
+    ("search-apply", Command::CopySearchApply),
+    ("send-enter", Command::SendEnter),
+    ("open-folder", Command::OpenFolder),
+    ("choose-buffer", Command::ChooseBuffer),
+    ("picker-up", Command::BufferPickerUp),
+    ("picker-down", Command::BufferPickerDown),
+    ("picker-paste", Command::BufferPickerPaste),
+    ("picker-delete", Command::BufferPickerDelete),
+    ("picker-close", Command::BufferPickerClose),
+    ("font-zoom-in", Command::ZoomIn),
+    ("font-zoom-out", Command::ZoomOut),
+    ("font-zoom-reset", Command::ZoomReset),
+];
+
+/// Commands with arguments, with their usage.
+const USAGES: &[(&str, &str)] = &[
+    ("switch-table", "switch-table TABLE"),
+    ("split-window", "split-window [-h | -v] [-c DIR]"),
+    ("split-horizontal", "split-horizontal"),
+    ("split-vertical", "split-vertical"),
+    ("kill-pane", "kill-pane [-t PANE]"),
+    ("select-pane", "select-pane [-t PANE] [-T TITLE]"),
+    ("select-layout", "select-layout LAYOUT"),
+    ("rename-window", "rename-window NAME"),
+    ("send-text", "send-text TEXT"),
+    ("send-keys", "send-keys [-l] [-t PANE] KEY..."),
+    ("set-buffer", "set-buffer [-b NAME] TEXT"),
+    ("paste-buffer", "paste-buffer [-b NAME]"),
+    ("team", "team SUBCOMMAND TEAM ..."),
+    ("bind", "bind [-r] [-n | -T TABLE] KEY COMMAND [ARGS]"),
+    ("unbind", "unbind [-n | -T TABLE] KEY"),
+    ("set", "set OPTION VALUE"),
+];
+
+/// Options understood by `set`.
+pub const OPTIONS: &[&str] = &["prefix", "repeat-time", "which-key"];
+
+/// Every command name, for completion and help.
+pub fn command_names() -> Vec<&'static str> {
+    let mut names: Vec<&str> = SIMPLE_COMMANDS
+        .iter()
+        .map(|(name, _)| *name)
+        .chain(USAGES.iter().map(|(name, _)| *name))
+        .collect();
+    names.sort_unstable();
+    names
+}
+
+fn usage(name: &str) -> anyhow::Error {
+    match USAGES.iter().find(|(n, _)| *n == name) {
+        Some((_, usage)) => anyhow!("usage: {usage}"),
+        None => anyhow!("{name} takes no arguments"),
+    }
+}
+
+/// Flags read by [`getopt`], with the value of each flag that takes one.
+struct Flags(Vec<(char, Option<String>)>);
+
+impl Flags {
+    fn has(&self, flag: char) -> bool {
+        self.0.iter().any(|(c, _)| *c == flag)
+    }
+
+    fn value(&self, flag: char) -> Option<&str> {
+        self.0
+            .iter()
+            .rev()
+            .find(|(c, _)| *c == flag)
+            .and_then(|(_, v)| v.as_deref())
+    }
+
+    fn pane(&self, flag: char) -> Result<Option<usize>> {
+        self.value(flag).map(parse_pane).transpose()
+    }
+}
+
+/// Reads leading flags as in getopt: `spec` lists the flag letters, each followed by `:`
+/// if it takes a value (`-c DIR` or `-cDIR`). Flags end at `--` or the first other word.
+fn getopt<'a>(name: &str, args: &'a [String], spec: &str) -> Result<(Flags, &'a [String])> {
+    let mut flags = Vec::new();
+    let mut rest = args;
+    while let Some((word, tail)) = rest.split_first() {
+        if word == "--" {
+            return Ok((Flags(flags), tail));
+        }
+        let Some(letters) = word.strip_prefix('-').filter(|l| !l.is_empty()) else {
+            break;
+        };
+        rest = tail;
+        for (i, c) in letters.char_indices() {
+            let Some(at) = spec.find(c).filter(|_| c != ':') else {
+                bail!("{name}: unknown flag -{c}");
+            };
+            if spec[at + c.len_utf8()..].starts_with(':') {
+                let inline = &letters[i + c.len_utf8()..];
+                let value = if inline.is_empty() {
+                    let (value, tail) = rest
+                        .split_first()
+                        .ok_or_else(|| anyhow!("{name}: -{c} needs a value"))?;
+                    rest = tail;
+                    value.clone()
+                } else {
+                    inline.to_string()
+                };
+                flags.push((c, Some(value)));
+                break;
+            }
+            flags.push((c, None));
+        }
+    }
+    Ok((Flags(flags), rest))
+}
+
+fn parse_pane(value: &str) -> Result<usize> {
+    // tmux writes pane ids as %N.
+    let id = value.strip_prefix('%').unwrap_or(value);
+    id.parse()
+        .with_context(|| format!("invalid pane id: {value}"))
+}
+
+impl Command {
+    /// Parses one command and its arguments. A literal `;` word (from `\;`) splits the
+    /// words into a [`Command::Sequence`], except after `bind`, whose command takes them.
+    pub fn parse(words: &[String]) -> Result<Self> {
+        let binds = words
+            .first()
+            .is_some_and(|w| w == "bind" || w == "bind-key");
+        if !binds && words.iter().any(|w| w == ";") {
+            let commands = words
+                .split(|w| w == ";")
+                .filter(|group| !group.is_empty())
+                .map(Self::parse)
+                .collect::<Result<Vec<_>>>()?;
+            return Ok(Self::Sequence(commands));
+        }
+        let (name, args) = words.split_first().context("missing command")?;
+        let name = name.as_str();
+        let command = match name {
+            "switch-table" => match args {
+                [table] => Self::SwitchTable(table.clone()),
+                _ => return Err(usage(name)),
+            },
+            "split-window" | "split-horizontal" | "split-vertical" => {
+                let (flags, rest) = getopt(name, args, "hvc:")?;
+                if !rest.is_empty() || (name != "split-window" && !args.is_empty()) {
+                    return Err(usage(name));
+                }
+                // tmux's -h puts the new pane beside the old one, -v below it.
+                let axis = if name == "split-vertical" || flags.has('h') {
+                    SplitAxis::Vertical
+                } else {
+                    SplitAxis::Horizontal
+                };
+                Self::SplitWindow {
+                    axis,
+                    dir: flags.value('c').map(str::to_string),
+                }
+            }
+            "kill-pane" => {
+                let (flags, rest) = getopt(name, args, "t:")?;
+                if !rest.is_empty() {
+                    return Err(usage(name));
+                }
+                Self::KillPane(flags.pane('t')?)
+            }
+            "select-pane" => {
+                let (flags, rest) = getopt(name, args, "t:T:")?;
+                let target = match rest {
+                    [] => flags.pane('t')?,
+                    [id] if !flags.has('t') => Some(parse_pane(id)?),
+                    _ => return Err(usage(name)),
+                };
+                let title = flags.value('T').map(str::to_string);
+                if target.is_none() && title.is_none() {
+                    return Err(usage(name));
+                }
+                Self::SelectPane { target, title }
+            }
+            "select-layout" => match args {
+                [layout] => Self::SelectLayout(
+                    Layout::parse(layout).ok_or_else(|| anyhow!("unknown layout: {layout}"))?,
+                ),
+                _ => return Err(usage(name)),
+            },
+            "rename-window" => match args {
+                [title] => Self::RenameWindow(title.clone()),
+                _ => return Err(usage(name)),
+            },
+            "send-text" => {
+                if args.is_empty() {
+                    return Err(usage(name));
+                }
+                Self::SendText(unescape(&args.join(" ")))
+            }
+            "send-keys" => {
+                let (flags, keys) = getopt(name, args, "lt:")?;
+                if keys.is_empty() {
+                    return Err(usage(name));
+                }
+                Self::SendKeys {
+                    target: flags.pane('t')?,
+                    literal: flags.has('l'),
+                    keys: keys.to_vec(),
+                }
+            }
+            "set-buffer" => {
+                let (flags, rest) = getopt(name, args, "b:")?;
+                let [text] = rest else {
+                    return Err(usage(name));
+                };
+                Self::SetBuffer {
+                    name: flags.value('b').map(str::to_string),
+                    text: text.clone(),
+                }
+            }
+            "paste-buffer" => {
+                let (flags, rest) = getopt(name, args, "b:")?;
+                match rest {
+                    [] => Self::PasteBuffer(flags.value('b').map(str::to_string)),
+                    [buffer] if !flags.has('b') => Self::PasteBuffer(Some(buffer.clone())),
+                    _ => return Err(usage(name)),
+                }
+            }
+            "team" => Self::Team(TeamCommand::parse(args)?),
+            "bind" | "bind-key" => {
+                let (flags, rest) = getopt(name, args, "rnT:")?;
+                let [keys, command @ ..] = rest else {
+                    return Err(usage("bind"));
+                };
+                keymap::parse_sequence(keys)?;
+                Self::Bind {
+                    table: table_flag(&flags),
+                    repeat: flags.has('r'),
+                    keys: keys.clone(),
+                    command: Box::new(Self::parse(command)?),
+                }
+            }
+            "unbind" | "unbind-key" => {
+                let (flags, rest) = getopt(name, args, "nT:")?;
+                let [keys] = rest else {
+                    return Err(usage("unbind"));
+                };
+                keymap::parse_sequence(keys)?;
+                Self::Unbind {
+                    table: table_flag(&flags),
+                    keys: keys.clone(),
+                }
+            }
+            "set" | "set-option" => {
+                let (_, rest) = getopt(name, args, "g")?;
+                let [option, value] = rest else {
+                    return Err(usage("set"));
+                };
+                if !OPTIONS.contains(&option.as_str()) {
+                    bail!("unknown option: {option}");
+                }
+                Self::Set {
+                    option: option.clone(),
+                    value: value.clone(),
+                }
+            }
+            _ => {
+                let command = SIMPLE_COMMANDS
+                    .iter()
+                    .find(|(n, _)| *n == name)
+                    .map(|(_, command)| command.clone())
+                    .ok_or_else(|| anyhow!("unknown command: {name}"))?;
+                if !args.is_empty() {
+                    return Err(usage(name));
+                }
+                command
+            }
+        };
+        Ok(command)
+    }
+
+    /// The command line that parses back to this command.
+    pub fn line(&self) -> String {
+        match self {
+            Self::SwitchTable(table) => format!("switch-table {}", quote(table)),
+            Self::SplitWindow { axis, dir } => {
+                let flag = match axis {
+                    SplitAxis::Vertical => "-h",
+                    SplitAxis::Horizontal => "-v",
+                };
+                match dir {
+                    Some(dir) => format!("split-window {flag} -c {}", quote(dir)),
+                    None => format!("split-window {flag}"),
+                }
+            }
+            Self::KillPane(None) => "kill-pane".to_string(),
+            Self::KillPane(Some(id)) => format!("kill-pane -t {id}"),
+            Self::SelectPane { target, title } => {
+                let mut line = "select-pane".to_string();
+                if let Some(id) = target {
+                    line.push_str(&format!(" -t {id}"));
+                }
+                if let Some(title) = title {
+                    line.push_str(&format!(" -T {}", quote(title)));
+                }
+                line
+            }
+            Self::SelectLayout(layout) => format!("select-layout {}", layout.name()),
+            Self::RenameWindow(title) => format!("rename-window {}", quote(title)),
+            Self::SendText(text) => format!("send-text \"{}\"", escape(text)),
+            Self::SendKeys {
+                target,
+                literal,
+                keys,
+            } => {
+                let mut line = "send-keys".to_string();
+                if *literal {
+                    line.push_str(" -l");
+                }
+                if let Some(id) = target {
+                    line.push_str(&format!(" -t {id}"));
+                }
+                for key in keys {
+                    line.push(' ');
+                    line.push_str(&quote(key));
+                }
+                line
+            }
+            Self::SetBuffer { name, text } => match name {
+                Some(name) => format!("set-buffer -b {} {}", quote(name), quote(text)),
+                None => format!("set-buffer {}", quote(text)),
+            },
+            Self::PasteBuffer(None) => "paste-buffer".to_string(),
+            Self::PasteBuffer(Some(name)) => format!("paste-buffer -b {}", quote(name)),
+            Self::Team(team) => join_words(&team.words()),
+            Self::Bind {
+                table,
+                repeat,
+                keys,
+                command,
+            } => {
+                let repeat = if *repeat { "-r " } else { "" };
+                let nested = command.line().replace(" ; ", " \\; ");
+                format!("bind {repeat}-T {} {} {nested}", quote(table), quote(keys))
+            }
+            Self::Unbind { table, keys } => {
+                format!("unbind -T {} {}", quote(table), quote(keys))
+            }
+            Self::Set { option, value } => format!("set {option} {}", quote(value)),
+            Self::Sequence(commands) => commands
+                .iter()
+                .map(Self::line)
+                .collect::<Vec<_>>()
+                .join(" ; "),
+            _ => SIMPLE_COMMANDS
+                .iter()
+                .find(|(_, command)| command == self)
+                .map(|(name, _)| name.to_string())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// What the command does, for the shortcuts panel and the which-key overlay.
+    pub fn describe(&self) -> String {
+        match self {
+            Self::EnterPrefix => "Prefix".to_string(),
+            Self::SwitchTable(table) => format!("+{table}"),
+            Self::CommandPrompt => "Command prompt".to_string(),
+            Self::CommandPalette => "Command palette".to_string(),
+            Self::EnterCopyMode => "Copy mode".to_string(),
+            Self::ExitCopyMode => "Cancel".to_string(),
+            Self::SplitWindow { axis, dir } => {
+                let split = match axis {
+                    SplitAxis::Horizontal => "Split horizontally",
+                    SplitAxis::Vertical => "Split vertically",
+                };
+                match dir {
+                    Some(dir) => format!("{split} in {dir}"),
+                    None => split.to_string(),
+                }
+            }
+            Self::KillPane(None) => "Close focused pane".to_string(),
+            Self::KillPane(Some(id)) => format!("Close pane {id}"),
+            Self::ToggleZoom => "Toggle zoom".to_string(),
+            Self::FocusPrev => "Focus previous pane".to_string(),
+            Self::FocusNext => "Focus next pane".to_string(),
+            Self::SelectPane {
+                target: Some(id),
+                title: None,
+            } => format!("Focus pane {id}"),
+            Self::SelectPane { title, .. } => {
+                format!("Title pane \"{}\"", title.as_deref().unwrap_or_default())
+            }
+            Self::SelectLayout(layout) => format!("Layout {}", layout.name()),
+            Self::RenameWindow(title) => format!("Rename window to \"{title}\""),
+            Self::CopyMoveUp => "Move up".to_string(),
+            Self::CopyMoveDown => "Move down".to_string(),
+            Self::CopyMoveLeft => "Move left".to_string(),
+            Self::CopyMoveRight => "Move right".to_string(),
+            Self::CopyStartSelection => "Start selection".to_string(),
+            Self::CopyCopySelection => "Copy selection".to_string(),
+            Self::CopySearchStart => "Search".to_string(),
+            Self::CopySearchApply => "Jump to match".to_string(),
+            Self::SendEnter => "Send Enter to focused terminal".to_string(),
+            Self::SendText(text) => format!("Send \"{}\"", escape(text)),
+            Self::SendKeys { keys, .. } => format!("Send keys {}", keys.join(" ")),
+            Self::OpenFolder => "Select workspace folder".to_string(),
+            Self::SetBuffer { name, .. } => match name {
+                Some(name) => format!("Set {name}"),
+                None => "Add a paste buffer".to_string(),
+            },
+            Self::PasteBuffer(None) => "Paste newest buffer".to_string(),
+            Self::PasteBuffer(Some(name)) => format!("Paste {name}"),
+            Self::ChooseBuffer => "Choose a paste buffer".to_string(),
+            Self::BufferPickerUp => "Previous buffer".to_string(),
+            Self::BufferPickerDown => "Next buffer".to_string(),
+            Self::BufferPickerPaste => "Paste buffer".to_string(),
+            Self::BufferPickerDelete => "Delete buffer".to_string(),
+            Self::BufferPickerClose => "Close".to_string(),
+            Self::ZoomIn => "Zoom terminal font in".to_string(),
+            Self::ZoomOut => "Zoom terminal font out".to_string(),
+            Self::ZoomReset => "Reset terminal font zoom".to_string(),
+            Self::Team(_) | Self::Bind { .. } | Self::Unbind { .. } | Self::Set { .. } => {
+                self.line()
+            }
+            Self::Sequence(commands) => commands
+                .iter()
+                .map(Self::describe)
+                .collect::<Vec<_>>()
+                .join(", then "),
+        }
+    }
+}
+
+fn table_flag(flags: &Flags) -> String {
+    if let Some(table) = flags.value('T') {
+        return match table {
+            "normal" => Mode::Normal.table_name().to_string(),
+            _ => table.to_string(),
+        };
+    }
+    if flags.has('n') {
+        Mode::Normal.table_name().to_string()
+    } else {
+        Mode::Prefix.table_name().to_string()
+    }
+}
+
+/// Team operations forwarded to the server, named like the `orchestraterm team`
+/// subcommands.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TeamCommand {
+    Claim {
+        team_id: String,
+        member_id: usize,
+        task_id: usize,
+    },
+    Done {
+        team_id: String,
+        member_id: usize,
+        task_id: usize,
+    },
+    AutoClaim {
+        team_id: String,
+        member_id: usize,
+    },
+    AddTask {
+        team_id: String,
+        title: String,
+    },
+    SubmitPlan {
+        team_id: String,
+        member_id: usize,
+        plan: String,
+    },
+    Message {
+        team_id: String,
+        text: String,
+    },
+    Cleanup {
+        team_id: String,
+    },
+}
+
+pub const TEAM_SUBCOMMANDS: &[(&str, &str)] = &[
+    ("claim", "team claim TEAM MEMBER TASK"),
+    ("done", "team done TEAM MEMBER TASK"),
+    ("auto-claim", "team auto-claim TEAM MEMBER"),
+    ("add-task", "team add-task TEAM TITLE"),
+    ("submit-plan", "team submit-plan TEAM MEMBER PLAN"),
+    ("message", "team message TEAM TEXT"),
+    ("cleanup", "team cleanup TEAM"),
+];
+
+impl TeamCommand {
+    fn parse(args: &[String]) -> Result<Self> {
+        let (sub, args) = args.split_first().ok_or_else(|| usage("team"))?;
+        let usage = || match TEAM_SUBCOMMANDS.iter().find(|(n, _)| n == sub) {
+            Some((_, usage)) => anyhow!("usage: {usage}"),
+            None => anyhow!("unknown team command: {sub}"),
+        };
+        let id = |value: &String| -> Result<usize> {
+            value
+                .parse()
+                .with_context(|| format!("invalid id: {value}"))
+        };
+        let team_id = |value: &String| value.clone();
+        Ok(match (sub.as_str(), args) {
+            ("claim", [team, member, task]) => Self::Claim {
+                team_id: team_id(team),
+                member_id: id(member)?,
+                task_id: id(task)?,
+            },
+            ("done", [team, member, task]) => Self::Done {
+                team_id: team_id(team),
+                member_id: id(member)?,
+                task_id: id(task)?,
+            },
+            ("auto-claim", [team, member]) => Self::AutoClaim {
+                team_id: team_id(team),
+                member_id: id(member)?,
+            },
+            ("add-task", [team, title]) => Self::AddTask {
+                team_id: team_id(team),
+                title: title.clone(),
+            },
+            ("submit-plan", [team, member, plan]) => Self::SubmitPlan {
+                team_id: team_id(team),
+                member_id: id(member)?,
+                plan: plan.clone(),
+            },
+            ("message", [team, text]) => Self::Message {
+                team_id: team_id(team),
+                text: text.clone(),
+            },
+            ("cleanup", [team]) => Self::Cleanup {
+                team_id: team_id(team),
+            },
+            _ => return Err(usage()),
+        })
+    }
+
+    fn words(&self) -> Vec<String> {
+        let words: Vec<String> = match self {
+            Self::Claim {
+                team_id,
+                member_id,
+                task_id,
+            } => vec![
+                "claim".into(),
+                team_id.clone(),
+                member_id.to_string(),
+                task_id.to_string(),
+            ],
+            Self::Done {
+                team_id,
+                member_id,
+                task_id,
+            } => vec![
+                "done".into(),
+                team_id.clone(),
+                member_id.to_string(),
+                task_id.to_string(),
+            ],
+            Self::AutoClaim { team_id, member_id } => {
+                vec!["auto-claim".into(), team_id.clone(), member_id.to_string()]
+            }
+            Self::AddTask { team_id, title } => {
+                vec!["add-task".into(), team_id.clone(), title.clone()]
+            }
+            Self::SubmitPlan {
+                team_id,
+                member_id,
+                plan,
+            } => vec![
+                "submit-plan".into(),
+                team_id.clone(),
+                member_id.to_string(),
+                plan.clone(),
+            ],
+            Self::Message { team_id, text } => {
+                vec!["message".into(), team_id.clone(), text.clone()]
+            }
+            Self::Cleanup { team_id } => vec!["cleanup".into(), team_id.clone()],
+        };
+        std::iter::once("team".to_string()).chain(words).collect()
+    }
+
+    pub fn request(&self) -> ServerRequest {
+        match self.clone() {
+            Self::Claim {
+                team_id,
+                member_id,
+                task_id,
+            } => ServerRequest::TeamClaimTask {
+                team_id,
+                member_id,
+                task_id,
+            },
+            Self::Done {
+                team_id,
+                member_id,
+                task_id,
+            } => ServerRequest::TeamCompleteTask {
+                team_id,
+                member_id,
+                task_id,
+                input_tokens: 0,
+                output_tokens: 0,
+                cost_usd: 0.0,
+            },
+            Self::AutoClaim { team_id, member_id } => {
+                ServerRequest::TeamAutoClaim { team_id, member_id }
+            }
+            Self::AddTask { team_id, title } => ServerRequest::TeamAddTask {
+                team_id,
+                title,
+                deps: Vec::new(),
+                touched_files: Vec::new(),
+            },
+            Self::SubmitPlan {
+                team_id,
+                member_id,
+                plan,
+            } => ServerRequest::TeamSubmitPlan {
+                team_id,
+                member_id,
+                plan,
+            },
+            Self::Message { team_id, text } => ServerRequest::TeamPostMessage {
+                team_id,
+                from_member: None,
+                to_member: None,
+                text,
+                priority: Default::default(),
+            },
+            Self::Cleanup { team_id } => ServerRequest::TeamCleanup { team_id },
+        }
+    }
+}
+
+/// Expands `\n`, `\r`, `\t`, `\e` and `\\`; other backslashes are kept.
+pub fn unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some('t') => out.push('\t'),
+            Some('e') => out.push('\x1b'),
+            Some('\\') => out.push('\\'),
+            Some(other) => {
+                out.push('\\');
+                out.push(other);
+            }
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+pub fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\x1b' => out.push_str("\\e"),
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// `word` quoted so that [`split_line`] reads it back as one word.
+pub fn quote(word: &str) -> String {
+    let plain = !word.is_empty()
+        && !word
+            .chars()
+            .any(|c| c.is_whitespace() || matches!(c, '\'' | '"' | '#' | ';' | '\\'));
+    if plain {
+        word.to_string()
+    } else if !word.contains('\'') {
+        format!("'{word}'")
+    } else {
+        format!("\"{}\"", word.replace('"', "\\\""))
+    }
+}
+
+/// Words (e.g. command line arguments) as a line, with `;` words kept as separators.
+pub fn join_words(words: &[String]) -> String {
+    words
+        .iter()
+        .map(|w| if w == ";" { w.clone() } else { quote(w) })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Splits a line into commands, each a list of words. An unquoted `;` (alone or ending a
+/// word) separates commands; `\;` is the word `;`.
+pub fn split_line(line: &str) -> Result<Vec<Vec<String>>> {
+    let mut commands = Vec::new();
+    let mut words = Vec::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let Some(&first) = chars.peek() else {
+            break;
+        };
+        if first == '#' {
+            break;
+        }
+        let mut word = String::new();
+        let mut quoted = false;
+        let mut ends_command = false;
+        while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+            match c {
+                '\'' => {
+                    quoted = true;
+                    loop {
+                        match chars.next() {
+                            Some('\'') => break,
+                            Some(c) => word.push(c),
+                            None => bail!("unterminated quote"),
+                        }
+                    }
+                }
+                '"' => {
+                    quoted = true;
+                    loop {
+                        match chars.next() {
+                            Some('"') => break,
+                            Some('\\') if chars.peek() == Some(&'"') => {
+                                word.push('"');
+                                chars.next();
+                            }
+                            Some(c) => word.push(c),
+                            None => bail!("unterminated quote"),
+                        }
+                    }
+                }
+                '\\' if chars.peek() == Some(&';') => {
+                    chars.next();
+                    word.push(';');
+                    quoted = true;
+                }
+                ';' if chars.peek().is_none_or(|c| c.is_whitespace()) => {
+                    ends_command = true;
+                }
+                _ => word.push(c),
+            }
+        }
+        if !word.is_empty() || quoted {
+            words.push(word);
+        }
+        if ends_command && !words.is_empty() {
+            commands.push(std::mem::take(&mut words));
+        }
+    }
+    if !words.is_empty() {
+        commands.push(words);
+    }
+    Ok(commands)
+}
+
+/// Parses every command on a line.
+pub fn parse_line(line: &str) -> Result<Vec<Command>> {
+    split_line(line)?
+        .iter()
+        .map(|words| Command::parse(words))
+        .collect()
+}
+
+/// Parses command line arguments, which are already split into words.
+pub fn parse_words(words: &[String]) -> Result<Vec<Command>> {
+    words
+        .split(|w| w == ";")
+        .filter(|group| !group.is_empty())
+        .map(Command::parse)
+        .collect()
+}
+
+/// `ORCHESTRATERM_STARTUP`, else `startup.conf` in the runtime directory: commands run
+/// when the GUI starts.
+pub fn startup_path() -> Result<PathBuf> {
+    if let Ok(explicit) = std::env::var("ORCHESTRATERM_STARTUP") {
+        let trimmed = explicit.trim();
+        if !trimmed.is_empty() {
+            return Ok(PathBuf::from(trimmed));
+        }
+    }
+    Ok(runtime_dir()?.join("startup.conf"))
+}
+
+/// What can be offered for completion besides command names.
+#[derive(Debug, Clone, Default)]
+pub struct CompletionContext {
+    pub panes: Vec<usize>,
+    pub buffers: Vec<String>,
+    pub teams: Vec<String>,
+}
+
+/// Completions for the word at the end of `line`: the byte offset where that word starts
+/// and the candidates for it, sorted.
+pub fn complete(line: &str, context: &CompletionContext) -> (usize, Vec<String>) {
+    let start = line.rfind(|c: char| c.is_whitespace()).map_or(0, |i| {
+        i + line[i..].chars().next().map_or(1, char::len_utf8)
+    });
+    let partial = &line[start..];
+    let Ok(commands) = split_line(&line[..start]) else {
+        return (start, Vec::new());
+    };
+    let ends_command = line[..start].trim_end().ends_with(';');
+    let words = match commands.last() {
+        Some(words) if !ends_command => words.as_slice(),
+        _ => &[],
+    };
+    let ids = |ids: &[usize]| ids.iter().map(usize::to_string).collect::<Vec<_>>();
+    let candidates: Vec<String> = match (words.first().map(String::as_str), words.last()) {
+        (None, _) => command_names().into_iter().map(str::to_string).collect(),
+        (Some("kill-pane" | "select-pane" | "send-keys"), Some(flag)) if flag == "-t" => {
+            ids(&context.panes)
+        }
+        (Some("set-buffer" | "paste-buffer"), Some(flag)) if flag == "-b" => {
+            context.buffers.clone()
+        }
+        (Some("select-layout"), _) if words.len() == 1 => {
+            Layout::all().iter().map(|l| l.name().to_string()).collect()
+        }
+        (Some("set" | "set-option"), _) if words.len() == 1 => {
+            OPTIONS.iter().map(|o| o.to_string()).collect()
+        }
+        (Some("team"), _) if words.len() == 1 => TEAM_SUBCOMMANDS
+            .iter()
+            .map(|(name, _)| name.to_string())
+            .collect(),
+        (Some("team"), _) if words.len() == 2 => context.teams.clone(),
+        (Some("bind" | "unbind"), Some(flag)) if flag == "-T" => [
+            GLOBAL_TABLE,
+            "root",
+            "prefix",
+            "copy",
+            "copy-search",
+            "buffer-picker",
+        ]
+        .iter()
+        .map(|t| t.to_string())
+        .collect(),
+        _ => Vec::new(),
+    };
+    let mut candidates: Vec<String> = candidates
+        .into_iter()
+        .filter(|c| c.starts_with(partial))
+        .collect();
+    candidates.sort();
+    candidates.dedup();
+    (start, candidates)
+}
+
+/// The longest prefix shared by all `candidates`.
+pub fn common_prefix(candidates: &[String]) -> &str {
+    let Some(first) = candidates.first() else {
+        return "";
+    };
+    let mut len = first.len();
+    for candidate in &candidates[1..] {
+        len = first
+            .char_indices()
+            .zip(candidate.chars())
+            .take_while(|((_, a), b)| a == b)
+            .last()
+            .map_or(0, |((i, a), _)| i + a.len_utf8())
+            .min(len);
+    }
+    &first[..len]
+}
+
+/// Most prompt lines remembered.
+const MAX_HISTORY: usize = 100;
+
+/// The `:` prompt's line, history and pending completions.
+#[derive(Debug, Default)]
+pub struct Prompt {
+    pub input: String,
+    history: Vec<String>,
+    /// Position in `history` while browsing it; `None` when editing a new line.
+    browsing: Option<usize>,
+    /// The line being edited before history browsing started.
+    draft: String,
+    /// Candidates shown after an ambiguous completion.
+    pub completions: Vec<String>,
+}
+
+impl Prompt {
+    pub fn open(&mut self) {
+        self.input.clear();
+        self.browsing = None;
+        self.completions.clear();
+    }
+
+    /// Returns the line to run and remembers it, skipping repeats of the last one.
+    pub fn submit(&mut self) -> String {
+        let line = std::mem::take(&mut self.input);
+        self.browsing = None;
+        self.completions.clear();
+        if !line.trim().is_empty() && self.history.last() != Some(&line) {
+            self.history.push(line.clone());
+            if self.history.len() > MAX_HISTORY {
+                self.history.remove(0);
+            }
+        }
+        line
+    }
+
+    pub fn history_prev(&mut self) {
+        let index = match self.browsing {
+            Some(0) => return,
+            Some(i) => i - 1,
+            None if self.history.is_empty() => return,
+            None => {
+                self.draft = self.input.clone();
+                self.history.len() - 1
+            }
+        };
+        self.browsing = Some(index);
+        self.input = self.history[index].clone();
+    }
+
+    pub fn history_next(&mut self) {
+        let Some(index) = self.browsing else {
+            return;
+        };
+        if index + 1 < self.history.len() {
+            self.browsing = Some(index + 1);
+            self.input = self.history[index + 1].clone();
+        } else {
+            self.browsing = None;
+            self.input = std::mem::take(&mut self.draft);
+        }
+    }
+
+    /// Completes the last word: fully if one candidate fits, else as far as the candidates
+    /// agree, listing them in `completions`.
+    pub fn complete(&mut self, context: &CompletionContext) {
+        let (start, candidates) = complete(&self.input, context);
+        self.completions.clear();
+        match candidates.as_slice() {
+            [] => {}
+            [only] => {
+                self.input.truncate(start);
+                self.input.push_str(only);
+                self.input.push(' ');
+            }
+            _ => {
+                let prefix = common_prefix(&candidates).to_string();
+                self.input.truncate(start);
+                self.input.push_str(&prefix);
+                self.completions = candidates;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(line: &str) -> Vec<String> {
+        line.split_whitespace().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn lines_split_into_commands() {
+        assert_eq!(
+            split_line("split-window -h; send-keys 'cargo test' Enter # run it").unwrap(),
+            vec![
+                words("split-window -h"),
+                vec!["send-keys".into(), "cargo test".into(), "Enter".into()]
+            ]
+        );
+        assert_eq!(
+            split_line(r#"bind w split-window \; send-keys "say \"hi\"""#).unwrap(),
+            vec![vec![
+                "bind".to_string(),
+                "w".into(),
+                "split-window".into(),
+                ";".into(),
+                "send-keys".into(),
+                "say \"hi\"".into()
+            ]]
+        );
+        assert_eq!(split_line("set-buffer ''").unwrap()[0][1], "");
+        assert!(split_line("send-keys 'oops").is_err());
+    }
+
+    #[test]
+    fn commands_parse_with_tmux_flags() {
+        assert_eq!(
+            parse_line("split-window -h -c ~/src").unwrap(),
+            vec![Command::SplitWindow {
+                axis: SplitAxis::Vertical,
+                dir: Some("~/src".into()),
+            }]
+        );
+        assert_eq!(
+            parse_line("send-keys -t 2 'cargo test' Enter").unwrap(),
+            vec![Command::SendKeys {
+                target: Some(2),
+                literal: false,
+                keys: vec!["cargo test".into(), "Enter".into()],
+            }]
+        );
+        assert_eq!(
+            parse_line("select-layout tiled ; rename-window build").unwrap(),
+            vec![
+                Command::SelectLayout(Layout::Tiled),
+                Command::RenameWindow("build".into())
+            ]
+        );
+        assert_eq!(
+            parse_line("team claim frontend 1 0").unwrap(),
+            vec![Command::Team(TeamCommand::Claim {
+                team_id: "frontend".into(),
+                member_id: 1,
+                task_id: 0,
+            })]
+        );
+        assert_eq!(
+            parse_line(r"bind -r x kill-pane \; select-layout tiled").unwrap(),
+            vec![Command::Bind {
+                table: "prefix".into(),
+                repeat: true,
+                keys: "x".into(),
+                command: Box::new(Command::Sequence(vec![
+                    Command::KillPane(None),
+                    Command::SelectLayout(Layout::Tiled)
+                ])),
+            }]
+        );
+
+        let error = |line| format!("{:#}", parse_line(line).unwrap_err());
+        assert_eq!(error("split-window -x"), "split-window: unknown flag -x");
+        assert_eq!(error("select-layout spiral"), "unknown layout: spiral");
+        assert_eq!(error("kill-pane -t"), "kill-pane: -t needs a value");
+        assert_eq!(
+            error("team claim frontend 1"),
+            "usage: team claim TEAM MEMBER TASK"
+        );
+        assert_eq!(error("zoom-pane now"), "zoom-pane takes no arguments");
+    }
+
+    #[test]
+    fn commands_round_trip() {
+        for line in [
+            "split-window -h -c '~/my src'",
+            "send-keys -l -t 3 'echo \"$HOME\"' Enter",
+            "select-pane -t 1 -T build",
+            "set-buffer -b notes 'two words'",
+            "team message core 'ship it'",
+            r"bind -r -T prefix C-Up select-pane-prev \; zoom-pane",
+            "send-text \"a\\tb\\n\"",
+            "switch-table 'prefix w'",
+        ] {
+            let commands = parse_line(line).unwrap();
+            let again = parse_line(&commands[0].line()).unwrap();
+            assert_eq!(again, commands, "{line}");
+        }
+        let words = vec![
+            "send-keys".to_string(),
+            "it's".into(),
+            ";".into(),
+            "kill-pane".into(),
+        ];
+        assert_eq!(
+            parse_line(&join_words(&words)).unwrap(),
+            parse_words(&words).unwrap()
+        );
+    }
+
+    #[test]
+    fn completion_offers_names_in_context() {
+        let context = CompletionContext {
+            panes: vec![1, 2, 11],
+            buffers: vec!["buffer0".into()],
+            teams: vec!["frontend".into()],
+        };
+        assert_eq!(
+            complete("split-w", &context),
+            (0, vec!["split-window".to_string()])
+        );
+        assert_eq!(
+            complete("select-layout even-", &context),
+            (
+                14,
+                vec!["even-horizontal".to_string(), "even-vertical".into()]
+            )
+        );
+        assert_eq!(
+            complete("send-keys -t 1", &context),
+            (13, vec!["1".to_string(), "11".into()])
+        );
+        assert_eq!(
+            complete("kill-pane ; team c", &context).1,
+            vec!["claim".to_string(), "cleanup".into()]
+        );
+        assert_eq!(
+            complete("team claim f", &context).1,
+            vec!["frontend".to_string()]
+        );
+        assert_eq!(
+            common_prefix(&["select-pane".into(), "select-layout".into()]),
+            "select-"
+        );
+    }
+
+    #[test]
+    fn prompt_completes_and_remembers() {
+        let context = CompletionContext::default();
+        let mut prompt = Prompt {
+            input: "sel".into(),
+            ..Default::default()
+        };
+        prompt.complete(&context);
+        assert_eq!(prompt.input, "select-");
+        assert_eq!(prompt.completions.len(), 4);
+        prompt.input.push_str("lay");
+        prompt.complete(&context);
+        assert_eq!(prompt.input, "select-layout ");
+        assert!(prompt.completions.is_empty());
+
+        prompt.input = "zoom-pane".into();
+        assert_eq!(prompt.submit(), "zoom-pane");
+        prompt.input = "kill-pane".into();
+        prompt.submit();
+        prompt.input = "sp".into();
+        prompt.history_prev();
+        assert_eq!(prompt.input, "kill-pane");
+        prompt.history_prev();
+        prompt.history_prev();
+        assert_eq!(prompt.input, "zoom-pane");
+        prompt.history_next();
+        prompt.history_next();
+        assert_eq!(prompt.input, "sp");
+    }
+}
@@ -2,6 +2,12 @@
 mod tests {
     use vt100::{Color, Parser};
 
+    use crate::emulator::{ClipboardRequest, CursorShape, CursorStyle, Emulator};
+    use crate::graphics;
+    use crate::render;
+    use crate::theme::{Rgb, Theme};
+    use crate::width::{AMBIGUOUS_PAD, AmbiguousWidth};
+
     #[test]
     fn ascii_and_cursor_movement_work() {
         let mut parser = Parser::new(8, 40, 0);
@@ -104,4 +110,453 @@ mod tests {
         assert_eq!(c0.fgcolor(), Color::Idx(46));
         assert_eq!(c1.fgcolor(), Color::Idx(46));
     }
+
+    #[test]
+    fn osc_color_queries_answer_from_theme() {
+        let mut emu = Emulator::new(8, 40, 0);
+        emu.set_theme(Theme::default());
+        emu.process(b"\x1b]11;?\x07");
+        assert_eq!(emu.take_responses(), b"\x1b]11;rgb:1616/1919/1d1d\x07");
+        emu.process(b"\x1b]4;1;?\x1b\\");
+        assert_eq!(emu.take_responses(), b"\x1b]4;1;rgb:cdcd/3131/3131\x1b\\");
+    }
+
+    #[test]
+    fn osc_color_overrides_apply_and_reset() {
+        let mut emu = Emulator::new(8, 40, 0);
+        emu.process(b"\x1b]10;#ff0000\x07\x1b]4;2;rgb:00/00/ff\x07");
+        assert_eq!(emu.colors().foreground, Rgb(255, 0, 0));
+        assert_eq!(emu.colors().palette[2], Rgb(0, 0, 255));
+        emu.process(b"\x1b]110\x07\x1b]104\x07");
+        assert_eq!(emu.colors(), Theme::default());
+    }
+
+    #[test]
+    fn osc8_hyperlinks_follow_scrolling_text() {
+        let mut emu = Emulator::new(3, 20, 10);
+        emu.process(b"\r\ngo \x1b]8;;https://example.com\x1b\\here\x1b]8;;\x1b\\ now\r\n");
+        let link = emu.hyperlink_at(1, 4).expect("link must cover its text");
+        assert_eq!(link.uri, "https://example.com");
+        assert_eq!((link.start_col, link.end_col), (3, 7));
+        assert!(emu.hyperlink_at(1, 8).is_none());
+
+        emu.process(b"a\r\nb");
+        assert!(emu.hyperlink_at(1, 4).is_none());
+        assert_eq!(
+            emu.hyperlink_at(0, 4).map(|l| l.uri.as_str()),
+            Some("https://example.com")
+        );
+
+        emu.process(b"\r\nc");
+        assert!(emu.hyperlink_at(0, 4).is_none());
+    }
+
+    #[test]
+    fn osc7_reports_working_directory() {
+        let mut emu = Emulator::new(8, 40, 0);
+        emu.process(b"\x1b]7;file://host/tmp/my%20dir\x07");
+        assert_eq!(emu.cwd(), Some("/tmp/my dir"));
+    }
+
+    #[test]
+    fn kitty_keyboard_flags_push_pop_and_query() {
+        let mut emu = Emulator::new(8, 40, 0);
+        emu.process(b"\x1b[>1u\x1b[?u");
+        assert_eq!(emu.take_responses(), b"\x1b[?1u");
+        emu.process(b"\x1b[=8;2u");
+        assert_eq!(emu.key_modes().kitty_flags, 9);
+        emu.process(b"\x1b[?1049h");
+        assert_eq!(
+            emu.key_modes().kitty_flags,
+            0,
+            "alternate screen has its own stack"
+        );
+        emu.process(b"\x1b[?1049l\x1b[<u");
+        assert_eq!(emu.key_modes().kitty_flags, 0);
+    }
+
+    #[test]
+    fn input_modes_track_decckm_and_bracketed_paste() {
+        let mut emu = Emulator::new(8, 40, 0);
+        assert!(!emu.key_modes().application_cursor);
+        emu.process(b"\x1b[?1h\x1b[?2004h");
+        let modes = emu.key_modes();
+        assert!(modes.application_cursor);
+        assert!(modes.bracketed_paste);
+    }
+
+    #[test]
+    fn dsr_reports_status_and_cursor_position() {
+        let mut emu = Emulator::new(8, 40, 0);
+        emu.process(b"\x1b[5n");
+        assert_eq!(emu.take_responses(), b"\x1b[0n");
+        emu.process(b"ab\r\ncde\x1b[6n");
+        assert_eq!(emu.take_responses(), b"\x1b[2;4R");
+        emu.process(b"\x1b[?6n");
+        assert_eq!(emu.take_responses(), b"\x1b[?2;4R");
+    }
+
+    #[test]
+    fn device_attributes_and_xtversion_identify_terminal() {
+        let mut emu = Emulator::new(8, 40, 0);
+        emu.process(b"\x1b[c");
+        assert_eq!(emu.take_responses(), b"\x1b[?62;4;22c");
+        emu.process(b"\x1b[0c");
+        assert_eq!(emu.take_responses(), b"\x1b[?62;4;22c");
+
+        emu.process(b"\x1b[>c");
+        let da2 = String::from_utf8(emu.take_responses()).unwrap();
+        assert!(
+            da2.starts_with("\x1b[>1;") && da2.ends_with(";0c"),
+            "{da2:?}"
+        );
+
+        emu.process(b"\x1b[>0q");
+        let version = format!("\x1bP>|OrchestraTerm({})\x1b\\", env!("CARGO_PKG_VERSION"));
+        assert_eq!(emu.take_responses(), version.as_bytes());
+    }
+
+    #[test]
+    fn decrqm_reports_mode_state() {
+        let mut emu = Emulator::new(8, 40, 0);
+        emu.process(b"\x1b[?2004$p\x1b[?25$p");
+        assert_eq!(emu.take_responses(), b"\x1b[?2004;2$y\x1b[?25;1$y");
+        emu.process(b"\x1b[?2004h\x1b[?25l\x1b[?2004$p\x1b[?25$p");
+        assert_eq!(emu.take_responses(), b"\x1b[?2004;1$y\x1b[?25;2$y");
+        emu.process(b"\x1b[?1049h\x1b[?1049$p\x1b[?9999$p\x1b[4$p");
+        assert_eq!(
+            emu.take_responses(),
+            b"\x1b[?1049;1$y\x1b[?9999;0$y\x1b[4;4$y"
+        );
+    }
+
+    #[test]
+    fn queries_do_not_disturb_screen_contents() {
+        let mut emu = Emulator::new(4, 20, 0);
+        emu.process(b"x\x1b[6n\x1b[c\x1b[?1$py");
+        assert_eq!(emu.screen().contents(), "xy");
+    }
+
+    #[test]
+    fn decscusr_sets_cursor_shape_and_blink() {
+        let mut emu = Emulator::new(8, 40, 0);
+        assert_eq!(emu.cursor_style(), CursorStyle::default());
+        emu.process(b"\x1b[6 q");
+        assert_eq!(
+            emu.cursor_style(),
+            CursorStyle {
+                shape: CursorShape::Bar,
+                blink: false
+            }
+        );
+        emu.process(b"\x1b[3 q");
+        assert_eq!(emu.cursor_style().shape, CursorShape::Underline);
+        assert!(emu.cursor_style().blink);
+        emu.process(b"\x1b[?12l");
+        assert!(!emu.cursor_style().blink);
+        emu.process(b"\x1b[0 q");
+        assert_eq!(emu.cursor_style(), CursorStyle::default());
+        assert_eq!(emu.screen().contents(), "");
+    }
+
+    #[test]
+    fn hidden_cursor_is_tracked() {
+        let mut emu = Emulator::new(8, 40, 0);
+        emu.process(b"\x1b[?25l");
+        assert!(emu.screen().hide_cursor());
+        emu.process(b"\x1b[?25h");
+        assert!(!emu.screen().hide_cursor());
+    }
+
+    #[test]
+    fn focus_reports_only_when_enabled() {
+        let mut emu = Emulator::new(8, 40, 0);
+        emu.report_focus(true);
+        assert!(emu.take_responses().is_empty());
+        emu.process(b"\x1b[?1004;2004h\x1b[?1004$p");
+        assert_eq!(emu.take_responses(), b"\x1b[?1004;1$y");
+        assert!(emu.key_modes().bracketed_paste);
+        emu.report_focus(false);
+        emu.report_focus(true);
+        assert_eq!(emu.take_responses(), b"\x1b[O\x1b[I");
+        emu.process(b"\x1b[?1004l");
+        emu.report_focus(false);
+        assert!(emu.take_responses().is_empty());
+    }
+
+    #[test]
+    fn osc52_sets_and_queries_clipboard() {
+        let mut emu = Emulator::new(8, 40, 0);
+        // "héllo" in base64, then a query with the default selection and BEL terminator.
+        emu.process(b"\x1b]52;c;aMOpbGxv\x1b\\\x1b]52;;?\x07");
+        let requests = emu.take_clipboard_requests();
+        assert_eq!(
+            requests[0],
+            ClipboardRequest::Set {
+                selection: "c".into(),
+                text: "héllo".into()
+            }
+        );
+        let ClipboardRequest::Query(query) = &requests[1] else {
+            panic!("expected a query, got {:?}", requests[1]);
+        };
+        assert_eq!(query.selection, "s0");
+        emu.answer_clipboard(query, "hi");
+        assert_eq!(emu.take_responses(), b"\x1b]52;s0;aGk=\x07");
+        assert_eq!(emu.screen().contents(), "");
+    }
+
+    #[test]
+    fn resize_reflows_wrapped_lines_both_ways() {
+        let mut emu = Emulator::new(4, 20, 100);
+        emu.process(b"0123456789abcdefghij-tail\r\n$ ");
+        emu.set_size(4, 10);
+        assert_eq!(emu.screen().contents(), "0123456789abcdefghij-tail\n$");
+        assert!(emu.screen().row_wrapped(0));
+        assert_eq!(emu.screen().cursor_position(), (3, 2));
+
+        emu.set_size(4, 30);
+        assert_eq!(emu.screen().contents(), "0123456789abcdefghij-tail\n$");
+        assert!(!emu.screen().row_wrapped(0));
+        assert_eq!(emu.screen().cursor_position(), (1, 2));
+        emu.process(b"ls");
+        assert_eq!(emu.screen().contents(), "0123456789abcdefghij-tail\n$ ls");
+    }
+
+    #[test]
+    fn reflow_pulls_lines_through_scrollback() {
+        let mut emu = Emulator::new(3, 10, 100);
+        emu.process(b"aaaaaaaaaabbbbb\r\nline2\r\nline3\r\nline4");
+        assert_eq!(emu.screen().contents(), "line2\nline3\nline4");
+        emu.set_size(3, 20);
+        emu.set_size(6, 20);
+        assert_eq!(
+            emu.screen().contents(),
+            "aaaaaaaaaabbbbb\nline2\nline3\nline4"
+        );
+        assert_eq!(emu.screen().cursor_position(), (3, 5));
+    }
+
+    #[test]
+    fn reflow_keeps_colours_and_wide_characters() {
+        let mut emu = Emulator::new(3, 6, 10);
+        emu.process("\x1b[31mab\x1b[0m한글한".as_bytes());
+        emu.set_size(3, 5);
+        let screen = emu.screen();
+        // `글` cannot start in the last column, so a blank pads the row and it wraps.
+        assert_eq!(screen.contents(), "ab한 글한");
+        assert_eq!(screen.cell(0, 0).unwrap().fgcolor(), Color::Idx(1));
+        assert_eq!(screen.cell(0, 2).unwrap().fgcolor(), Color::Default);
+        assert_eq!(screen.cell(1, 0).unwrap().contents(), "글");
+        emu.set_size(3, 8);
+        assert_eq!(emu.screen().contents(), "ab한글한");
+        assert_eq!(emu.screen().cursor_position(), (1, 0));
+    }
+
+    #[test]
+    fn alternate_screen_is_resized_without_reflow() {
+        let mut emu = Emulator::new(4, 20, 10);
+        emu.process(b"main\x1b[?1049hfull-screen");
+        emu.set_size(4, 10);
+        assert!(emu.screen().alternate_screen());
+        assert_eq!(emu.screen().contents(), "full-scree");
+    }
+
+    #[test]
+    fn reflow_reads_scrollback_deeper_than_the_screen() {
+        let mut emu = Emulator::new(2, 8, 100);
+        for i in 0..10 {
+            emu.process(format!("row-{i}-xyz\r\n").as_bytes());
+        }
+        emu.set_size(12, 12);
+        let contents = emu.screen().contents();
+        assert!(
+            contents.starts_with("row-0-xyz\nrow-1-xyz\n"),
+            "{contents:?}"
+        );
+        assert!(contents.ends_with("row-9-xyz"), "{contents:?}");
+        assert_eq!(emu.screen().cursor_position(), (10, 0));
+    }
+
+    #[test]
+    fn text_includes_scrollback_and_leaves_the_screen_alone() {
+        let mut emu = Emulator::new(2, 8, 100);
+        for i in 0..5 {
+            emu.process(format!("row-{i}-wrapping\r\n").as_bytes());
+        }
+        emu.process(b"$");
+        let before = emu.screen().contents();
+        let text = emu.text();
+        assert!(
+            text.starts_with("row-0-wrapping\nrow-1-wrapping\n"),
+            "{text:?}"
+        );
+        assert!(text.ends_with("row-4-wrapping\n$"), "{text:?}");
+        assert_eq!(emu.screen().contents(), before);
+        assert_eq!(emu.screen().cursor_position(), (1, 1));
+
+        // Scroll region and saved cursor survive.
+        let mut emu = Emulator::new(4, 8, 100);
+        for i in 0..6 {
+            emu.process(format!("\r\nrow-{i}").as_bytes());
+        }
+        emu.process(b"\x1b[2;3r\x1b[1;4H\x1b7");
+        assert!(emu.text().starts_with("\nrow-0\n"));
+        emu.process(b"\x1b8x\x1b[3;1H\n");
+        assert_eq!(emu.screen().contents(), "rowx2\nrow-4\n\nrow-5");
+
+        // Pads after wide ambiguous characters are not text, on either screen.
+        let mut emu = Emulator::new(2, 8, 100);
+        emu.set_ambiguous_width(AmbiguousWidth::Wide);
+        emu.process("±x".as_bytes());
+        assert_eq!(emu.text(), "±x");
+        emu.process("\x1b[?1049h±y".as_bytes());
+        assert_eq!(emu.text().trim_end(), "±y");
+    }
+
+    #[test]
+    fn snapshot_rebuilds_the_pane_on_a_fresh_emulator() {
+        let mut emu = Emulator::new(3, 10, 100);
+        emu.process(b"\x1b]2;build\x07\x1b]7;file://host/tmp/my%20dir\x07");
+        for i in 0..6 {
+            emu.process(format!("line {i}\r\n").as_bytes());
+        }
+        emu.process(b"\x1b[31m$\x1b[0m \x1b[6 q\x1b[?1004h\x1b[>1u\x1b[?2004h");
+        let mut copy = Emulator::new(3, 10, 100);
+        copy.process(&emu.snapshot());
+        assert_eq!(copy.screen().contents(), emu.screen().contents());
+        assert_eq!(copy.screen().cursor_position(), (2, 2));
+        assert_eq!(copy.screen().title(), "build");
+        assert_eq!(copy.screen().cell(2, 0).unwrap().fgcolor(), Color::Idx(1));
+        assert!(copy.text().starts_with("line 0\nline 1\n"));
+        assert_eq!(copy.cwd(), Some("/tmp/my dir"));
+        assert_eq!(copy.cursor_style(), emu.cursor_style());
+        assert_eq!(copy.key_modes(), emu.key_modes());
+        copy.report_focus(true);
+        assert_eq!(copy.take_responses(), b"\x1b[I");
+
+        emu.process(b"\x1b[?1049h\x1b[2;3Hvim");
+        let mut copy = Emulator::new(3, 10, 100);
+        copy.process(&emu.snapshot());
+        assert!(copy.screen().alternate_screen());
+        assert_eq!(copy.screen().contents(), emu.screen().contents());
+        assert_eq!(copy.screen().cursor_position(), (1, 5));
+        assert_eq!(copy.key_modes(), emu.key_modes());
+    }
+
+    #[test]
+    fn mirrored_output_leaves_queries_to_the_server() {
+        let mut emu = Emulator::new(24, 80, 0);
+        emu.process(b"\x1b[?1004h");
+        emu.report_focus(false);
+        emu.process_mirrored(b"\x1b[c\x1b[6n");
+        assert_eq!(emu.take_responses(), b"\x1b[O");
+    }
+
+    #[test]
+    fn cell_pixel_size_reports() {
+        let mut emu = Emulator::new(24, 80, 0);
+        emu.set_cell_pixels(9, 18);
+        emu.process(b"\x1b[16t\x1b[14t\x1b[18t");
+        assert_eq!(
+            emu.take_responses(),
+            b"\x1b[6;18;9t\x1b[4;432;720t\x1b[8;24;80t"
+        );
+    }
+
+    #[test]
+    fn sixel_images_are_anchored_to_cells_and_scroll() {
+        let mut emu = Emulator::new(4, 20, 10);
+        emu.set_cell_pixels(10, 10);
+        // A 15x12 pixel image covers 2x2 cells.
+        emu.process(b"ab\x1bP0;1;0q\"1;1;15;12#1;2;100;0;0!15~-!15~\x1b\\");
+        let screen = emu.screen();
+        let (id, row, col) = graphics::placeholder(screen.cell(0, 2).unwrap()).unwrap();
+        assert_eq!((row, col), (0, 0));
+        assert_eq!(
+            graphics::placeholder(screen.cell(1, 3).unwrap()),
+            Some((id, 1, 1))
+        );
+        assert!(graphics::placeholder(screen.cell(0, 4).unwrap()).is_none());
+        assert_eq!(screen.cursor_position(), (1, 2));
+        let placement = emu.placement(id).expect("placement is kept");
+        assert_eq!((placement.image.width, placement.image.height), (15, 12));
+        assert_eq!(placement.span(), (2, 2));
+
+        emu.process(b"\r\nx\r\ny\r\nz");
+        assert_eq!(
+            graphics::placeholder(emu.screen().cell(0, 3).unwrap()),
+            Some((id, 1, 1))
+        );
+        assert!(emu.screen().contents().ends_with("\nx\ny\nz"));
+    }
+
+    #[test]
+    fn kitty_images_are_placed_and_answered() {
+        let mut emu = Emulator::new(6, 20, 0);
+        emu.set_cell_pixels(10, 20);
+        // Transmit a 2x1 RGB image as id 3, then put it at 4x2 cells without moving.
+        emu.process(b"\x1b_Ga=t,f=24,s=2,v=1,i=3;////AAAA\x1b\\");
+        assert_eq!(emu.take_responses(), b"\x1b_Gi=3;OK\x1b\\");
+        emu.process(b"\x1b[2;3H\x1b_Ga=p,i=3,c=4,r=2,C=1,q=1\x1b\\");
+        assert!(emu.take_responses().is_empty());
+        let (id, _, _) = graphics::placeholder(emu.screen().cell(2, 5).unwrap()).unwrap();
+        assert_eq!(emu.placement(id).unwrap().span(), (2, 4));
+        assert_eq!(emu.screen().cursor_position(), (1, 2));
+
+        emu.process(b"\x1b_Ga=d,d=A\x1b\\");
+        assert!(emu.placement(id).is_none());
+    }
+
+    #[test]
+    fn clusters_keep_shell_widths_and_render_once() {
+        let mut emu = Emulator::new(4, 20, 0);
+        // A ZWJ family, a flag and a combining accent, then a marker.
+        emu.process("👨\u{200D}👩\u{200D}👧|🇰🇷|e\u{301}|".as_bytes());
+        // Cells follow per-codepoint widths, as the shell counted them.
+        assert_eq!(emu.screen().cursor_position(), (0, 12));
+        let row = render::row_spans(emu.screen(), 0, 20, &Theme::default(), true);
+        let emoji: Vec<_> = row
+            .emoji
+            .iter()
+            .map(|r| (r.col, r.width, r.text.as_str()))
+            .collect();
+        assert_eq!(emoji, [(0, 6, "👨\u{200D}👩\u{200D}👧"), (7, 2, "🇰🇷")]);
+        assert!(row.text.iter().any(|r| r.col == 10 && r.text == "e\u{301}"));
+    }
+
+    #[test]
+    fn ambiguous_width_policy_pads_characters() {
+        let mut emu = Emulator::new(4, 20, 0);
+        emu.set_ambiguous_width(AmbiguousWidth::Wide);
+        // "○" split across two reads, an escape sequence, then "※".
+        emu.process(b"\xe2\x97");
+        emu.process(&[b"\x8b", "\x1b[1m※\x1b[0mx".as_bytes()].concat());
+        let screen = emu.screen();
+        assert_eq!(screen.cursor_position(), (0, 5));
+        assert_eq!(
+            screen.cell(0, 1).unwrap().contents(),
+            AMBIGUOUS_PAD.to_string()
+        );
+        let row = render::row_spans(screen, 0, 20, &Theme::default(), true);
+        let runs: Vec<_> = row
+            .text
+            .iter()
+            .map(|r| (r.col, r.width, r.text.as_str()))
+            .collect();
+        assert_eq!(runs, [(0, 2, "○※"), (4, 1, "x")]);
+
+        emu.set_ambiguous_width(AmbiguousWidth::Narrow);
+        emu.process("○".as_bytes());
+        assert_eq!(emu.screen().cursor_position(), (0, 6));
+    }
+
+    #[test]
+    fn bells_are_counted_but_osc_terminators_are_not() {
+        let mut emu = Emulator::new(4, 20, 0);
+        emu.process(b"a\x07b\x1b]0;title\x07\x07");
+        assert_eq!(emu.take_bells(), 2);
+        assert_eq!(emu.take_bells(), 0);
+        assert_eq!(emu.screen().contents(), "ab");
+    }
 }
@@ -0,0 +1,744 @@
+//! `config.toml`: the tunables that used to be hardcoded, in one file, with per-workspace
+//! overrides.
+//!
+//! The user file is `ORCHESTRATERM_CONFIG`, else `$XDG_CONFIG_HOME/orchestraterm/config.toml`
+//! (`~/.config/...` without XDG). A workspace's `.orchestraterm.toml` is applied on top.
+//! Both use a subset of TOML: `[section]` headers and `key = value` lines whose values
+//! are strings, numbers, booleans or one-line arrays. Bad lines are reported and skipped,
+//! so the rest of the file still applies. Keys also set by an `ORCHESTRATERM_*` variable
+//! keep the variable's value.
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use anyhow::{Context, Result, anyhow, bail};
+
+use crate::bell::BellPolicy;
+use crate::buffers::ClipboardAccess;
+use crate::core::LineCaps;
+use crate::fonts::{self, FontConfig};
+use crate::links::LinkOpener;
+use crate::render::RenderPreset;
+use crate::status::{StatusConfig, Template};
+use crate::theme::{Rgb, Theme};
+use crate::width::AmbiguousWidth;
+
+/// Address the server listens on when neither the config nor the environment names one.
+pub const DEFAULT_SERVER_ADDR: &str = "127.0.0.1:7899";
+
+/// Name of the override file in a workspace folder.
+pub const WORKSPACE_FILE: &str = ".orchestraterm.toml";
+
+/// Every key, with the environment variable that takes precedence over it.
+const KEYS: &[(&str, Option<&str>)] = &[
+    ("render.preset", None),
+    ("cursor.blink_interval_ms", None),
+    ("terminal.shell", None),
+    ("terminal.shell_args", None),
+    ("terminal.scrollback", None),
+    (
+        "terminal.ambiguous_width",
+        Some("ORCHESTRATERM_AMBIGUOUS_WIDTH"),
+    ),
+    ("terminal.osc52", Some("ORCHESTRATERM_OSC52")),
+    ("terminal.bell", Some("ORCHESTRATERM_BELL")),
+    ("terminal.drop_relative_paths", None),
+    ("panes.log_lines", None),
+    ("panes.focused_log_lines", None),
+    ("colors.theme", None),
+    ("colors.foreground", None),
+    ("colors.background", None),
+    ("colors.cursor", None),
+    ("colors.selection", None),
+    ("colors.palette", None),
+    ("font.family", Some("ORCHESTRATERM_FONT")),
+    ("font.size", Some("ORCHESTRATERM_FONT_SIZE")),
+    ("font.fallbacks", Some("ORCHESTRATERM_FONT_FALLBACKS")),
+    ("font.emoji", Some("ORCHESTRATERM_EMOJI_FONT")),
+    ("links.url_opener", Some("ORCHESTRATERM_URL_OPENER")),
+    ("links.file_opener", Some("ORCHESTRATERM_FILE_OPENER")),
+    ("status.left", None),
+    ("status.centre", None),
+    ("status.right", None),
+    ("status.interval", None),
+    ("server.address", Some("ORCHESTRATERM_SERVER_ADDR")),
+];
+
+/// The keys a workspace's `.orchestraterm.toml` may set. A checked-out repository is not
+/// trusted to pick the shell, run commands or reach the clipboard, so only looks and
+/// limits are left to it.
+const WORKSPACE_KEYS: &[&str] = &[
+    "render.preset",
+    "cursor.blink_interval_ms",
+    "terminal.scrollback",
+    "terminal.ambiguous_width",
+    "terminal.bell",
+    "terminal.drop_relative_paths",
+    "panes.log_lines",
+    "panes.focused_log_lines",
+    "colors.theme",
+    "colors.foreground",
+    "colors.background",
+    "colors.cursor",
+    "colors.selection",
+    "colors.palette",
+    "font.family",
+    "font.size",
+    "font.fallbacks",
+    "font.emoji",
+];
+
+/// Theme colours replaced by the config.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ThemeColors {
+    pub foreground: Option<Rgb>,
+    pub background: Option<Rgb>,
+    pub cursor: Option<Rgb>,
+    pub selection: Option<Rgb>,
+    pub palette: Vec<Rgb>,
+}
+
+impl ThemeColors {
+    pub fn apply(&self, theme: &mut Theme) {
+        let slots = [
+            (self.foreground, &mut theme.foreground),
+            (self.background, &mut theme.background),
+            (self.cursor, &mut theme.cursor),
+            (self.selection, &mut theme.selection),
+        ];
+        for (color, slot) in slots {
+            if let Some(color) = color {
+                *slot = color;
+            }
+        }
+        for (slot, color) in theme.palette.iter_mut().zip(&self.palette) {
+            *slot = *color;
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    pub render_preset: RenderPreset,
+    /// Half-period of the blinking cursor; zero keeps it steady.
+    pub cursor_blink: Duration,
+    pub shell: String,
+    pub shell_args: Vec<String>,
+    /// Scrollback rows kept by each new pane.
+    pub scrollback: usize,
+    pub ambiguous_width: AmbiguousWidth,
+    pub osc52: ClipboardAccess,
+    pub bell: BellPolicy,
+    /// Whether dropped files are typed relative to the pane's directory.
+    pub drop_relative_paths: bool,
+    pub line_caps: LineCaps,
+    /// Name of a built-in or discovered theme; the current one when `None`.
+    pub theme: Option<String>,
+    pub colors: ThemeColors,
+    pub font: FontConfig,
+    pub links: LinkOpener,
+    pub status: StatusConfig,
+    pub server_address: String,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            render_preset: RenderPreset::Balanced,
+            cursor_blink: Duration::from_millis(530),
+            shell: "/bin/zsh".to_string(),
+            shell_args: vec!["-i".to_string()],
+            scrollback: 10_000,
+            ambiguous_width: AmbiguousWidth::default(),
+            osc52: ClipboardAccess::default(),
+            bell: BellPolicy::default(),
+            drop_relative_paths: false,
+            line_caps: LineCaps::default(),
+            theme: None,
+            colors: ThemeColors::default(),
+            font: FontConfig::default(),
+            links: LinkOpener::default(),
+            status: StatusConfig::default(),
+            server_address: std::env::var("ORCHESTRATERM_SERVER_ADDR")
+                .ok()
+                .filter(|v| !v.trim().is_empty())
+                .unwrap_or_else(|| DEFAULT_SERVER_ADDR.to_string()),
+        }
+    }
+}
+
+impl Config {
+    /// The user config with `workspace`'s overrides, and every problem found, as
+    /// `path:line: message`.
+    pub fn load(workspace: Option<&Path>) -> (Self, Vec<String>) {
+        let mut config = Self::default();
+        let mut errors = Vec::new();
+        for (path, is_workspace) in config_files(workspace) {
+            match std::fs::read_to_string(&path) {
+                Ok(text) => {
+                    for error in config.apply_text(&text, is_workspace, env_is_set) {
+                        errors.push(format!("{}:{error}", path.display()));
+                    }
+                }
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                Err(err) => errors.push(format!("{}: {err}", path.display())),
+            }
+        }
+        (config, errors)
+    }
+
+    /// Applies a config file's text, returning `line: message` for every line that was
+    /// skipped. Keys for which `env_is_set` holds are left alone.
+    pub fn apply_text(
+        &mut self,
+        text: &str,
+        is_workspace: bool,
+        env_is_set: impl Fn(&str) -> bool,
+    ) -> Vec<String> {
+        let mut errors = Vec::new();
+        let mut section = String::new();
+        for (index, line) in text.lines().enumerate() {
+            let result = parse_line(line).and_then(|parsed| match parsed {
+                Line::Empty => Ok(()),
+                Line::Section(name) => {
+                    section = name;
+                    Ok(())
+                }
+                Line::Entry(key, value) => {
+                    let key = if section.is_empty() {
+                        key
+                    } else {
+                        format!("{section}.{key}")
+                    };
+                    self.set_entry(&key, &value, is_workspace, &env_is_set)
+                }
+            });
+            if let Err(err) = result {
+                errors.push(format!("{}: {err:#}", index + 1));
+            }
+        }
+        errors
+    }
+
+    fn set_entry(
+        &mut self,
+        key: &str,
+        value: &Value,
+        is_workspace: bool,
+        env_is_set: &impl Fn(&str) -> bool,
+    ) -> Result<()> {
+        let Some((_, env)) = KEYS.iter().find(|(name, _)| *name == key) else {
+            return Err(match suggest(key) {
+                Some(near) => anyhow!("unknown key {key} (did you mean {near}?)"),
+                None => anyhow!("unknown key {key}"),
+            });
+        };
+        if env.is_some_and(env_is_set) {
+            return Ok(());
+        }
+        if is_workspace && !WORKSPACE_KEYS.contains(&key) {
+            bail!("{key} can only be set in the user config");
+        }
+        self.set(key, value).with_context(|| key.to_string())
+    }
+
+    fn set(&mut self, key: &str, value: &Value) -> Result<()> {
+        match key {
+            "render.preset" => {
+                let names = RenderPreset::all().map(RenderPreset::name);
+                self.render_preset = RenderPreset::parse(value.string()?)
+                    .with_context(|| format!("expected one of {}", names.join(", ")))?;
+            }
+            "cursor.blink_interval_ms" => {
+                self.cursor_blink = Duration::from_millis(value.integer(0, 10_000)? as u64);
+            }
+            "terminal.shell" => {
+                let shell = value.string()?;
+                if shell.trim().is_empty() {
+                    bail!("must not be empty");
+                }
+                self.shell = shell.to_string();
+            }
+            "terminal.shell_args" => self.shell_args = value.strings()?,
+            "terminal.scrollback" => self.scrollback = value.integer(0, 1_000_000)? as usize,
+            "terminal.ambiguous_width" => {
+                self.ambiguous_width = parse_with(value, AmbiguousWidth::parse, "narrow or wide")?;
+            }
+            "terminal.osc52" => {
+                self.osc52 = parse_with(value, ClipboardAccess::parse, "off, write or read-write")?;
+            }
+            "terminal.bell" => {
+                self.bell = parse_with(
+                    value,
+                    BellPolicy::parse,
+                    "none or a list of flash, badge and attention",
+                )?;
+            }
+            "terminal.drop_relative_paths" => self.drop_relative_paths = value.boolean()?,
+            "panes.log_lines" => self.line_caps.pane = value.integer(1, 100_000)? as usize,
+            "panes.focused_log_lines" => {
+                self.line_caps.focused = value.integer(1, 100_000)? as usize;
+            }
+            "colors.theme" => self.theme = Some(value.string()?.to_string()),
+            "colors.foreground" => self.colors.foreground = Some(value.color()?),
+            "colors.background" => self.colors.background = Some(value.color()?),
+            "colors.cursor" => self.colors.cursor = Some(value.color()?),
+            "colors.selection" => self.colors.selection = Some(value.color()?),
+            "colors.palette" => {
+                let Value::Array(items) = value else {
+                    bail!("expected an array of colours, got {}", value.describe());
+                };
+                if items.len() > 16 {
+                    bail!("expected at most 16 colours, got {}", items.len());
+                }
+                self.colors.palette = items.iter().map(Value::color).collect::<Result<_>>()?;
+            }
+            "font.family" => self.font.family = Some(value.string()?.to_string()),
+            "font.size" => {
+                let size = value.number()?;
+                if !(fonts::MIN_SIZE..=fonts::MAX_SIZE).contains(&size) {
+                    bail!(
+                        "expected a size from {} to {}, got {size}",
+                        fonts::MIN_SIZE,
+                        fonts::MAX_SIZE
+                    );
+                }
+                self.font.size = size;
+            }
+            "font.fallbacks" => self.font.fallbacks = value.strings()?,
+            "font.emoji" => self.font.emoji = Some(value.string()?.to_string()),
+            "links.url_opener" => self.links.url_command = value.string()?.to_string(),
+            "links.file_opener" => self.links.file_command = value.string()?.to_string(),
+            "status.left" => self.status.left = Template::parse(value.string()?)?,
+            "status.centre" => self.status.centre = Template::parse(value.string()?)?,
+            "status.right" => self.status.right = Template::parse(value.string()?)?,
+            "status.interval" => {
+                self.status.interval = Duration::from_secs(value.integer(1, 3600)? as u64);
+            }
+            "server.address" => {
+                let address = value.string()?;
+                if !address.contains(':') {
+                    bail!("expected HOST:PORT, got \"{address}\"");
+                }
+                self.server_address = address.to_string();
+            }
+            _ => unreachable!("{key} is listed in KEYS"),
+        }
+        Ok(())
+    }
+
+    /// The shell and its arguments, for [`crate::terminal::PaneTerminal::spawn`].
+    pub fn shell_command(&self) -> Vec<String> {
+        std::iter::once(self.shell.clone())
+            .chain(self.shell_args.iter().cloned())
+            .collect()
+    }
+}
+
+fn env_is_set(name: &str) -> bool {
+    std::env::var(name).is_ok_and(|v| !v.trim().is_empty())
+}
+
+fn parse_with<T>(value: &Value, parse: impl Fn(&str) -> Option<T>, expected: &str) -> Result<T> {
+    let text = value.string()?;
+    parse(text).with_context(|| format!("expected {expected}, got \"{text}\""))
+}
+
+/// The known key closest to a misspelt one, if any is close.
+fn suggest(key: &str) -> Option<&'static str> {
+    KEYS.iter()
+        .map(|(name, _)| (edit_distance(key, name), *name))
+        .filter(|(distance, _)| *distance <= 3)
+        .min()
+        .map(|(_, name)| name)
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != *cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// `ORCHESTRATERM_CONFIG`, else `config.toml` in the XDG config directory.
+pub fn config_path() -> Option<PathBuf> {
+    let env = |name| {
+        std::env::var_os(name)
+            .filter(|v| !v.is_empty())
+            .map(PathBuf::from)
+    };
+    if let Some(explicit) = env("ORCHESTRATERM_CONFIG") {
+        return Some(explicit);
+    }
+    let base = env("XDG_CONFIG_HOME").or_else(|| env("HOME").map(|home| home.join(".config")))?;
+    Some(base.join("orchestraterm").join("config.toml"))
+}
+
+/// The files [`Config::load`] reads, in order, each marked if it belongs to the workspace.
+pub fn config_files(workspace: Option<&Path>) -> Vec<(PathBuf, bool)> {
+    let mut files: Vec<(PathBuf, bool)> = config_path().into_iter().map(|p| (p, false)).collect();
+    if let Some(dir) = workspace {
+        files.push((dir.join(WORKSPACE_FILE), true));
+    }
+    files
+}
+
+/// When each file was last modified, `None` where it is missing; compared to spot edits.
+pub fn modified_times(paths: &[PathBuf]) -> Vec<Option<SystemTime>> {
+    paths
+        .iter()
+        .map(|path| std::fs::metadata(path).and_then(|m| m.modified()).ok())
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Bool(bool),
+    Array(Vec<Value>),
+}
+
+impl Value {
+    fn describe(&self) -> String {
+        match self {
+            Self::String(s) => format!("\"{s}\""),
+            Self::Integer(n) => n.to_string(),
+            Self::Float(n) => n.to_string(),
+            Self::Bool(b) => b.to_string(),
+            Self::Array(_) => "an array".to_string(),
+        }
+    }
+
+    fn string(&self) -> Result<&str> {
+        match self {
+            Self::String(s) => Ok(s),
+            _ => bail!("expected a string, got {}", self.describe()),
+        }
+    }
+
+    fn strings(&self) -> Result<Vec<String>> {
+        match self {
+            Self::Array(items) => items
+                .iter()
+                .map(|v| v.string().map(str::to_string))
+                .collect(),
+            _ => bail!("expected an array of strings, got {}", self.describe()),
+        }
+    }
+
+    fn boolean(&self) -> Result<bool> {
+        match self {
+            Self::Bool(b) => Ok(*b),
+            _ => bail!("expected true or false, got {}", self.describe()),
+        }
+    }
+
+    fn integer(&self, min: i64, max: i64) -> Result<i64> {
+        match self {
+            Self::Integer(n) if (min..=max).contains(n) => Ok(*n),
+            _ => bail!(
+                "expected a whole number from {min} to {max}, got {}",
+                self.describe()
+            ),
+        }
+    }
+
+    fn number(&self) -> Result<f32> {
+        match self {
+            Self::Integer(n) => Ok(*n as f32),
+            Self::Float(n) => Ok(*n as f32),
+            _ => bail!("expected a number, got {}", self.describe()),
+        }
+    }
+
+    fn color(&self) -> Result<Rgb> {
+        Rgb::parse(self.string()?).with_context(|| {
+            format!(
+                "expected a colour like \"#1d1f21\", got {}",
+                self.describe()
+            )
+        })
+    }
+}
+
+enum Line {
+    Empty,
+    Section(String),
+    Entry(String, Value),
+}
+
+fn parse_line(line: &str) -> Result<Line> {
+    let mut chars = line.trim_start().chars().peekable();
+    match chars.peek() {
+        None | Some('#') => return Ok(Line::Empty),
+        Some('[') => {
+            chars.next();
+            let rest: String = chars.collect();
+            let (name, tail) = rest
+                .split_once(']')
+                .context("expected ] after the section")?;
+            expect_end(tail)?;
+            let name = name.trim();
+            if !is_key(name) {
+                bail!("invalid section name \"{name}\"");
+            }
+            return Ok(Line::Section(name.to_string()));
+        }
+        Some(_) => {}
+    }
+    let rest: String = chars.collect();
+    let (key, value) = rest.split_once('=').context("expected key = value")?;
+    let key = key.trim();
+    if !is_key(key) {
+        bail!("invalid key \"{key}\"");
+    }
+    let mut value = value.trim_start().chars().peekable();
+    let parsed = parse_value(&mut value)?;
+    expect_end(&value.collect::<String>())?;
+    Ok(Line::Entry(key.to_string(), parsed))
+}
+
+fn is_key(key: &str) -> bool {
+    !key.is_empty()
+        && key.split('.').all(|part| {
+            !part.is_empty()
+                && part
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        })
+}
+
+/// Only a comment may follow a value.
+fn expect_end(tail: &str) -> Result<()> {
+    let tail = tail.trim();
+    if tail.is_empty() || tail.starts_with('#') {
+        Ok(())
+    } else {
+        bail!("unexpected \"{tail}\"")
+    }
+}
+
+fn parse_value(chars: &mut std::iter::Peekable<std::str::Chars>) -> Result<Value> {
+    match chars.peek() {
+        None => bail!("missing value"),
+        Some('"') => {
+            chars.next();
+            let mut s = String::new();
+            loop {
+                match chars.next().context("unterminated string")? {
+                    '"' => return Ok(Value::String(s)),
+                    '\\' => s.push(match chars.next().context("unterminated string")? {
+                        'n' => '\n',
+                        't' => '\t',
+                        'r' => '\r',
+                        'e' => '\x1b',
+                        c @ ('"' | '\\') => c,
+                        c => bail!("unknown escape \\{c}"),
+                    }),
+                    c => s.push(c),
+                }
+            }
+        }
+        Some('\'') => {
+            chars.next();
+            let mut s = String::new();
+            loop {
+                match chars.next().context("unterminated string")? {
+                    '\'' => return Ok(Value::String(s)),
+                    c => s.push(c),
+                }
+            }
+        }
+        Some('[') => {
+            chars.next();
+            let mut items = Vec::new();
+            loop {
+                while chars.next_if(|c| c.is_whitespace()).is_some() {}
+                if chars.next_if_eq(&']').is_some() {
+                    return Ok(Value::Array(items));
+                }
+                items.push(parse_value(chars)?);
+                while chars.next_if(|c| c.is_whitespace()).is_some() {}
+                match chars.next() {
+                    Some(',') => {}
+                    Some(']') => return Ok(Value::Array(items)),
+                    _ => bail!("expected , or ] in the array"),
+                }
+            }
+        }
+        Some(_) => {
+            let mut word = String::new();
+            while let Some(c) =
+                chars.next_if(|c| !c.is_whitespace() && !matches!(c, ',' | ']' | '#'))
+            {
+                word.push(c);
+            }
+            let digits = word.replace('_', "");
+            match word.as_str() {
+                "true" => Ok(Value::Bool(true)),
+                "false" => Ok(Value::Bool(false)),
+                _ => digits
+                    .parse::<i64>()
+                    .map(Value::Integer)
+                    .or_else(|_| digits.parse::<f64>().map(Value::Float))
+                    .map_err(|_| anyhow!("invalid value \"{word}\" (strings need quotes)")),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(text: &str, is_workspace: bool) -> (Config, Vec<String>) {
+        let mut config = Config::default();
+        let errors = config.apply_text(text, is_workspace, |_| false);
+        (config, errors)
+    }
+
+    #[test]
+    fn reads_sections_values_and_comments() {
+        let (config, errors) = apply(
+            r##"
+# user config
+[render]
+preset = "compact"   # smaller
+
+[terminal]
+shell = '/bin/bash'
+shell_args = ["-l", "-i"]
+scrollback = 50_000
+bell = "flash,attention"
+drop_relative_paths = true
+
+[panes]
+log_lines = 1000
+
+[colors]
+theme = "Solarized Dark"
+background = "#101010"
+palette = ["#000000", "#ff0000"]
+
+[font]
+size = 15.5
+fallbacks = ["Noto Sans CJK KR"]
+
+[cursor]
+blink_interval_ms = 0
+
+[status]
+right = "#(uptime) #{time}"
+interval = 30
+"##,
+            false,
+        );
+        assert_eq!(errors, Vec::<String>::new());
+        assert_eq!(config.render_preset, RenderPreset::Compact);
+        assert_eq!(config.shell_command(), ["/bin/bash", "-l", "-i"]);
+        assert_eq!(config.scrollback, 50_000);
+        assert!(config.bell.flash && config.bell.attention && !config.bell.badge);
+        assert!(config.drop_relative_paths);
+        assert_eq!(config.line_caps.pane, 1000);
+        assert_eq!(config.line_caps.focused, 200);
+        assert_eq!(config.theme.as_deref(), Some("Solarized Dark"));
+        assert_eq!(config.colors.background, Some(Rgb(16, 16, 16)));
+        assert_eq!(config.colors.palette, [Rgb(0, 0, 0), Rgb(255, 0, 0)]);
+        assert_eq!(config.font.size, 15.5);
+        assert_eq!(config.font.fallbacks, ["Noto Sans CJK KR"]);
+        assert_eq!(config.cursor_blink, Duration::ZERO);
+        assert_eq!(config.status.commands(), ["uptime"]);
+        assert_eq!(config.status.interval, Duration::from_secs(30));
+    }
+
+    #[test]
+    fn bad_lines_are_reported_and_skipped() {
+        let (config, errors) = apply(
+            r##"[font]
+sise = 12
+size = 200
+family = Menlo
+[terminal]
+scrollback = -1
+osc52 = "sometimes"
+[render]
+preset = "compact"
+[colors
+foreground = "#12"
+[status]
+left = "#{sesion}"
+"##,
+            false,
+        );
+        assert_eq!(
+            errors,
+            [
+                "2: unknown key font.sise (did you mean font.size?)",
+                "3: font.size: expected a size from 6 to 72, got 200",
+                "4: invalid value \"Menlo\" (strings need quotes)",
+                "6: terminal.scrollback: expected a whole number from 0 to 1000000, got -1",
+                "7: terminal.osc52: expected off, write or read-write, got \"sometimes\"",
+                "10: expected ] after the section",
+                "11: unknown key render.foreground",
+                "13: status.left: unknown variable: sesion",
+            ]
+        );
+        // Good lines around the bad ones still apply.
+        assert_eq!(config.render_preset, RenderPreset::Compact);
+        assert_eq!(config.font.size, FontConfig::default().size);
+    }
+
+    #[test]
+    fn workspace_overrides_apply_on_top() {
+        let mut config = Config::default();
+        config.apply_text("[render]\npreset = \"pixel\"\n", false, |_| false);
+        let errors = config.apply_text(
+            "[render]\npreset = \"compact\"\n[server]\naddress = \"10.0.0.1:1\"\n",
+            true,
+            |_| false,
+        );
+        assert_eq!(config.render_preset, RenderPreset::Compact);
+        assert_eq!(
+            errors,
+            ["4: server.address can only be set in the user config"]
+        );
+
+        let errors = config.apply_text(
+            "[terminal]\nshell = \"/tmp/evil\"\nshell_args = [\"-c\", \"x\"]\n\
+             [links]\nurl_opener = \"sh -c {url}\"\n[status]\nright = \"#(id)\"\n",
+            true,
+            |_| false,
+        );
+        assert_eq!(
+            errors,
+            [
+                "2: terminal.shell can only be set in the user config",
+                "3: terminal.shell_args can only be set in the user config",
+                "5: links.url_opener can only be set in the user config",
+                "7: status.right can only be set in the user config",
+            ]
+        );
+        assert_eq!(config.shell_command(), Config::default().shell_command());
+        assert!(config.status.commands().is_empty());
+
+        let errors = config.apply_text("[font]\nsize = 20\n", false, |name| {
+            name == "ORCHESTRATERM_FONT_SIZE"
+        });
+        assert!(errors.is_empty());
+        assert_ne!(config.font.size, 20.0);
+    }
+}
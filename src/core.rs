@@ -11,11 +11,175 @@ pub enum LayoutNode {
     Leaf(usize),
     Split {
         axis: SplitAxis,
+        /// Share of the space given to `first`.
+        #[serde(default = "half")]
+        ratio: f32,
         first: Box<LayoutNode>,
         second: Box<LayoutNode>,
     },
 }
 
+fn half() -> f32 {
+    0.5
+}
+
+impl LayoutNode {
+    /// Pane ids from top-left to bottom-right.
+    pub fn leaves(&self) -> Vec<usize> {
+        match self {
+            Self::Leaf(id) => vec![*id],
+            Self::Split { first, second, .. } => {
+                let mut ids = first.leaves();
+                ids.extend(second.leaves());
+                ids
+            }
+        }
+    }
+
+    pub fn contains(&self, id: usize) -> bool {
+        match self {
+            Self::Leaf(leaf) => *leaf == id,
+            Self::Split { first, second, .. } => first.contains(id) || second.contains(id),
+        }
+    }
+}
+
+/// Where a dragged pane lands on another: beside it, splitting its space, or in its
+/// place, swapping the two.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DropZone {
+    Left,
+    Right,
+    Top,
+    Bottom,
+    Centre,
+}
+
+impl DropZone {
+    /// The zone under a point given as fractions of the target's width and height: the
+    /// outer quarter on each side, else the centre.
+    pub fn at(x: f32, y: f32) -> Self {
+        let edges = [
+            (x, Self::Left),
+            (1.0 - x, Self::Right),
+            (y, Self::Top),
+            (1.0 - y, Self::Bottom),
+        ];
+        edges
+            .into_iter()
+            .filter(|(distance, _)| *distance < 0.25)
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+            .map_or(Self::Centre, |(_, zone)| zone)
+    }
+
+    /// The part of the target the dropped pane will take, as fractions `(x0, y0, x1, y1)`.
+    pub fn area(self) -> (f32, f32, f32, f32) {
+        match self {
+            Self::Left => (0.0, 0.0, 0.5, 1.0),
+            Self::Right => (0.5, 0.0, 1.0, 1.0),
+            Self::Top => (0.0, 0.0, 1.0, 0.5),
+            Self::Bottom => (0.0, 0.5, 1.0, 1.0),
+            Self::Centre => (0.0, 0.0, 1.0, 1.0),
+        }
+    }
+}
+
+/// Preset arrangements, as in tmux's `select-layout`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    /// Side by side, equal widths.
+    EvenHorizontal,
+    /// Stacked, equal heights.
+    EvenVertical,
+    /// The first pane across the top, the others side by side below it.
+    MainHorizontal,
+    /// The first pane down the left, the others stacked to its right.
+    MainVertical,
+    /// A grid as close to square as the pane count allows.
+    Tiled,
+}
+
+impl Layout {
+    pub fn all() -> [Self; 5] {
+        [
+            Self::EvenHorizontal,
+            Self::EvenVertical,
+            Self::MainHorizontal,
+            Self::MainVertical,
+            Self::Tiled,
+        ]
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::EvenHorizontal => "even-horizontal",
+            Self::EvenVertical => "even-vertical",
+            Self::MainHorizontal => "main-horizontal",
+            Self::MainVertical => "main-vertical",
+            Self::Tiled => "tiled",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::all().into_iter().find(|l| l.name() == value)
+    }
+
+    /// Arranges `ids` in this layout; `None` if there are none.
+    pub fn build(self, ids: &[usize]) -> Option<LayoutNode> {
+        // `SplitAxis::Vertical` puts panes side by side, `Horizontal` stacks them.
+        match self {
+            Self::EvenHorizontal => even(SplitAxis::Vertical, ids),
+            Self::EvenVertical => even(SplitAxis::Horizontal, ids),
+            Self::MainHorizontal | Self::MainVertical => {
+                let (main, rest) = ids.split_first()?;
+                let (axis, rest_axis) = if self == Self::MainHorizontal {
+                    (SplitAxis::Horizontal, SplitAxis::Vertical)
+                } else {
+                    (SplitAxis::Vertical, SplitAxis::Horizontal)
+                };
+                let Some(rest) = even(rest_axis, rest) else {
+                    return Some(LayoutNode::Leaf(*main));
+                };
+                Some(LayoutNode::Split {
+                    axis,
+                    ratio: 0.6,
+                    first: Box::new(LayoutNode::Leaf(*main)),
+                    second: Box::new(rest),
+                })
+            }
+            Self::Tiled => {
+                let columns = (ids.len() as f64).sqrt().ceil().max(1.0) as usize;
+                let rows: Vec<LayoutNode> = ids
+                    .chunks(columns)
+                    .filter_map(|row| even(SplitAxis::Vertical, row))
+                    .collect();
+                chain(SplitAxis::Horizontal, rows)
+            }
+        }
+    }
+}
+
+/// `ids` split evenly along `axis`.
+fn even(axis: SplitAxis, ids: &[usize]) -> Option<LayoutNode> {
+    chain(axis, ids.iter().map(|id| LayoutNode::Leaf(*id)).collect())
+}
+
+/// Nests `nodes` along `axis` with ratios that give each an equal share.
+fn chain(axis: SplitAxis, mut nodes: Vec<LayoutNode>) -> Option<LayoutNode> {
+    let mut node = nodes.pop()?;
+    let mut count = 1;
+    while let Some(first) = nodes.pop() {
+        count += 1;
+        node = LayoutNode::Split {
+            axis,
+            ratio: 1.0 / count as f32,
+            first: Box::new(first),
+            second: Box::new(node),
+        };
+    }
+    Some(node)
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Pane {
     pub id: usize,
@@ -23,13 +187,37 @@ pub struct Pane {
     pub lines: Vec<String>,
 }
 
+/// How many lines `Pane.lines` keeps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LineCaps {
+    /// For lines appended to the focused pane.
+    pub focused: usize,
+    /// For lines appended to a pane by id.
+    pub pane: usize,
+}
+
+impl Default for LineCaps {
+    fn default() -> Self {
+        Self {
+            focused: 200,
+            pane: 400,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SessionCore {
     pub name: String,
     pub panes: Vec<Pane>,
+    /// The main window's layout.
     pub layout: LayoutNode,
+    /// Layouts of panes broken out into windows of their own.
+    #[serde(default)]
+    pub windows: Vec<LayoutNode>,
     pub focused_pane: usize,
     pub zoomed: bool,
+    #[serde(default)]
+    pub line_caps: LineCaps,
     next_id: usize,
 }
 
@@ -44,8 +232,10 @@ impl SessionCore {
             name: name.into(),
             panes: vec![pane],
             layout: LayoutNode::Leaf(0),
+            windows: Vec::new(),
             focused_pane: 0,
             zoomed: false,
+            line_caps: LineCaps::default(),
             next_id: 1,
         }
     }
@@ -54,7 +244,8 @@ impl SessionCore {
         self.panes.iter().map(|p| p.id).collect()
     }
 
-    pub fn split_focused(&mut self, axis: SplitAxis) {
+    /// Splits the focused pane, focuses the new one and returns its id.
+    pub fn split_focused(&mut self, axis: SplitAxis) -> usize {
         let new_id = self.next_id;
         self.next_id += 1;
 
@@ -64,23 +255,128 @@ impl SessionCore {
             lines: vec![format!("split from pane {}", self.focused_pane)],
         });
 
-        self.layout = Self::split_leaf(self.layout.clone(), self.focused_pane, new_id, axis);
+        let focused = self.focused_pane;
+        for layout in std::iter::once(&mut self.layout).chain(&mut self.windows) {
+            *layout = Self::split_leaf(layout.clone(), focused, new_id, axis);
+        }
         self.focused_pane = new_id;
+        new_id
     }
 
     pub fn close_focused(&mut self) {
-        if self.panes.len() == 1 {
+        self.close_pane(self.focused_pane);
+    }
+
+    /// Closes a pane, unless it is the last one.
+    pub fn close_pane(&mut self, removed: usize) {
+        if self.panes.len() == 1 || !self.panes.iter().any(|p| p.id == removed) {
             return;
         }
 
-        let removed = self.focused_pane;
         self.panes.retain(|p| p.id != removed);
-        self.layout = Self::remove_leaf(self.layout.clone(), removed)
-            .unwrap_or_else(|| LayoutNode::Leaf(self.panes[0].id));
-        self.focused_pane = self.panes[0].id;
+        self.windows = std::mem::take(&mut self.windows)
+            .into_iter()
+            .filter_map(|layout| Self::remove_leaf(layout, removed))
+            .collect();
+        if self.layout.contains(removed) {
+            self.layout = match Self::remove_leaf(self.layout.clone(), removed) {
+                Some(layout) => layout,
+                // The main window always shows something: take over a broken-out window.
+                None if !self.windows.is_empty() => self.windows.remove(0),
+                None => LayoutNode::Leaf(self.panes[0].id),
+            };
+        }
+        if self.focused_pane == removed {
+            self.focused_pane = self.panes[0].id;
+        }
+        self.zoomed = false;
+    }
+
+    /// Rearranges the main window's panes, in creation order, into `layout`.
+    pub fn select_layout(&mut self, layout: Layout) {
+        let ids: Vec<usize> = self
+            .pane_ids()
+            .into_iter()
+            .filter(|id| self.layout.contains(*id))
+            .collect();
+        if let Some(node) = layout.build(&ids) {
+            self.layout = node;
+            self.zoomed = false;
+        }
+    }
+
+    pub fn has_pane(&self, id: usize) -> bool {
+        self.panes.iter().any(|p| p.id == id)
+    }
+
+    /// Moves `pane` onto `target`: beside it for an edge, or swapping places for the
+    /// centre. Returns whether anything changed; the main window is never left empty.
+    pub fn move_pane(&mut self, pane: usize, target: usize, zone: DropZone) -> bool {
+        if pane == target || !self.has_pane(pane) || !self.has_pane(target) {
+            return false;
+        }
+        if zone == DropZone::Centre {
+            for layout in std::iter::once(&mut self.layout).chain(&mut self.windows) {
+                *layout = Self::swap_leaves(layout.clone(), pane, target);
+            }
+            return true;
+        }
+        if self.layout.leaves() == [pane] {
+            return false;
+        }
+        self.detach(pane);
+        let (axis, before) = match zone {
+            DropZone::Left => (SplitAxis::Vertical, true),
+            DropZone::Right => (SplitAxis::Vertical, false),
+            DropZone::Top => (SplitAxis::Horizontal, true),
+            DropZone::Bottom | DropZone::Centre => (SplitAxis::Horizontal, false),
+        };
+        for layout in std::iter::once(&mut self.layout).chain(&mut self.windows) {
+            *layout = Self::insert_beside(layout.clone(), target, pane, axis, before);
+        }
+        self.zoomed = false;
+        true
+    }
+
+    /// Moves `pane` out of its window into a new one of its own; `false` if it is alone
+    /// in the main window already.
+    pub fn break_pane(&mut self, pane: usize) -> bool {
+        if !self.has_pane(pane) || self.layout.leaves() == [pane] {
+            return false;
+        }
+        self.detach(pane);
+        self.windows.push(LayoutNode::Leaf(pane));
+        self.zoomed = false;
+        true
+    }
+
+    /// Puts a broken-out window's panes back into the main window, beside the others.
+    pub fn join_window(&mut self, index: usize) {
+        if index >= self.windows.len() {
+            return;
+        }
+        let window = self.windows.remove(index);
+        self.layout = LayoutNode::Split {
+            axis: SplitAxis::Vertical,
+            ratio: 0.5,
+            first: Box::new(self.layout.clone()),
+            second: Box::new(window),
+        };
         self.zoomed = false;
     }
 
+    /// Takes `pane` out of whichever layout holds it, dropping windows left empty. The
+    /// caller keeps the main window non-empty.
+    fn detach(&mut self, pane: usize) {
+        if let Some(layout) = Self::remove_leaf(self.layout.clone(), pane) {
+            self.layout = layout;
+        }
+        self.windows = std::mem::take(&mut self.windows)
+            .into_iter()
+            .filter_map(|layout| Self::remove_leaf(layout, pane))
+            .collect();
+    }
+
     pub fn focus_next(&mut self) {
         let ids = self.pane_ids();
         if ids.is_empty() {
@@ -109,7 +405,7 @@ impl SessionCore {
     pub fn append_line_focused(&mut self, line: impl Into<String>) {
         if let Some(pane) = self.panes.iter_mut().find(|p| p.id == self.focused_pane) {
             pane.lines.push(line.into());
-            while pane.lines.len() > 200 {
+            while pane.lines.len() > self.line_caps.focused {
                 pane.lines.remove(0);
             }
         }
@@ -118,7 +414,7 @@ impl SessionCore {
     pub fn append_line_to_pane(&mut self, pane_id: usize, line: impl Into<String>) {
         if let Some(pane) = self.panes.iter_mut().find(|p| p.id == pane_id) {
             pane.lines.push(line.into());
-            while pane.lines.len() > 400 {
+            while pane.lines.len() > self.line_caps.pane {
                 pane.lines.remove(0);
             }
         }
@@ -128,28 +424,83 @@ impl SessionCore {
         match node {
             LayoutNode::Leaf(id) if id == target => LayoutNode::Split {
                 axis,
+                ratio: 0.5,
                 first: Box::new(LayoutNode::Leaf(id)),
                 second: Box::new(LayoutNode::Leaf(new_id)),
             },
             LayoutNode::Leaf(_) => node,
             LayoutNode::Split {
                 axis: current,
+                ratio,
                 first,
                 second,
             } => LayoutNode::Split {
                 axis: current,
+                ratio,
                 first: Box::new(Self::split_leaf(*first, target, new_id, axis)),
                 second: Box::new(Self::split_leaf(*second, target, new_id, axis)),
             },
         }
     }
 
+    fn insert_beside(
+        node: LayoutNode,
+        target: usize,
+        pane: usize,
+        axis: SplitAxis,
+        before: bool,
+    ) -> LayoutNode {
+        match node {
+            LayoutNode::Leaf(id) if id == target => {
+                let (first, second) = if before { (pane, id) } else { (id, pane) };
+                LayoutNode::Split {
+                    axis,
+                    ratio: 0.5,
+                    first: Box::new(LayoutNode::Leaf(first)),
+                    second: Box::new(LayoutNode::Leaf(second)),
+                }
+            }
+            LayoutNode::Leaf(_) => node,
+            LayoutNode::Split {
+                axis: current,
+                ratio,
+                first,
+                second,
+            } => LayoutNode::Split {
+                axis: current,
+                ratio,
+                first: Box::new(Self::insert_beside(*first, target, pane, axis, before)),
+                second: Box::new(Self::insert_beside(*second, target, pane, axis, before)),
+            },
+        }
+    }
+
+    fn swap_leaves(node: LayoutNode, a: usize, b: usize) -> LayoutNode {
+        match node {
+            LayoutNode::Leaf(id) if id == a => LayoutNode::Leaf(b),
+            LayoutNode::Leaf(id) if id == b => LayoutNode::Leaf(a),
+            LayoutNode::Leaf(_) => node,
+            LayoutNode::Split {
+                axis,
+                ratio,
+                first,
+                second,
+            } => LayoutNode::Split {
+                axis,
+                ratio,
+                first: Box::new(Self::swap_leaves(*first, a, b)),
+                second: Box::new(Self::swap_leaves(*second, a, b)),
+            },
+        }
+    }
+
     fn remove_leaf(node: LayoutNode, target: usize) -> Option<LayoutNode> {
         match node {
             LayoutNode::Leaf(id) if id == target => None,
             LayoutNode::Leaf(id) => Some(LayoutNode::Leaf(id)),
             LayoutNode::Split {
                 axis,
+                ratio,
                 first,
                 second,
             } => {
@@ -158,6 +509,7 @@ impl SessionCore {
                 match (left, right) {
                     (Some(l), Some(r)) => Some(LayoutNode::Split {
                         axis,
+                        ratio,
                         first: Box::new(l),
                         second: Box::new(r),
                     }),
@@ -170,47 +522,6 @@ impl SessionCore {
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct Shortcut {
-    pub key: &'static str,
-    pub action: &'static str,
-}
-
-pub const SHORTCUTS: &[Shortcut] = &[
-    Shortcut {
-        key: "Ctrl+B, S",
-        action: "Split horizontally",
-    },
-    Shortcut {
-        key: "Ctrl+B, V",
-        action: "Split vertically",
-    },
-    Shortcut {
-        key: "Ctrl+B, X",
-        action: "Close focused pane",
-    },
-    Shortcut {
-        key: "Ctrl+B, Z",
-        action: "Toggle zoom",
-    },
-    Shortcut {
-        key: "Ctrl+B, ←/↑",
-        action: "Focus previous pane",
-    },
-    Shortcut {
-        key: "Ctrl+B, →/↓",
-        action: "Focus next pane",
-    },
-    Shortcut {
-        key: "Ctrl+Enter",
-        action: "Send Enter to focused terminal",
-    },
-    Shortcut {
-        key: "Cmd+O",
-        action: "Select workspace folder",
-    },
-];
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -225,6 +536,85 @@ mod tests {
         assert_eq!(core.panes.len(), 2);
     }
 
+    #[test]
+    fn layouts_share_space_evenly() {
+        let mut core = SessionCore::new("test");
+        core.split_focused(SplitAxis::Vertical);
+        core.split_focused(SplitAxis::Vertical);
+        core.select_layout(Layout::EvenHorizontal);
+        let LayoutNode::Split {
+            axis,
+            ratio,
+            second,
+            ..
+        } = &core.layout
+        else {
+            panic!("expected a split");
+        };
+        assert_eq!(*axis, SplitAxis::Vertical);
+        assert!((ratio - 1.0 / 3.0).abs() < 1e-6);
+        assert!(matches!(**second, LayoutNode::Split { ratio, .. } if ratio == 0.5));
+
+        core.split_focused(SplitAxis::Vertical);
+        core.select_layout(Layout::Tiled);
+        let LayoutNode::Split { axis, first, .. } = &core.layout else {
+            panic!("expected a split");
+        };
+        assert_eq!(*axis, SplitAxis::Horizontal);
+        assert!(matches!(
+            **first,
+            LayoutNode::Split {
+                axis: SplitAxis::Vertical,
+                ..
+            }
+        ));
+        assert_eq!(Layout::parse("main-vertical"), Some(Layout::MainVertical));
+    }
+
+    #[test]
+    fn dragged_panes_move_swap_and_break_out() {
+        let mut core = SessionCore::new("test");
+        core.split_focused(SplitAxis::Vertical);
+        core.split_focused(SplitAxis::Vertical);
+        assert_eq!(core.layout.leaves(), [0, 1, 2]);
+
+        assert!(core.move_pane(2, 0, DropZone::Left));
+        assert_eq!(core.layout.leaves(), [2, 0, 1]);
+        assert!(core.move_pane(1, 2, DropZone::Top));
+        let LayoutNode::Split { first, .. } = &core.layout else {
+            panic!("expected a split");
+        };
+        assert!(matches!(
+            **first,
+            LayoutNode::Split {
+                axis: SplitAxis::Horizontal,
+                ..
+            }
+        ));
+        assert_eq!(core.layout.leaves(), [1, 2, 0]);
+        assert!(core.move_pane(1, 0, DropZone::Centre));
+        assert_eq!(core.layout.leaves(), [0, 2, 1]);
+
+        assert!(core.break_pane(2));
+        assert_eq!(core.layout.leaves(), [0, 1]);
+        assert_eq!(core.windows.len(), 1);
+        // Dropping it back onto a main-window pane empties and removes its window.
+        assert!(core.move_pane(2, 1, DropZone::Right));
+        assert_eq!(core.layout.leaves(), [0, 1, 2]);
+        assert!(core.windows.is_empty());
+
+        assert!(core.break_pane(0));
+        core.close_pane(1);
+        core.close_pane(2);
+        assert_eq!(core.layout.leaves(), [0]);
+        assert!(core.windows.is_empty());
+        assert!(!core.break_pane(0));
+
+        assert_eq!(DropZone::at(0.1, 0.5), DropZone::Left);
+        assert_eq!(DropZone::at(0.5, 0.9), DropZone::Bottom);
+        assert_eq!(DropZone::at(0.5, 0.5), DropZone::Centre);
+    }
+
     #[test]
     fn focus_cycle_works() {
         let mut core = SessionCore::new("test");
@@ -0,0 +1,164 @@
+//! Colour emoji from a bitmap emoji font.
+//!
+//! egui only renders glyph outlines, so emoji clusters are drawn as images taken from the
+//! font's PNG strikes (`sbix` in Apple Color Emoji, `CBDT` in Noto Color Emoji). ZWJ
+//! sequences, flags and keycaps are mapped through the font's `GSUB` ligatures; a cluster
+//! the font cannot turn into one glyph falls back to ordinary text.
+
+use std::collections::HashMap;
+
+use anyhow::{Context, Result};
+use eframe::egui;
+use ttf_parser::gsub::SubstitutionSubtable;
+use ttf_parser::{Face, GlyphId, RasterImageFormat};
+
+use crate::fonts::FontFace;
+use crate::graphics;
+
+/// Rasterised clusters kept before the cache starts over.
+const MAX_CACHED: usize = 2048;
+
+/// A loaded colour emoji face.
+pub struct EmojiFont {
+    data: Vec<u8>,
+    index: u32,
+    pub family: String,
+}
+
+impl EmojiFont {
+    pub fn load(face: &FontFace) -> Result<Self> {
+        let data = std::fs::read(&face.path)
+            .with_context(|| format!("failed to read font: {}", face.path.display()))?;
+        Face::parse(&data, face.index)
+            .with_context(|| format!("failed to parse font: {}", face.path.display()))?;
+        Ok(Self {
+            data,
+            index: face.index,
+            family: face.family.clone(),
+        })
+    }
+
+    fn face(&self) -> Option<Face<'_>> {
+        Face::parse(&self.data, self.index).ok()
+    }
+
+    /// The single glyph the font draws for `cluster`, if it has one.
+    pub fn glyph(&self, cluster: &str) -> Option<GlyphId> {
+        glyph(&self.face()?, cluster)
+    }
+
+    /// The cluster's bitmap from the strike closest to `pixels_per_em`.
+    pub fn rasterize(&self, cluster: &str, pixels_per_em: u16) -> Option<egui::ColorImage> {
+        let face = self.face()?;
+        let glyph = glyph(&face, cluster)?;
+        let raster = face.glyph_raster_image(glyph, pixels_per_em)?;
+        if raster.format != RasterImageFormat::PNG {
+            return None;
+        }
+        let image = graphics::decode_png(raster.data).ok()?;
+        Some(egui::ColorImage::from_rgba_unmultiplied(
+            [image.width as usize, image.height as usize],
+            &image.rgba,
+        ))
+    }
+}
+
+/// Maps each codepoint through `cmap`, then applies ligatures until one glyph is left.
+/// Variation selectors are dropped: emoji fonts key their sequences without them.
+fn glyph(face: &Face<'_>, cluster: &str) -> Option<GlyphId> {
+    let mut glyphs = cluster
+        .chars()
+        .filter(|c| !matches!(c, '\u{FE0E}' | '\u{FE0F}'))
+        .map(|c| face.glyph_index(c))
+        .collect::<Option<Vec<_>>>()?;
+    if glyphs.len() > 1 {
+        apply_ligatures(face, &mut glyphs);
+    }
+    match glyphs[..] {
+        [glyph] => Some(glyph),
+        _ => None,
+    }
+}
+
+/// Applies every ligature lookup in order, left to right, as a shaper would for the
+/// `ccmp` and `liga` features emoji fonts put their sequences in.
+fn apply_ligatures(face: &Face<'_>, glyphs: &mut Vec<GlyphId>) {
+    let Some(gsub) = face.tables().gsub else {
+        return;
+    };
+    for lookup in gsub.lookups {
+        for subtable in lookup.subtables.into_iter::<SubstitutionSubtable>() {
+            let SubstitutionSubtable::Ligature(ligatures) = subtable else {
+                continue;
+            };
+            let mut i = 0;
+            while i < glyphs.len() {
+                let found = ligatures
+                    .coverage
+                    .get(glyphs[i])
+                    .and_then(|index| ligatures.ligature_sets.get(index))
+                    .and_then(|set| {
+                        set.into_iter().find(|lig| {
+                            let rest = &glyphs[i + 1..];
+                            usize::from(lig.components.len()) <= rest.len()
+                                && lig.components.into_iter().zip(rest).all(|(a, b)| a == *b)
+                        })
+                    });
+                if let Some(lig) = found {
+                    let end = i + 1 + usize::from(lig.components.len());
+                    glyphs.splice(i..end, [lig.glyph]);
+                }
+                i += 1;
+            }
+        }
+    }
+}
+
+/// Emoji textures shared by all panes, keyed by cluster and strike size.
+#[derive(Default)]
+pub struct EmojiCache {
+    font: Option<EmojiFont>,
+    textures: HashMap<(String, u16), Option<egui::TextureHandle>>,
+}
+
+impl EmojiCache {
+    pub fn new(font: Option<EmojiFont>) -> Self {
+        Self {
+            font,
+            textures: HashMap::new(),
+        }
+    }
+
+    /// Whether emoji are drawn as images at all.
+    pub fn available(&self) -> bool {
+        self.font.is_some()
+    }
+
+    pub fn family(&self) -> Option<&str> {
+        self.font.as_ref().map(|f| f.family.as_str())
+    }
+
+    /// The texture for `cluster`, or `None` if the font cannot draw it as one image.
+    pub fn texture(
+        &mut self,
+        ctx: &egui::Context,
+        cluster: &str,
+        pixels_per_em: u16,
+    ) -> Option<&egui::TextureHandle> {
+        let font = self.font.as_ref()?;
+        if self.textures.len() >= MAX_CACHED {
+            self.textures.clear();
+        }
+        self.textures
+            .entry((cluster.to_string(), pixels_per_em))
+            .or_insert_with(|| {
+                let image = font.rasterize(cluster, pixels_per_em)?;
+                Some(ctx.load_texture(
+                    format!("emoji-{cluster}-{pixels_per_em}"),
+                    image,
+                    egui::TextureOptions::LINEAR,
+                ))
+            })
+            .as_ref()
+    }
+}
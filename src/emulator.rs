@@ -0,0 +1,1007 @@
+use std::sync::Arc;
+
+use vt100::{MouseProtocolEncoding, MouseProtocolMode};
+
+use crate::base64;
+use crate::graphics::{self, CursorAfter, Graphics, Placement};
+use crate::input::{KITTY_SUPPORTED, KeyModes};
+use crate::reflow;
+use crate::theme::{ColorOverrides, Rgb, Theme};
+use crate::width::{self, AMBIGUOUS_PAD, AmbiguousWidth};
+
+/// Upper bound for OSC/DCS/APC payloads kept by the side-channel scanner.
+const MAX_STRING_LEN: usize = 4 * 1024 * 1024;
+/// OSC 8 link segments kept per pane; the oldest are dropped first.
+const MAX_HYPERLINKS: usize = 1024;
+/// Depth of each kitty keyboard flag stack, as recommended by the protocol.
+const MAX_KITTY_STACK: usize = 16;
+
+/// Escape sequences recognised alongside `vt100`, which ignores everything here.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Sequence {
+    Csi(Csi),
+    Osc { data: Vec<u8>, bel_terminated: bool },
+    Dcs(Vec<u8>),
+    Apc(Vec<u8>),
+    Esc(u8),
+    Bell,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Csi {
+    pub params: String,
+    pub intermediates: String,
+    pub action: char,
+}
+
+impl Csi {
+    /// Private marker (`?`, `>`, `<`, `=`) leading the parameter bytes.
+    pub fn private(&self) -> Option<char> {
+        self.params
+            .chars()
+            .next()
+            .filter(|c| matches!(c, '?' | '>' | '<' | '='))
+    }
+
+    /// Numeric parameters with the private marker stripped; empty fields read as 0.
+    pub fn numbers(&self) -> Vec<u32> {
+        let body = match self.private() {
+            Some(_) => &self.params[1..],
+            None => self.params.as_str(),
+        };
+        if body.is_empty() {
+            return Vec::new();
+        }
+        body.split(';')
+            .map(|p| {
+                p.split(':')
+                    .next()
+                    .and_then(|n| n.parse().ok())
+                    .unwrap_or(0)
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ScanState {
+    Ground,
+    Escape,
+    Csi,
+    Osc,
+    Dcs,
+    Apc,
+    StringEscape,
+}
+
+/// Minimal escape-sequence recogniser that runs in lockstep with `vt100::Parser`.
+#[derive(Debug)]
+struct Scanner {
+    state: ScanState,
+    string_kind: ScanState,
+    csi: Csi,
+    buf: Vec<u8>,
+}
+
+impl Scanner {
+    fn new() -> Self {
+        Self {
+            state: ScanState::Ground,
+            string_kind: ScanState::Osc,
+            csi: Csi::default(),
+            buf: Vec::new(),
+        }
+    }
+
+    fn in_ground(&self) -> bool {
+        self.state == ScanState::Ground
+    }
+
+    fn advance(&mut self, byte: u8) -> Option<Sequence> {
+        match self.state {
+            ScanState::Ground => match byte {
+                0x1b => {
+                    self.state = ScanState::Escape;
+                    None
+                }
+                0x07 => Some(Sequence::Bell),
+                _ => None,
+            },
+            ScanState::Escape => {
+                self.state = ScanState::Ground;
+                match byte {
+                    b'[' => {
+                        self.csi = Csi::default();
+                        self.state = ScanState::Csi;
+                        None
+                    }
+                    b']' | b'P' | b'_' => {
+                        self.buf.clear();
+                        self.state = match byte {
+                            b']' => ScanState::Osc,
+                            b'P' => ScanState::Dcs,
+                            _ => ScanState::Apc,
+                        };
+                        None
+                    }
+                    0x1b => {
+                        self.state = ScanState::Escape;
+                        None
+                    }
+                    0x20..=0x2f => {
+                        // Intermediate bytes (charset designations etc.) are not interesting.
+                        self.state = ScanState::Escape;
+                        None
+                    }
+                    _ => Some(Sequence::Esc(byte)),
+                }
+            }
+            ScanState::Csi => match byte {
+                0x1b => {
+                    self.state = ScanState::Escape;
+                    None
+                }
+                0x30..=0x3f => {
+                    self.csi.params.push(char::from(byte));
+                    None
+                }
+                0x20..=0x2f => {
+                    self.csi.intermediates.push(char::from(byte));
+                    None
+                }
+                0x40..=0x7e => {
+                    self.state = ScanState::Ground;
+                    self.csi.action = char::from(byte);
+                    Some(Sequence::Csi(std::mem::take(&mut self.csi)))
+                }
+                0x18 | 0x1a => {
+                    self.state = ScanState::Ground;
+                    None
+                }
+                _ => None,
+            },
+            ScanState::Osc | ScanState::Dcs | ScanState::Apc => match byte {
+                0x07 if self.state == ScanState::Osc => {
+                    self.state = ScanState::Ground;
+                    Some(Sequence::Osc {
+                        data: std::mem::take(&mut self.buf),
+                        bel_terminated: true,
+                    })
+                }
+                0x1b => {
+                    self.string_kind = self.state;
+                    self.state = ScanState::StringEscape;
+                    None
+                }
+                0x18 | 0x1a => {
+                    self.state = ScanState::Ground;
+                    self.buf.clear();
+                    None
+                }
+                _ => {
+                    if self.buf.len() < MAX_STRING_LEN {
+                        self.buf.push(byte);
+                    }
+                    None
+                }
+            },
+            ScanState::StringEscape => {
+                if byte != b'\\' {
+                    // Unterminated string: drop it and reinterpret the byte after ESC.
+                    self.buf.clear();
+                    self.state = ScanState::Escape;
+                    return self.advance(byte);
+                }
+                self.state = ScanState::Ground;
+                let data = std::mem::take(&mut self.buf);
+                Some(match self.string_kind {
+                    ScanState::Dcs => Sequence::Dcs(data),
+                    ScanState::Apc => Sequence::Apc(data),
+                    _ => Sequence::Osc {
+                        data,
+                        bel_terminated: false,
+                    },
+                })
+            }
+        }
+    }
+}
+
+/// Decodes printable UTF-8 and appends [`AMBIGUOUS_PAD`] after ambiguous-width
+/// characters, so `vt100` gives them two cells. A character split across reads is held
+/// until it completes; malformed input passes through untouched.
+#[derive(Debug, Default)]
+struct Widener {
+    pending: [u8; 4],
+    len: usize,
+    need: usize,
+}
+
+impl Widener {
+    fn push(&mut self, byte: u8, out: &mut Vec<u8>) {
+        match byte {
+            0x80..=0xbf if self.len > 0 => {
+                self.pending[self.len] = byte;
+                self.len += 1;
+                if self.len == self.need {
+                    let bytes = &self.pending[..self.len];
+                    out.extend_from_slice(bytes);
+                    if let Some(c) = std::str::from_utf8(bytes)
+                        .ok()
+                        .and_then(|s| s.chars().next())
+                        && width::is_ambiguous(c)
+                    {
+                        let mut pad = [0; 4];
+                        out.extend_from_slice(AMBIGUOUS_PAD.encode_utf8(&mut pad).as_bytes());
+                    }
+                    self.len = 0;
+                }
+            }
+            0xc2..=0xf4 => {
+                self.flush(out);
+                self.pending[0] = byte;
+                self.len = 1;
+                self.need = match byte {
+                    0xc2..=0xdf => 2,
+                    0xe0..=0xef => 3,
+                    _ => 4,
+                };
+            }
+            _ => {
+                self.flush(out);
+                out.push(byte);
+            }
+        }
+    }
+
+    /// Passes on an incomplete character as it is.
+    fn flush(&mut self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.pending[..self.len]);
+        self.len = 0;
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CursorShape {
+    #[default]
+    Block,
+    Underline,
+    Bar,
+}
+
+/// Cursor appearance requested through DECSCUSR (`CSI Ps SP q`) and `?12`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CursorStyle {
+    pub shape: CursorShape,
+    pub blink: bool,
+}
+
+impl Default for CursorStyle {
+    fn default() -> Self {
+        Self {
+            shape: CursorShape::Block,
+            blink: true,
+        }
+    }
+}
+
+/// An OSC 52 request, applied by the GUI under its clipboard access policy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClipboardRequest {
+    /// Set the selection to `text`.
+    Set { selection: String, text: String },
+    /// Report the selection; answered with [`Emulator::answer_clipboard`].
+    Query(ClipboardQuery),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClipboardQuery {
+    /// Selection targets as sent (`c`, `p`, `s0`, ...), echoed in the reply.
+    pub selection: String,
+    bel_terminated: bool,
+}
+
+/// One row of an OSC 8 hyperlink. `text` is what the cells held when the link closed and
+/// is used to follow the segment as the screen scrolls.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hyperlink {
+    pub uri: String,
+    pub row: u16,
+    pub start_col: u16,
+    pub end_col: u16,
+    text: String,
+}
+
+/// A `vt100` parser extended with the sequences it does not understand.
+///
+/// Bytes are fed to both the scanner and the parser; whenever the scanner completes a
+/// sequence the parser is first brought up to that byte so handlers observe the screen
+/// exactly as it was when the sequence arrived.
+pub struct Emulator {
+    parser: vt100::Parser,
+    scrollback_len: usize,
+    scanner: Scanner,
+    theme: Theme,
+    colors: ColorOverrides,
+    responses: Vec<u8>,
+    open_link: Option<(String, (u16, u16))>,
+    hyperlinks: Vec<Hyperlink>,
+    cwd: Option<String>,
+    /// Kitty keyboard flag stacks for the main and alternate screens.
+    kitty_stacks: [Vec<u32>; 2],
+    cursor_style: CursorStyle,
+    focus_reporting: bool,
+    graphics: Graphics,
+    clipboard_requests: Vec<ClipboardRequest>,
+    ambiguous_width: AmbiguousWidth,
+    widener: Widener,
+    /// BELs received since the GUI last looked.
+    bells: usize,
+    /// Bumped whenever anything drawn from the screen may have changed.
+    generation: u64,
+}
+
+impl Emulator {
+    pub fn new(rows: u16, cols: u16, scrollback_len: usize) -> Self {
+        Self {
+            parser: vt100::Parser::new(rows, cols, scrollback_len),
+            scrollback_len,
+            scanner: Scanner::new(),
+            theme: Theme::default(),
+            colors: ColorOverrides::default(),
+            responses: Vec::new(),
+            open_link: None,
+            hyperlinks: Vec::new(),
+            cwd: None,
+            kitty_stacks: [Vec::new(), Vec::new()],
+            cursor_style: CursorStyle::default(),
+            focus_reporting: false,
+            graphics: Graphics::default(),
+            clipboard_requests: Vec::new(),
+            ambiguous_width: AmbiguousWidth::default(),
+            widener: Widener::default(),
+            bells: 0,
+            generation: 0,
+        }
+    }
+
+    pub fn process(&mut self, bytes: &[u8]) {
+        self.generation += 1;
+        if self.ambiguous_width == AmbiguousWidth::Wide {
+            self.process_widened(bytes);
+        } else {
+            let mut flushed = 0;
+            for (i, byte) in bytes.iter().enumerate() {
+                let Some(seq) = self.scanner.advance(*byte) else {
+                    continue;
+                };
+                self.parser.process(&bytes[flushed..=i]);
+                flushed = i + 1;
+                self.handle(seq);
+            }
+            if flushed < bytes.len() {
+                self.parser.process(&bytes[flushed..]);
+            }
+        }
+        if !self.hyperlinks.is_empty() {
+            self.reanchor_hyperlinks();
+        }
+    }
+
+    /// [`Self::process`] with text outside escape sequences padded by the widener.
+    fn process_widened(&mut self, bytes: &[u8]) {
+        let mut out = Vec::with_capacity(bytes.len() + bytes.len() / 4);
+        for &byte in bytes {
+            if self.scanner.in_ground() {
+                self.widener.push(byte, &mut out);
+            } else {
+                out.push(byte);
+            }
+            if let Some(seq) = self.scanner.advance(byte) {
+                self.parser.process(&out);
+                out.clear();
+                self.handle(seq);
+            }
+        }
+        self.parser.process(&out);
+    }
+
+    pub fn ambiguous_width(&self) -> AmbiguousWidth {
+        self.ambiguous_width
+    }
+
+    /// Applies to output from now on; cells already written keep their width.
+    pub fn set_ambiguous_width(&mut self, policy: AmbiguousWidth) {
+        if policy == AmbiguousWidth::Narrow {
+            let mut held = Vec::new();
+            self.widener.flush(&mut held);
+            self.parser.process(&held);
+        }
+        self.ambiguous_width = policy;
+    }
+
+    pub fn screen(&self) -> &vt100::Screen {
+        self.parser.screen()
+    }
+
+    /// Resizes the screen, rewrapping soft-wrapped lines of the main screen and its
+    /// scrollback. The alternate screen is only resized; its owner redraws it.
+    pub fn set_size(&mut self, rows: u16, cols: u16) {
+        if self.screen().size() == (rows, cols) {
+            return;
+        }
+        self.generation += 1;
+        if self.screen().alternate_screen() {
+            self.parser.set_size(rows, cols);
+        } else {
+            reflow::resize(&mut self.parser, rows, cols, self.scrollback_len);
+        }
+        self.reanchor_hyperlinks();
+    }
+
+    /// Everything the pane holds as plain text: the scrollback and screen of the main
+    /// screen, or just the alternate screen while a full-screen program has it.
+    ///
+    /// Wide-character pads and image placeholders are left out, as when copying.
+    ///
+    /// Needs `&mut` only because `vt100` scrolls back through the parser itself; the
+    /// parser is put back as it was, so nothing changes for the application.
+    pub fn text(&mut self) -> String {
+        let text = if self.screen().alternate_screen() {
+            self.screen().contents()
+        } else {
+            reflow::text(&mut self.parser)
+        };
+        width::strip_pads(&graphics::strip_placeholders(&text))
+    }
+
+    /// Bytes that bring a fresh emulator of the same size to this one's state: screen,
+    /// scrollback, title, modes, cursor style, keyboard flags and working directory.
+    ///
+    /// While the alternate screen is active only it is carried over; the main screen
+    /// underneath comes back blank. Images, hyperlinks and colour overrides are not kept.
+    pub fn snapshot(&mut self) -> Vec<u8> {
+        let push_kitty = |out: &mut Vec<u8>, stack: &[u32]| {
+            for flags in stack {
+                out.extend_from_slice(format!("\x1b[>{flags}u").as_bytes());
+            }
+        };
+        let mut out = Vec::new();
+        if self.screen().alternate_screen() {
+            push_kitty(&mut out, &self.kitty_stacks[0]);
+            out.extend_from_slice(b"\x1b[?1049h");
+            out.extend(self.screen().state_formatted());
+            push_kitty(&mut out, &self.kitty_stacks[1]);
+        } else {
+            self.generation += 1;
+            out = reflow::snapshot(&mut self.parser, self.scrollback_len);
+            self.reanchor_hyperlinks();
+            push_kitty(&mut out, &self.kitty_stacks[0]);
+        }
+        let shape = match self.cursor_style.shape {
+            CursorShape::Block => 1,
+            CursorShape::Underline => 3,
+            CursorShape::Bar => 5,
+        };
+        let shape = shape + u8::from(!self.cursor_style.blink);
+        out.extend_from_slice(format!("\x1b[{shape} q").as_bytes());
+        if self.focus_reporting {
+            out.extend_from_slice(b"\x1b[?1004h");
+        }
+        if let Some(cwd) = &self.cwd {
+            let cwd = cwd.replace('%', "%25").replace(';', "%3B");
+            out.extend_from_slice(format!("\x1b]7;file://{cwd}\x1b\\").as_bytes());
+        }
+        out
+    }
+
+    /// [`Self::process`] for output another emulator has already answered: query
+    /// replies it generates are dropped, while ones queued by the GUI are kept.
+    pub fn process_mirrored(&mut self, bytes: &[u8]) {
+        let queued = std::mem::take(&mut self.responses);
+        self.process(bytes);
+        self.responses = queued;
+    }
+
+    /// The OSC 8 hyperlink covering a cell, if any.
+    pub fn hyperlink_at(&self, row: u16, col: u16) -> Option<&Hyperlink> {
+        self.hyperlinks
+            .iter()
+            .rev()
+            .find(|l| l.row == row && (l.start_col..l.end_col).contains(&col))
+    }
+
+    /// Modes the key encoder must honour for this pane.
+    pub fn key_modes(&self) -> KeyModes {
+        let screen = self.screen();
+        KeyModes {
+            application_cursor: screen.application_cursor(),
+            application_keypad: screen.application_keypad(),
+            bracketed_paste: screen.bracketed_paste(),
+            kitty_flags: self.kitty_flags(),
+        }
+    }
+
+    fn kitty_flags(&self) -> u32 {
+        self.kitty_stacks[usize::from(self.screen().alternate_screen())]
+            .last()
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Working directory last reported by the shell through OSC 7.
+    pub fn cwd(&self) -> Option<&str> {
+        self.cwd.as_deref()
+    }
+
+    /// Cell size in device pixels, used to size images and answer `CSI 14t`/`16t`.
+    pub fn set_cell_pixels(&mut self, width: u16, height: u16) {
+        self.graphics.cell_px = (width.max(1), height.max(1));
+    }
+
+    /// The image placement a placeholder cell refers to.
+    pub fn placement(&self, id: u32) -> Option<&Placement> {
+        self.graphics.placement(id)
+    }
+
+    pub fn placements(&self) -> impl Iterator<Item = &Placement> {
+        self.graphics.placements()
+    }
+
+    pub fn cursor_style(&self) -> CursorStyle {
+        self.cursor_style
+    }
+
+    /// Queues a focus in/out report (`CSI I` / `CSI O`) if the application enabled `?1004`.
+    pub fn report_focus(&mut self, focused: bool) {
+        if self.focus_reporting {
+            self.responses
+                .extend_from_slice(if focused { b"\x1b[I" } else { b"\x1b[O" });
+        }
+    }
+
+    /// Bytes the terminal owes the application (query replies), drained by the caller.
+    pub fn take_responses(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.responses)
+    }
+
+    /// Number of BELs rung since the last call. BELs ending an OSC string do not count.
+    pub fn take_bells(&mut self) -> usize {
+        std::mem::take(&mut self.bells)
+    }
+
+    /// OSC 52 requests received since the last call, oldest first.
+    pub fn take_clipboard_requests(&mut self) -> Vec<ClipboardRequest> {
+        std::mem::take(&mut self.clipboard_requests)
+    }
+
+    /// Queues the reply to an OSC 52 query; an empty `text` reports an empty selection.
+    pub fn answer_clipboard(&mut self, query: &ClipboardQuery, text: &str) {
+        self.responses.extend_from_slice(b"\x1b]52;");
+        self.responses.extend_from_slice(query.selection.as_bytes());
+        self.responses.push(b';');
+        self.responses
+            .extend_from_slice(base64::encode(text.as_bytes()).as_bytes());
+        self.responses.extend_from_slice(if query.bel_terminated {
+            b"\x07"
+        } else {
+            b"\x1b\\"
+        });
+    }
+
+    pub fn set_theme(&mut self, theme: Theme) {
+        self.theme = theme;
+        self.generation += 1;
+    }
+
+    /// Changes whenever the screen contents or colours may have changed, so renderers can
+    /// skip unchanged frames.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// The active theme with any OSC colour overrides applied.
+    pub fn colors(&self) -> Theme {
+        self.theme.with_overrides(&self.colors)
+    }
+
+    fn handle(&mut self, seq: Sequence) {
+        match seq {
+            Sequence::Osc {
+                data,
+                bel_terminated,
+            } => self.handle_osc(&data, bel_terminated),
+            Sequence::Csi(csi) => self.handle_csi(&csi),
+            Sequence::Bell => self.bells += 1,
+            // RIS also resets the state kept outside `vt100`.
+            Sequence::Esc(b'c') => {
+                self.kitty_stacks = [Vec::new(), Vec::new()];
+                self.cursor_style = CursorStyle::default();
+                self.focus_reporting = false;
+                self.graphics.clear();
+            }
+            Sequence::Apc(data) => {
+                if let Some(command) = data.strip_prefix(b"G") {
+                    let outcome = self.graphics.kitty(command);
+                    if let Some(reply) = outcome.reply {
+                        self.responses.extend_from_slice(reply.as_bytes());
+                    }
+                    if let Some((placement, cursor)) = outcome.place {
+                        self.place_image(placement, cursor);
+                    }
+                }
+            }
+            Sequence::Dcs(data) if graphics::is_sixel(&data) => {
+                if let Ok(image) = graphics::decode_sixel(&data) {
+                    let (cols, rows) = self.graphics.natural_cells(image.width, image.height);
+                    let placement = Placement {
+                        image: Arc::new(image),
+                        cols,
+                        rows,
+                    };
+                    self.place_image(placement, CursorAfter::BelowStart);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Covers the cells from the cursor with placeholders for a new placement.
+    fn place_image(&mut self, placement: Placement, cursor: CursorAfter) {
+        let (screen_rows, screen_cols) = self.screen().size();
+        let (_, col) = self.screen().cursor_position();
+        let col = col.min(screen_cols.saturating_sub(1));
+        let (rows, cols) = placement.span();
+        let span = (rows, cols.min(screen_cols - col));
+        let pen = self.screen().attributes_formatted();
+        let id = self.graphics.insert(placement);
+        self.parser
+            .process(&graphics::placeholder_bytes(id, col, span));
+        self.parser.process(&pen);
+
+        let (last_row, _) = self.screen().cursor_position();
+        let (row, col) = match cursor {
+            CursorAfter::RightOfImage => (last_row, (col + span.1).min(screen_cols - 1)),
+            CursorAfter::Unmoved => ((last_row + 1).saturating_sub(span.0), col),
+            CursorAfter::BelowStart => (last_row, col),
+        };
+        let row = row.min(screen_rows - 1);
+        self.parser
+            .process(format!("\x1b[{};{}H", row + 1, col + 1).as_bytes());
+    }
+
+    fn handle_csi(&mut self, csi: &Csi) {
+        let params = csi.numbers();
+        let first = params.first().copied().unwrap_or(0);
+        match (csi.private(), csi.intermediates.as_str(), csi.action) {
+            (_, "", 'u') => self.handle_kitty_keyboard(csi),
+            (None, " ", 'q') => {
+                let (shape, blink) = match first {
+                    0 | 1 => (CursorShape::Block, true),
+                    2 => (CursorShape::Block, false),
+                    3 => (CursorShape::Underline, true),
+                    4 => (CursorShape::Underline, false),
+                    5 => (CursorShape::Bar, true),
+                    6 => (CursorShape::Bar, false),
+                    _ => return,
+                };
+                self.cursor_style = CursorStyle { shape, blink };
+            }
+            // Private modes `vt100` does not track.
+            (Some('?'), "", action @ ('h' | 'l')) => {
+                let enable = action == 'h';
+                for mode in params {
+                    match mode {
+                        12 => self.cursor_style.blink = enable,
+                        1004 => self.focus_reporting = enable,
+                        _ => {}
+                    }
+                }
+            }
+            // DSR: operating status and cursor position (DECXCPR for the `?` form).
+            (None, "", 'n') if first == 5 => self.responses.extend_from_slice(b"\x1b[0n"),
+            (marker @ (None | Some('?')), "", 'n') if first == 6 => {
+                let (row, col) = self.screen().cursor_position();
+                let marker = marker.map(String::from).unwrap_or_default();
+                let reply = format!("\x1b[{marker}{};{}R", row + 1, col + 1);
+                self.responses.extend_from_slice(reply.as_bytes());
+            }
+            // DA1: a VT220-class terminal with ANSI colour.
+            (None, "", 'c') if first == 0 => self.responses.extend_from_slice(DA1_REPLY),
+            // XTWINOPS size reports: text area and cell in pixels, text area in cells.
+            (None, "", 't') if matches!(first, 14 | 16 | 18) => {
+                let (rows, cols) = self.screen().size();
+                let (cell_w, cell_h) = self.graphics.cell_px;
+                let reply = match first {
+                    14 => format!(
+                        "\x1b[4;{};{}t",
+                        u32::from(rows) * u32::from(cell_h),
+                        u32::from(cols) * u32::from(cell_w)
+                    ),
+                    16 => format!("\x1b[6;{cell_h};{cell_w}t"),
+                    _ => format!("\x1b[8;{rows};{cols}t"),
+                };
+                self.responses.extend_from_slice(reply.as_bytes());
+            }
+            // DA2: terminal type, firmware version and ROM cartridge number.
+            (Some('>'), "", 'c') if first == 0 => {
+                let reply = format!("\x1b[>1;{};0c", firmware_version());
+                self.responses.extend_from_slice(reply.as_bytes());
+            }
+            (Some('>'), "", 'q') if first == 0 => {
+                let reply = format!("\x1bP>|OrchestraTerm({})\x1b\\", env!("CARGO_PKG_VERSION"));
+                self.responses.extend_from_slice(reply.as_bytes());
+            }
+            (marker @ (None | Some('?')), "$", 'p') => {
+                let state = match marker {
+                    Some(_) => self.dec_mode_state(first),
+                    None => ansi_mode_state(first),
+                };
+                let marker = marker.map(String::from).unwrap_or_default();
+                let reply = format!("\x1b[{marker}{first};{state}$y");
+                self.responses.extend_from_slice(reply.as_bytes());
+            }
+            _ => {}
+        }
+    }
+
+    /// DECRQM state for a private mode: 1 set, 2 reset, 0 not recognised.
+    fn dec_mode_state(&self, mode: u32) -> u8 {
+        let screen = self.screen();
+        let set = match mode {
+            1 => screen.application_cursor(),
+            // Autowrap is always on in `vt100`.
+            7 => true,
+            12 => self.cursor_style.blink,
+            1004 => self.focus_reporting,
+            25 => !screen.hide_cursor(),
+            47 | 1047 | 1049 => screen.alternate_screen(),
+            66 => screen.application_keypad(),
+            9 => screen.mouse_protocol_mode() == MouseProtocolMode::Press,
+            1000 => screen.mouse_protocol_mode() == MouseProtocolMode::PressRelease,
+            1002 => screen.mouse_protocol_mode() == MouseProtocolMode::ButtonMotion,
+            1003 => screen.mouse_protocol_mode() == MouseProtocolMode::AnyMotion,
+            1005 => screen.mouse_protocol_encoding() == MouseProtocolEncoding::Utf8,
+            1006 => screen.mouse_protocol_encoding() == MouseProtocolEncoding::Sgr,
+            2004 => screen.bracketed_paste(),
+            _ => return 0,
+        };
+        if set { 1 } else { 2 }
+    }
+
+    /// Kitty progressive enhancement: `CSI > f u` push, `CSI < n u` pop,
+    /// `CSI = f ; mode u` set and `CSI ? u` query.
+    fn handle_kitty_keyboard(&mut self, csi: &Csi) {
+        let params = csi.numbers();
+        let stack = &mut self.kitty_stacks[usize::from(self.parser.screen().alternate_screen())];
+        match csi.private() {
+            Some('>') => {
+                if stack.len() == MAX_KITTY_STACK {
+                    stack.remove(0);
+                }
+                stack.push(params.first().copied().unwrap_or(0) & KITTY_SUPPORTED);
+            }
+            Some('<') => {
+                let n = params.first().copied().unwrap_or(1).max(1) as usize;
+                stack.truncate(stack.len().saturating_sub(n));
+            }
+            Some('=') => {
+                let flags = params.first().copied().unwrap_or(0) & KITTY_SUPPORTED;
+                let current = stack.last().copied().unwrap_or(0);
+                let next = match params.get(1).copied().unwrap_or(1) {
+                    2 => current | flags,
+                    3 => current & !flags,
+                    _ => flags,
+                };
+                match stack.last_mut() {
+                    Some(top) => *top = next,
+                    None => stack.push(next),
+                }
+            }
+            Some('?') => {
+                let flags = self.kitty_flags();
+                self.responses
+                    .extend_from_slice(format!("\x1b[?{flags}u").as_bytes());
+            }
+            _ => {}
+        }
+    }
+
+    fn handle_osc(&mut self, data: &[u8], bel_terminated: bool) {
+        let text = String::from_utf8_lossy(data);
+        let mut parts = text.split(';');
+        let Some(Ok(code)) = parts.next().map(str::parse::<u16>) else {
+            return;
+        };
+        let terminator: &[u8] = if bel_terminated { b"\x07" } else { b"\x1b\\" };
+        let args: Vec<&str> = parts.collect();
+
+        match code {
+            4 => {
+                for pair in args.chunks(2) {
+                    let [idx, spec] = pair else {
+                        break;
+                    };
+                    let Ok(idx) = idx.parse::<u8>() else {
+                        continue;
+                    };
+                    if *spec == "?" {
+                        let color = self.colors().ansi256(idx);
+                        self.reply_color(&format!("4;{idx}"), color, terminator);
+                    } else if let Some(color) = Rgb::parse(spec)
+                        && idx < 16
+                    {
+                        self.colors.palette.insert(idx, color);
+                    }
+                }
+            }
+            10..=12 => {
+                // One OSC may address several consecutive dynamic colours (`OSC 10;fg;bg`).
+                for (offset, spec) in args.iter().enumerate() {
+                    let slot = code as usize + offset;
+                    if slot > 12 {
+                        break;
+                    }
+                    if *spec == "?" {
+                        let colors = self.colors();
+                        let color = match slot {
+                            10 => colors.foreground,
+                            11 => colors.background,
+                            _ => colors.cursor,
+                        };
+                        self.reply_color(&slot.to_string(), color, terminator);
+                    } else if let Some(color) = Rgb::parse(spec) {
+                        match slot {
+                            10 => self.colors.foreground = Some(color),
+                            11 => self.colors.background = Some(color),
+                            _ => self.colors.cursor = Some(color),
+                        }
+                    }
+                }
+            }
+            104 => {
+                if args.iter().all(|a| a.is_empty()) {
+                    self.colors.palette.clear();
+                }
+                for idx in args.iter().filter_map(|a| a.parse::<u8>().ok()) {
+                    self.colors.palette.remove(&idx);
+                }
+            }
+            7 => {
+                let uri = args.join(";");
+                if let Some(rest) = uri.strip_prefix("file://") {
+                    let path = rest.find('/').map(|i| &rest[i..]).unwrap_or("/");
+                    self.cwd = Some(percent_decode(path));
+                }
+            }
+            8 => {
+                self.close_hyperlink();
+                let uri = args.get(1..).map(|a| a.join(";")).unwrap_or_default();
+                if !uri.is_empty() {
+                    self.open_link = Some((uri, self.screen().cursor_position()));
+                }
+            }
+            52 => {
+                let selection = match args.first() {
+                    Some(s) if !s.is_empty() => (*s).to_string(),
+                    _ => "s0".to_string(),
+                };
+                match args.get(1) {
+                    Some(&"?") => {
+                        self.clipboard_requests
+                            .push(ClipboardRequest::Query(ClipboardQuery {
+                                selection,
+                                bel_terminated,
+                            }))
+                    }
+                    Some(data) if !data.is_empty() => {
+                        let text =
+                            String::from_utf8_lossy(&base64::decode(data.as_bytes())).into_owned();
+                        self.clipboard_requests
+                            .push(ClipboardRequest::Set { selection, text });
+                    }
+                    _ => {}
+                }
+            }
+            110 => self.colors.foreground = None,
+            111 => self.colors.background = None,
+            112 => self.colors.cursor = None,
+            _ => {}
+        }
+    }
+
+    fn close_hyperlink(&mut self) {
+        let Some((uri, (start_row, start_col))) = self.open_link.take() else {
+            return;
+        };
+        let (end_row, end_col) = self.screen().cursor_position();
+        let (_, cols) = self.screen().size();
+        for row in start_row..=end_row {
+            let c0 = if row == start_row { start_col } else { 0 };
+            let c1 = if row == end_row { end_col } else { cols };
+            if c1 <= c0 {
+                continue;
+            }
+            let text = self.screen().contents_between(row, c0, row, c1);
+            self.hyperlinks.push(Hyperlink {
+                uri: uri.clone(),
+                row,
+                start_col: c0,
+                end_col: c1,
+                text,
+            });
+        }
+        if self.hyperlinks.len() > MAX_HYPERLINKS {
+            let excess = self.hyperlinks.len() - MAX_HYPERLINKS;
+            self.hyperlinks.drain(..excess);
+        }
+    }
+
+    /// vt100 does not report scrolling, so each segment is re-found by its text: first at
+    /// its recorded row, then on the rows above it. Segments whose text is gone are dropped.
+    fn reanchor_hyperlinks(&mut self) {
+        let screen = self.parser.screen();
+        let (rows, _) = screen.size();
+        self.hyperlinks.retain_mut(|link| {
+            let start = link.row.min(rows.saturating_sub(1));
+            for row in (0..=start).rev() {
+                if screen.contents_between(row, link.start_col, row, link.end_col) == link.text {
+                    link.row = row;
+                    return true;
+                }
+            }
+            false
+        });
+    }
+
+    fn reply_color(&mut self, prefix: &str, color: Rgb, terminator: &[u8]) {
+        self.responses.extend_from_slice(b"\x1b]");
+        self.responses.extend_from_slice(prefix.as_bytes());
+        self.responses.push(b';');
+        self.responses
+            .extend_from_slice(color.to_x11_spec().as_bytes());
+        self.responses.extend_from_slice(terminator);
+    }
+}
+
+/// DA1 reply: VT220 conformance level with sixel graphics and ANSI colour.
+const DA1_REPLY: &[u8] = b"\x1b[?62;4;22c";
+
+/// Crate version packed as `major * 10000 + minor * 100 + patch`, the form xterm uses in DA2.
+fn firmware_version() -> u32 {
+    env!("CARGO_PKG_VERSION")
+        .split('.')
+        .take(3)
+        .map(|part| part.parse::<u32>().unwrap_or(0))
+        .fold(0, |acc, part| acc * 100 + part)
+}
+
+/// DECRQM state for an ANSI mode. Only insert (4) and linefeed (20) modes exist, and
+/// `vt100` keeps both permanently reset.
+fn ansi_mode_state(mode: u32) -> u8 {
+    match mode {
+        4 | 20 => 4,
+        _ => 0,
+    }
+}
+
+fn percent_decode(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%'
+            && i + 2 < bytes.len()
+            && bytes[i + 1].is_ascii_hexdigit()
+            && bytes[i + 2].is_ascii_hexdigit()
+            && let Ok(v) = u8::from_str_radix(&input[i + 1..i + 3], 16)
+        {
+            out.push(v);
+            i += 3;
+            continue;
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).to_string()
+}
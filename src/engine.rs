@@ -540,12 +540,11 @@ impl EngineState {
             if !visible {
                 continue;
             }
-            if unread_only {
-                if let Some(viewer) = viewer_member {
-                    if msg.read_by.contains(&viewer) {
-                        continue;
-                    }
-                }
+            if unread_only
+                && let Some(viewer) = viewer_member
+                && msg.read_by.contains(&viewer)
+            {
+                continue;
             }
             out.push(msg.clone());
         }
@@ -714,19 +713,14 @@ pub enum TeamDisplayMode {
     Auto,
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
 #[serde(rename_all = "snake_case")]
 pub enum RecoveryPolicy {
+    #[default]
     AutoReassign,
     Manual,
 }
 
-impl Default for RecoveryPolicy {
-    fn default() -> Self {
-        Self::AutoReassign
-    }
-}
-
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MemberState {
     pub id: usize,
@@ -756,19 +750,14 @@ pub struct MemberState {
     pub cost_usd: f64,
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
 #[serde(rename_all = "snake_case")]
 pub enum MemberStatus {
+    #[default]
     Active,
     Terminated,
 }
 
-impl Default for MemberStatus {
-    fn default() -> Self {
-        Self::Active
-    }
-}
-
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
 pub enum PlanStatus {
@@ -817,21 +806,16 @@ pub struct TeamMessage {
     pub read_by: Vec<usize>,
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
 #[serde(rename_all = "snake_case")]
 pub enum TeamMessagePriority {
     Low,
+    #[default]
     Normal,
     High,
     Urgent,
 }
 
-impl Default for TeamMessagePriority {
-    fn default() -> Self {
-        Self::Normal
-    }
-}
-
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
 pub enum TaskStatus {
@@ -0,0 +1,446 @@
+//! Font selection: a configured primary family and fallback chain, resolved against the
+//! fonts found in the platform's standard font directories.
+//!
+//! Font files are only read when needed. Files whose names look like the family being
+//! searched for are inspected first; every other file is read only if none of those match.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use eframe::egui;
+
+/// Monospace families tried, in order, when none is configured.
+const DEFAULT_MONOSPACE: &[&str] = &[
+    "Menlo",
+    "SF Mono",
+    "JetBrains Mono",
+    "DejaVu Sans Mono",
+    "Liberation Mono",
+    "Noto Sans Mono",
+    "Ubuntu Mono",
+    "Cascadia Mono",
+    "Consolas",
+];
+
+/// Families tried, in order, for Hangul and Han coverage when the chain has none.
+const DEFAULT_CJK: &[&str] = &[
+    "Apple SD Gothic Neo",
+    "AppleGothic",
+    "Noto Sans Mono CJK KR",
+    "Noto Sans CJK KR",
+    "Source Han Sans K",
+    "NanumGothicCoding",
+    "Nanum Gothic",
+    "Malgun Gothic",
+    "WenQuanYi Zen Hei",
+    "Droid Sans Fallback",
+];
+
+/// Colour emoji families tried, in order, when none is configured. Only faces with PNG
+/// strikes qualify; see [`crate::emoji`].
+const DEFAULT_EMOJI: &[&str] = &[
+    "Apple Color Emoji",
+    "Noto Color Emoji",
+    "Twemoji",
+    "JoyPixels",
+];
+
+/// Sample characters a face must have to count as covering Korean, Chinese and Japanese.
+const CJK_SAMPLES: [char; 3] = ['한', '漢', 'か'];
+
+pub const MIN_SIZE: f32 = 6.0;
+pub const MAX_SIZE: f32 = 72.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FontConfig {
+    /// Primary monospace family; the first installed default when `None`.
+    pub family: Option<String>,
+    /// Terminal font size in points, before the render preset and zoom.
+    pub size: f32,
+    /// Families consulted, in order, for characters the primary face lacks.
+    pub fallbacks: Vec<String>,
+    /// Colour emoji family; the first installed default when `None`.
+    pub emoji: Option<String>,
+}
+
+impl Default for FontConfig {
+    /// `ORCHESTRATERM_FONT`, `ORCHESTRATERM_FONT_SIZE`, the comma-separated
+    /// `ORCHESTRATERM_FONT_FALLBACKS` and `ORCHESTRATERM_EMOJI_FONT`.
+    fn default() -> Self {
+        let env = |name| {
+            std::env::var(name)
+                .ok()
+                .filter(|v: &String| !v.trim().is_empty())
+        };
+        Self {
+            family: env("ORCHESTRATERM_FONT").map(|v| v.trim().to_string()),
+            size: env("ORCHESTRATERM_FONT_SIZE")
+                .and_then(|v| v.trim().parse::<f32>().ok())
+                .map_or(14.0, |v| v.clamp(MIN_SIZE, MAX_SIZE)),
+            fallbacks: env("ORCHESTRATERM_FONT_FALLBACKS")
+                .map(|v| parse_list(&v))
+                .unwrap_or_default(),
+            emoji: env("ORCHESTRATERM_EMOJI_FONT").map(|v| v.trim().to_string()),
+        }
+    }
+}
+
+/// Splits a comma-separated family list, dropping empty entries.
+pub fn parse_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// One face of a font file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FontFace {
+    pub family: String,
+    pub path: PathBuf,
+    /// Face index within a collection (`.ttc`).
+    pub index: u32,
+    pub monospace: bool,
+    pub regular: bool,
+    pub cjk: bool,
+    /// Has PNG bitmap strikes (`sbix` or `CBDT`) with emoji in them.
+    pub color_emoji: bool,
+}
+
+/// The faces chosen for the terminal, primary first.
+#[derive(Debug, Clone, Default)]
+pub struct ResolvedFonts {
+    pub primary: Option<FontFace>,
+    pub fallbacks: Vec<FontFace>,
+    pub emoji: Option<FontFace>,
+    /// Configured families that were not found.
+    pub missing: Vec<String>,
+}
+
+impl ResolvedFonts {
+    /// Human-readable summary for the settings panel.
+    pub fn describe(&self) -> String {
+        let mut names: Vec<&str> = self.primary.iter().map(|f| f.family.as_str()).collect();
+        if names.is_empty() {
+            names.push("built-in");
+        }
+        names.extend(self.fallbacks.iter().map(|f| f.family.as_str()));
+        let mut description = names.join(" → ");
+        if let Some(emoji) = &self.emoji {
+            description.push_str(&format!(", emoji: {}", emoji.family));
+        }
+        description
+    }
+}
+
+/// Standard font directories for the current platform, including per-user ones.
+pub fn font_dirs() -> Vec<PathBuf> {
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+    let mut dirs: Vec<PathBuf> = if cfg!(target_os = "macos") {
+        vec!["/System/Library/Fonts".into(), "/Library/Fonts".into()]
+    } else if cfg!(windows) {
+        let root = std::env::var_os("WINDIR").map_or_else(|| "C:\\Windows".into(), PathBuf::from);
+        vec![root.join("Fonts")]
+    } else {
+        vec!["/usr/share/fonts".into(), "/usr/local/share/fonts".into()]
+    };
+    if let Some(home) = home {
+        if cfg!(target_os = "macos") {
+            dirs.push(home.join("Library/Fonts"));
+        } else {
+            dirs.push(home.join(".local/share/fonts"));
+            dirs.push(home.join(".fonts"));
+        }
+    }
+    if let Some(data) = std::env::var_os("XDG_DATA_HOME") {
+        dirs.push(PathBuf::from(data).join("fonts"));
+    }
+    dirs
+}
+
+/// Font files under `dirs`, recursively, in a stable order.
+pub fn font_files(dirs: &[PathBuf]) -> Vec<PathBuf> {
+    fn walk(dir: &Path, depth: usize, out: &mut Vec<PathBuf>) {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                if depth < 8 {
+                    walk(&path, depth + 1, out);
+                }
+            } else if path.extension().and_then(|e| e.to_str()).is_some_and(|e| {
+                ["ttf", "otf", "ttc", "otc"].contains(&e.to_ascii_lowercase().as_str())
+            }) {
+                out.push(path);
+            }
+        }
+    }
+    let mut files = Vec::new();
+    for dir in dirs {
+        walk(dir, 0, &mut files);
+    }
+    files.sort();
+    files.dedup();
+    files
+}
+
+/// Lowercase alphanumerics only, so "DejaVu Sans Mono" matches `DejaVuSansMono.ttf`.
+fn family_key(name: &str) -> String {
+    name.chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+/// Whether a file name suggests it holds `family`: the file's stem up to the style
+/// suffix is a prefix of the family, or the other way round.
+fn file_may_hold(path: &Path, family: &str) -> bool {
+    let Some(stem) = path.file_stem().and_then(|s| s.to_str()) else {
+        return false;
+    };
+    let base = family_key(stem.split(['-', '_']).next().unwrap_or(stem));
+    !base.is_empty() && (family.starts_with(&base) || base.starts_with(family))
+}
+
+/// Faces in a font file, read with `ttf-parser`.
+pub fn inspect(path: &Path) -> Result<Vec<FontFace>> {
+    let data =
+        std::fs::read(path).with_context(|| format!("failed to read font: {}", path.display()))?;
+    let count = ttf_parser::fonts_in_collection(&data).unwrap_or(1);
+    let mut faces = Vec::new();
+    for index in 0..count {
+        let Ok(face) = ttf_parser::Face::parse(&data, index) else {
+            continue;
+        };
+        let family = [
+            ttf_parser::name_id::TYPOGRAPHIC_FAMILY,
+            ttf_parser::name_id::FAMILY,
+        ]
+        .into_iter()
+        .find_map(|id| {
+            face.names()
+                .into_iter()
+                .filter(|n| n.name_id == id && n.is_unicode())
+                .find_map(|n| n.to_string())
+        });
+        let Some(family) = family else {
+            continue;
+        };
+        faces.push(FontFace {
+            family,
+            path: path.to_path_buf(),
+            index,
+            monospace: face.is_monospaced(),
+            regular: !face.is_bold() && !face.is_italic(),
+            cjk: CJK_SAMPLES.iter().all(|c| face.glyph_index(*c).is_some()),
+            color_emoji: (face.tables().sbix.is_some() || face.tables().cbdt.is_some())
+                && face.glyph_index('😀').is_some(),
+        });
+    }
+    Ok(faces)
+}
+
+/// Font files with their faces inspected on demand.
+pub struct Catalog {
+    files: Vec<PathBuf>,
+    inspected: HashMap<PathBuf, Vec<FontFace>>,
+}
+
+impl Catalog {
+    pub fn new(files: Vec<PathBuf>) -> Self {
+        Self {
+            files,
+            inspected: HashMap::new(),
+        }
+    }
+
+    pub fn discover() -> Self {
+        Self::new(font_files(&font_dirs()))
+    }
+
+    fn faces(&mut self, path: &Path) -> &[FontFace] {
+        self.inspected
+            .entry(path.to_path_buf())
+            .or_insert_with(|| inspect(path).unwrap_or_default())
+    }
+
+    /// The face of `family` (case and spacing ignored), preferring the regular style.
+    /// Files whose names do not suggest the family are read only if `thorough`.
+    pub fn family(&mut self, family: &str, thorough: bool) -> Option<FontFace> {
+        let key = family_key(family);
+        let (hinted, rest): (Vec<_>, Vec<_>) = self
+            .files
+            .clone()
+            .into_iter()
+            .partition(|p| file_may_hold(p, &key));
+        let passes = if thorough {
+            vec![hinted, rest]
+        } else {
+            vec![hinted]
+        };
+        for files in passes {
+            let mut found: Option<FontFace> = None;
+            for path in files {
+                for face in self.faces(&path) {
+                    if family_key(&face.family) == key
+                        && found.as_ref().is_none_or(|f| !f.regular && face.regular)
+                    {
+                        found = Some(face.clone());
+                    }
+                }
+                if found.as_ref().is_some_and(|f| f.regular) {
+                    break;
+                }
+            }
+            if found.is_some() {
+                return found;
+            }
+        }
+        None
+    }
+
+    /// The first face, in file order, matching `predicate`.
+    pub fn find(&mut self, predicate: impl Fn(&FontFace) -> bool) -> Option<FontFace> {
+        for path in self.files.clone() {
+            if let Some(face) = self.faces(&path).iter().find(|f| predicate(f)) {
+                return Some(face.clone());
+            }
+        }
+        None
+    }
+
+    /// Chooses the primary face, the fallback chain and the emoji face for `config`. Without a configured
+    /// CJK-capable fallback, one is added so Hangul and Han always render.
+    pub fn resolve(&mut self, config: &FontConfig) -> ResolvedFonts {
+        let mut resolved = ResolvedFonts::default();
+        if let Some(family) = &config.family {
+            resolved.primary = self.family(family, true);
+            if resolved.primary.is_none() {
+                resolved.missing.push(family.clone());
+            }
+        }
+        if resolved.primary.is_none() {
+            resolved.primary = DEFAULT_MONOSPACE
+                .iter()
+                .find_map(|family| self.family(family, false))
+                .or_else(|| self.find(|f| f.monospace && f.regular));
+        }
+        for family in &config.fallbacks {
+            match self.family(family, true) {
+                Some(face) => resolved.fallbacks.push(face),
+                None => resolved.missing.push(family.clone()),
+            }
+        }
+        let covered = resolved
+            .primary
+            .iter()
+            .chain(&resolved.fallbacks)
+            .any(|f| f.cjk);
+        if !covered
+            && let Some(face) = DEFAULT_CJK
+                .iter()
+                .find_map(|family| self.family(family, false).filter(|f| f.cjk))
+                .or_else(|| self.find(|f| f.cjk && f.regular))
+        {
+            resolved.fallbacks.push(face);
+        }
+        if let Some(family) = &config.emoji {
+            resolved.emoji = self.family(family, true).filter(|f| f.color_emoji);
+            if resolved.emoji.is_none() {
+                resolved.missing.push(family.clone());
+            }
+        }
+        if resolved.emoji.is_none() {
+            resolved.emoji = DEFAULT_EMOJI
+                .iter()
+                .find_map(|family| self.family(family, false).filter(|f| f.color_emoji))
+                .or_else(|| self.find(|f| f.color_emoji));
+        }
+        resolved
+    }
+}
+
+/// Installs `resolved` into egui: the primary face leads the monospace family and the
+/// fallbacks follow egui's defaults in both families, so UI text gets them too.
+pub fn install(ctx: &egui::Context, resolved: &ResolvedFonts) {
+    let mut fonts = egui::FontDefinitions::default();
+    let mut load = |name: String, face: &FontFace| -> bool {
+        let Ok(bytes) = std::fs::read(&face.path) else {
+            return false;
+        };
+        let mut data = egui::FontData::from_owned(bytes);
+        data.index = face.index;
+        fonts.font_data.insert(name, data.into());
+        true
+    };
+    let primary = resolved
+        .primary
+        .as_ref()
+        .filter(|face| load("primary".to_owned(), face));
+    let fallbacks: Vec<String> = resolved
+        .fallbacks
+        .iter()
+        .enumerate()
+        .map(|(i, face)| (format!("fallback-{i}"), face))
+        .filter(|(name, face)| load(name.clone(), face))
+        .map(|(name, _)| name)
+        .collect();
+
+    if primary.is_some()
+        && let Some(mono) = fonts.families.get_mut(&egui::FontFamily::Monospace)
+    {
+        mono.insert(0, "primary".to_owned());
+    }
+    for family in [egui::FontFamily::Monospace, egui::FontFamily::Proportional] {
+        fonts
+            .families
+            .entry(family)
+            .or_default()
+            .extend(fallbacks.iter().cloned());
+    }
+    ctx.set_fonts(fonts);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn family_names_match_file_names() {
+        let key = family_key("DejaVu Sans Mono");
+        assert_eq!(key, "dejavusansmono");
+        assert!(file_may_hold(Path::new("/f/DejaVuSansMono-Bold.ttf"), &key));
+        assert!(file_may_hold(
+            Path::new("/f/NotoSansCJK-Regular.ttc"),
+            &family_key("Noto Sans CJK KR")
+        ));
+        assert!(!file_may_hold(Path::new("/f/Menlo.ttc"), &key));
+    }
+
+    #[test]
+    fn fallback_lists_are_trimmed() {
+        assert_eq!(
+            parse_list(" Noto Sans CJK KR ,, Symbola"),
+            ["Noto Sans CJK KR", "Symbola"]
+        );
+    }
+
+    #[test]
+    fn missing_fonts_are_reported() {
+        let mut catalog = Catalog::new(Vec::new());
+        let resolved = catalog.resolve(&FontConfig {
+            family: Some("Nonexistent Mono".into()),
+            size: 14.0,
+            fallbacks: vec!["Nope".into()],
+            emoji: Some("No Emoji".into()),
+        });
+        assert!(resolved.primary.is_none());
+        assert_eq!(resolved.missing, ["Nonexistent Mono", "Nope", "No Emoji"]);
+        assert_eq!(resolved.describe(), "built-in");
+    }
+}
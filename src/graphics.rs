@@ -0,0 +1,796 @@
+//! Inline images from the kitty graphics protocol and sixel.
+//!
+//! A placement is anchored to the text grid by writing placeholder cells over the area
+//! it covers: each holds [`PLACEHOLDER`] plus two zero-width variation selectors giving
+//! its row and column inside the image, and its foreground colour carries the placement
+//! id. The cells then scroll, reflow and get erased exactly like text, and the renderer
+//! draws the matching slice of the image wherever it finds one.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::io::Read as _;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use anyhow::{Context, Result};
+
+use crate::base64;
+
+/// Private-use codepoint marking a cell covered by an image (the one kitty uses).
+pub const PLACEHOLDER: char = '\u{10EEEE}';
+/// Row/column indices are encoded as variation selectors U+E0100..U+E01EF.
+const DIACRITIC_BASE: u32 = 0xE0100;
+/// Largest placement, in cells, that the diacritics can address.
+pub const MAX_SPAN: u16 = 240;
+/// Largest decoded image side in pixels.
+const MAX_IMAGE_SIDE: u32 = 8192;
+/// Decoded pixel data kept per pane before the oldest placements are dropped.
+const MAX_IMAGE_BYTES: usize = 256 * 1024 * 1024;
+const MAX_PLACEMENTS: usize = 4096;
+/// Largest kitty payload accepted, whether sent in chunks or read from a file.
+const MAX_TRANSMIT_BYTES: usize = 64 * 1024 * 1024;
+/// Largest inflated `o=z` payload: a raw RGBA image of the largest side.
+const MAX_INFLATED_BYTES: usize = 4 * (MAX_IMAGE_SIDE as usize) * (MAX_IMAGE_SIDE as usize);
+/// Placement ids travel in a 24-bit colour.
+const MAX_PLACEMENT_ID: u32 = 0xff_ffff;
+
+static NEXT_IMAGE_KEY: AtomicU64 = AtomicU64::new(1);
+
+/// Decoded RGBA pixels. `key` is unique per process and names the GPU texture.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ImageData {
+    pub key: u64,
+    pub width: u32,
+    pub height: u32,
+    pub rgba: Vec<u8>,
+}
+
+impl ImageData {
+    fn new(width: u32, height: u32, rgba: Vec<u8>) -> Self {
+        Self {
+            key: NEXT_IMAGE_KEY.fetch_add(1, Ordering::Relaxed),
+            width,
+            height,
+            rgba,
+        }
+    }
+}
+
+/// An image shown on the grid, scaled to `cols` x `rows` cells (fractional, so that the
+/// native aspect ratio survives the rounding up to whole cells).
+#[derive(Debug, Clone)]
+pub struct Placement {
+    pub image: Arc<ImageData>,
+    pub cols: f32,
+    pub rows: f32,
+}
+
+impl Placement {
+    /// Cells the placeholders cover.
+    pub fn span(&self) -> (u16, u16) {
+        let whole = |v: f32| (v.ceil() as u16).clamp(1, MAX_SPAN);
+        (whole(self.rows), whole(self.cols))
+    }
+}
+
+/// Where the text cursor goes once an image has been placed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CursorAfter {
+    /// Kitty default: after the last column, on the last row.
+    RightOfImage,
+    /// Kitty `C=1`: back where the image started.
+    Unmoved,
+    /// Sixel: at the starting column of the image's last row.
+    BelowStart,
+}
+
+/// Placement id, row and column encoded in a placeholder cell.
+pub fn placeholder(cell: &vt100::Cell) -> Option<(u32, u16, u16)> {
+    if !cell.has_contents() {
+        return None;
+    }
+    let contents = cell.contents();
+    let mut chars = contents.chars();
+    if chars.next()? != PLACEHOLDER {
+        return None;
+    }
+    let mut index = || {
+        let offset = u32::from(chars.next()?).checked_sub(DIACRITIC_BASE)?;
+        (offset < u32::from(MAX_SPAN)).then_some(offset as u16)
+    };
+    let (row, col) = (index()?, index()?);
+    let vt100::Color::Rgb(r, g, b) = cell.fgcolor() else {
+        return None;
+    };
+    Some((u32::from_be_bytes([0, r, g, b]), row, col))
+}
+
+/// Text written into one placeholder cell.
+fn placeholder_text(row: u16, col: u16, out: &mut String) {
+    out.push(PLACEHOLDER);
+    for index in [row, col] {
+        out.push(char::from_u32(DIACRITIC_BASE + u32::from(index)).unwrap_or(PLACEHOLDER));
+    }
+}
+
+/// Bytes that fill `span` cells from the cursor with placeholders for placement `id`,
+/// scrolling the screen as needed. The caller restores the pen and cursor afterwards.
+pub fn placeholder_bytes(id: u32, start_col: u16, span: (u16, u16)) -> Vec<u8> {
+    let [_, r, g, b] = id.to_be_bytes();
+    let mut out = format!("\x1b[0;38;2;{r};{g};{b}m");
+    for row in 0..span.0 {
+        if row > 0 {
+            out.push_str(&format!("\r\n\x1b[{}G", start_col + 1));
+        }
+        for col in 0..span.1 {
+            placeholder_text(row, col, &mut out);
+        }
+    }
+    out.into_bytes()
+}
+
+/// Replaces placeholder cells with spaces so copied text stays readable.
+pub fn strip_placeholders(text: &str) -> String {
+    let diacritics = DIACRITIC_BASE..DIACRITIC_BASE + u32::from(MAX_SPAN);
+    text.chars()
+        .filter(|c| !diacritics.contains(&u32::from(*c)))
+        .map(|c| if c == PLACEHOLDER { ' ' } else { c })
+        .collect()
+}
+
+/// Per-pane image state: kitty's transmitted images and every live placement.
+#[derive(Debug)]
+pub struct Graphics {
+    /// Kitty images by client id, in transmission order for eviction.
+    images: HashMap<u32, Arc<ImageData>>,
+    image_order: VecDeque<u32>,
+    placements: BTreeMap<u32, Placement>,
+    placement_order: VecDeque<u32>,
+    next_placement: u32,
+    /// First chunk's control data and the payload gathered so far (`m=1`); `None` once
+    /// it outgrew [`MAX_TRANSMIT_BYTES`], so the rest is discarded and refused at the end.
+    pending: Option<(KittyControl, Option<Vec<u8>>)>,
+    /// Cell size in device pixels, as reported to the application.
+    pub cell_px: (u16, u16),
+}
+
+impl Default for Graphics {
+    fn default() -> Self {
+        Self {
+            images: HashMap::new(),
+            image_order: VecDeque::new(),
+            placements: BTreeMap::new(),
+            placement_order: VecDeque::new(),
+            next_placement: 1,
+            pending: None,
+            cell_px: (8, 16),
+        }
+    }
+}
+
+/// What a kitty command asks of the emulator.
+#[derive(Debug, Default)]
+pub struct KittyOutcome {
+    pub reply: Option<String>,
+    pub place: Option<(Placement, CursorAfter)>,
+}
+
+impl Graphics {
+    pub fn placement(&self, id: u32) -> Option<&Placement> {
+        self.placements.get(&id)
+    }
+
+    pub fn placements(&self) -> impl Iterator<Item = &Placement> {
+        self.placements.values()
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self {
+            cell_px: self.cell_px,
+            ..Self::default()
+        };
+    }
+
+    /// Registers a placement and returns the id its placeholder cells carry.
+    pub fn insert(&mut self, placement: Placement) -> u32 {
+        let id = self.next_placement;
+        self.next_placement = if id >= MAX_PLACEMENT_ID { 1 } else { id + 1 };
+        if self.placements.insert(id, placement).is_some() {
+            self.placement_order.retain(|p| *p != id);
+        }
+        self.placement_order.push_back(id);
+        self.evict();
+        id
+    }
+
+    /// Natural size in cells of an image shown at one image pixel per device pixel.
+    pub fn natural_cells(&self, width: u32, height: u32) -> (f32, f32) {
+        let (cw, ch) = (
+            f32::from(self.cell_px.0.max(1)),
+            f32::from(self.cell_px.1.max(1)),
+        );
+        (width as f32 / cw, height as f32 / ch)
+    }
+
+    fn stored_bytes(&self) -> usize {
+        let mut keys = std::collections::HashSet::new();
+        self.images
+            .values()
+            .chain(self.placements.values().map(|p| &p.image))
+            .filter(|image| keys.insert(image.key))
+            .map(|image| image.rgba.len())
+            .sum()
+    }
+
+    fn evict(&mut self) {
+        while self.placements.len() > MAX_PLACEMENTS
+            || (self.stored_bytes() > MAX_IMAGE_BYTES && !self.placement_order.is_empty())
+        {
+            if let Some(id) = self.placement_order.pop_front() {
+                self.placements.remove(&id);
+            }
+        }
+        while self.stored_bytes() > MAX_IMAGE_BYTES {
+            let Some(id) = self.image_order.pop_front() else {
+                break;
+            };
+            self.images.remove(&id);
+        }
+    }
+
+    fn store_image(&mut self, id: u32, image: Arc<ImageData>) {
+        if self.images.insert(id, image).is_some() {
+            self.image_order.retain(|i| *i != id);
+        }
+        self.image_order.push_back(id);
+        self.evict();
+    }
+
+    /// Handles one kitty graphics APC payload (everything after the `G`).
+    pub fn kitty(&mut self, data: &[u8]) -> KittyOutcome {
+        let (control, payload) = match data.iter().position(|b| *b == b';') {
+            Some(i) => (&data[..i], &data[i + 1..]),
+            None => (data, &data[data.len()..]),
+        };
+        let control = KittyControl::parse(control);
+        let chunk = base64::decode(payload);
+
+        let (control, payload) = match self.pending.take() {
+            Some((first, gathered)) => {
+                let gathered = gathered
+                    .filter(|g| g.len() + chunk.len() <= MAX_TRANSMIT_BYTES)
+                    .map(|mut g| {
+                        g.extend(chunk);
+                        g
+                    });
+                if control.more {
+                    self.pending = Some((first, gathered));
+                    return KittyOutcome::default();
+                }
+                (first, gathered)
+            }
+            None if control.more => {
+                self.pending = Some((control, Some(chunk)));
+                return KittyOutcome::default();
+            }
+            None => (control, Some(chunk)),
+        };
+
+        let result = match payload {
+            Some(payload) => self.run_kitty(&control, payload),
+            None => Err(anyhow::anyhow!("EFBIG:image data too large")),
+        };
+        let reply = match &result {
+            Ok(_) if control.quiet >= 1 => None,
+            Err(_) if control.quiet >= 2 => None,
+            Ok(_) => Some("OK".to_string()),
+            Err(err) => Some(format!("{err:#}")),
+        };
+        // Kitty only answers commands that name an image.
+        let reply = reply
+            .filter(|_| control.id != 0 || control.number != 0)
+            .map(|msg| {
+                let mut keys = format!("i={}", control.id);
+                if control.number != 0 {
+                    keys.push_str(&format!(",I={}", control.number));
+                }
+                if control.placement != 0 {
+                    keys.push_str(&format!(",p={}", control.placement));
+                }
+                format!("\x1b_G{keys};{msg}\x1b\\")
+            });
+        KittyOutcome {
+            reply,
+            place: result.ok().flatten(),
+        }
+    }
+
+    fn run_kitty(
+        &mut self,
+        control: &KittyControl,
+        payload: Vec<u8>,
+    ) -> Result<Option<(Placement, CursorAfter)>> {
+        match control.action {
+            b't' | b'T' | b'q' => {
+                let image = Arc::new(control.decode(payload)?);
+                if control.action == b'q' {
+                    return Ok(None);
+                }
+                if control.id != 0 {
+                    self.store_image(control.id, image.clone());
+                }
+                Ok((control.action == b'T').then(|| self.display(control, image)))
+            }
+            b'p' => {
+                let image = self
+                    .images
+                    .get(&control.id)
+                    .cloned()
+                    .context("ENOENT:image not found")?;
+                Ok(Some(self.display(control, image)))
+            }
+            b'd' => {
+                match control.delete {
+                    b'a' | b'A' => {
+                        self.placements.clear();
+                        self.placement_order.clear();
+                        if control.delete == b'A' {
+                            self.images.clear();
+                            self.image_order.clear();
+                        }
+                    }
+                    b'i' | b'I' => {
+                        if let Some(image) = self.images.get(&control.id).cloned() {
+                            self.placements.retain(|_, p| p.image.key != image.key);
+                            let placements = &self.placements;
+                            self.placement_order
+                                .retain(|id| placements.contains_key(id));
+                            if control.delete == b'I' {
+                                self.images.remove(&control.id);
+                                self.image_order.retain(|i| *i != control.id);
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+                Ok(None)
+            }
+            _ => anyhow::bail!("EINVAL:unsupported action"),
+        }
+    }
+
+    fn display(&self, control: &KittyControl, image: Arc<ImageData>) -> (Placement, CursorAfter) {
+        let (natural_cols, natural_rows) = self.natural_cells(image.width, image.height);
+        let (cols, rows) = match (control.cols, control.rows) {
+            (0, 0) => (natural_cols, natural_rows),
+            (c, 0) => (c as f32, c as f32 * natural_rows / natural_cols),
+            (0, r) => (r as f32 * natural_cols / natural_rows, r as f32),
+            (c, r) => (c as f32, r as f32),
+        };
+        let cursor = if control.no_cursor_move {
+            CursorAfter::Unmoved
+        } else {
+            CursorAfter::RightOfImage
+        };
+        (Placement { image, cols, rows }, cursor)
+    }
+}
+
+/// The kitty control keys this terminal understands.
+#[derive(Debug, Clone)]
+struct KittyControl {
+    action: u8,
+    format: u32,
+    medium: u8,
+    compressed: bool,
+    width: u32,
+    height: u32,
+    id: u32,
+    number: u32,
+    placement: u32,
+    cols: u32,
+    rows: u32,
+    more: bool,
+    quiet: u32,
+    no_cursor_move: bool,
+    delete: u8,
+}
+
+impl KittyControl {
+    fn parse(control: &[u8]) -> Self {
+        let mut parsed = Self {
+            action: b't',
+            format: 32,
+            medium: b'd',
+            compressed: false,
+            width: 0,
+            height: 0,
+            id: 0,
+            number: 0,
+            placement: 0,
+            cols: 0,
+            rows: 0,
+            more: false,
+            quiet: 0,
+            no_cursor_move: false,
+            delete: b'a',
+        };
+        for pair in control.split(|b| *b == b',') {
+            let [key, b'=', value @ ..] = pair else {
+                continue;
+            };
+            let number = std::str::from_utf8(value)
+                .ok()
+                .and_then(|v| v.parse::<u32>().ok())
+                .unwrap_or(0);
+            let letter = value.first().copied().unwrap_or(0);
+            match key {
+                b'a' => parsed.action = letter,
+                b'f' => parsed.format = number,
+                b't' => parsed.medium = letter,
+                b'o' => parsed.compressed = letter == b'z',
+                b's' => parsed.width = number,
+                b'v' => parsed.height = number,
+                b'i' => parsed.id = number,
+                b'I' => parsed.number = number,
+                b'p' => parsed.placement = number,
+                b'c' => parsed.cols = number.min(u32::from(MAX_SPAN)),
+                b'r' => parsed.rows = number.min(u32::from(MAX_SPAN)),
+                b'm' => parsed.more = number == 1,
+                b'q' => parsed.quiet = number,
+                b'C' => parsed.no_cursor_move = number == 1,
+                b'd' => parsed.delete = letter,
+                _ => {}
+            }
+        }
+        parsed
+    }
+
+    fn decode(&self, payload: Vec<u8>) -> Result<ImageData> {
+        let data = match self.medium {
+            b'd' => payload,
+            b'f' | b't' => {
+                let path = String::from_utf8(payload).context("EINVAL:bad file name")?;
+                // Devices, FIFOs and `/proc` files could block the reader or never end.
+                let regular = |m: std::fs::Metadata| m.is_file();
+                if !std::fs::metadata(&path).is_ok_and(regular) {
+                    anyhow::bail!("EINVAL:not a regular file: {path}");
+                }
+                let file = std::fs::File::open(&path)
+                    .with_context(|| format!("EBADF:cannot read {path}"))?;
+                if !file.metadata().is_ok_and(regular) {
+                    anyhow::bail!("EINVAL:not a regular file: {path}");
+                }
+                let data = read_limited(file, MAX_TRANSMIT_BYTES)
+                    .with_context(|| format!("EBADF:cannot read {path}"))??;
+                // Temporary files are the terminal's to delete, but only recognisable ones.
+                if self.medium == b't' && path.contains("tty-graphics-protocol") {
+                    let _ = std::fs::remove_file(&path);
+                }
+                data
+            }
+            _ => anyhow::bail!("EINVAL:unsupported transmission medium"),
+        };
+        let data = if self.compressed {
+            read_limited(
+                flate2::read::ZlibDecoder::new(data.as_slice()),
+                MAX_INFLATED_BYTES,
+            )
+            .context("EINVAL:bad zlib data")??
+        } else {
+            data
+        };
+        match self.format {
+            100 => decode_png(&data),
+            24 | 32 => {
+                let channels = if self.format == 24 { 3 } else { 4 };
+                let (w, h) = (self.width, self.height);
+                if w == 0 || h == 0 || w > MAX_IMAGE_SIDE || h > MAX_IMAGE_SIDE {
+                    anyhow::bail!("EINVAL:bad image size");
+                }
+                let expected = w as usize * h as usize * channels;
+                if data.len() < expected {
+                    anyhow::bail!("ENODATA:insufficient image data");
+                }
+                let rgba = if channels == 4 {
+                    data[..expected].to_vec()
+                } else {
+                    data[..expected]
+                        .chunks_exact(3)
+                        .flat_map(|p| [p[0], p[1], p[2], 255])
+                        .collect()
+                };
+                Ok(ImageData::new(w, h, rgba))
+            }
+            _ => anyhow::bail!("EINVAL:unsupported format"),
+        }
+    }
+}
+
+/// Reads all of `reader` unless it holds more than `limit` bytes, which is `EFBIG`. The
+/// outer error is the reader's own.
+fn read_limited(reader: impl std::io::Read, limit: usize) -> std::io::Result<Result<Vec<u8>>> {
+    let mut data = Vec::new();
+    reader.take(limit as u64 + 1).read_to_end(&mut data)?;
+    if data.len() > limit {
+        return Ok(Err(anyhow::anyhow!("EFBIG:image data too large")));
+    }
+    Ok(Ok(data))
+}
+
+/// Decodes a PNG to RGBA; errors carry kitty's `EINVAL` code.
+pub fn decode_png(data: &[u8]) -> Result<ImageData> {
+    let mut decoder = png::Decoder::new(std::io::Cursor::new(data));
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let mut reader = decoder.read_info().context("EINVAL:bad png")?;
+    let (width, height) = {
+        let info = reader.info();
+        (info.width, info.height)
+    };
+    if width > MAX_IMAGE_SIDE || height > MAX_IMAGE_SIDE {
+        anyhow::bail!("EINVAL:image too large");
+    }
+    let mut buf = vec![0; reader.output_buffer_size().context("EINVAL:bad png")?];
+    let frame = reader.next_frame(&mut buf).context("EINVAL:bad png")?;
+    let pixels = &buf[..frame.buffer_size()];
+    let rgba = match frame.color_type {
+        png::ColorType::Rgba => pixels.to_vec(),
+        png::ColorType::Rgb => pixels
+            .chunks_exact(3)
+            .flat_map(|p| [p[0], p[1], p[2], 255])
+            .collect(),
+        png::ColorType::GrayscaleAlpha => pixels
+            .chunks_exact(2)
+            .flat_map(|p| [p[0], p[0], p[0], p[1]])
+            .collect(),
+        png::ColorType::Grayscale => pixels.iter().flat_map(|v| [*v, *v, *v, 255]).collect(),
+        png::ColorType::Indexed => anyhow::bail!("EINVAL:unexpanded palette"),
+    };
+    Ok(ImageData::new(width, height, rgba))
+}
+
+/// The VT340's default sixel colour registers, in percent.
+const VT340_PALETTE: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (20, 20, 80),
+    (80, 13, 13),
+    (20, 80, 20),
+    (80, 20, 80),
+    (20, 80, 80),
+    (80, 80, 20),
+    (53, 53, 53),
+    (26, 26, 26),
+    (33, 33, 60),
+    (60, 26, 26),
+    (33, 60, 33),
+    (60, 33, 60),
+    (33, 60, 60),
+    (60, 60, 33),
+    (80, 80, 80),
+];
+
+/// Whether a DCS payload is a sixel image (`P1;P2;P3 q ...`).
+pub fn is_sixel(dcs: &[u8]) -> bool {
+    dcs.iter()
+        .find(|b| !matches!(b, b'0'..=b'9' | b';'))
+        .is_some_and(|b| *b == b'q')
+}
+
+/// Decodes a sixel DCS payload. Pixels no sixel touched stay transparent.
+pub fn decode_sixel(dcs: &[u8]) -> Result<ImageData> {
+    let start = dcs
+        .iter()
+        .position(|b| *b == b'q')
+        .context("not a sixel sequence")?;
+    let data = &dcs[start + 1..];
+
+    let percent = |v: u32| (v.min(100) * 255 / 100) as u8;
+    let mut palette: Vec<[u8; 4]> = (0..256)
+        .map(|i| {
+            let (r, g, b) = VT340_PALETTE.get(i).copied().unwrap_or((0, 0, 0));
+            [percent(r.into()), percent(g.into()), percent(b.into()), 255]
+        })
+        .collect();
+    let mut color = palette[0];
+    let mut rows: Vec<Vec<[u8; 4]>> = Vec::new();
+    let (mut x, mut y) = (0u32, 0u32);
+    let mut width = 0u32;
+    let mut repeat = 1u32;
+
+    let mut i = 0;
+    let number = |i: &mut usize| {
+        let mut params = vec![0u32];
+        while let Some(b) = data.get(*i) {
+            match b {
+                b'0'..=b'9' => {
+                    let last = params.last_mut().expect("params is never empty");
+                    *last = last.saturating_mul(10).saturating_add(u32::from(b - b'0'));
+                }
+                b';' => params.push(0),
+                _ => break,
+            }
+            *i += 1;
+        }
+        params
+    };
+    while i < data.len() {
+        let byte = data[i];
+        i += 1;
+        match byte {
+            b'"' => {
+                let params = number(&mut i);
+                if let (Some(w), Some(h)) = (params.get(2), params.get(3)) {
+                    width = width.max((*w).min(MAX_IMAGE_SIDE));
+                    let h = (*h).min(MAX_IMAGE_SIDE) as usize;
+                    if rows.len() < h {
+                        rows.resize(h, Vec::new());
+                    }
+                }
+            }
+            b'#' => {
+                let params = number(&mut i);
+                let register = params[0].min(255) as usize;
+                if let [_, space, a, b, c, ..] = params[..] {
+                    palette[register] = if space == 1 {
+                        hls_to_rgb(a, b, c)
+                    } else {
+                        [percent(a), percent(b), percent(c), 255]
+                    };
+                }
+                color = palette[register];
+            }
+            b'!' => repeat = number(&mut i)[0].max(1),
+            b'$' => x = 0,
+            b'-' => {
+                x = 0;
+                y += 6;
+            }
+            b'?'..=b'~' => {
+                let bits = byte - b'?';
+                let end = (x + repeat).min(MAX_IMAGE_SIDE);
+                for bit in 0..6 {
+                    let row = y + bit;
+                    if bits & (1 << bit) == 0 || row >= MAX_IMAGE_SIDE {
+                        continue;
+                    }
+                    let row = row as usize;
+                    if rows.len() <= row {
+                        rows.resize(row + 1, Vec::new());
+                    }
+                    let pixels = &mut rows[row];
+                    if pixels.len() < end as usize {
+                        pixels.resize(end as usize, [0; 4]);
+                    }
+                    pixels[x as usize..end as usize].fill(color);
+                }
+                x = end;
+                width = width.max(x);
+                repeat = 1;
+            }
+            _ => {}
+        }
+    }
+
+    let height = rows.len() as u32;
+    if width == 0 || height == 0 {
+        anyhow::bail!("empty sixel image");
+    }
+    let mut rgba = Vec::with_capacity(width as usize * height as usize * 4);
+    for row in &rows {
+        for px in 0..width as usize {
+            rgba.extend(row.get(px).copied().unwrap_or([0; 4]));
+        }
+    }
+    Ok(ImageData::new(width, height, rgba))
+}
+
+/// DEC HLS (hue 0 = blue) in degrees and percent to RGBA.
+fn hls_to_rgb(h: u32, l: u32, s: u32) -> [u8; 4] {
+    let h = ((h + 240) % 360) as f32 / 360.0;
+    let (l, s) = (l.min(100) as f32 / 100.0, s.min(100) as f32 / 100.0);
+    let q = if l < 0.5 {
+        l * (1.0 + s)
+    } else {
+        l + s - l * s
+    };
+    let p = 2.0 * l - q;
+    let channel = |t: f32| {
+        let t = t.rem_euclid(1.0);
+        let v = if t < 1.0 / 6.0 {
+            p + (q - p) * 6.0 * t
+        } else if t < 0.5 {
+            q
+        } else if t < 2.0 / 3.0 {
+            p + (q - p) * (2.0 / 3.0 - t) * 6.0
+        } else {
+            p
+        };
+        (v * 255.0).round() as u8
+    };
+    [
+        channel(h + 1.0 / 3.0),
+        channel(h),
+        channel(h - 1.0 / 3.0),
+        255,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sixel_bands_repeat_and_colours() {
+        // Two red pixels over a 1x6 column, then a second band one pixel tall.
+        let image = decode_sixel(b"0;1;0q#1;2;100;0;0!2~-#2;2;0;0;100@").unwrap();
+        assert_eq!((image.width, image.height), (2, 7));
+        assert_eq!(&image.rgba[..4], &[255, 0, 0, 255]);
+        let last_row = 6 * 2 * 4;
+        assert_eq!(&image.rgba[last_row..last_row + 4], &[0, 0, 255, 255]);
+        assert_eq!(&image.rgba[last_row + 4..], &[0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn dec_hls_hue_starts_at_blue() {
+        assert_eq!(hls_to_rgb(0, 50, 100), [0, 0, 255, 255]);
+        assert_eq!(hls_to_rgb(120, 50, 100), [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn kitty_chunks_are_joined_and_answered() {
+        let mut graphics = Graphics::default();
+        // Two RGB pixels (white, then black) split over two chunks.
+        assert!(
+            graphics
+                .kitty(b"a=T,f=24,s=2,v=1,i=7,m=1;////")
+                .reply
+                .is_none()
+        );
+        let outcome = graphics.kitty(b"m=0;AAAA");
+        assert_eq!(outcome.reply.as_deref(), Some("\x1b_Gi=7;OK\x1b\\"));
+        let (placement, cursor) = outcome.place.expect("a=T displays the image");
+        assert_eq!(placement.image.rgba, vec![255, 255, 255, 255, 0, 0, 0, 255]);
+        assert_eq!(cursor, CursorAfter::RightOfImage);
+
+        let missing = graphics.kitty(b"a=p,i=9");
+        assert!(missing.reply.unwrap().contains("ENOENT"));
+    }
+
+    #[test]
+    fn kitty_refuses_devices_and_oversized_payloads() {
+        let mut graphics = Graphics::default();
+        let command = format!("a=T,t=f,f=100,i=3;{}", base64::encode(b"/dev/zero"));
+        let reply = graphics.kitty(command.as_bytes()).reply.unwrap();
+        assert!(
+            reply.starts_with("\x1b_Gi=3;EINVAL:not a regular file"),
+            "{reply:?}"
+        );
+
+        // 66 MiB of zero pixels in 3 MiB chunks, past the 64 MiB cap.
+        let chunk = format!("m=1;{}", base64::encode(&vec![0; 3 * 1024 * 1024]));
+        assert!(
+            graphics
+                .kitty(b"a=T,f=32,s=4096,v=4096,i=4,m=1;")
+                .reply
+                .is_none()
+        );
+        for _ in 0..22 {
+            assert!(graphics.kitty(chunk.as_bytes()).reply.is_none());
+        }
+        let outcome = graphics.kitty(b"m=0;");
+        assert_eq!(
+            outcome.reply.as_deref(),
+            Some("\x1b_Gi=4;EFBIG:image data too large\x1b\\")
+        );
+        assert!(outcome.place.is_none());
+
+        // Inflating stops at the limit rather than at the end of the stream.
+        let mut encoder =
+            flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        std::io::Write::write_all(&mut encoder, &[0; 4096]).unwrap();
+        let compressed = encoder.finish().unwrap();
+        let inflate = || flate2::read::ZlibDecoder::new(compressed.as_slice());
+        assert_eq!(read_limited(inflate(), 4096).unwrap().unwrap().len(), 4096);
+        let err = read_limited(inflate(), 1024).unwrap().unwrap_err();
+        assert_eq!(err.to_string(), "EFBIG:image data too large");
+    }
+}
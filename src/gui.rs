@@ -1,34 +1,40 @@
-use std::collections::BTreeMap;
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 
 use eframe::egui;
-
-use crate::core::{LayoutNode, SHORTCUTS, SessionCore, SplitAxis};
-use crate::engine::EngineState;
-use crate::keymap::{Action, Mode, map_key};
-use crate::terminal::PaneTerminal;
-
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum RenderPreset {
-    Balanced,
-    Compact,
-    Pixel,
-}
-
-impl RenderPreset {
-    fn label(self) -> &'static str {
-        match self {
-            Self::Balanced => "Balanced",
-            Self::Compact => "Compact",
-            Self::Pixel => "Pixel",
-        }
-    }
-
-    fn all() -> [Self; 3] {
-        [Self::Balanced, Self::Compact, Self::Pixel]
-    }
-}
+use eframe::epaint::text::FontsView;
+use unicode_width::UnicodeWidthStr;
+
+use crate::bell::{self, BellPolicy, BellState};
+use crate::buffers::{BufferSource, ClipboardAccess, PasteBuffers};
+use crate::command::{self, Command, CompletionContext, Prompt};
+use crate::config::{self, Config};
+use crate::core::{DropZone, LayoutNode, SessionCore, SplitAxis};
+use crate::emoji::{EmojiCache, EmojiFont};
+use crate::emulator::{ClipboardRequest, CursorShape, Emulator};
+use crate::engine::{self, AgentTeam, EngineState, MemberStatus};
+use crate::fonts::{self, Catalog, FontConfig, ResolvedFonts};
+use crate::graphics;
+use crate::input::{self, KeyEventKind, KeyModes};
+use crate::keymap::{self, Keymap, Mode};
+use crate::links::{self, LinkOpener, LinkTarget};
+use crate::palette::{self, Palette, PaneInfo, Target};
+use crate::protocol::{PaneSpawn, ServerEvent, ServerRequest};
+use crate::render::{CellMetrics, PaneView, RenderPreset, RowCache, rgb_to_egui};
+use crate::server;
+use crate::status::{self, Color, Style};
+use crate::terminal::{self, PaneTerminal, SharedEmulator};
+use crate::theme::{Rgb, Theme};
+use crate::width::{self, AmbiguousWidth};
+
+/// How long a pane busy parsing output may be drawn from its last view before a frame
+/// waits for the emulator.
+const MAX_VIEW_AGE: Duration = Duration::from_millis(100);
+/// How long `engine_state` changes settle before being written to disk.
+const STATE_SAVE_DELAY: Duration = Duration::from_millis(500);
 
 #[derive(Debug, Clone, Copy)]
 struct RenderMetrics {
@@ -38,45 +44,108 @@ struct RenderMetrics {
 }
 
 impl RenderMetrics {
-    fn for_preset(preset: RenderPreset) -> Self {
-        match preset {
-            RenderPreset::Balanced => Self {
-                cell_w: 9.0,
-                cell_h: 18.0,
-                font_size: 14.0,
-            },
-            RenderPreset::Compact => Self {
-                cell_w: 8.0,
-                cell_h: 16.0,
-                font_size: 12.5,
-            },
-            RenderPreset::Pixel => Self {
-                cell_w: 10.0,
-                cell_h: 20.0,
-                font_size: 15.0,
-            },
+    /// Cell size measured from the monospace font, snapped to whole device pixels so the
+    /// grid stays crisp.
+    fn measure(fonts: &mut FontsView<'_>, font_size: f32, line_spacing: f32, ppp: f32) -> Self {
+        let font = egui::FontId::monospace(font_size);
+        let snap = |v: f32| (v * ppp).round().max(1.0) / ppp;
+        Self {
+            cell_w: snap(fonts.glyph_width(&font, 'M')),
+            cell_h: snap(fonts.row_height(&font) * line_spacing),
+            font_size,
         }
     }
 }
 
+/// Zoom factor applied per Ctrl+=/Ctrl+- step.
+const ZOOM_STEP: f32 = 1.1;
+/// How long a command's result stays in the status line.
+const STATUS_DURATION: Duration = Duration::from_secs(5);
+/// Wait between attempts to subscribe to the server for remote commands.
+const SUBSCRIBE_RETRY: Duration = Duration::from_secs(2);
+/// How often the layout is compared with the copy last sent to the server.
+const LAYOUT_SYNC_INTERVAL: Duration = Duration::from_secs(1);
+/// How often the config files are checked for edits.
+const CONFIG_POLL: Duration = Duration::from_secs(1);
+
+/// Sent to the GUI thread by background threads.
+enum Incoming {
+    /// A command line from `orchestraterm command`, for one session or any.
+    CommandLine {
+        session: Option<String>,
+        line: String,
+    },
+    /// The outcome of a command that went to the server.
+    Status(String),
+    /// A config file was edited.
+    ConfigChanged,
+    /// Fresh status line inputs: `#(command)` output, the UTC offset and the teams on disk.
+    StatusRefreshed {
+        /// `None` for a command that ran over or could not start.
+        output: HashMap<String, Option<String>>,
+        utc_offset: i64,
+        teams: BTreeMap<String, AgentTeam>,
+    },
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum InputMode {
     Normal,
-    Prefix,
+    /// Reading one key from `key_table` (the prefix table or a user-defined one).
+    KeyTable,
     Copy,
     CopySearch,
+    BufferPicker,
+    /// Typing into the `:` prompt.
+    CommandPrompt,
+    Palette,
+    /// Editing a pane title in its header.
+    RenamePane,
+}
+
+/// Something asked for from a pane's header buttons or context menu, applied once the
+/// panes are drawn.
+#[derive(Debug, Clone, PartialEq)]
+enum PaneAction {
+    Split(SplitAxis),
+    Zoom,
+    Close,
+    StartRename,
+    Rename(String),
+    CancelRename,
+    Respawn,
+    Signal(&'static str),
+    CopyAll,
+    SaveScrollback,
+    BindMember { team: String, member: String },
+    ToggleLog,
 }
 
+/// Signals offered by the pane menu.
+const SIGNALS: &[&str] = &["INT", "TERM", "HUP", "QUIT", "KILL", "STOP", "CONT"];
+
 struct PaneRuntime {
     terminal: PaneTerminal,
-    parser: vt100::Parser,
+    emulator: SharedEmulator,
     cols: u16,
     rows: u16,
+    /// Cell size in device pixels last reported to the PTY and the emulator.
+    cell_px: (u16, u16),
+    /// Uploaded images, keyed by [`graphics::ImageData::key`].
+    textures: HashMap<u64, egui::TextureHandle>,
+    row_cache: RowCache,
+    /// What the last frame drew, and since when the emulator was too busy to refresh it.
+    view: PaneView,
+    busy_since: Option<Instant>,
+    bell: BellState,
 }
 
 pub struct OrchestraApp {
     core: SessionCore,
     input_mode: InputMode,
+    key_table: String,
+    /// While set, a repeatable binding keeps `key_table` active until then.
+    repeat_until: Option<Instant>,
     runtimes: BTreeMap<usize, PaneRuntime>,
     workspace_dir: Option<PathBuf>,
     copy_cursor: (u16, u16),
@@ -84,25 +153,126 @@ pub struct OrchestraApp {
     copy_search_input: String,
     pending_copy_text: Option<String>,
     engine_state: EngineState,
+    /// Woken by PTY reader threads when output arrives.
+    ctx: egui::Context,
     cursor_visible: bool,
     last_cursor_toggle: Instant,
+    /// When `engine_state` first changed since it was last saved.
+    state_dirty_since: Option<Instant>,
     render_preset: RenderPreset,
+    theme: Theme,
+    themes: Vec<Theme>,
+    link_opener: LinkOpener,
+    ime_preedit: String,
+    ime_cursor_rect: Option<egui::Rect>,
+    /// Pane that last received a focus-in report; `None` while the window is unfocused.
+    reported_focus: Option<usize>,
+    window_focused: bool,
+    paste_buffers: PasteBuffers,
+    clipboard_access: ClipboardAccess,
+    /// Highlighted row of the buffer picker.
+    buffer_selection: usize,
+    font_config: FontConfig,
+    resolved_fonts: ResolvedFonts,
+    font_zoom: f32,
+    emoji: EmojiCache,
+    ambiguous_width: AmbiguousWidth,
+    /// Bell policy given to new panes.
+    bell_policy: BellPolicy,
+    keymap: Keymap,
+    /// Lines of the keymap file that were rejected on the last load.
+    keymap_errors: Vec<String>,
+    prompt: Prompt,
+    palette: Palette,
+    /// Set when the prompt opens so the key that opened it is not typed into it.
+    prompt_opened: bool,
+    /// Last command result or error, and when it was shown.
+    status_message: Option<(String, Instant)>,
+    /// Directories given to `split-window -c`, used when those panes' shells start.
+    pane_start_dirs: HashMap<usize, PathBuf>,
+    /// Panes whose next runtime replaces the shell the server is still running for them.
+    pane_respawns: HashSet<usize>,
+    /// Set by `rename-window`.
+    window_title: Option<String>,
+    incoming_tx: Sender<Incoming>,
+    incoming: Receiver<Incoming>,
+    config: Config,
+    /// Problems found on the last config load.
+    config_errors: Vec<String>,
+    /// Files the config watcher checks; they change with the workspace.
+    config_files: Arc<Mutex<Vec<PathBuf>>>,
+    /// First line of each status `#(command)`'s output.
+    status_output: HashMap<String, String>,
+    /// Local offset from UTC, for `#{time}` and `#{date}`.
+    utc_offset: i64,
+    /// When the status inputs were last refreshed; `None` forces a refresh.
+    status_refreshed: Option<Instant>,
+    /// Set while a refresh thread is running.
+    status_refreshing: bool,
+    /// `#{cwd}` and `#{command}` of the focused pane as of the last refresh, and which
+    /// pane that was; they ask the system, so they are not looked up every frame.
+    status_pane_vars: Option<(usize, String, String)>,
+    /// Pane whose title is being edited, and the text so far.
+    renaming: Option<(usize, String)>,
+    pane_action: Option<(usize, PaneAction)>,
+    /// Pane whose header is being dragged.
+    dragging: Option<usize>,
+    /// Pane and zone under the pointer while dragging, found as the panes are drawn.
+    drop_target: Option<(usize, DropZone)>,
+    /// Pane under the pointer while files are dragged over the window.
+    file_drop_target: Option<usize>,
+    /// Type dropped paths relative to the pane's directory.
+    drop_relative_paths: bool,
+    /// Shells run in the server, which keeps them when the GUI closes.
+    server_panes: bool,
+    /// The layout as last sent to the server, serialized, and when it was last compared.
+    layout_sent: String,
+    layout_checked: Instant,
 }
 
 impl OrchestraApp {
-    pub fn new() -> Self {
+    pub fn new(ctx: &egui::Context) -> Self {
+        // Ctrl+=/Ctrl+- zoom the terminal font, not the whole UI.
+        ctx.options_mut(|o| o.zoom_with_keyboard = false);
+        let (config, config_errors) = Config::load(None);
+        let font_config = config.font.clone();
+        let resolved_fonts = Catalog::discover().resolve(&font_config);
+        fonts::install(ctx, &resolved_fonts);
+        let emoji_font = resolved_fonts
+            .emoji
+            .as_ref()
+            .and_then(|face| EmojiFont::load(face).ok());
+
         let engine_state = EngineState::load_or_default();
         let session_name = engine_state
             .active_session
             .as_deref()
             .unwrap_or("default")
             .to_string();
-        let mut core = SessionCore::new(session_name);
-        core.append_line_focused("Interactive shell attached");
+        let server_panes =
+            std::env::var("ORCHESTRATERM_LOCAL_PANES").is_err() && server::ensure_running().is_ok();
+        let mut core = server_panes
+            .then(|| load_layout(&session_name))
+            .flatten()
+            .unwrap_or_else(|| {
+                let mut core = SessionCore::new(&session_name);
+                core.append_line_focused("Interactive shell attached");
+                core
+            });
+        let (keymap, keymap_errors) = Keymap::load();
+        for error in &keymap_errors {
+            core.append_line_focused(format!("keymap: {error}"));
+        }
+        let (incoming_tx, incoming) = mpsc::channel();
+        listen_for_commands(ctx.clone(), incoming_tx.clone());
+        let config_files = Arc::new(Mutex::new(config_paths(None)));
+        watch_config(ctx.clone(), incoming_tx.clone(), Arc::clone(&config_files));
 
         let mut app = Self {
             core,
             input_mode: InputMode::Normal,
+            key_table: String::new(),
+            repeat_until: None,
             runtimes: BTreeMap::new(),
             workspace_dir: None,
             copy_cursor: (0, 0),
@@ -110,33 +280,201 @@ impl OrchestraApp {
             copy_search_input: String::new(),
             pending_copy_text: None,
             engine_state,
+            ctx: ctx.clone(),
             cursor_visible: true,
             last_cursor_toggle: Instant::now(),
+            state_dirty_since: None,
             render_preset: RenderPreset::Balanced,
+            theme: Theme::default(),
+            themes: Theme::discover(),
+            link_opener: LinkOpener::default(),
+            ime_preedit: String::new(),
+            ime_cursor_rect: None,
+            reported_focus: None,
+            window_focused: true,
+            paste_buffers: PasteBuffers::default(),
+            clipboard_access: ClipboardAccess::default(),
+            buffer_selection: 0,
+            font_config,
+            resolved_fonts,
+            font_zoom: 1.0,
+            emoji: EmojiCache::new(emoji_font),
+            ambiguous_width: AmbiguousWidth::default(),
+            bell_policy: BellPolicy::default(),
+            keymap,
+            keymap_errors,
+            prompt: Prompt::default(),
+            palette: Palette::default(),
+            prompt_opened: false,
+            status_message: None,
+            pane_start_dirs: HashMap::new(),
+            pane_respawns: HashSet::new(),
+            window_title: None,
+            incoming_tx,
+            incoming,
+            config: Config::default(),
+            config_errors: Vec::new(),
+            config_files,
+            status_output: HashMap::new(),
+            utc_offset: 0,
+            status_refreshed: None,
+            status_refreshing: false,
+            status_pane_vars: None,
+            renaming: None,
+            pane_action: None,
+            dragging: None,
+            drop_target: None,
+            file_drop_target: None,
+            drop_relative_paths: false,
+            server_panes,
+            layout_sent: String::new(),
+            layout_checked: Instant::now(),
         };
+        app.apply_config(config, config_errors);
+        app.run_startup_file();
         app.sync_runtimes();
         app
     }
 
+    /// Runs the startup command file, if there is one; bad lines are reported in the
+    /// focused pane.
+    fn run_startup_file(&mut self) {
+        let Ok(path) = command::startup_path() else {
+            return;
+        };
+        let Ok(text) = std::fs::read_to_string(&path) else {
+            return;
+        };
+        for (number, line) in text.lines().enumerate() {
+            match command::parse_line(line) {
+                Ok(commands) => commands.into_iter().for_each(|c| self.run_command(c)),
+                Err(err) => self.core.append_line_focused(format!(
+                    "{}:{}: {err:#}",
+                    path.display(),
+                    number + 1
+                )),
+            }
+        }
+    }
+
+    /// Parses and runs a command line, showing parse errors in the status line.
+    fn run_line(&mut self, line: &str) {
+        match command::parse_line(line) {
+            Ok(commands) => commands.into_iter().for_each(|c| self.run_command(c)),
+            Err(err) => self.set_status(format!("{err:#}")),
+        }
+    }
+
+    fn set_status(&mut self, message: impl Into<String>) {
+        self.status_message = Some((message.into(), Instant::now()));
+        self.ctx.request_repaint_after(STATUS_DURATION);
+    }
+
+    fn poll_incoming(&mut self) {
+        while let Ok(incoming) = self.incoming.try_recv() {
+            match incoming {
+                Incoming::CommandLine { session, line } => {
+                    if session.is_none_or(|s| s == self.core.name) {
+                        self.run_line(&line);
+                    }
+                }
+                Incoming::Status(message) => self.set_status(message),
+                Incoming::ConfigChanged => self.reload_config(),
+                Incoming::StatusRefreshed {
+                    output,
+                    utc_offset,
+                    teams,
+                } => {
+                    let mut previous = std::mem::take(&mut self.status_output);
+                    for (command, text) in output {
+                        let text = text
+                            .or_else(|| previous.remove(&command))
+                            .unwrap_or_else(|| status::COMMAND_FAILED.to_string());
+                        self.status_output.insert(command, text);
+                    }
+                    self.utc_offset = utc_offset;
+                    // Compared as JSON: teams have no `PartialEq`, and an unchanged reload
+                    // must not cause a save.
+                    let json = |teams| serde_json::to_string(teams).ok();
+                    if json(&teams) != json(&self.engine_state.teams) {
+                        self.engine_state.teams = teams;
+                        self.mark_state_dirty();
+                    }
+                    self.status_refreshing = false;
+                }
+            }
+        }
+    }
+
     fn spawn_runtime_for(&mut self, pane_id: usize) {
         if self.runtimes.contains_key(&pane_id) {
             return;
         }
 
         let start_dir = self
-            .workspace_dir
-            .as_ref()
+            .pane_start_dirs
+            .remove(&pane_id)
+            .or_else(|| self.workspace_dir.clone())
             .map(|p| p.to_string_lossy().to_string());
 
-        match PaneTerminal::spawn(start_dir.as_deref()) {
+        let mut emulator = Emulator::new(48, 160, self.config.scrollback);
+        emulator.set_theme(self.theme.clone());
+        emulator.set_ambiguous_width(self.ambiguous_width);
+        let emulator = Arc::new(Mutex::new(emulator));
+        let ctx = self.ctx.clone();
+        let on_output = move |_: &mut Emulator, _: &[u8]| ctx.request_repaint();
+        let shell = self.config.shell_command();
+        let attached = if self.server_panes {
+            let spawn = PaneSpawn {
+                cwd: start_dir.clone(),
+                rows: 48,
+                cols: 160,
+                scrollback: self.config.scrollback,
+                theme: self.theme.clone(),
+                ambiguous_width: self.ambiguous_width,
+            };
+            PaneTerminal::attach(
+                &self.core.name,
+                pane_id,
+                Some(spawn),
+                self.pane_respawns.remove(&pane_id),
+                Arc::clone(&emulator),
+                on_output.clone(),
+            )
+            .inspect_err(|err| {
+                self.core.append_line_to_pane(
+                    pane_id,
+                    format!("[error] server pane unavailable, running locally: {err:#}"),
+                );
+            })
+            .ok()
+        } else {
+            None
+        };
+        let started = match attached {
+            Some(terminal) => Ok(terminal),
+            None => PaneTerminal::spawn(
+                &shell,
+                start_dir.as_deref(),
+                Arc::clone(&emulator),
+                on_output,
+            ),
+        };
+        match started {
             Ok(terminal) => {
                 self.runtimes.insert(
                     pane_id,
                     PaneRuntime {
                         terminal,
-                        parser: vt100::Parser::new(48, 160, 10_000),
+                        emulator,
                         cols: 160,
                         rows: 48,
+                        cell_px: (0, 0),
+                        textures: HashMap::new(),
+                        row_cache: RowCache::default(),
+                        view: PaneView::default(),
+                        busy_since: None,
+                        bell: BellState::new(self.bell_policy),
                     },
                 );
             }
@@ -168,34 +506,213 @@ impl OrchestraApp {
     }
 
     fn poll_runtime_output(&mut self) {
+        let mut rung = Vec::new();
         for pane_id in self.core.pane_ids() {
             let Some(runtime) = self.runtimes.get_mut(&pane_id) else {
                 continue;
             };
 
-            while let Ok(chunk) = runtime.terminal.output_rx.try_recv() {
-                runtime.parser.process(&chunk);
+            // A pane mid-parse is polled on a later frame; its output keeps them coming.
+            let Some(mut emulator) = terminal::try_lock(&runtime.emulator) else {
+                continue;
+            };
+            for request in emulator.take_clipboard_requests() {
+                match request {
+                    ClipboardRequest::Set { text, .. } if self.clipboard_access.allows_write() => {
+                        self.paste_buffers
+                            .push(text.clone(), BufferSource::Osc52 { pane: pane_id });
+                        self.pending_copy_text = Some(text);
+                    }
+                    ClipboardRequest::Set { .. } => {}
+                    // Apps wait for an answer, so a denied read reports an empty selection.
+                    ClipboardRequest::Query(query) => {
+                        let text = self
+                            .paste_buffers
+                            .latest()
+                            .filter(|_| self.clipboard_access.allows_read())
+                            .map_or("", |b| b.text.as_str());
+                        emulator.answer_clipboard(&query, text);
+                    }
+                }
+            }
+            let responses = emulator.take_responses();
+            let bells = emulator.take_bells();
+            drop(emulator);
+            if !responses.is_empty() {
+                let _ = runtime.terminal.write_bytes(&responses);
+            }
+            if let Some(count) = runtime.bell.ring(bells, Instant::now()) {
+                rung.push((pane_id, count));
+            }
+        }
+        for (pane_id, count) in rung {
+            self.on_bell(pane_id, count);
+        }
+    }
+
+    /// Applies the pane's bell policy and reports the bell to server subscribers.
+    fn on_bell(&mut self, pane_id: usize, count: usize) {
+        let looking = self.window_focused && pane_id == self.core.focused_pane;
+        let Some(runtime) = self.runtimes.get_mut(&pane_id) else {
+            return;
+        };
+        let policy = runtime.bell.policy;
+        if policy.badge && !looking {
+            runtime.bell.badged = true;
+        }
+        if policy.flash {
+            self.ctx.request_repaint_after(bell::FLASH_DURATION);
+        }
+        if policy.attention && !self.window_focused {
+            self.ctx
+                .send_viewport_cmd(egui::ViewportCommand::RequestUserAttention(
+                    egui::UserAttentionType::Informational,
+                ));
+        }
+
+        let pane_title = self
+            .core
+            .panes
+            .iter()
+            .find(|p| p.id == pane_id)
+            .map(|p| p.title.clone())
+            .unwrap_or_default();
+        let event = ServerEvent::Bell {
+            session: self.core.name.clone(),
+            pane_id,
+            pane_title,
+            count,
+        };
+        // Best effort: there may be no server running.
+        std::thread::spawn(move || {
+            let _ = server::send_request(&ServerRequest::Publish { event });
+        });
+    }
+
+    fn set_theme(&mut self, theme: Theme) {
+        for runtime in self.runtimes.values_mut() {
+            terminal::lock(&runtime.emulator).set_theme(theme.clone());
+            let _ = runtime
+                .terminal
+                .set_appearance(&theme, self.ambiguous_width);
+        }
+        self.theme = theme;
+    }
+
+    fn reload_config(&mut self) {
+        let (config, errors) = Config::load(self.workspace_dir.as_deref());
+        if let Ok(mut files) = self.config_files.lock() {
+            *files = config_paths(self.workspace_dir.as_deref());
+        }
+        self.apply_config(config, errors);
+    }
+
+    /// Applies what changed since the last config (the defaults, at startup); settings
+    /// picked in the side panel stay until the config changes them.
+    fn apply_config(&mut self, config: Config, mut errors: Vec<String>) {
+        let old = std::mem::replace(&mut self.config, config);
+        let config = &self.config;
+
+        if old.render_preset != config.render_preset {
+            self.render_preset = config.render_preset;
+        }
+        self.core.line_caps = config.line_caps;
+        if old.links != config.links {
+            self.link_opener = config.links.clone();
+        }
+        if old.osc52 != config.osc52 {
+            self.clipboard_access = config.osc52;
+        }
+        if old.drop_relative_paths != config.drop_relative_paths {
+            self.drop_relative_paths = config.drop_relative_paths;
+        }
+        if old.bell != config.bell {
+            self.bell_policy = config.bell;
+            for runtime in self.runtimes.values_mut() {
+                runtime.bell.policy = config.bell;
+            }
+        }
+        if old.ambiguous_width != config.ambiguous_width {
+            self.set_ambiguous_width(self.config.ambiguous_width);
+        }
+        if self.config.font != self.font_config {
+            self.set_font_config(self.config.font.clone());
+        }
+
+        let config = &self.config;
+        if (old.theme != config.theme || old.colors != config.colors)
+            && (config.theme.is_some() || !config.colors.is_empty())
+        {
+            let base = match &config.theme {
+                Some(name) => self.themes.iter().find(|t| t.name == *name).cloned(),
+                None => Some(self.theme.clone()),
+            };
+            match base {
+                Some(mut theme) => {
+                    config.colors.apply(&mut theme);
+                    self.set_theme(theme);
+                }
+                None => errors.push(format!(
+                    "colors.theme: unknown theme \"{}\"",
+                    config.theme.as_deref().unwrap_or_default()
+                )),
             }
         }
+
+        if old.status != self.config.status {
+            self.status_refreshed = None;
+        }
+
+        for error in &errors {
+            self.core.append_line_focused(format!("config: {error}"));
+        }
+        self.config_errors = errors;
+    }
+
+    fn set_font_config(&mut self, font_config: FontConfig) {
+        self.resolved_fonts = Catalog::discover().resolve(&font_config);
+        fonts::install(&self.ctx, &self.resolved_fonts);
+        let emoji_font = self
+            .resolved_fonts
+            .emoji
+            .as_ref()
+            .and_then(|face| EmojiFont::load(face).ok());
+        self.emoji = EmojiCache::new(emoji_font);
+        self.font_config = font_config;
+        for runtime in self.runtimes.values_mut() {
+            runtime.row_cache = RowCache::default();
+        }
+    }
+
+    fn set_ambiguous_width(&mut self, policy: AmbiguousWidth) {
+        for runtime in self.runtimes.values_mut() {
+            terminal::lock(&runtime.emulator).set_ambiguous_width(policy);
+            let _ = runtime.terminal.set_appearance(&self.theme, policy);
+        }
+        self.ambiguous_width = policy;
     }
 
     fn open_folder(&mut self) {
         if let Some(folder) = rfd::FileDialog::new().pick_folder() {
             let folder_text = folder.to_string_lossy().to_string();
             self.workspace_dir = Some(folder.clone());
+            self.reload_config();
 
             for pane_id in self.core.pane_ids() {
                 if let Some(runtime) = self.runtimes.get_mut(&pane_id) {
                     let _ = runtime
                         .terminal
-                        .send_line(&format!("cd '{}'", folder_text.replace('\'', "'\"'\"'")));
+                        .send_line(&format!("cd {}", terminal::shell_quote(&folder_text)));
                 }
             }
         }
     }
 
     fn send_focused_bytes(&mut self, bytes: &[u8]) {
-        let pane_id = self.core.focused_pane;
+        self.send_bytes_to(self.core.focused_pane, bytes);
+    }
+
+    fn send_bytes_to(&mut self, pane_id: usize, bytes: &[u8]) {
         if let Some(runtime) = self.runtimes.get_mut(&pane_id) {
             let _ = runtime.terminal.write_bytes(bytes);
         }
@@ -208,88 +725,120 @@ impl OrchestraApp {
         self.send_focused_bytes(text.as_bytes());
     }
 
+    fn focused_key_modes(&self) -> KeyModes {
+        self.key_modes(self.core.focused_pane)
+    }
+
+    fn key_modes(&self, pane_id: usize) -> KeyModes {
+        self.runtimes
+            .get(&pane_id)
+            .map(|r| terminal::lock(&r.emulator).key_modes())
+            .unwrap_or_default()
+    }
+
+    /// `send-keys`: named keys are encoded for the pane's keyboard modes, anything else is
+    /// sent as text.
+    fn send_keys(&mut self, pane_id: usize, literal: bool, keys: &[String]) {
+        let modes = self.key_modes(pane_id);
+        let mut bytes = Vec::new();
+        for key in keys {
+            let encoded = (!literal && key.chars().count() > 1)
+                .then(|| keymap::parse_chord(key).ok())
+                .flatten()
+                .and_then(|chord| match chord.logical_key {
+                    egui::Key::Space if chord.modifiers.is_none() => Some(b" ".to_vec()),
+                    key => input::encode_key(key, chord.modifiers, KeyEventKind::Press, &modes),
+                });
+            match encoded {
+                Some(encoded) => bytes.extend(encoded),
+                None => bytes.extend(key.as_bytes()),
+            }
+        }
+        self.send_bytes_to(pane_id, &bytes);
+    }
+
     fn handle_terminal_input(&mut self, ctx: &egui::Context) {
-        if self.input_mode == InputMode::Prefix
-            || self.input_mode == InputMode::Copy
-            || self.input_mode == InputMode::CopySearch
-        {
+        if self.input_mode != InputMode::Normal {
+            return;
+        }
+
+        // A focused egui widget (e.g. a side-panel text field) owns the keyboard.
+        if ctx.wants_keyboard_input() {
             return;
         }
 
+        let modes = self.focused_key_modes();
+        let mut swallow_text = false;
+        let mut committed = None;
         let events = ctx.input(|i| i.events.clone());
         for ev in events {
             match ev {
+                egui::Event::Ime(ime) => match ime {
+                    egui::ImeEvent::Preedit(text) => self.ime_preedit = text,
+                    egui::ImeEvent::Commit(text) => {
+                        self.ime_preedit.clear();
+                        self.send_focused_text(&text);
+                        committed = Some(text);
+                    }
+                    egui::ImeEvent::Enabled | egui::ImeEvent::Disabled => {
+                        self.ime_preedit.clear();
+                    }
+                },
+                // While composing, keystrokes belong to the input method.
+                egui::Event::Key { .. } | egui::Event::Text(_) if !self.ime_preedit.is_empty() => {}
+                // Some platforms echo the committed string as a plain text event as well.
+                egui::Event::Text(text) if committed.as_deref() == Some(text.as_str()) => {
+                    committed = None;
+                }
                 egui::Event::Paste(text) => {
-                    self.send_focused_bytes(b"\x1b[200~");
-                    self.send_focused_text(&text);
-                    self.send_focused_bytes(b"\x1b[201~");
+                    self.send_focused_bytes(&input::encode_paste(&text, &modes));
+                }
+                // Off macOS, egui turns Ctrl+C/Ctrl+X into clipboard events; the shell wants them.
+                egui::Event::Copy | egui::Event::Cut if !cfg!(target_os = "macos") => {
+                    let key = if matches!(ev, egui::Event::Copy) {
+                        egui::Key::C
+                    } else {
+                        egui::Key::X
+                    };
+                    if let Some(bytes) =
+                        input::encode_key(key, egui::Modifiers::CTRL, KeyEventKind::Press, &modes)
+                    {
+                        self.send_focused_bytes(&bytes);
+                    }
                 }
                 egui::Event::Text(text) => {
+                    if std::mem::take(&mut swallow_text) {
+                        continue;
+                    }
                     self.send_focused_text(&text);
                 }
                 egui::Event::Key {
                     key,
                     pressed,
+                    repeat,
                     modifiers,
                     ..
-                } if pressed => {
-                    if let Some(action) = map_key(Mode::Normal, key, modifiers) {
-                        self.apply_action(action);
-                        continue;
-                    }
-                    if modifiers.command {
+                } => {
+                    // Only the text of the key just pressed may be swallowed; a key that
+                    // produced none must not eat the next one's.
+                    swallow_text = false;
+                    // Bindings were already applied by `handle_shortcuts`.
+                    if self
+                        .keymap
+                        .lookup(Mode::Normal.table_name(), key, modifiers)
+                        .is_some()
+                        || modifiers.mac_cmd
+                    {
                         continue;
                     }
-                    if modifiers.ctrl {
-                        if let Some(byte) = ctrl_key_to_byte(key) {
-                            self.send_focused_bytes(&[byte]);
-                        }
-                        continue;
-                    }
-
-                    if modifiers.alt {
-                        match key {
-                            egui::Key::ArrowLeft => self.send_focused_bytes(b"\x1bb"),
-                            egui::Key::ArrowRight => self.send_focused_bytes(b"\x1bf"),
-                            _ => {}
-                        }
-                        continue;
-                    }
-
-                    match key {
-                        egui::Key::Enter => self.send_focused_bytes(b"\r"),
-                        egui::Key::Backspace => self.send_focused_bytes(&[0x7f]),
-                        egui::Key::Tab => {
-                            if modifiers.shift {
-                                self.send_focused_bytes(b"\x1b[Z");
-                            } else {
-                                self.send_focused_bytes(b"\t");
-                            }
-                        }
-                        egui::Key::ArrowUp => self.send_focused_bytes(b"\x1b[A"),
-                        egui::Key::ArrowDown => self.send_focused_bytes(b"\x1b[B"),
-                        egui::Key::ArrowRight => self.send_focused_bytes(b"\x1b[C"),
-                        egui::Key::ArrowLeft => self.send_focused_bytes(b"\x1b[D"),
-                        egui::Key::Home => self.send_focused_bytes(b"\x1b[H"),
-                        egui::Key::End => self.send_focused_bytes(b"\x1b[F"),
-                        egui::Key::Insert => self.send_focused_bytes(b"\x1b[2~"),
-                        egui::Key::Delete => self.send_focused_bytes(b"\x1b[3~"),
-                        egui::Key::PageUp => self.send_focused_bytes(b"\x1b[5~"),
-                        egui::Key::PageDown => self.send_focused_bytes(b"\x1b[6~"),
-                        egui::Key::F1 => self.send_focused_bytes(b"\x1bOP"),
-                        egui::Key::F2 => self.send_focused_bytes(b"\x1bOQ"),
-                        egui::Key::F3 => self.send_focused_bytes(b"\x1bOR"),
-                        egui::Key::F4 => self.send_focused_bytes(b"\x1bOS"),
-                        egui::Key::F5 => self.send_focused_bytes(b"\x1b[15~"),
-                        egui::Key::F6 => self.send_focused_bytes(b"\x1b[17~"),
-                        egui::Key::F7 => self.send_focused_bytes(b"\x1b[18~"),
-                        egui::Key::F8 => self.send_focused_bytes(b"\x1b[19~"),
-                        egui::Key::F9 => self.send_focused_bytes(b"\x1b[20~"),
-                        egui::Key::F10 => self.send_focused_bytes(b"\x1b[21~"),
-                        egui::Key::F11 => self.send_focused_bytes(b"\x1b[23~"),
-                        egui::Key::F12 => self.send_focused_bytes(b"\x1b[24~"),
-                        egui::Key::Escape => self.send_focused_bytes(&[0x1b]),
-                        _ => {}
+                    let kind = match (pressed, repeat) {
+                        (false, _) => KeyEventKind::Release,
+                        (true, true) => KeyEventKind::Repeat,
+                        (true, false) => KeyEventKind::Press,
+                    };
+                    if let Some(bytes) = input::encode_key(key, modifiers, kind, &modes) {
+                        self.send_focused_bytes(&bytes);
+                        swallow_text = pressed && input::suppresses_text(key, modifiers);
                     }
                 }
                 _ => {}
@@ -297,21 +846,25 @@ impl OrchestraApp {
         }
     }
 
-    fn handle_shortcuts(&mut self, ctx: &egui::Context) {
-        let mode = match self.input_mode {
-            InputMode::Normal => Mode::Normal,
-            InputMode::Prefix => Mode::Prefix,
-            InputMode::Copy => Mode::Copy,
-            InputMode::CopySearch => Mode::CopySearch,
-        };
+    /// Leaves a repeating key table once its repeat time is up.
+    fn expire_key_table(&mut self) {
+        if self
+            .repeat_until
+            .is_some_and(|until| Instant::now() >= until)
+        {
+            self.repeat_until = None;
+            self.input_mode = InputMode::Normal;
+        }
+    }
 
+    fn handle_shortcuts(&mut self, ctx: &egui::Context) {
         let events = ctx.input(|i| i.events.clone());
         for ev in events {
-            if self.input_mode == InputMode::CopySearch {
-                if let egui::Event::Text(text) = &ev {
-                    self.copy_search_input.push_str(text);
-                    continue;
-                }
+            if self.input_mode == InputMode::CopySearch
+                && let egui::Event::Text(text) = &ev
+            {
+                self.copy_search_input.push_str(text);
+                continue;
             }
             let egui::Event::Key {
                 key,
@@ -322,76 +875,269 @@ impl OrchestraApp {
             else {
                 continue;
             };
-            if !pressed {
+            if !pressed
+                || matches!(
+                    self.input_mode,
+                    InputMode::CommandPrompt | InputMode::Palette | InputMode::RenamePane
+                )
+            {
                 continue;
             }
-            if let Some(action) = map_key(mode, key, modifiers) {
-                self.apply_action(action);
+            let table = match self.input_mode {
+                InputMode::Normal => Mode::Normal.table_name(),
+                InputMode::KeyTable => self.key_table.as_str(),
+                InputMode::Copy => Mode::Copy.table_name(),
+                InputMode::CopySearch => Mode::CopySearch.table_name(),
+                InputMode::BufferPicker => Mode::BufferPicker.table_name(),
+                InputMode::CommandPrompt | InputMode::Palette | InputMode::RenamePane => continue,
+            }
+            .to_string();
+            let mut binding = self.keymap.lookup(&table, key, modifiers).cloned();
+            if self.input_mode == InputMode::KeyTable {
+                // A key table reads one key; while repeating, only repeatable keys count.
+                if self.repeat_until.take().is_some() && !binding.as_ref().is_some_and(|b| b.repeat)
+                {
+                    binding = None;
+                }
+                self.input_mode = InputMode::Normal;
+            }
+            let Some(binding) = binding else {
+                continue;
+            };
+            self.run_command(binding.action);
+            if binding.repeat
+                && table != Mode::Normal.table_name()
+                && self.input_mode == InputMode::Normal
+            {
+                self.enter_key_table(table);
+                self.repeat_until = Some(Instant::now() + self.keymap.repeat_time);
             }
         }
+        if let Some(until) = self.repeat_until {
+            ctx.request_repaint_after(until.saturating_duration_since(Instant::now()));
+        }
     }
 
-    fn apply_action(&mut self, action: Action) {
-        match action {
-            Action::OpenFolder => self.open_folder(),
-            Action::SendEnter => self.send_focused_bytes(b"\r"),
-            Action::EnterPrefix => self.input_mode = InputMode::Prefix,
-            Action::EnterCopyMode => {
+    fn enter_key_table(&mut self, table: String) {
+        self.input_mode = InputMode::KeyTable;
+        self.key_table = table;
+        self.repeat_until = None;
+    }
+
+    fn run_command(&mut self, command: Command) {
+        match command {
+            Command::OpenFolder => self.open_folder(),
+            Command::SendEnter => self.send_focused_bytes(b"\r"),
+            Command::SendText(text) => {
+                self.input_mode = InputMode::Normal;
+                self.send_focused_text(&text);
+            }
+            Command::EnterPrefix => self.enter_key_table(Mode::Prefix.table_name().to_string()),
+            Command::SwitchTable(table) => self.enter_key_table(table),
+            Command::EnterCopyMode => {
                 self.input_mode = InputMode::Copy;
                 self.copy_cursor = (0, 0);
                 self.copy_anchor = None;
                 self.copy_search_input.clear();
             }
-            Action::ExitCopyMode => {
+            Command::ExitCopyMode => {
                 self.input_mode = InputMode::Normal;
                 self.copy_anchor = None;
                 self.copy_search_input.clear();
             }
-            Action::SplitHorizontal => {
-                self.core.split_focused(SplitAxis::Horizontal);
-                self.input_mode = InputMode::Normal;
+            Command::CommandPrompt => {
+                self.input_mode = InputMode::CommandPrompt;
+                self.prompt.open();
+                self.prompt_opened = true;
+            }
+            Command::CommandPalette => {
+                let items = self.palette_items();
+                self.palette.open(items);
+                self.input_mode = InputMode::Palette;
             }
-            Action::SplitVertical => {
-                self.core.split_focused(SplitAxis::Vertical);
+            Command::SplitWindow { axis, dir } => {
+                let id = self.core.split_focused(axis);
+                if let Some(dir) = dir {
+                    self.pane_start_dirs.insert(id, links::expand_home(&dir));
+                }
                 self.input_mode = InputMode::Normal;
             }
-            Action::ClosePane => {
-                self.core.close_focused();
+            Command::KillPane(target) => {
+                let id = target.unwrap_or(self.core.focused_pane);
+                if self.core.has_pane(id) {
+                    self.core.close_pane(id);
+                } else {
+                    self.set_status(format!("kill-pane: no pane %{id}"));
+                }
                 self.input_mode = InputMode::Normal;
             }
-            Action::ToggleZoom => {
+            Command::ToggleZoom => {
                 self.core.toggle_zoom();
                 self.input_mode = InputMode::Normal;
             }
-            Action::FocusPrev => {
+            Command::FocusPrev => {
                 self.core.focus_prev();
                 self.input_mode = InputMode::Normal;
             }
-            Action::FocusNext => {
+            Command::FocusNext => {
                 self.core.focus_next();
                 self.input_mode = InputMode::Normal;
             }
-            Action::CopyMoveUp => self.copy_cursor.1 = self.copy_cursor.1.saturating_sub(1),
-            Action::CopyMoveDown => self.copy_cursor.1 = self.copy_cursor.1.saturating_add(1),
-            Action::CopyMoveLeft => self.copy_cursor.0 = self.copy_cursor.0.saturating_sub(1),
-            Action::CopyMoveRight => self.copy_cursor.0 = self.copy_cursor.0.saturating_add(1),
-            Action::CopyStartSelection => {
+            Command::SelectPane { target, title } => {
+                self.input_mode = InputMode::Normal;
+                let id = target.unwrap_or(self.core.focused_pane);
+                let Some(pane) = self.core.panes.iter_mut().find(|p| p.id == id) else {
+                    self.set_status(format!("select-pane: no pane %{id}"));
+                    return;
+                };
+                if let Some(title) = title {
+                    pane.title = title;
+                }
+                self.core.focused_pane = id;
+            }
+            Command::SelectLayout(layout) => {
+                self.core.select_layout(layout);
+                self.input_mode = InputMode::Normal;
+            }
+            Command::RenameWindow(title) => {
+                self.ctx
+                    .send_viewport_cmd(egui::ViewportCommand::Title(title.clone()));
+                self.window_title = Some(title);
+                self.input_mode = InputMode::Normal;
+            }
+            Command::CopyMoveUp => self.copy_cursor.1 = self.copy_cursor.1.saturating_sub(1),
+            Command::CopyMoveDown => self.copy_cursor.1 = self.copy_cursor.1.saturating_add(1),
+            Command::CopyMoveLeft => self.copy_cursor.0 = self.copy_cursor.0.saturating_sub(1),
+            Command::CopyMoveRight => self.copy_cursor.0 = self.copy_cursor.0.saturating_add(1),
+            Command::CopyStartSelection => {
                 self.copy_anchor = Some(self.copy_cursor);
             }
-            Action::CopyCopySelection => {
+            Command::CopyCopySelection => {
                 self.pending_copy_text = self.extract_copy_selection();
+                if let Some(text) = &self.pending_copy_text {
+                    self.paste_buffers
+                        .push(text.clone(), BufferSource::CopyMode);
+                }
             }
-            Action::CopySearchStart => {
+            Command::CopySearchStart => {
                 self.input_mode = InputMode::CopySearch;
                 self.copy_search_input.clear();
             }
-            Action::CopySearchApply => {
+            Command::CopySearchApply => {
                 self.apply_copy_search();
                 self.input_mode = InputMode::Copy;
             }
+            Command::PasteBuffer(name) => {
+                self.input_mode = InputMode::Normal;
+                let buffer = match name {
+                    Some(name) => self.paste_buffers.get(name.as_str()),
+                    None => self.paste_buffers.latest(),
+                };
+                if let Some(text) = buffer.map(|b| b.text.clone()) {
+                    self.paste_into_focused(&text);
+                }
+            }
+            Command::ChooseBuffer => {
+                self.input_mode = InputMode::BufferPicker;
+                self.buffer_selection = 0;
+            }
+            Command::BufferPickerUp => {
+                self.buffer_selection = self.buffer_selection.saturating_sub(1);
+            }
+            Command::BufferPickerDown => {
+                let last = self.paste_buffers.len().saturating_sub(1);
+                self.buffer_selection = (self.buffer_selection + 1).min(last);
+            }
+            Command::BufferPickerPaste => {
+                self.input_mode = InputMode::Normal;
+                let text = self
+                    .paste_buffers
+                    .iter()
+                    .nth(self.buffer_selection)
+                    .map(|b| b.text.clone());
+                if let Some(text) = text {
+                    self.paste_into_focused(&text);
+                }
+            }
+            Command::BufferPickerDelete => {
+                let name = self
+                    .paste_buffers
+                    .iter()
+                    .nth(self.buffer_selection)
+                    .map(|b| b.name.clone());
+                if let Some(name) = name {
+                    self.paste_buffers.remove(&name);
+                }
+                let last = self.paste_buffers.len().saturating_sub(1);
+                self.buffer_selection = self.buffer_selection.min(last);
+            }
+            Command::BufferPickerClose => self.input_mode = InputMode::Normal,
+            Command::ZoomIn => self.zoom(ZOOM_STEP),
+            Command::ZoomOut => self.zoom(1.0 / ZOOM_STEP),
+            Command::ZoomReset => self.font_zoom = 1.0,
+            Command::SendKeys {
+                target,
+                literal,
+                keys,
+            } => {
+                let id = target.unwrap_or(self.core.focused_pane);
+                if self.core.has_pane(id) {
+                    self.send_keys(id, literal, &keys);
+                } else {
+                    self.set_status(format!("send-keys: no pane %{id}"));
+                }
+            }
+            Command::SetBuffer { name, text } => match name {
+                Some(name) => self.paste_buffers.set(&name, text),
+                None => {
+                    self.paste_buffers.push(text, BufferSource::Named);
+                }
+            },
+            Command::Team(team) => {
+                let request = team.request();
+                let tx = self.incoming_tx.clone();
+                let ctx = self.ctx.clone();
+                std::thread::spawn(move || {
+                    let message = match server::send_request(&request) {
+                        Ok(resp) if resp.ok => resp.message,
+                        Ok(resp) => format!("team: {}", resp.message),
+                        Err(err) => format!("team: {err:#}"),
+                    };
+                    let _ = tx.send(Incoming::Status(message));
+                    ctx.request_repaint();
+                });
+            }
+            command @ (Command::Bind { .. } | Command::Unbind { .. } | Command::Set { .. }) => {
+                if let Err(err) = self.keymap.run(&command) {
+                    self.set_status(format!("{err:#}"));
+                }
+            }
+            Command::Sequence(commands) => {
+                for command in commands {
+                    self.run_command(command);
+                }
+            }
         }
     }
 
+    /// Terminal font size after the render preset and zoom.
+    fn font_size(&self) -> f32 {
+        let (scale, _) = self.render_preset.scale();
+        (self.font_config.size * scale * self.font_zoom).clamp(fonts::MIN_SIZE, fonts::MAX_SIZE)
+    }
+
+    fn zoom(&mut self, factor: f32) {
+        let base = self.font_config.size * self.render_preset.scale().0;
+        self.font_zoom =
+            (self.font_zoom * factor).clamp(fonts::MIN_SIZE / base, fonts::MAX_SIZE / base);
+    }
+
+    /// Pastes like the system clipboard would, bracketed if the application asked for it.
+    fn paste_into_focused(&mut self, text: &str) {
+        let modes = self.focused_key_modes();
+        self.send_focused_bytes(&input::encode_paste(text, &modes));
+    }
+
     fn apply_copy_search(&mut self) {
         let query = self.copy_search_input.trim();
         if query.is_empty() {
@@ -401,8 +1147,9 @@ impl OrchestraApp {
         let Some(runtime) = self.runtimes.get(&pane_id) else {
             return;
         };
-        let screen = runtime.parser.screen();
-        let contents = screen.contents();
+        let contents = width::strip_pads(&graphics::strip_placeholders(
+            &terminal::lock(&runtime.emulator).screen().contents(),
+        ));
         for (y, line) in contents.lines().enumerate() {
             if let Some(x) = line.find(query) {
                 self.copy_cursor = (x as u16, y as u16);
@@ -418,8 +1165,9 @@ impl OrchestraApp {
         let anchor = self.copy_anchor?;
         let cursor = self.copy_cursor;
 
-        let screen = runtime.parser.screen();
-        let lines = screen.contents();
+        let lines = width::strip_pads(&graphics::strip_placeholders(
+            &terminal::lock(&runtime.emulator).screen().contents(),
+        ));
         let lines: Vec<&str> = lines.lines().collect();
         if lines.is_empty() {
             return None;
@@ -431,8 +1179,8 @@ impl OrchestraApp {
         let y1 = sy.max(ey) as usize;
         let mut out = Vec::new();
 
-        for y in y0..=y1.min(lines.len().saturating_sub(1)) {
-            let line = &lines[y];
+        let last = y1.min(lines.len().saturating_sub(1));
+        for (y, line) in lines.iter().enumerate().take(last + 1).skip(y0) {
             let (x0, x1) = if y == y0 && y == y1 {
                 (sx.min(ex) as usize, sx.max(ex) as usize)
             } else if y == y0 {
@@ -459,48 +1207,80 @@ impl OrchestraApp {
             LayoutNode::Leaf(id) => self.draw_leaf(ui, rect, *id),
             LayoutNode::Split {
                 axis,
+                ratio,
                 first,
                 second,
             } => {
                 let spacing = 6.0;
-                match axis {
+                let (r1, r2) = match axis {
                     SplitAxis::Horizontal => {
-                        let half = (rect.height() - spacing) / 2.0;
+                        let height = (rect.height() - spacing) * ratio;
                         let r1 =
-                            egui::Rect::from_min_size(rect.min, egui::vec2(rect.width(), half));
-                        let r2 = egui::Rect::from_min_size(
-                            egui::pos2(rect.left(), rect.top() + half + spacing),
-                            egui::vec2(rect.width(), half),
+                            egui::Rect::from_min_size(rect.min, egui::vec2(rect.width(), height));
+                        let r2 = egui::Rect::from_min_max(
+                            egui::pos2(rect.left(), r1.bottom() + spacing),
+                            rect.max,
                         );
-                        self.draw_node(ui, r1, first);
-                        self.draw_node(ui, r2, second);
+                        (r1, r2)
                     }
                     SplitAxis::Vertical => {
-                        let half = (rect.width() - spacing) / 2.0;
+                        let width = (rect.width() - spacing) * ratio;
                         let r1 =
-                            egui::Rect::from_min_size(rect.min, egui::vec2(half, rect.height()));
-                        let r2 = egui::Rect::from_min_size(
-                            egui::pos2(rect.left() + half + spacing, rect.top()),
-                            egui::vec2(half, rect.height()),
+                            egui::Rect::from_min_size(rect.min, egui::vec2(width, rect.height()));
+                        let r2 = egui::Rect::from_min_max(
+                            egui::pos2(r1.right() + spacing, rect.top()),
+                            rect.max,
                         );
-                        self.draw_node(ui, r1, first);
-                        self.draw_node(ui, r2, second);
+                        (r1, r2)
                     }
-                }
+                };
+                self.draw_node(ui, r1, first);
+                self.draw_node(ui, r2, second);
             }
         }
     }
 
+    /// Sends `?1004` focus-out/in reports when the focused pane or window focus changes.
+    fn sync_focus_reports(&mut self) {
+        let focused = self.window_focused.then_some(self.core.focused_pane);
+        if focused == self.reported_focus {
+            return;
+        }
+        if let Some(runtime) = self
+            .reported_focus
+            .and_then(|id| self.runtimes.get_mut(&id))
+        {
+            terminal::lock(&runtime.emulator).report_focus(false);
+        }
+        if let Some(runtime) = focused.and_then(|id| self.runtimes.get_mut(&id)) {
+            terminal::lock(&runtime.emulator).report_focus(true);
+        }
+        self.reported_focus = focused;
+    }
+
     fn draw_leaf(&mut self, ui: &mut egui::Ui, rect: egui::Rect, pane_id: usize) {
         let is_focused = pane_id == self.core.focused_pane;
-        let stroke = if is_focused {
+        let flashing = self.runtimes.get_mut(&pane_id).is_some_and(|runtime| {
+            if is_focused && self.window_focused {
+                runtime.bell.badged = false;
+            }
+            runtime.bell.flashing(Instant::now())
+        });
+        let stroke = if flashing {
+            egui::Stroke::new(3.0, egui::Color32::from_rgb(240, 200, 60))
+        } else if is_focused {
             egui::Stroke::new(2.0, egui::Color32::from_rgb(80, 200, 120))
         } else {
             egui::Stroke::new(1.0, egui::Color32::from_gray(90))
         };
 
+        let colors = self
+            .runtimes
+            .get(&pane_id)
+            .map(|r| terminal::lock(&r.emulator).colors())
+            .unwrap_or_else(|| self.theme.clone());
         ui.painter()
-            .rect_filled(rect, 6.0, egui::Color32::from_rgb(22, 25, 29));
+            .rect_filled(rect, 6.0, rgb_to_egui(colors.background));
         ui.painter()
             .rect_stroke(rect, 6.0, stroke, egui::StrokeKind::Inside);
 
@@ -508,6 +1288,17 @@ impl OrchestraApp {
         if response.clicked() {
             self.core.focused_pane = pane_id;
         }
+        let logging = self
+            .runtimes
+            .get(&pane_id)
+            .is_some_and(|r| r.terminal.is_logging());
+        let mut action = None;
+        response.context_menu(|ui| {
+            action = pane_menu(ui, &self.engine_state.teams, logging);
+        });
+        let mut clicked_link = None;
+        let (_, line_spacing) = self.render_preset.scale();
+        let font_size = self.font_size();
         ui.scope_builder(
             egui::UiBuilder::new().max_rect(rect.shrink2(egui::vec2(8.0, 8.0))),
             |ui| {
@@ -519,190 +1310,1426 @@ impl OrchestraApp {
                         .find(|p| p.id == pane_id)
                         .expect("pane must exist");
 
-                    let metrics = RenderMetrics::for_preset(self.render_preset);
+                    let ppp = ui.ctx().pixels_per_point();
+                    let metrics = ui.fonts_mut(|fonts| {
+                        RenderMetrics::measure(fonts, font_size, line_spacing, ppp)
+                    });
                     let cols = ((rect.width() - 22.0) / metrics.cell_w).max(20.0) as u16;
                     let rows = ((rect.height() - 42.0) / metrics.cell_h).max(8.0) as u16;
 
-                    if cols != runtime.cols || rows != runtime.rows {
+                    let cell_px = (
+                        (metrics.cell_w * ppp).round() as u16,
+                        (metrics.cell_h * ppp).round() as u16,
+                    );
+
+                    if cols != runtime.cols || rows != runtime.rows || cell_px != runtime.cell_px {
                         runtime.cols = cols;
                         runtime.rows = rows;
-                        let _ = runtime.terminal.resize(cols, rows);
-                        runtime.parser.set_size(rows, cols);
+                        runtime.cell_px = cell_px;
+                        let pixels = (
+                            cols.saturating_mul(cell_px.0),
+                            rows.saturating_mul(cell_px.1),
+                        );
+                        // The emulator takes the new size first so the application's redraw
+                        // after SIGWINCH is parsed at that size.
+                        {
+                            let mut emulator = terminal::lock(&runtime.emulator);
+                            emulator.set_size(rows, cols);
+                            emulator.set_cell_pixels(cell_px.0, cell_px.1);
+                        }
+                        let _ = runtime.terminal.resize(cols, rows, pixels);
                     }
 
                     ui.horizontal(|ui| {
-                        ui.strong(format!("{} (#{} )", pane.title, pane.id));
+                        match &mut self.renaming {
+                            Some((id, title)) if *id == pane_id => {
+                                let edit =
+                                    ui.add(egui::TextEdit::singleline(title).desired_width(180.0));
+                                if edit.lost_focus() {
+                                    action =
+                                        Some(if ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+                                            PaneAction::CancelRename
+                                        } else {
+                                            PaneAction::Rename(title.clone())
+                                        });
+                                } else {
+                                    edit.request_focus();
+                                }
+                            }
+                            _ => {
+                                let title = ui
+                                    .add(
+                                        egui::Label::new(
+                                            egui::RichText::new(format!(
+                                                "{} (#{} )",
+                                                pane.title, pane.id
+                                            ))
+                                            .strong(),
+                                        )
+                                        .sense(egui::Sense::click_and_drag()),
+                                    )
+                                    .on_hover_text("Double-click to rename, drag to move");
+                                if title.double_clicked() {
+                                    action = Some(PaneAction::StartRename);
+                                }
+                                // Drops are tracked in the main window only.
+                                if title.drag_started() && self.core.layout.contains(pane_id) {
+                                    self.dragging = Some(pane_id);
+                                }
+                                title.context_menu(|ui| {
+                                    action = pane_menu(ui, &self.engine_state.teams, logging);
+                                });
+                            }
+                        }
+                        if runtime.bell.badged {
+                            ui.colored_label(egui::Color32::from_rgb(240, 200, 60), "🔔");
+                        }
+                        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                            if ui.small_button("×").on_hover_text("Close pane").clicked() {
+                                action = Some(PaneAction::Close);
+                            }
+                            let zoom = if self.core.zoomed { "Unzoom" } else { "Zoom" };
+                            if ui.small_button(zoom).clicked() {
+                                action = Some(PaneAction::Zoom);
+                            }
+                            for (label, axis) in
+                                [("|", SplitAxis::Vertical), ("—", SplitAxis::Horizontal)]
+                            {
+                                let hover = Command::SplitWindow { axis, dir: None }.describe();
+                                if ui.small_button(label).on_hover_text(hover).clicked() {
+                                    action = Some(PaneAction::Split(axis));
+                                }
+                            }
+                        });
                     });
                     ui.separator();
 
-                    let screen = runtime.parser.screen();
                     let mut origin = ui.cursor().min;
                     origin.x = origin.x.round();
                     origin.y = origin.y.round();
                     let cell_w = metrics.cell_w;
                     let cell_h = metrics.cell_h;
                     let font_regular = egui::FontId::monospace(metrics.font_size);
-                    let font_bold =
-                        egui::FontId::new(metrics.font_size, egui::FontFamily::Monospace);
 
                     let max_rows = runtime.rows.min(200);
                     let max_cols = runtime.cols.min(400);
-
-                    for row in 0..max_rows {
-                        let mut col = 0;
-                        while col < max_cols {
-                            let Some(cell) = screen.cell(row, col) else {
-                                col += 1;
+                    let cell_metrics = CellMetrics {
+                        cell_w,
+                        cell_h,
+                        font_size: metrics.font_size,
+                        color_emoji: self.emoji.available(),
+                    };
+                    // A pane busy parsing is drawn as it last was; only once that is
+                    // stale does the frame wait for the reader's current slice.
+                    let wait = runtime
+                        .busy_since
+                        .is_some_and(|since| since.elapsed() >= MAX_VIEW_AGE);
+                    if runtime.view.capture(
+                        &runtime.emulator,
+                        &runtime.row_cache,
+                        &colors,
+                        cell_metrics,
+                        (max_rows, max_cols),
+                        wait,
+                    ) {
+                        runtime.busy_since = None;
+                    } else {
+                        runtime.busy_since.get_or_insert_with(Instant::now);
+                        ui.ctx().request_repaint();
+                    }
+                    if let Some((generation, spans)) = runtime.view.spans.take() {
+                        ui.fonts_mut(|fonts| {
+                            runtime.row_cache.update_spans(
+                                fonts,
+                                spans,
+                                generation,
+                                cell_metrics,
+                                max_cols,
+                            );
+                        });
+                    }
+                    let view = &runtime.view;
+
+                    let painter = ui.painter();
+                    let cell_rect = |row: usize, start: u16, end: u16| {
+                        egui::Rect::from_min_max(
+                            egui::pos2(
+                                origin.x + f32::from(start) * cell_w,
+                                origin.y + row as f32 * cell_h,
+                            ),
+                            egui::pos2(
+                                origin.x + f32::from(end) * cell_w,
+                                origin.y + (row + 1) as f32 * cell_h,
+                            ),
+                        )
+                    };
+                    for (row, cached) in runtime.row_cache.rows().iter().enumerate() {
+                        for span in &cached.spans.backgrounds {
+                            painter.rect_filled(
+                                cell_rect(row, span.start, span.end),
+                                0.0,
+                                span.color,
+                            );
+                        }
+                        if let Some(galley) = &cached.galley {
+                            painter.galley(
+                                cell_rect(row, 0, 0).min,
+                                Arc::clone(galley),
+                                rgb_to_egui(colors.foreground),
+                            );
+                        }
+                        for run in &cached.spans.emoji {
+                            let rect = cell_rect(row, run.col, run.col + run.width);
+                            let strike = (cell_h * ppp).round() as u16;
+                            if let Some(texture) = self.emoji.texture(ui.ctx(), &run.text, strike) {
+                                // Fit the bitmap to the cell height, centred in its cells.
+                                let size = texture.size_vec2();
+                                let scale = (rect.height() / size.y).min(rect.width() / size.x);
+                                painter.image(
+                                    texture.id(),
+                                    egui::Rect::from_center_size(rect.center(), size * scale),
+                                    egui::Rect::from_min_max(
+                                        egui::pos2(0.0, 0.0),
+                                        egui::pos2(1.0, 1.0),
+                                    ),
+                                    egui::Color32::WHITE,
+                                );
+                            } else {
+                                painter.text(
+                                    rect.center(),
+                                    egui::Align2::CENTER_CENTER,
+                                    &run.text,
+                                    font_regular.clone(),
+                                    run.color,
+                                );
+                            }
+                        }
+                        for span in &cached.spans.underlines {
+                            let rect = cell_rect(row, span.start, span.end);
+                            painter.line_segment(
+                                [
+                                    egui::pos2(rect.left(), rect.bottom() - 2.0),
+                                    egui::pos2(rect.right(), rect.bottom() - 2.0),
+                                ],
+                                egui::Stroke::new(1.0, span.color),
+                            );
+                        }
+                        for run in &cached.spans.images {
+                            let (id, prow, pcol) = run.placeholder;
+                            let Some(placement) = view.placements.get(&id) else {
                                 continue;
                             };
-                            if cell.is_wide_continuation() {
-                                col += 1;
+                            let image = &placement.image;
+                            let texture = runtime.textures.entry(image.key).or_insert_with(|| {
+                                ui.ctx().load_texture(
+                                    format!("image-{}", image.key),
+                                    egui::ColorImage::from_rgba_unmultiplied(
+                                        [image.width as usize, image.height as usize],
+                                        &image.rgba,
+                                    ),
+                                    egui::TextureOptions::LINEAR,
+                                )
+                            });
+                            // The placement may cover a fractional number of cells; the last
+                            // column and row only show the part of the image they hold.
+                            let u0 = f32::from(pcol) / placement.cols;
+                            let u1 = (f32::from(pcol + run.len) / placement.cols).min(1.0);
+                            let v0 = f32::from(prow) / placement.rows;
+                            let v1 = (f32::from(prow + 1) / placement.rows).min(1.0);
+                            if u0 >= u1 || v0 >= v1 {
                                 continue;
                             }
-
-                            let is_wide = col + 1 < max_cols
-                                && screen
-                                    .cell(row, col + 1)
-                                    .map(|c| c.is_wide_continuation())
-                                    .unwrap_or(false);
-                            let span = if is_wide { 2.0 } else { 1.0 };
-
-                            let mut fg = vt_fg_to_egui(cell.fgcolor());
-                            let mut bg = vt_bg_to_egui(cell.bgcolor());
-                            if cell.inverse() {
-                                std::mem::swap(&mut fg, &mut bg);
-                            }
-                            if cell.bgcolor() != vt100::Color::Default || cell.inverse() {
-                                let x = origin.x + f32::from(col) * cell_w;
-                                let y = origin.y + f32::from(row) * cell_h;
-                                let cell_rect = egui::Rect::from_min_size(
-                                    egui::pos2(x, y),
-                                    egui::vec2(cell_w * span, cell_h),
-                                );
-                                ui.painter().rect_filled(cell_rect, 0.0, bg);
+                            let image_rect = egui::Rect::from_min_size(
+                                cell_rect(row, run.col, run.col).min,
+                                egui::vec2(
+                                    (u1 - u0) * placement.cols * cell_w,
+                                    (v1 - v0) * placement.rows * cell_h,
+                                ),
+                            );
+                            painter.image(
+                                texture.id(),
+                                image_rect,
+                                egui::Rect::from_min_max(egui::pos2(u0, v0), egui::pos2(u1, v1)),
+                                egui::Color32::WHITE,
+                            );
+                        }
+                    }
+                    let live: HashSet<u64> =
+                        view.placements.values().map(|p| p.image.key).collect();
+                    runtime.textures.retain(|key, _| live.contains(key));
+
+                    if let Some(pos) = response.hover_pos() {
+                        let col = ((pos.x - origin.x) / cell_w).floor();
+                        let row = ((pos.y - origin.y) / cell_h).floor();
+                        if col >= 0.0
+                            && row >= 0.0
+                            && (row as u16) < max_rows
+                            && (col as u16) < max_cols
+                            && let Some(emulator) = terminal::try_lock(&runtime.emulator)
+                            && let Some(link) = link_at(
+                                &emulator,
+                                &runtime.terminal,
+                                row as u16,
+                                col as u16,
+                                max_cols,
+                                self.workspace_dir.as_deref(),
+                            )
+                        {
+                            let y = origin.y + f32::from(link.row + 1) * cell_h - 1.0;
+                            ui.painter().line_segment(
+                                [
+                                    egui::pos2(origin.x + f32::from(link.start_col) * cell_w, y),
+                                    egui::pos2(origin.x + f32::from(link.end_col) * cell_w, y),
+                                ],
+                                egui::Stroke::new(1.0, rgb_to_egui(colors.foreground)),
+                            );
+                            ui.ctx().set_cursor_icon(egui::CursorIcon::PointingHand);
+                            if response.clicked()
+                                && ui.input(|i| i.modifiers.command || i.modifiers.ctrl)
+                            {
+                                clicked_link = Some(link.target);
                             }
+                        }
+                    }
 
-                            let content = cell.contents();
-                            if content.is_empty() {
-                                col += if is_wide { 2 } else { 1 };
-                                continue;
-                            }
-                            let x = origin.x + f32::from(col) * cell_w;
-                            let y = origin.y + f32::from(row) * cell_h;
+                    if is_focused
+                        && self.input_mode == InputMode::Copy
+                        && let Some(anchor) = self.copy_anchor
+                    {
+                        let selection = rgb_to_egui(colors.selection).gamma_multiply(0.6);
+                        let (start, end) =
+                            if (anchor.1, anchor.0) <= (self.copy_cursor.1, self.copy_cursor.0) {
+                                (anchor, self.copy_cursor)
+                            } else {
+                                (self.copy_cursor, anchor)
+                            };
+                        for row in start.1..=end.1.min(max_rows.saturating_sub(1)) {
+                            let x0 = if row == start.1 { start.0 } else { 0 };
+                            let x1 = if row == end.1 {
+                                end.0
+                            } else {
+                                max_cols.saturating_sub(1)
+                            };
+                            let sel_rect = egui::Rect::from_min_max(
+                                egui::pos2(
+                                    origin.x + f32::from(x0) * cell_w,
+                                    origin.y + f32::from(row) * cell_h,
+                                ),
+                                egui::pos2(
+                                    origin.x + f32::from(x1 + 1) * cell_w,
+                                    origin.y + f32::from(row + 1) * cell_h,
+                                ),
+                            );
+                            ui.painter().rect_filled(sel_rect, 0.0, selection);
+                        }
+                    }
+
+                    if is_focused {
+                        let (crow, ccol) = view.cursor;
+                        let cell_rect = egui::Rect::from_min_size(
+                            egui::pos2(
+                                origin.x + f32::from(ccol.min(max_cols.saturating_sub(1))) * cell_w,
+                                origin.y + f32::from(crow.min(max_rows.saturating_sub(1))) * cell_h,
+                            ),
+                            egui::vec2(cell_w, cell_h),
+                        );
+                        self.ime_cursor_rect = Some(cell_rect);
+
+                        if !self.ime_preedit.is_empty() {
+                            // Composition is drawn over the grid, never written to the PTY.
+                            let width = UnicodeWidthStr::width(self.ime_preedit.as_str()).max(1);
+                            let preedit_rect = egui::Rect::from_min_size(
+                                cell_rect.min,
+                                egui::vec2(cell_w * width as f32, cell_h),
+                            );
+                            ui.painter().rect_filled(
+                                preedit_rect,
+                                0.0,
+                                rgb_to_egui(colors.background),
+                            );
                             ui.painter().text(
-                                egui::pos2(x, y),
+                                preedit_rect.min,
                                 egui::Align2::LEFT_TOP,
-                                content,
-                                if cell.bold() {
-                                    font_bold.clone()
-                                } else {
-                                    font_regular.clone()
-                                },
-                                fg,
+                                &self.ime_preedit,
+                                font_regular.clone(),
+                                rgb_to_egui(colors.foreground),
+                            );
+                            ui.painter().line_segment(
+                                [
+                                    egui::pos2(preedit_rect.left(), preedit_rect.bottom() - 1.5),
+                                    egui::pos2(preedit_rect.right(), preedit_rect.bottom() - 1.5),
+                                ],
+                                egui::Stroke::new(1.5, rgb_to_egui(colors.cursor)),
                             );
-                            if cell.underline() {
-                                ui.painter().line_segment(
-                                    [
-                                        egui::pos2(x, y + cell_h - 2.0),
-                                        egui::pos2(x + (cell_w * span), y + cell_h - 2.0),
-                                    ],
-                                    egui::Stroke::new(1.0, fg),
-                                );
-                            }
-                            col += if is_wide { 2 } else { 1 };
                         }
                     }
 
-                    if pane_id == self.core.focused_pane && self.cursor_visible {
-                        let (crow, ccol) = screen.cursor_position();
+                    let active = is_focused && self.window_focused;
+                    let style = view.cursor_style;
+                    let blink_off = active && style.blink && !self.cursor_visible;
+                    let composing = active && !self.ime_preedit.is_empty();
+                    if !view.hide_cursor && !blink_off && !composing {
+                        let (crow, ccol) = view.cursor;
                         if crow < max_rows && ccol < max_cols {
-                            let mut draw_col = ccol;
-                            if let Some(cur_cell) = screen.cell(crow, ccol) {
-                                if cur_cell.is_wide_continuation() && ccol > 0 {
-                                    draw_col = ccol - 1;
-                                }
-                            }
-                            let cursor_span = if draw_col + 1 < max_cols
-                                && screen
-                                    .cell(crow, draw_col + 1)
-                                    .map(|c| c.is_wide_continuation())
-                                    .unwrap_or(false)
-                            {
-                                2.0
-                            } else {
-                                1.0
-                            };
+                            let (draw_col, cursor_span) = view.cursor_cells;
+                            let cursor_span = f32::from(cursor_span);
                             let x = origin.x + f32::from(draw_col) * cell_w;
                             let y = origin.y + f32::from(crow) * cell_h;
                             let cursor_rect = egui::Rect::from_min_size(
                                 egui::pos2(x, y),
                                 egui::vec2(cell_w * cursor_span, cell_h),
                             );
-                            ui.painter().rect_filled(
-                                cursor_rect,
-                                0.0,
-                                egui::Color32::from_rgba_unmultiplied(120, 220, 160, 28),
-                            );
-                            ui.painter().rect_stroke(
-                                cursor_rect,
-                                0.0,
-                                egui::Stroke::new(1.5, egui::Color32::from_rgb(120, 220, 160)),
-                                egui::StrokeKind::Inside,
-                            );
+                            let cursor_color = rgb_to_egui(colors.cursor);
+                            let thickness = (cell_h / 8.0).clamp(1.5, 3.0);
+                            match style.shape {
+                                // Inactive panes get a hollow box whatever the shape.
+                                _ if !active => {
+                                    ui.painter().rect_stroke(
+                                        cursor_rect,
+                                        0.0,
+                                        egui::Stroke::new(1.0, cursor_color),
+                                        egui::StrokeKind::Inside,
+                                    );
+                                }
+                                CursorShape::Block => {
+                                    let Rgb(cr, cg, cb) = colors.cursor;
+                                    ui.painter().rect_filled(
+                                        cursor_rect,
+                                        0.0,
+                                        egui::Color32::from_rgba_unmultiplied(cr, cg, cb, 28),
+                                    );
+                                    ui.painter().rect_stroke(
+                                        cursor_rect,
+                                        0.0,
+                                        egui::Stroke::new(1.5, cursor_color),
+                                        egui::StrokeKind::Inside,
+                                    );
+                                }
+                                CursorShape::Underline => {
+                                    let bar = egui::Rect::from_min_max(
+                                        egui::pos2(
+                                            cursor_rect.left(),
+                                            cursor_rect.bottom() - thickness,
+                                        ),
+                                        cursor_rect.max,
+                                    );
+                                    ui.painter().rect_filled(bar, 0.0, cursor_color);
+                                }
+                                CursorShape::Bar => {
+                                    let bar = egui::Rect::from_min_max(
+                                        cursor_rect.min,
+                                        egui::pos2(
+                                            cursor_rect.left() + thickness,
+                                            cursor_rect.bottom(),
+                                        ),
+                                    );
+                                    ui.painter().rect_filled(bar, 0.0, cursor_color);
+                                }
+                            }
                         }
                     }
                 }
             },
         );
+
+        if let Some(action) = action {
+            self.pane_action = Some((pane_id, action));
+        }
+        if self.dragging.is_some_and(|dragged| dragged != pane_id)
+            && let Some(pos) = ui.input(|i| i.pointer.latest_pos())
+            && rect.contains(pos)
+        {
+            let zone = DropZone::at(
+                (pos.x - rect.left()) / rect.width(),
+                (pos.y - rect.top()) / rect.height(),
+            );
+            let (x0, y0, x1, y1) = zone.area();
+            let area = egui::Rect::from_min_max(
+                rect.lerp_inside(egui::vec2(x0, y0)),
+                rect.lerp_inside(egui::vec2(x1, y1)),
+            );
+            let accent = egui::Color32::from_rgb(80, 200, 120);
+            ui.painter()
+                .rect_filled(area, 6.0, accent.gamma_multiply(0.25));
+            ui.painter().rect_stroke(
+                area,
+                6.0,
+                egui::Stroke::new(2.0, accent),
+                egui::StrokeKind::Inside,
+            );
+            self.drop_target = Some((pane_id, zone));
+        }
+        let (files_over, split) = ui.input(|i| {
+            (
+                !i.raw.hovered_files.is_empty() || !i.raw.dropped_files.is_empty(),
+                i.modifiers.shift,
+            )
+        });
+        if files_over
+            && ui
+                .input(|i| i.pointer.hover_pos())
+                .is_some_and(|pos| rect.contains(pos))
+        {
+            let accent = egui::Color32::from_rgb(80, 160, 240);
+            ui.painter().rect_stroke(
+                rect,
+                6.0,
+                egui::Stroke::new(3.0, accent),
+                egui::StrokeKind::Inside,
+            );
+            let hint = if split {
+                "Drop to open a split in this folder"
+            } else {
+                "Drop to insert the paths"
+            };
+            ui.painter().text(
+                rect.center(),
+                egui::Align2::CENTER_CENTER,
+                hint,
+                egui::TextStyle::Heading.resolve(ui.style()),
+                accent,
+            );
+            self.file_drop_target = Some(pane_id);
+        }
+        if let Some(target) = clicked_link
+            && let Err(err) = self.link_opener.open(&target)
+        {
+            self.core
+                .append_line_to_pane(pane_id, format!("[error] failed to open link: {err}"));
+        }
+    }
+
+    fn run_pane_action(&mut self, pane_id: usize, action: PaneAction) {
+        if !self.core.has_pane(pane_id) {
+            return;
+        }
+        match action {
+            PaneAction::Split(axis) => {
+                self.core.focused_pane = pane_id;
+                self.run_command(Command::SplitWindow { axis, dir: None });
+            }
+            PaneAction::Zoom => {
+                self.core.focused_pane = pane_id;
+                self.run_command(Command::ToggleZoom);
+            }
+            PaneAction::Close => self.run_command(Command::KillPane(Some(pane_id))),
+            PaneAction::StartRename if self.runtimes.contains_key(&pane_id) => {
+                let title = self
+                    .core
+                    .panes
+                    .iter()
+                    .find(|p| p.id == pane_id)
+                    .map(|p| p.title.clone())
+                    .unwrap_or_default();
+                self.renaming = Some((pane_id, title));
+                self.input_mode = InputMode::RenamePane;
+            }
+            PaneAction::Rename(title) => {
+                self.renaming = None;
+                let title = title.trim();
+                if title.is_empty() {
+                    self.input_mode = InputMode::Normal;
+                } else {
+                    self.run_command(Command::SelectPane {
+                        target: Some(pane_id),
+                        title: Some(title.to_string()),
+                    });
+                }
+            }
+            PaneAction::StartRename => {}
+            PaneAction::CancelRename => {
+                self.renaming = None;
+                self.input_mode = InputMode::Normal;
+            }
+            PaneAction::Respawn => {
+                // The new shell starts where the old one was.
+                if let Some(mut runtime) = self.runtimes.remove(&pane_id) {
+                    if let Some(cwd) = runtime.terminal.cwd() {
+                        self.pane_start_dirs.insert(pane_id, cwd);
+                    }
+                    // The server ends the old shell as part of the attach; killing it
+                    // first could attach to it again while it exits.
+                    if runtime.terminal.is_remote() {
+                        self.pane_respawns.insert(pane_id);
+                    } else {
+                        runtime.terminal.kill();
+                    }
+                }
+                self.spawn_runtime_for(pane_id);
+                self.set_status(format!("respawned pane {pane_id}"));
+            }
+            PaneAction::Signal(signal) => {
+                let result = match self.runtimes.get(&pane_id) {
+                    Some(runtime) => runtime.terminal.signal(signal),
+                    None => return,
+                };
+                match result {
+                    Ok(()) => self.set_status(format!("sent SIG{signal} to pane {pane_id}")),
+                    Err(err) => self.set_status(format!("{err:#}")),
+                }
+            }
+            PaneAction::CopyAll => {
+                if let Some(text) = self.pane_text(pane_id) {
+                    self.paste_buffers
+                        .push(text.clone(), BufferSource::CopyMode);
+                    self.pending_copy_text = Some(text);
+                    self.set_status(format!("copied pane {pane_id}"));
+                }
+            }
+            PaneAction::SaveScrollback => {
+                let Some(text) = self.pane_text(pane_id) else {
+                    return;
+                };
+                let Some(path) = rfd::FileDialog::new()
+                    .set_file_name(format!("pane-{pane_id}.txt"))
+                    .save_file()
+                else {
+                    return;
+                };
+                match std::fs::write(&path, text + "\n") {
+                    Ok(()) => self.set_status(format!("saved {}", path.display())),
+                    Err(err) => {
+                        self.set_status(format!("failed to save {}: {err}", path.display()))
+                    }
+                }
+            }
+            PaneAction::BindMember { team, member } => {
+                // Panes are matched to team members by title.
+                self.run_command(Command::SelectPane {
+                    target: Some(pane_id),
+                    title: Some(member.clone()),
+                });
+                self.set_status(format!("pane {pane_id} bound to {team}/{member}"));
+            }
+            PaneAction::ToggleLog => {
+                let session = self.core.name.clone();
+                let Some(runtime) = self.runtimes.get_mut(&pane_id) else {
+                    return;
+                };
+                let message = if runtime.terminal.is_logging() {
+                    runtime
+                        .terminal
+                        .set_log(None)
+                        .map(|()| format!("stopped logging pane {pane_id}"))
+                } else {
+                    engine::runtime_dir().and_then(|dir| {
+                        let path = dir.join(format!("{session}-pane-{pane_id}.log"));
+                        runtime.terminal.set_log(Some(&path))?;
+                        Ok(format!("logging pane {pane_id} to {}", path.display()))
+                    })
+                };
+                match message {
+                    Ok(message) => self.set_status(message),
+                    Err(err) => self.set_status(format!("{err:#}")),
+                }
+            }
+        }
+    }
+
+    /// Files dropped on a pane (the focused one if the pointer position is unknown) are
+    /// pasted as shell-quoted paths; with Shift held, the pane is split instead, the new
+    /// shell starting in the dropped folder (or the dropped file's folder).
+    fn handle_dropped_files(&mut self, ctx: &egui::Context) {
+        let (paths, split) = ctx.input(|i| {
+            let paths: Vec<PathBuf> = i
+                .raw
+                .dropped_files
+                .iter()
+                .filter_map(|f| f.path.clone())
+                .collect();
+            (paths, i.modifiers.shift)
+        });
+        if paths.is_empty() {
+            return;
+        }
+        let target = self
+            .file_drop_target
+            .take()
+            .filter(|id| self.core.has_pane(*id))
+            .unwrap_or(self.core.focused_pane);
+        self.core.focused_pane = target;
+        if split {
+            let dir = if paths[0].is_dir() {
+                paths[0].clone()
+            } else {
+                paths[0].parent().map(Path::to_path_buf).unwrap_or_default()
+            };
+            self.run_command(Command::SplitWindow {
+                axis: SplitAxis::Vertical,
+                dir: Some(dir.to_string_lossy().to_string()),
+            });
+            return;
+        }
+        let cwd = self
+            .drop_relative_paths
+            .then(|| self.runtimes.get(&target).and_then(|r| r.terminal.cwd()))
+            .flatten();
+        let text = terminal::quote_paths(&paths, cwd.as_deref());
+        self.paste_into_focused(&text);
+    }
+
+    /// Draws each broken-out window in a viewport of its own. Keys typed there go to its
+    /// panes; closing it puts the panes back into the main window.
+    fn show_pane_windows(&mut self, ctx: &egui::Context) {
+        let windows = self.core.windows.clone();
+        let mut closed = Vec::new();
+        for (index, layout) in windows.iter().enumerate() {
+            let leaves = layout.leaves();
+            let title = leaves
+                .iter()
+                .filter_map(|id| self.core.panes.iter().find(|p| p.id == *id))
+                .map(|p| p.title.as_str())
+                .collect::<Vec<_>>()
+                .join(" | ");
+            ctx.show_viewport_immediate(
+                egui::ViewportId::from_hash_of(("pane_window", leaves[0])),
+                egui::ViewportBuilder::default()
+                    .with_title(format!("{title} — OrchestraTerm"))
+                    .with_inner_size([900.0, 560.0]),
+                |ctx, _| {
+                    if ctx.input(|i| i.focused) {
+                        if !layout.contains(self.core.focused_pane) {
+                            self.core.focused_pane = leaves[0];
+                        }
+                        let was_normal = self.input_mode == InputMode::Normal;
+                        self.handle_shortcuts(ctx);
+                        if was_normal {
+                            self.handle_terminal_input(ctx);
+                        }
+                    }
+                    self.file_drop_target = None;
+                    egui::CentralPanel::default().show(ctx, |ui| {
+                        let rect = ui.max_rect().shrink2(egui::vec2(6.0, 6.0));
+                        self.draw_node(ui, rect, layout);
+                    });
+                    self.handle_dropped_files(ctx);
+                    if ctx.input(|i| i.viewport().close_requested()) {
+                        closed.push(index);
+                    }
+                },
+            );
+        }
+        for index in closed.into_iter().rev() {
+            self.core.join_window(index);
+        }
+    }
+
+    /// Drops a dragged pane once the button is released: onto the zone under the pointer,
+    /// or into a new window when released outside this one.
+    fn finish_pane_drag(&mut self, ctx: &egui::Context) {
+        let Some(pane) = self.dragging else {
+            return;
+        };
+        let (pos, released) = ctx.input(|i| (i.pointer.latest_pos(), !i.pointer.any_down()));
+        if !released {
+            if let Some(pos) = pos {
+                let title = self
+                    .core
+                    .panes
+                    .iter()
+                    .find(|p| p.id == pane)
+                    .map(|p| p.title.clone())
+                    .unwrap_or_default();
+                let painter = ctx.layer_painter(egui::LayerId::new(
+                    egui::Order::Tooltip,
+                    egui::Id::new("pane_drag"),
+                ));
+                painter.text(
+                    pos + egui::vec2(12.0, 12.0),
+                    egui::Align2::LEFT_TOP,
+                    title,
+                    egui::TextStyle::Button.resolve(&ctx.style()),
+                    ctx.style().visuals.strong_text_color(),
+                );
+            }
+            ctx.set_cursor_icon(egui::CursorIcon::Grabbing);
+            return;
+        }
+        self.dragging = None;
+        let moved = match self.drop_target.take() {
+            Some((target, zone)) => self.core.move_pane(pane, target, zone),
+            None if pos.is_none_or(|pos| !ctx.content_rect().contains(pos)) => {
+                let broken = self.core.break_pane(pane);
+                if broken {
+                    self.set_status(format!("pane {pane} moved to a new window"));
+                }
+                broken
+            }
+            None => false,
+        };
+        if moved {
+            self.core.focused_pane = pane;
+        }
+    }
+
+    fn pane_text(&self, pane_id: usize) -> Option<String> {
+        self.runtimes
+            .get(&pane_id)
+            .map(|r| terminal::lock(&r.emulator).text())
+    }
+
+    /// Records the focused session and pane in `engine_state`, marking it dirty only when
+    /// something actually changed.
+    fn sync_engine_state(&mut self) {
+        let mut changed = false;
+        match self.engine_state.sessions.get_mut(&self.core.name) {
+            Some(session) => {
+                if let Some(window) = session.windows.get_mut(0)
+                    && window.active_pane != self.core.focused_pane
+                {
+                    window.active_pane = self.core.focused_pane;
+                    changed = true;
+                }
+            }
+            None => {
+                self.engine_state.create_session(&self.core.name);
+                changed = true;
+            }
+        }
+        if self.engine_state.active_session.as_deref() != Some(self.core.name.as_str()) {
+            self.engine_state.active_session = Some(self.core.name.clone());
+            changed = true;
+        }
+        if changed {
+            self.mark_state_dirty();
+        }
+    }
+
+    /// Schedules `engine_state` to be saved; every change to it must call this.
+    fn mark_state_dirty(&mut self) {
+        if self.state_dirty_since.is_none() {
+            self.state_dirty_since = Some(Instant::now());
+        }
+    }
+
+    /// Sends the layout to the server when it changed, at most every
+    /// [`LAYOUT_SYNC_INTERVAL`] unless `now`, in which case it is sent before returning.
+    fn sync_server_layout(&mut self, now: bool) {
+        if !self.server_panes || (!now && self.layout_checked.elapsed() < LAYOUT_SYNC_INTERVAL) {
+            return;
+        }
+        self.layout_checked = Instant::now();
+        let Ok(raw) = serde_json::to_string(&self.core) else {
+            return;
+        };
+        if raw == self.layout_sent {
+            return;
+        }
+        self.layout_sent = raw;
+        let request = ServerRequest::SaveLayout {
+            layout: self.core.clone(),
+        };
+        if now {
+            let _ = server::send_request(&request);
+        } else {
+            std::thread::spawn(move || server::send_request(&request));
+        }
+    }
+
+    /// Detaches from the current session's panes, which keep running in the server, and
+    /// shows `name` as it was left, or a new session. Without the server the session is
+    /// only renamed.
+    fn attach_session(&mut self, name: String) {
+        self.set_status(format!("attached to session {name}"));
+        if !self.server_panes {
+            self.core.name = name;
+            return;
+        }
+        self.sync_server_layout(true);
+        self.runtimes.clear();
+        self.renaming = None;
+        self.dragging = None;
+        self.core = load_layout(&name).unwrap_or_else(|| SessionCore::new(name));
+        self.sync_runtimes();
+    }
+
+    /// Saves once changes have settled for [`STATE_SAVE_DELAY`], or right away if `force`,
+    /// and otherwise wakes the loop when the delay runs out.
+    fn save_state_if_due(&mut self, force: bool) {
+        let Some(since) = self.state_dirty_since else {
+            return;
+        };
+        let elapsed = since.elapsed();
+        if !force && elapsed < STATE_SAVE_DELAY {
+            self.ctx.request_repaint_after(STATE_SAVE_DELAY - elapsed);
+            return;
+        }
+        self.state_dirty_since = None;
+        if let Err(err) = self.engine_state.save() {
+            self.core
+                .append_line_focused(format!("[error] failed to save state: {err}"));
+        }
+    }
+
+    /// Input and PTY output already trigger frames; the only timer is the blinking cursor,
+    /// and it only runs while a blinking cursor is actually on screen.
+    fn schedule_cursor_blink(&mut self, ctx: &egui::Context) {
+        let blinking = self.window_focused
+            && !self.config.cursor_blink.is_zero()
+            && self.ime_preedit.is_empty()
+            && self
+                .runtimes
+                .get(&self.core.focused_pane)
+                .is_some_and(|r| r.view.cursor_style.blink && !r.view.hide_cursor);
+        if blinking {
+            ctx.request_repaint_after(
+                self.config
+                    .cursor_blink
+                    .saturating_sub(self.last_cursor_toggle.elapsed()),
+            );
+        } else {
+            self.cursor_visible = true;
+        }
+    }
+
+    fn show_buffer_picker(&mut self, ctx: &egui::Context) {
+        let mut chosen = None;
+        egui::Window::new("Paste Buffers")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_TOP, egui::vec2(0.0, 80.0))
+            .show(ctx, |ui| {
+                if self.paste_buffers.is_empty() {
+                    ui.label("No buffers yet: copy in copy mode or via OSC 52");
+                }
+                for (i, buffer) in self.paste_buffers.iter().enumerate() {
+                    let label = format!(
+                        "{}  ({} bytes)  {}",
+                        buffer.name,
+                        buffer.text.len(),
+                        buffer.preview(60)
+                    );
+                    let selected = i == self.buffer_selection;
+                    if ui
+                        .add(egui::Button::selectable(
+                            selected,
+                            egui::RichText::new(label).monospace(),
+                        ))
+                        .clicked()
+                    {
+                        chosen = Some(i);
+                    }
+                }
+                ui.separator();
+                ui.label("↑/↓ select · Enter paste · Delete remove · Esc close");
+            });
+        if let Some(i) = chosen {
+            self.buffer_selection = i;
+            self.run_command(Command::BufferPickerPaste);
+        }
+    }
+
+    fn palette_items(&self) -> Vec<palette::Item> {
+        let panes: Vec<PaneInfo> = self
+            .core
+            .panes
+            .iter()
+            .map(|pane| {
+                let terminal = self.runtimes.get(&pane.id).map(|r| &r.terminal);
+                PaneInfo {
+                    id: pane.id,
+                    title: pane.title.clone(),
+                    cwd: terminal
+                        .and_then(PaneTerminal::cwd)
+                        .map(|p| p.to_string_lossy().to_string()),
+                    command: terminal.and_then(PaneTerminal::foreground_command),
+                }
+            })
+            .collect();
+        let mut items = palette::action_items(&self.keymap);
+        items.extend(palette::pane_items(&panes));
+        items.extend(palette::session_items(&self.engine_state, &self.core.name));
+        items.extend(palette::team_items(&self.engine_state));
+        items
+    }
+
+    fn choose_palette_item(&mut self, item: palette::Item) {
+        self.input_mode = InputMode::Normal;
+        match item.target {
+            Target::Command(command) => self.run_command(command),
+            Target::Pane(id) => self.run_command(Command::SelectPane {
+                target: Some(id),
+                title: None,
+            }),
+            Target::Session(name) => {
+                if name != self.core.name {
+                    self.attach_session(name);
+                }
+            }
+            Target::Member { team, member } => {
+                let name = self
+                    .engine_state
+                    .teams
+                    .get(&team)
+                    .and_then(|t| t.members.iter().find(|m| m.id == member))
+                    .map(|m| m.name.clone());
+                self.focus_member_pane(&team, name);
+            }
+            Target::Task { team, task } => {
+                let name = self.engine_state.teams.get(&team).and_then(|t| {
+                    let assignee = t.tasks.iter().find(|entry| entry.id == task)?.assignee?;
+                    t.members
+                        .iter()
+                        .find(|m| m.id == assignee)
+                        .map(|m| m.name.clone())
+                });
+                self.focus_member_pane(&team, name);
+            }
+        }
+    }
+
+    /// Focuses the pane titled after a team member, if there is one.
+    fn focus_member_pane(&mut self, team: &str, member: Option<String>) {
+        let Some(member) = member else {
+            self.set_status(format!("{team}: nobody is assigned"));
+            return;
+        };
+        match self
+            .core
+            .panes
+            .iter()
+            .find(|p| p.title.eq_ignore_ascii_case(&member))
+        {
+            Some(pane) => self.core.focused_pane = pane.id,
+            None => self.set_status(format!("{team}: no pane is titled {member}")),
+        }
+    }
+
+    fn show_palette(&mut self, ctx: &egui::Context) {
+        let (up, down, enter, escape) = ctx.input_mut(|i| {
+            (
+                i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowUp),
+                i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowDown),
+                i.consume_key(egui::Modifiers::NONE, egui::Key::Enter),
+                i.consume_key(egui::Modifiers::NONE, egui::Key::Escape),
+            )
+        });
+        if escape {
+            self.input_mode = InputMode::Normal;
+            return;
+        }
+        let results = self.palette.results();
+        if up {
+            self.palette.move_selection(-1, results.len());
+        }
+        if down {
+            self.palette.move_selection(1, results.len());
+        }
+        if enter {
+            self.input_mode = InputMode::Normal;
+            if let Some(item) = self.palette.choose() {
+                self.choose_palette_item(item);
+            }
+            return;
+        }
+
+        let mut clicked = None;
+        let query_before = self.palette.query.clone();
+        egui::Window::new("Command Palette")
+            .title_bar(false)
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_TOP, egui::vec2(0.0, 60.0))
+            .fixed_size(egui::vec2(560.0, 0.0))
+            .show(ctx, |ui| {
+                ui.add(
+                    egui::TextEdit::singleline(&mut self.palette.query)
+                        .hint_text("Search actions, panes, sessions, teams…")
+                        .desired_width(f32::INFINITY),
+                )
+                .request_focus();
+                ui.separator();
+                if results.is_empty() {
+                    ui.label("No matches");
+                }
+                let font = egui::TextStyle::Body.resolve(ui.style());
+                let normal = ui.visuals().text_color();
+                let weak = ui.visuals().weak_text_color();
+                egui::ScrollArea::vertical()
+                    .max_height(420.0)
+                    .show(ui, |ui| {
+                        for (row, (index, positions)) in results.iter().enumerate() {
+                            let item = &self.palette.items[*index];
+                            let mut job = egui::text::LayoutJob::default();
+                            let format = |color| egui::TextFormat::simple(font.clone(), color);
+                            job.append(&format!("{:<8}", item.kind.label()), 0.0, format(weak));
+                            for (i, c) in item.label.chars().enumerate() {
+                                let color = if positions.contains(&i) {
+                                    egui::Color32::YELLOW
+                                } else {
+                                    normal
+                                };
+                                job.append(c.encode_utf8(&mut [0; 4]), 0.0, format(color));
+                            }
+                            job.append(&format!("   {}", item.detail), 0.0, format(weak));
+                            let selected = row == self.palette.selected;
+                            let response = ui.add(egui::Button::selectable(selected, job));
+                            if selected && (up || down) {
+                                response.scroll_to_me(None);
+                            }
+                            if response.clicked() {
+                                clicked = Some(row);
+                            }
+                        }
+                    });
+            });
+        if self.palette.query != query_before {
+            self.palette.selected = 0;
+        }
+        if let Some(row) = clicked {
+            self.palette.selected = row;
+            self.input_mode = InputMode::Normal;
+            if let Some(item) = self.palette.choose() {
+                self.choose_palette_item(item);
+            }
+        }
+    }
+
+    fn completion_context(&self) -> CompletionContext {
+        CompletionContext {
+            panes: self.core.pane_ids(),
+            buffers: self.paste_buffers.iter().map(|b| b.name.clone()).collect(),
+            teams: self.engine_state.teams.keys().cloned().collect(),
+        }
+    }
+
+    /// The `:` prompt, or the last command's result while it is fresh.
+    fn show_prompt(&mut self, ctx: &egui::Context) {
+        let status = self
+            .status_message
+            .as_ref()
+            .filter(|(_, at)| at.elapsed() < STATUS_DURATION)
+            .map(|(message, _)| message.clone());
+        if self.input_mode != InputMode::CommandPrompt {
+            if let Some(message) = status {
+                egui::TopBottomPanel::bottom("command_prompt").show(ctx, |ui| {
+                    ui.label(message);
+                });
+            }
+            return;
+        }
+
+        let (tab, up, down, enter, escape) = ctx.input_mut(|i| {
+            (
+                i.consume_key(egui::Modifiers::NONE, egui::Key::Tab),
+                i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowUp),
+                i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowDown),
+                i.consume_key(egui::Modifiers::NONE, egui::Key::Enter),
+                i.consume_key(egui::Modifiers::NONE, egui::Key::Escape),
+            )
+        });
+        if escape {
+            self.input_mode = InputMode::Normal;
+            return;
+        }
+        if enter {
+            self.input_mode = InputMode::Normal;
+            let line = self.prompt.submit();
+            self.run_line(&line);
+            return;
+        }
+        if tab {
+            let context = self.completion_context();
+            self.prompt.complete(&context);
+        }
+        if up {
+            self.prompt.history_prev();
+        }
+        if down {
+            self.prompt.history_next();
+        }
+
+        egui::TopBottomPanel::bottom("command_prompt").show(ctx, |ui| {
+            if !self.prompt.completions.is_empty() {
+                ui.monospace(self.prompt.completions.join("  "));
+            }
+            ui.horizontal(|ui| {
+                ui.monospace(":");
+                let mut edit = egui::TextEdit::singleline(&mut self.prompt.input)
+                    .font(egui::TextStyle::Monospace)
+                    .desired_width(f32::INFINITY)
+                    .show(ui);
+                if std::mem::take(&mut self.prompt_opened) {
+                    // The key that opened the prompt arrives as text in the same frame.
+                    self.prompt.input.clear();
+                }
+                if tab || up || down {
+                    let end = egui::text::CCursor::new(self.prompt.input.chars().count());
+                    edit.state
+                        .cursor
+                        .set_char_range(Some(egui::text::CCursorRange::one(end)));
+                    edit.state.store(ui.ctx(), edit.response.id);
+                }
+                edit.response.request_focus();
+            });
+        });
+    }
+
+    /// Reruns the status `#(command)`s and rereads the teams every `status.interval`, on a
+    /// background thread, and wakes the loop for the next refresh so the clock moves.
+    fn refresh_status(&mut self) {
+        if self
+            .status_pane_vars
+            .as_ref()
+            .is_none_or(|(id, ..)| *id != self.core.focused_pane)
+        {
+            self.refresh_pane_vars();
+        }
+        let interval = self.config.status.interval;
+        if let Some(at) = self.status_refreshed
+            && at.elapsed() < interval
+        {
+            self.ctx.request_repaint_after(interval - at.elapsed());
+            return;
+        }
+        if self.status_refreshing {
+            return;
+        }
+        self.status_refreshing = true;
+        self.status_refreshed = Some(Instant::now());
+        self.refresh_pane_vars();
+        self.ctx.request_repaint_after(interval);
+
+        let commands = self.config.status.commands();
+        let tx = self.incoming_tx.clone();
+        let ctx = self.ctx.clone();
+        std::thread::spawn(move || {
+            let output = commands
+                .into_iter()
+                .map(|command| {
+                    let text = status::run_command(&command, status::COMMAND_TIMEOUT);
+                    (command, text)
+                })
+                .collect();
+            let refreshed = Incoming::StatusRefreshed {
+                output,
+                utc_offset: status::utc_offset(),
+                teams: EngineState::load_or_default().teams,
+            };
+            if tx.send(refreshed).is_ok() {
+                ctx.request_repaint();
+            }
+        });
+    }
+
+    /// Looks up the focused pane's `#{cwd}` and `#{command}`, each only if a template
+    /// shows it.
+    fn refresh_pane_vars(&mut self) {
+        let pane_id = self.core.focused_pane;
+        let terminal = self.runtimes.get(&pane_id).map(|r| &r.terminal);
+        let status = &self.config.status;
+        let cwd = terminal
+            .filter(|_| status.uses("cwd"))
+            .and_then(PaneTerminal::cwd)
+            .map(|p| status::abbreviate_home(&p))
+            .unwrap_or_default();
+        let command = terminal
+            .filter(|_| status.uses("command"))
+            .and_then(PaneTerminal::foreground_command)
+            .unwrap_or_default();
+        self.status_pane_vars = Some((pane_id, cwd, command));
+    }
+
+    /// What the status templates' variables expand to this frame.
+    fn status_vars(&self) -> HashMap<&'static str, String> {
+        let pane_id = self.core.focused_pane;
+        let pane_title = self
+            .core
+            .panes
+            .iter()
+            .find(|p| p.id == pane_id)
+            .map(|p| p.title.clone())
+            .unwrap_or_default();
+        let (cwd, command) = self
+            .status_pane_vars
+            .as_ref()
+            .map(|(_, cwd, command)| (cwd.clone(), command.clone()))
+            .unwrap_or_default();
+        let unix_secs = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or_default();
+        let (date, time) = status::date_and_time(unix_secs, self.utc_offset);
+
+        let mut vars = HashMap::from([
+            ("session", self.core.name.clone()),
+            (
+                "window",
+                self.window_title
+                    .as_deref()
+                    .unwrap_or("OrchestraTerm")
+                    .to_string(),
+            ),
+            ("mode", self.mode_label()),
+            ("pane_id", pane_id.to_string()),
+            ("panes", self.core.panes.len().to_string()),
+            (
+                "zoomed",
+                if self.core.zoomed { "1" } else { "" }.to_string(),
+            ),
+            ("cwd", cwd),
+            ("command", command),
+            (
+                "workspace",
+                self.workspace_dir
+                    .as_deref()
+                    .map(status::abbreviate_home)
+                    .unwrap_or_default(),
+            ),
+            ("date", date),
+            ("time", time),
+        ]);
+        status::team_vars(&self.engine_state, &pane_title, &mut vars);
+        vars.insert("pane_title", pane_title);
+        vars
+    }
+
+    /// The input mode as shown by `#{mode}`; empty in normal mode.
+    fn mode_label(&self) -> String {
+        match self.input_mode {
+            InputMode::Normal | InputMode::Palette => String::new(),
+            InputMode::KeyTable => {
+                let label = if self.key_table == Mode::Prefix.table_name() {
+                    let prefix = self.keymap.prefix().map(|c| keymap::format_chord(&c));
+                    format!("PREFIX ({})", prefix.unwrap_or_default())
+                } else {
+                    format!("KEY TABLE: {}", self.key_table)
+                };
+                let repeat = if self.repeat_until.is_some() {
+                    " · repeat"
+                } else {
+                    ""
+                };
+                format!("{label}{repeat}")
+            }
+            InputMode::Copy => format!("COPY ({}, {})", self.copy_cursor.0, self.copy_cursor.1),
+            InputMode::CopySearch => format!("COPY SEARCH: /{}", self.copy_search_input),
+            InputMode::BufferPicker => "CHOOSE BUFFER".to_string(),
+            InputMode::CommandPrompt => "PROMPT".to_string(),
+            InputMode::RenamePane => "RENAME".to_string(),
+        }
+    }
+
+    /// The left, centre and right status templates, rendered in the theme's colours.
+    fn status_jobs(&self, ui: &egui::Ui) -> [egui::text::LayoutJob; 3] {
+        let vars = self.status_vars();
+        let status = &self.config.status;
+        let font = egui::TextStyle::Body.resolve(ui.style());
+        let plain = ui.visuals().text_color();
+        let strong = ui.visuals().strong_text_color();
+        [&status.left, &status.centre, &status.right].map(|template| {
+            let mut job = egui::text::LayoutJob::default();
+            for (text, style) in template.render(&vars, &self.status_output) {
+                job.append(&text, 0.0, self.status_format(style, &font, plain, strong));
+            }
+            job
+        })
+    }
+
+    fn status_format(
+        &self,
+        style: Style,
+        font: &egui::FontId,
+        plain: egui::Color32,
+        strong: egui::Color32,
+    ) -> egui::TextFormat {
+        // Bold shows the bright variant of a palette colour, as terminals do.
+        let fg = match style.fg {
+            Some(Color::Palette(i)) if style.bold && i < 8 => {
+                Some(Color::Palette(i + 8).resolve(&self.theme))
+            }
+            Some(color) => Some(color.resolve(&self.theme)),
+            None => None,
+        };
+        let mut format = egui::TextFormat::simple(
+            font.clone(),
+            match fg {
+                Some(rgb) => rgb_to_egui(rgb),
+                None if style.bold => strong,
+                None => plain,
+            },
+        );
+        if let Some(bg) = style.bg {
+            format.background = rgb_to_egui(bg.resolve(&self.theme));
+        }
+        format
+    }
+
+    /// Lists the keys the active key table accepts, like which-key.
+    fn show_which_key(&self, ctx: &egui::Context) {
+        let entries = self.keymap.continuations(&self.key_table);
+        egui::Area::new(egui::Id::new("which_key"))
+            .anchor(egui::Align2::CENTER_BOTTOM, egui::vec2(0.0, -24.0))
+            .interactable(false)
+            .show(ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    if entries.is_empty() {
+                        ui.label(format!("{}: no bindings", self.key_table));
+                        return;
+                    }
+                    let columns = entries.len().div_ceil(12);
+                    egui::Grid::new("which_key_grid")
+                        .num_columns(columns * 2)
+                        .spacing(egui::vec2(12.0, 2.0))
+                        .show(ui, |ui| {
+                            let rows = entries.len().div_ceil(columns);
+                            for row in 0..rows {
+                                for column in 0..columns {
+                                    if let Some((key, action)) = entries.get(column * rows + row) {
+                                        ui.monospace(
+                                            egui::RichText::new(key).color(egui::Color32::YELLOW),
+                                        );
+                                        ui.label(action);
+                                    }
+                                }
+                                ui.end_row();
+                            }
+                        });
+                });
+            });
     }
 }
 
 impl eframe::App for OrchestraApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        if self.last_cursor_toggle.elapsed() >= Duration::from_millis(530) {
+        if !self.config.cursor_blink.is_zero()
+            && self.last_cursor_toggle.elapsed() >= self.config.cursor_blink
+        {
             self.cursor_visible = !self.cursor_visible;
             self.last_cursor_toggle = Instant::now();
         }
 
+        // Set again by whichever pane the files are over this frame; a drop elsewhere, or
+        // with no pointer position, goes to the focused pane.
+        self.file_drop_target = None;
         self.sync_runtimes();
+        self.window_focused = ctx.input(|i| i.focused);
+        self.sync_focus_reports();
         self.poll_runtime_output();
+        self.poll_incoming();
+        // A key read by a key table or copy mode must not reach the terminal as well, even
+        // when its binding hands back to normal mode.
+        self.expire_key_table();
+        let was_normal = self.input_mode == InputMode::Normal;
         self.handle_shortcuts(ctx);
-        self.handle_terminal_input(ctx);
+        if was_normal {
+            self.handle_terminal_input(ctx);
+        }
 
+        self.refresh_status();
         egui::TopBottomPanel::top("header").show(ctx, |ui| {
+            let [left, centre, right] = self.status_jobs(ui);
             ui.horizontal(|ui| {
-                ui.heading("OrchestraTerm");
-                ui.separator();
-                ui.label(format!("Session: {}", self.core.name));
-                ui.separator();
-                ui.label(format!("Focused: {}", self.core.focused_pane));
-                ui.separator();
-                ui.label(format!("Panes: {}", self.core.panes.len()));
-                ui.separator();
-                if let Some(path) = &self.workspace_dir {
-                    ui.label(format!("Workspace: {}", path.display()));
-                } else {
-                    ui.label("Workspace: (not selected)");
-                }
-                if ui.button("Open Folder").clicked() {
-                    self.open_folder();
-                }
-                if self.input_mode == InputMode::Prefix {
-                    ui.colored_label(egui::Color32::YELLOW, "PREFIX MODE (Ctrl+B)");
-                } else if self.input_mode == InputMode::Copy {
-                    ui.colored_label(
-                        egui::Color32::LIGHT_BLUE,
-                        format!(
-                            "COPY MODE (cursor: {}, {})",
-                            self.copy_cursor.0, self.copy_cursor.1
-                        ),
-                    );
-                } else if self.input_mode == InputMode::CopySearch {
-                    ui.colored_label(
-                        egui::Color32::LIGHT_BLUE,
-                        format!("COPY SEARCH: /{}", self.copy_search_input),
-                    );
-                }
+                ui.add(egui::Label::new(left).truncate());
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    if ui.button("Open Folder").clicked() {
+                        self.open_folder();
+                    }
+                    ui.add(egui::Label::new(right).truncate());
+                    ui.centered_and_justified(|ui| {
+                        ui.add(egui::Label::new(centre).truncate());
+                    });
+                });
             });
         });
 
@@ -719,13 +2746,123 @@ impl eframe::App for OrchestraApp {
                             ui.selectable_value(&mut self.render_preset, preset, preset.label());
                         }
                     });
+                ui.label(format!(
+                    "Font: {} at {:.1}pt ({:.0}%)",
+                    self.resolved_fonts.describe(),
+                    self.font_size(),
+                    self.font_zoom * 100.0
+                ));
+                if !self.resolved_fonts.missing.is_empty() {
+                    ui.colored_label(
+                        egui::Color32::YELLOW,
+                        format!(
+                            "Fonts not found: {}",
+                            self.resolved_fonts.missing.join(", ")
+                        ),
+                    );
+                }
+                let mut selected = None;
+                egui::ComboBox::from_label("Theme")
+                    .selected_text(self.theme.name.clone())
+                    .show_ui(ui, |ui| {
+                        for theme in &self.themes {
+                            if ui
+                                .selectable_label(theme.name == self.theme.name, &theme.name)
+                                .clicked()
+                            {
+                                selected = Some(theme.clone());
+                            }
+                        }
+                    });
+                if ui.button("Reload Themes").clicked() {
+                    self.themes = Theme::discover();
+                }
+                if let Some(theme) = selected {
+                    self.set_theme(theme);
+                }
                 ui.separator();
-                for sc in SHORTCUTS {
+                for (keys, action) in self.keymap.describe() {
                     ui.horizontal(|ui| {
-                        ui.monospace(sc.key);
-                        ui.label(sc.action);
+                        ui.monospace(keys);
+                        ui.label(action);
                     });
                 }
+                for error in &self.keymap_errors {
+                    ui.colored_label(egui::Color32::LIGHT_RED, format!("keymap: {error}"));
+                }
+                if ui.button("Reload Keymap").clicked() {
+                    (self.keymap, self.keymap_errors) = Keymap::load();
+                }
+                ui.separator();
+                ui.heading("Config");
+                let files = self
+                    .config_files
+                    .lock()
+                    .map(|f| f.clone())
+                    .unwrap_or_default();
+                for path in files {
+                    let state = if path.exists() { "" } else { " (not found)" };
+                    ui.label(format!("{}{state}", path.display()));
+                }
+                for error in &self.config_errors {
+                    ui.colored_label(egui::Color32::LIGHT_RED, format!("config: {error}"));
+                }
+                if ui.button("Reload Config").clicked() {
+                    self.reload_config();
+                }
+                ui.separator();
+                ui.heading("Links");
+                ui.label("Ctrl/Cmd-click opens URLs and file:line:col references");
+                ui.horizontal(|ui| {
+                    ui.label("URL");
+                    ui.text_edit_singleline(&mut self.link_opener.url_command);
+                });
+                ui.horizontal(|ui| {
+                    ui.label("File");
+                    ui.text_edit_singleline(&mut self.link_opener.file_command);
+                });
+                ui.separator();
+                ui.heading("Clipboard");
+                egui::ComboBox::from_label("OSC 52")
+                    .selected_text(self.clipboard_access.label())
+                    .show_ui(ui, |ui| {
+                        for access in ClipboardAccess::all() {
+                            ui.selectable_value(&mut self.clipboard_access, access, access.label());
+                        }
+                    });
+                ui.label(format!("{} paste buffers", self.paste_buffers.len()));
+                ui.checkbox(
+                    &mut self.drop_relative_paths,
+                    "Dropped paths relative to the pane's directory",
+                );
+                ui.separator();
+                ui.heading("Bell");
+                if let Some(runtime) = self.runtimes.get_mut(&self.core.focused_pane) {
+                    let policy = &mut runtime.bell.policy;
+                    ui.checkbox(&mut policy.flash, "Flash pane border");
+                    ui.checkbox(&mut policy.badge, "Badge unfocused pane");
+                    ui.checkbox(&mut policy.attention, "Request window attention");
+                    if policy.is_none() {
+                        ui.label("Bells here are only reported to server subscribers");
+                    }
+                }
+                ui.separator();
+                ui.heading("Text");
+                let mut ambiguous = self.ambiguous_width;
+                egui::ComboBox::from_label("Ambiguous width")
+                    .selected_text(ambiguous.label())
+                    .show_ui(ui, |ui| {
+                        for policy in AmbiguousWidth::all() {
+                            ui.selectable_value(&mut ambiguous, policy, policy.label());
+                        }
+                    });
+                if ambiguous != self.ambiguous_width {
+                    self.set_ambiguous_width(ambiguous);
+                }
+                ui.label(match self.emoji.family() {
+                    Some(family) => format!("Colour emoji: {family}"),
+                    None => "Colour emoji: no bitmap emoji font found".to_string(),
+                });
                 ui.separator();
                 ui.heading("Team Modes");
                 if self.engine_state.teams.is_empty() {
@@ -740,115 +2877,248 @@ impl eframe::App for OrchestraApp {
                 }
             });
 
+        if self.input_mode == InputMode::BufferPicker {
+            self.show_buffer_picker(ctx);
+        }
+        if self.input_mode == InputMode::KeyTable && self.keymap.which_key {
+            self.show_which_key(ctx);
+        }
+        if self.input_mode == InputMode::Palette {
+            self.show_palette(ctx);
+        }
+        self.show_prompt(ctx);
+
+        self.drop_target = None;
         egui::CentralPanel::default().show(ctx, |ui| {
             let rect = ui.max_rect().shrink2(egui::vec2(6.0, 6.0));
-            let layout = if self.core.zoomed {
+            let layout = if self.core.zoomed && self.core.layout.contains(self.core.focused_pane) {
                 LayoutNode::Leaf(self.core.focused_pane)
             } else {
                 self.core.layout.clone()
             };
             self.draw_node(ui, rect, &layout);
         });
+        self.handle_dropped_files(ctx);
+        self.show_pane_windows(ctx);
+        self.finish_pane_drag(ctx);
+        if let Some((pane_id, action)) = self.pane_action.take() {
+            self.run_pane_action(pane_id, action);
+        }
+        if self.input_mode == InputMode::RenamePane
+            && !self
+                .renaming
+                .as_ref()
+                .is_some_and(|(id, _)| self.runtimes.contains_key(id))
+        {
+            // The pane went away while its title was being edited.
+            self.renaming = None;
+            self.input_mode = InputMode::Normal;
+        }
+
+        // Keep the platform IME enabled for the terminal and anchor its candidate window
+        // to the focused cursor cell.
+        if self.input_mode == InputMode::Normal
+            && !ctx.wants_keyboard_input()
+            && let Some(cursor_rect) = self.ime_cursor_rect.take()
+        {
+            ctx.output_mut(|o| {
+                o.ime = Some(egui::output::IMEOutput {
+                    rect: cursor_rect,
+                    cursor_rect,
+                });
+            });
+        }
 
         if let Some(text) = self.pending_copy_text.take() {
             ctx.copy_text(text);
         }
 
-        if let Some(session) = self.engine_state.sessions.get_mut(&self.core.name) {
-            if let Some(window) = session.windows.get_mut(0) {
-                window.active_pane = self.core.focused_pane;
-            }
-        } else {
-            self.engine_state.create_session(&self.core.name);
-        }
-        self.engine_state.active_session = Some(self.core.name.clone());
-        let _ = self.engine_state.save();
+        self.sync_engine_state();
+        self.save_state_if_due(false);
+        self.sync_server_layout(false);
+        self.schedule_cursor_blink(ctx);
+    }
 
-        ctx.request_repaint();
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        self.save_state_if_due(true);
+        self.sync_server_layout(true);
     }
 }
 
-impl OrchestraApp {}
-
-fn ctrl_key_to_byte(key: egui::Key) -> Option<u8> {
-    match key {
-        egui::Key::A => Some(0x01),
-        egui::Key::B => Some(0x02),
-        egui::Key::C => Some(0x03),
-        egui::Key::D => Some(0x04),
-        egui::Key::E => Some(0x05),
-        egui::Key::F => Some(0x06),
-        egui::Key::G => Some(0x07),
-        egui::Key::H => Some(0x08),
-        egui::Key::I => Some(0x09),
-        egui::Key::J => Some(0x0a),
-        egui::Key::K => Some(0x0b),
-        egui::Key::L => Some(0x0c),
-        egui::Key::M => Some(0x0d),
-        egui::Key::N => Some(0x0e),
-        egui::Key::O => Some(0x0f),
-        egui::Key::P => Some(0x10),
-        egui::Key::Q => Some(0x11),
-        egui::Key::R => Some(0x12),
-        egui::Key::S => Some(0x13),
-        egui::Key::T => Some(0x14),
-        egui::Key::U => Some(0x15),
-        egui::Key::V => Some(0x16),
-        egui::Key::W => Some(0x17),
-        egui::Key::X => Some(0x18),
-        egui::Key::Y => Some(0x19),
-        egui::Key::Z => Some(0x1a),
-        _ => None,
+/// The right-click menu of a pane.
+fn pane_menu(
+    ui: &mut egui::Ui,
+    teams: &BTreeMap<String, AgentTeam>,
+    logging: bool,
+) -> Option<PaneAction> {
+    let mut action = None;
+    if ui.button("Rename").clicked() {
+        action = Some(PaneAction::StartRename);
+    }
+    if ui.button("Respawn").clicked() {
+        action = Some(PaneAction::Respawn);
+    }
+    ui.menu_button("Send Signal", |ui| {
+        for signal in SIGNALS {
+            if ui.button(format!("SIG{signal}")).clicked() {
+                action = Some(PaneAction::Signal(signal));
+            }
+        }
+    });
+    ui.separator();
+    if ui.button("Copy All").clicked() {
+        action = Some(PaneAction::CopyAll);
+    }
+    if ui.button("Save Scrollback…").clicked() {
+        action = Some(PaneAction::SaveScrollback);
+    }
+    ui.separator();
+    ui.menu_button("Bind to Team Member", |ui| {
+        if teams.is_empty() {
+            ui.label("No teams");
+        }
+        for team in teams.values() {
+            ui.menu_button(&team.id, |ui| {
+                for member in &team.members {
+                    if member.status == MemberStatus::Active && ui.button(&member.name).clicked() {
+                        action = Some(PaneAction::BindMember {
+                            team: team.id.clone(),
+                            member: member.name.clone(),
+                        });
+                    }
+                }
+            });
+        }
+    });
+    let log = if logging {
+        "Stop Logging"
+    } else {
+        "Start Logging"
+    };
+    if ui.button(log).clicked() {
+        action = Some(PaneAction::ToggleLog);
+    }
+    if action.is_some() {
+        ui.close();
     }
+    action
 }
 
-fn vt_fg_to_egui(color: vt100::Color) -> egui::Color32 {
-    match color {
-        vt100::Color::Default => egui::Color32::from_rgb(210, 220, 230),
-        vt100::Color::Rgb(r, g, b) => egui::Color32::from_rgb(r, g, b),
-        vt100::Color::Idx(idx) => ansi256_to_egui(idx),
-    }
+/// The config files to watch: the user file and the workspace's override file.
+fn config_paths(workspace: Option<&Path>) -> Vec<PathBuf> {
+    config::config_files(workspace)
+        .into_iter()
+        .map(|(path, _)| path)
+        .collect()
 }
 
-fn vt_bg_to_egui(color: vt100::Color) -> egui::Color32 {
-    match color {
-        vt100::Color::Default => egui::Color32::from_rgb(22, 25, 29),
-        vt100::Color::Rgb(r, g, b) => egui::Color32::from_rgb(r, g, b),
-        vt100::Color::Idx(idx) => ansi256_to_egui(idx),
-    }
+/// Reports edits to the config files, polling their modification times. A change of
+/// the file list itself (a new workspace) is not reported; the GUI reloads for that.
+fn watch_config(ctx: egui::Context, tx: Sender<Incoming>, files: Arc<Mutex<Vec<PathBuf>>>) {
+    std::thread::spawn(move || {
+        let mut last: Option<(Vec<PathBuf>, Vec<_>)> = None;
+        loop {
+            let paths = files.lock().map(|f| f.clone()).unwrap_or_default();
+            let times = config::modified_times(&paths);
+            if let Some((last_paths, last_times)) = &last
+                && *last_paths == paths
+                && *last_times != times
+            {
+                if tx.send(Incoming::ConfigChanged).is_err() {
+                    return;
+                }
+                ctx.request_repaint();
+            }
+            last = Some((paths, times));
+            std::thread::sleep(CONFIG_POLL);
+        }
+    });
+}
+
+/// The layout the server holds for `session`, if any.
+fn load_layout(session: &str) -> Option<SessionCore> {
+    let request = ServerRequest::LoadLayout {
+        session: session.to_string(),
+    };
+    server::send_request(&request).ok()?.layout
 }
 
-fn ansi256_to_egui(i: u8) -> egui::Color32 {
-    const BASE: [(u8, u8, u8); 16] = [
-        (0, 0, 0),
-        (205, 49, 49),
-        (13, 188, 121),
-        (229, 229, 16),
-        (36, 114, 200),
-        (188, 63, 188),
-        (17, 168, 205),
-        (229, 229, 229),
-        (102, 102, 102),
-        (241, 76, 76),
-        (35, 209, 139),
-        (245, 245, 67),
-        (59, 142, 234),
-        (214, 112, 214),
-        (41, 184, 219),
-        (255, 255, 255),
-    ];
-    if i < 16 {
-        let (r, g, b) = BASE[usize::from(i)];
-        return egui::Color32::from_rgb(r, g, b);
-    }
-    if (16..=231).contains(&i) {
-        let idx = i - 16;
-        let r = idx / 36;
-        let g = (idx % 36) / 6;
-        let b = idx % 6;
-        let map = |v: u8| if v == 0 { 0 } else { 55 + v * 40 };
-        return egui::Color32::from_rgb(map(r), map(g), map(b));
-    }
-    let gray = 8 + (i - 232) * 10;
-    egui::Color32::from_rgb(gray, gray, gray)
+/// Forwards commands sent with `orchestraterm command` to the GUI, resubscribing while the
+/// server is down.
+fn listen_for_commands(ctx: egui::Context, tx: Sender<Incoming>) {
+    std::thread::spawn(move || {
+        loop {
+            let _ = server::subscribe(|event| {
+                if let ServerEvent::Command { session, command } = event {
+                    tx.send(Incoming::CommandLine {
+                        session,
+                        line: command,
+                    })?;
+                    ctx.request_repaint();
+                }
+                Ok(())
+            });
+            std::thread::sleep(SUBSCRIBE_RETRY);
+        }
+    });
+}
+
+struct HoveredLink {
+    row: u16,
+    start_col: u16,
+    end_col: u16,
+    target: LinkTarget,
+}
+
+/// Explicit OSC 8 links win; otherwise the row is scanned for URLs and file references,
+/// and file references are only offered when they resolve to an existing path.
+fn link_at(
+    emulator: &Emulator,
+    terminal: &PaneTerminal,
+    row: u16,
+    col: u16,
+    cols: u16,
+    workspace_dir: Option<&Path>,
+) -> Option<HoveredLink> {
+    if let Some(link) = emulator.hyperlink_at(row, col) {
+        let target = match link.uri.strip_prefix("file://") {
+            Some(rest) => LinkTarget::File {
+                path: rest
+                    .find('/')
+                    .map(|i| &rest[i..])
+                    .unwrap_or(rest)
+                    .to_string(),
+                line: None,
+                column: None,
+            },
+            None => LinkTarget::Url(link.uri.clone()),
+        };
+        return Some(HoveredLink {
+            row,
+            start_col: link.start_col,
+            end_col: link.end_col,
+            target,
+        });
+    }
+
+    let (text, columns) = links::row_text(emulator.screen(), row, cols);
+    let idx = columns.iter().rposition(|c| *c <= col)?;
+    let span = links::detect(&text)
+        .into_iter()
+        .find(|s| (s.start..s.end).contains(&idx))?;
+    let cwd = emulator
+        .cwd()
+        .map(PathBuf::from)
+        .or_else(|| terminal.cwd())
+        .or_else(|| workspace_dir.map(Path::to_path_buf));
+    let target = span.target.resolve(cwd.as_deref());
+    if !target.exists() {
+        return None;
+    }
+    Some(HoveredLink {
+        row,
+        start_col: columns[span.start],
+        end_col: columns.get(span.end).copied().unwrap_or(cols),
+        target,
+    })
 }
@@ -0,0 +1,412 @@
+use eframe::egui;
+
+/// Kitty progressive-enhancement flags (`CSI > flags u`) understood by the encoder.
+pub const KITTY_DISAMBIGUATE: u32 = 0b1;
+pub const KITTY_REPORT_EVENTS: u32 = 0b10;
+pub const KITTY_REPORT_ALL_KEYS: u32 = 0b1000;
+/// Flags we advertise in reply to `CSI ? u`; unsupported bits are masked off on push.
+pub const KITTY_SUPPORTED: u32 = KITTY_DISAMBIGUATE | KITTY_REPORT_EVENTS | KITTY_REPORT_ALL_KEYS;
+
+/// Terminal modes that change how keys and pastes are encoded.
+///
+/// egui does not report keypad keys separately from the main block, so DECKPAM is
+/// tracked here but cannot change any encoding yet.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct KeyModes {
+    pub application_cursor: bool,
+    pub application_keypad: bool,
+    pub bracketed_paste: bool,
+    pub kitty_flags: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyEventKind {
+    Press,
+    Repeat,
+    Release,
+}
+
+impl KeyEventKind {
+    fn kitty_code(self) -> u8 {
+        match self {
+            Self::Press => 1,
+            Self::Repeat => 2,
+            Self::Release => 3,
+        }
+    }
+}
+
+/// xterm modifier parameter: 1 + shift(1) + alt(2) + ctrl(4) + super(8).
+fn modifier_param(m: egui::Modifiers) -> u8 {
+    1 + u8::from(m.shift) + 2 * u8::from(m.alt) + 4 * u8::from(m.ctrl) + 8 * u8::from(m.mac_cmd)
+}
+
+/// Codepoint of a key that normally produces text, as used by the kitty protocol.
+pub fn text_codepoint(key: egui::Key) -> Option<char> {
+    use egui::Key;
+    let name = key.name();
+    if name.len() == 1 {
+        let ch = name.chars().next()?;
+        if ch.is_ascii_alphanumeric() {
+            return Some(ch.to_ascii_lowercase());
+        }
+    }
+    Some(match key {
+        Key::Space => ' ',
+        Key::Minus => '-',
+        Key::Equals => '=',
+        Key::Plus => '+',
+        Key::OpenBracket => '[',
+        Key::CloseBracket => ']',
+        Key::OpenCurlyBracket => '{',
+        Key::CloseCurlyBracket => '}',
+        Key::Backslash => '\\',
+        Key::Pipe => '|',
+        Key::Semicolon => ';',
+        Key::Colon => ':',
+        Key::Quote => '\'',
+        Key::Comma => ',',
+        Key::Period => '.',
+        Key::Slash => '/',
+        Key::Questionmark => '?',
+        Key::Exclamationmark => '!',
+        Key::Backtick => '`',
+        _ => return None,
+    })
+}
+
+/// Whether egui will follow this key press with an `Event::Text` that must be swallowed
+/// because the key itself was already encoded.
+pub fn suppresses_text(key: egui::Key, modifiers: egui::Modifiers) -> bool {
+    text_codepoint(key).is_some() && !modifiers.ctrl && !modifiers.mac_cmd
+}
+
+/// Bytes to send for a key event, or `None` when the key is left to `Event::Text`
+/// (or is not reported at all, e.g. releases outside kitty event reporting).
+pub fn encode_key(
+    key: egui::Key,
+    modifiers: egui::Modifiers,
+    kind: KeyEventKind,
+    modes: &KeyModes,
+) -> Option<Vec<u8>> {
+    if modes.kitty_flags & (KITTY_DISAMBIGUATE | KITTY_REPORT_ALL_KEYS) != 0 {
+        return encode_kitty(key, modifiers, kind, modes);
+    }
+    if kind == KeyEventKind::Release {
+        return None;
+    }
+    encode_legacy(key, modifiers, modes)
+}
+
+fn csi(number: u32, modifiers: u8, event: Option<KeyEventKind>, action: char) -> Vec<u8> {
+    let event = event.filter(|e| *e != KeyEventKind::Press);
+    let mut out = String::from("\x1b[");
+    let needs_number = action == '~' || action == 'u' || modifiers > 1 || event.is_some();
+    if needs_number {
+        out.push_str(&number.to_string());
+    }
+    if modifiers > 1 || event.is_some() {
+        out.push(';');
+        out.push_str(&modifiers.to_string());
+        if let Some(event) = event {
+            out.push(':');
+            out.push_str(&event.kitty_code().to_string());
+        }
+    }
+    out.push(action);
+    out.into_bytes()
+}
+
+/// `(number, final)` for keys that keep their legacy CSI/SS3 form under every mode.
+fn functional_key(key: egui::Key) -> Option<(u32, char)> {
+    use egui::Key;
+    Some(match key {
+        Key::ArrowUp => (1, 'A'),
+        Key::ArrowDown => (1, 'B'),
+        Key::ArrowRight => (1, 'C'),
+        Key::ArrowLeft => (1, 'D'),
+        Key::Home => (1, 'H'),
+        Key::End => (1, 'F'),
+        Key::Insert => (2, '~'),
+        Key::Delete => (3, '~'),
+        Key::PageUp => (5, '~'),
+        Key::PageDown => (6, '~'),
+        Key::F1 => (1, 'P'),
+        Key::F2 => (1, 'Q'),
+        Key::F3 => (1, 'R'),
+        Key::F4 => (1, 'S'),
+        Key::F5 => (15, '~'),
+        Key::F6 => (17, '~'),
+        Key::F7 => (18, '~'),
+        Key::F8 => (19, '~'),
+        Key::F9 => (20, '~'),
+        Key::F10 => (21, '~'),
+        Key::F11 => (23, '~'),
+        Key::F12 => (24, '~'),
+        _ => return None,
+    })
+}
+
+fn encode_legacy(key: egui::Key, m: egui::Modifiers, modes: &KeyModes) -> Option<Vec<u8>> {
+    use egui::Key;
+    let param = modifier_param(m);
+    let meta = |mut bytes: Vec<u8>| {
+        if m.alt {
+            bytes.insert(0, 0x1b);
+        }
+        bytes
+    };
+
+    if let Some((number, action)) = functional_key(key) {
+        // Option+Left/Right keeps the readline word-motion bytes macOS users expect;
+        // elsewhere Alt+arrows are modifier-encoded like any other combination.
+        if param == 3 && cfg!(target_os = "macos") {
+            match key {
+                Key::ArrowLeft => return Some(b"\x1bb".to_vec()),
+                Key::ArrowRight => return Some(b"\x1bf".to_vec()),
+                _ => {}
+            }
+        }
+        if param == 1 && action != '~' {
+            let ss3 = matches!(action, 'P' | 'Q' | 'R' | 'S')
+                || (modes.application_cursor
+                    && matches!(action, 'A' | 'B' | 'C' | 'D' | 'H' | 'F'));
+            if ss3 {
+                return Some(vec![0x1b, b'O', action as u8]);
+            }
+        }
+        return Some(csi(number, param, None, action));
+    }
+
+    match key {
+        Key::Enter => return Some(meta(b"\r".to_vec())),
+        Key::Escape => return Some(meta(vec![0x1b])),
+        Key::Backspace => {
+            return Some(meta(if m.ctrl { vec![0x08] } else { vec![0x7f] }));
+        }
+        Key::Tab => {
+            return Some(if m.shift {
+                b"\x1b[Z".to_vec()
+            } else {
+                meta(b"\t".to_vec())
+            });
+        }
+        _ => {}
+    }
+
+    if m.ctrl {
+        let byte = ctrl_byte(key)?;
+        return Some(meta(vec![byte]));
+    }
+    // Alt as Meta: ESC-prefix the character. macOS Option composes characters instead.
+    if m.alt && !cfg!(target_os = "macos") {
+        let ch = text_codepoint(key)?;
+        let ch = if m.shift { ch.to_ascii_uppercase() } else { ch };
+        return Some(vec![0x1b, ch as u8]);
+    }
+    None
+}
+
+fn ctrl_byte(key: egui::Key) -> Option<u8> {
+    use egui::Key;
+    match key {
+        Key::Space | Key::Num2 => Some(0x00),
+        Key::OpenBracket => Some(0x1b),
+        Key::Backslash => Some(0x1c),
+        Key::CloseBracket => Some(0x1d),
+        Key::Slash | Key::Minus => Some(0x1f),
+        _ => {
+            let ch = text_codepoint(key)?;
+            ch.is_ascii_lowercase().then(|| ch as u8 - b'a' + 1)
+        }
+    }
+}
+
+fn encode_kitty(
+    key: egui::Key,
+    m: egui::Modifiers,
+    kind: KeyEventKind,
+    modes: &KeyModes,
+) -> Option<Vec<u8>> {
+    use egui::Key;
+    let flags = modes.kitty_flags;
+    let report_all = flags & KITTY_REPORT_ALL_KEYS != 0;
+    let event = (flags & KITTY_REPORT_EVENTS != 0).then_some(kind);
+    if kind == KeyEventKind::Release && event.is_none() {
+        return None;
+    }
+    let param = modifier_param(m);
+
+    if let Some((number, action)) = functional_key(key) {
+        let (number, action) = if key == Key::F3 {
+            (13, '~')
+        } else {
+            (number, action)
+        };
+        if param == 1 && event.is_none() {
+            return encode_legacy(key, m, modes);
+        }
+        return Some(csi(number, param, event, action));
+    }
+
+    let code = match key {
+        Key::Escape => 27,
+        Key::Enter => 13,
+        Key::Tab => 9,
+        Key::Backspace => 127,
+        _ => u32::from(text_codepoint(key)?),
+    };
+    let plain = param == 1 || (param == 2 && text_codepoint(key).is_some());
+    if !report_all {
+        if kind == KeyEventKind::Release && matches!(key, Key::Enter | Key::Tab | Key::Backspace) {
+            return None;
+        }
+        // Disambiguate only touches keys that would otherwise be ambiguous.
+        if key != Key::Escape && plain && event.is_none_or(|e| e == KeyEventKind::Press) {
+            return match key {
+                Key::Enter | Key::Tab | Key::Backspace => encode_legacy(key, m, modes),
+                _ => None,
+            };
+        }
+    }
+    Some(csi(code, param, event, 'u'))
+}
+
+/// Paste payload, wrapped in bracketed-paste markers only when the application enabled them.
+/// Embedded end markers are stripped so pasted text cannot terminate the bracket early.
+pub fn encode_paste(text: &str, modes: &KeyModes) -> Vec<u8> {
+    let body = text.replace("\r\n", "\r").replace('\n', "\r");
+    if !modes.bracketed_paste {
+        return body.into_bytes();
+    }
+    let body = body.replace("\x1b[201~", "");
+    let mut out = b"\x1b[200~".to_vec();
+    out.extend_from_slice(body.as_bytes());
+    out.extend_from_slice(b"\x1b[201~");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use egui::{Key, Modifiers};
+
+    fn legacy() -> KeyModes {
+        KeyModes::default()
+    }
+
+    fn kitty(flags: u32) -> KeyModes {
+        KeyModes {
+            kitty_flags: flags,
+            ..KeyModes::default()
+        }
+    }
+
+    #[test]
+    fn cursor_keys_follow_decckm() {
+        let press = KeyEventKind::Press;
+        assert_eq!(
+            encode_key(Key::ArrowUp, Modifiers::NONE, press, &legacy()),
+            Some(b"\x1b[A".to_vec())
+        );
+        let app = KeyModes {
+            application_cursor: true,
+            ..KeyModes::default()
+        };
+        assert_eq!(
+            encode_key(Key::ArrowUp, Modifiers::NONE, press, &app),
+            Some(b"\x1bOA".to_vec())
+        );
+    }
+
+    #[test]
+    fn modifiers_use_xterm_parameters() {
+        let press = KeyEventKind::Press;
+        assert_eq!(
+            encode_key(Key::ArrowUp, Modifiers::CTRL, press, &legacy()),
+            Some(b"\x1b[1;5A".to_vec())
+        );
+        assert_eq!(
+            encode_key(Key::F5, Modifiers::SHIFT, press, &legacy()),
+            Some(b"\x1b[15;2~".to_vec())
+        );
+        assert_eq!(
+            encode_key(
+                Key::F1,
+                Modifiers::CTRL | Modifiers::SHIFT,
+                press,
+                &legacy()
+            ),
+            Some(b"\x1b[1;6P".to_vec())
+        );
+        assert_eq!(
+            encode_key(Key::C, Modifiers::CTRL, press, &legacy()),
+            Some(vec![0x03])
+        );
+        assert_eq!(encode_key(Key::A, Modifiers::NONE, press, &legacy()), None);
+
+        let alt_left = encode_key(Key::ArrowLeft, Modifiers::ALT, press, &legacy());
+        let alt_right = encode_key(Key::ArrowRight, Modifiers::ALT, press, &legacy());
+        if cfg!(target_os = "macos") {
+            assert_eq!(alt_left, Some(b"\x1bb".to_vec()));
+            assert_eq!(alt_right, Some(b"\x1bf".to_vec()));
+        } else {
+            assert_eq!(alt_left, Some(b"\x1b[1;3D".to_vec()));
+            assert_eq!(alt_right, Some(b"\x1b[1;3C".to_vec()));
+        }
+    }
+
+    #[test]
+    fn kitty_disambiguates_ctrl_and_escape() {
+        let press = KeyEventKind::Press;
+        let modes = kitty(KITTY_DISAMBIGUATE);
+        assert_eq!(
+            encode_key(Key::Escape, Modifiers::NONE, press, &modes),
+            Some(b"\x1b[27u".to_vec())
+        );
+        assert_eq!(
+            encode_key(Key::I, Modifiers::CTRL, press, &modes),
+            Some(b"\x1b[105;5u".to_vec())
+        );
+        assert_eq!(
+            encode_key(Key::Enter, Modifiers::NONE, press, &modes),
+            Some(b"\r".to_vec())
+        );
+        assert_eq!(encode_key(Key::A, Modifiers::SHIFT, press, &modes), None);
+    }
+
+    #[test]
+    fn kitty_reports_all_keys_and_events() {
+        let modes = kitty(KITTY_REPORT_ALL_KEYS | KITTY_REPORT_EVENTS);
+        assert_eq!(
+            encode_key(Key::A, Modifiers::NONE, KeyEventKind::Press, &modes),
+            Some(b"\x1b[97u".to_vec())
+        );
+        assert_eq!(
+            encode_key(Key::A, Modifiers::NONE, KeyEventKind::Release, &modes),
+            Some(b"\x1b[97;1:3u".to_vec())
+        );
+        assert_eq!(
+            encode_key(
+                Key::ArrowLeft,
+                Modifiers::NONE,
+                KeyEventKind::Repeat,
+                &modes
+            ),
+            Some(b"\x1b[1;1:2D".to_vec())
+        );
+    }
+
+    #[test]
+    fn paste_is_bracketed_only_when_enabled() {
+        assert_eq!(encode_paste("a\nb", &legacy()), b"a\rb".to_vec());
+        let modes = KeyModes {
+            bracketed_paste: true,
+            ..KeyModes::default()
+        };
+        assert_eq!(
+            encode_paste("x\x1b[201~y", &modes),
+            b"\x1b[200~xy\x1b[201~".to_vec()
+        );
+    }
+}
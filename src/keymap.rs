@@ -1,4 +1,33 @@
+//! Key bindings: which key runs which command in each input mode.
+//!
+//! The bindings start from [`DEFAULT_BINDINGS`] and are then amended by the user's
+//! `keymap.conf` in the runtime directory (or the file named by `ORCHESTRATERM_KEYMAP`),
+//! written in the same tmux-like syntax:
+//!
+//! ```text
+//! set prefix C-a                   # the key that enters the prefix table
+//! bind | split-vertical            # prefix table unless -n or -T is given
+//! bind -n M-Left select-pane-prev
+//! bind -T copy k copy-up
+//! bind -n F5 send-text "make\n"
+//! bind -r Tab select-pane-next     # repeatable without retyping the prefix
+//! bind "w 1" select-pane 1         # a key sequence: prefix, w, then 1
+//! bind -n M-f switch-table fonts   # a user-defined key table...
+//! bind -T fonts -r = font-zoom-in  # ...and its bindings
+//! unbind z
+//! ```
+//!
+//! Key tables (the prefix table and user-defined ones) take a single key and then hand
+//! back to the root table, unless the key was bound with `-r`: then the table stays
+//! active for `repeat-time`, restarted by every repeat.
+
+use anyhow::{Context, Result, anyhow, bail};
 use eframe::egui;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::command::{self, Command};
+use crate::engine::runtime_dir;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Mode {
@@ -6,72 +35,605 @@ pub enum Mode {
     Prefix,
     Copy,
     CopySearch,
+    BufferPicker,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum Action {
-    EnterPrefix,
-    EnterCopyMode,
-    ExitCopyMode,
-    SplitHorizontal,
-    SplitVertical,
-    ClosePane,
-    ToggleZoom,
-    FocusPrev,
-    FocusNext,
-    CopyMoveUp,
-    CopyMoveDown,
-    CopyMoveLeft,
-    CopyMoveRight,
-    CopyStartSelection,
-    CopyCopySelection,
-    CopySearchStart,
-    CopySearchApply,
-    SendEnter,
-    OpenFolder,
+impl Mode {
+    /// Table names as used by `bind -T`; `root` is what `bind -n` binds in.
+    pub fn table_name(self) -> &'static str {
+        match self {
+            Self::Normal => "root",
+            Self::Prefix => "prefix",
+            Self::Copy => "copy",
+            Self::CopySearch => "copy-search",
+            Self::BufferPicker => "buffer-picker",
+        }
+    }
+
+    pub fn from_table_name(name: &str) -> Option<Self> {
+        [
+            Self::Normal,
+            Self::Prefix,
+            Self::Copy,
+            Self::CopySearch,
+            Self::BufferPicker,
+        ]
+        .into_iter()
+        .find(|m| m.table_name() == name)
+    }
+}
+
+/// Parses a key in tmux notation: modifier prefixes `C-` (Ctrl), `M-`/`A-` (Alt),
+/// `S-` (Shift) and `Cmd-` (Cmd on macOS, Ctrl elsewhere), then an egui key name such
+/// as `b`, `Enter`, `Left`, `F5` or `[`. Letters are case-insensitive; use `S-` for Shift.
+pub fn parse_chord(text: &str) -> Result<egui::KeyboardShortcut> {
+    let mut modifiers = egui::Modifiers::NONE;
+    let mut rest = text;
+    loop {
+        let (modifier, len) = if rest.starts_with("Cmd-") {
+            (egui::Modifiers::COMMAND, 4)
+        } else if rest.starts_with("C-") {
+            (egui::Modifiers::CTRL, 2)
+        } else if rest.starts_with("M-") || rest.starts_with("A-") {
+            (egui::Modifiers::ALT, 2)
+        } else if rest.starts_with("S-") {
+            (egui::Modifiers::SHIFT, 2)
+        } else {
+            break;
+        };
+        if rest.len() == len {
+            break;
+        }
+        modifiers |= modifier;
+        rest = &rest[len..];
+    }
+    let key = egui::Key::from_name(rest).ok_or_else(|| anyhow!("unknown key: {text}"))?;
+    Ok(egui::KeyboardShortcut::new(modifiers, key))
+}
+
+/// A chord as shown to the user, e.g. `Ctrl+B` or `Shift+Cmd+O`.
+pub fn format_chord(chord: &egui::KeyboardShortcut) -> String {
+    let is_mac = cfg!(target_os = "macos");
+    let mut s = egui::ModifierNames::NAMES.format(&chord.modifiers, is_mac);
+    if !s.is_empty() {
+        s.push('+');
+    }
+    let key = chord.logical_key;
+    s.push_str(match key {
+        egui::Key::ArrowUp
+        | egui::Key::ArrowDown
+        | egui::Key::ArrowLeft
+        | egui::Key::ArrowRight => key.name(),
+        _ => key.symbol_or_name(),
+    });
+    s
+}
+
+pub const DEFAULT_BINDINGS: &str = r#"
+set repeat-time 500
+set which-key on
+
+bind -T global Cmd-o open-folder
+bind -T global Cmd-= font-zoom-in
+bind -T global Cmd-+ font-zoom-in
+bind -T global Cmd-- font-zoom-out
+bind -T global Cmd-0 font-zoom-reset
+
+set prefix C-b
+bind -n C-Enter send-enter
+bind -n C-S-p command-palette
+
+bind s split-horizontal
+bind v split-vertical
+bind x kill-pane
+bind z zoom-pane
+bind -r Left select-pane-prev
+bind -r Up select-pane-prev
+bind -r Right select-pane-next
+bind -r Down select-pane-next
+bind [ copy-mode
+bind ] paste-buffer
+bind = choose-buffer
+bind : command-prompt
+bind Escape cancel
+
+bind -T copy Up copy-up
+bind -T copy Down copy-down
+bind -T copy Left copy-left
+bind -T copy Right copy-right
+bind -T copy Space begin-selection
+bind -T copy Enter copy-selection
+bind -T copy / search
+bind -T copy Escape cancel
+
+bind -T copy-search Enter search-apply
+bind -T copy-search Escape cancel
+
+bind -T buffer-picker Up picker-up
+bind -T buffer-picker Down picker-down
+bind -T buffer-picker Enter picker-paste
+bind -T buffer-picker Delete picker-delete
+bind -T buffer-picker Backspace picker-delete
+bind -T buffer-picker Escape picker-close
+"#;
+
+/// The table whose bindings apply in every mode.
+pub const GLOBAL_TABLE: &str = "global";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Binding {
+    /// A mode's table (see [`Mode::table_name`]), [`GLOBAL_TABLE`] or a user-defined one.
+    pub table: String,
+    pub chord: egui::KeyboardShortcut,
+    pub action: Command,
+    /// Stay in the table after running, for `repeat_time`, so the key can be pressed again.
+    pub repeat: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Keymap {
+    bindings: Vec<Binding>,
+    /// How long a repeatable binding keeps its table active.
+    pub repeat_time: Duration,
+    /// Show the keys available in a key table while it is active.
+    pub which_key: bool,
 }
 
-pub fn map_key(mode: Mode, key: egui::Key, modifiers: egui::Modifiers) -> Option<Action> {
-    if modifiers.command && key == egui::Key::O {
-        return Some(Action::OpenFolder);
+impl Keymap {
+    pub fn defaults() -> Self {
+        let mut keymap = Self::default();
+        let errors = keymap.apply_config(DEFAULT_BINDINGS);
+        debug_assert!(errors.is_empty(), "{errors:?}");
+        keymap
+    }
+
+    /// The defaults amended by the user's keymap file, with one message per rejected line.
+    pub fn load() -> (Self, Vec<String>) {
+        let mut keymap = Self::defaults();
+        let Ok(path) = keymap_path() else {
+            return (keymap, Vec::new());
+        };
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                return (keymap, Vec::new());
+            }
+            Err(err) => return (keymap, vec![format!("{}: {err}", path.display())]),
+        };
+        let errors = keymap
+            .apply_config(&text)
+            .into_iter()
+            .map(|e| format!("{}:{e}", path.display()))
+            .collect();
+        (keymap, errors)
+    }
+
+    /// Applies each line of `text`, skipping the ones that fail. Errors read `LINE: message`.
+    pub fn apply_config(&mut self, text: &str) -> Vec<String> {
+        text.lines()
+            .enumerate()
+            .filter_map(|(i, line)| {
+                self.apply_line(line)
+                    .err()
+                    .map(|e| format!("{}: {e:#}", i + 1))
+            })
+            .collect()
+    }
+
+    /// Applies one line of `set`, `bind` and `unbind` commands.
+    pub fn apply_line(&mut self, line: &str) -> Result<()> {
+        for command in command::parse_line(line)? {
+            self.run(&command)?;
+        }
+        Ok(())
+    }
+
+    /// Runs a `set`, `bind` or `unbind` command.
+    pub fn run(&mut self, command: &Command) -> Result<()> {
+        match command {
+            Command::Set { option, value } => match option.as_str() {
+                "prefix" => self.set_prefix(parse_chord(value)?),
+                "repeat-time" => {
+                    let ms: u64 = value
+                        .parse()
+                        .with_context(|| format!("invalid repeat-time: {value}"))?;
+                    self.repeat_time = Duration::from_millis(ms);
+                }
+                "which-key" => {
+                    self.which_key = match value.as_str() {
+                        "on" => true,
+                        "off" => false,
+                        _ => bail!("which-key is on or off"),
+                    }
+                }
+                _ => bail!("unknown option: {option}"),
+            },
+            Command::Bind {
+                table,
+                repeat,
+                keys,
+                command,
+            } => {
+                let chords = parse_sequence(keys)?;
+                self.bind_sequence(table, keys, &chords, (**command).clone(), *repeat);
+            }
+            Command::Unbind { table, keys } => {
+                let chords = parse_sequence(keys)?;
+                let (last, leading) = chords.split_last().context("missing key")?;
+                let mut table = table.clone();
+                for chord in leading {
+                    table = self
+                        .table_switched_to(&table, *chord)
+                        .with_context(|| format!("{keys} is not bound"))?;
+                }
+                if !self.unbind(&table, *last) {
+                    bail!("{keys} is not bound");
+                }
+            }
+            _ => bail!("only set, bind and unbind belong in the keymap"),
+        }
+        Ok(())
+    }
+
+    /// Binds `chord` in `table`, replacing what it was bound to there.
+    pub fn bind(
+        &mut self,
+        table: &str,
+        chord: egui::KeyboardShortcut,
+        action: Command,
+        repeat: bool,
+    ) {
+        self.unbind(table, chord);
+        self.bindings.push(Binding {
+            table: table.to_string(),
+            chord,
+            action,
+            repeat,
+        });
+    }
+
+    /// Binds a key sequence such as `w 1`: every key but the last switches to a table of
+    /// its own, named after the keys typed so far, unless it already switches to one.
+    fn bind_sequence(
+        &mut self,
+        table: &str,
+        keys: &str,
+        chords: &[egui::KeyboardShortcut],
+        action: Command,
+        repeat: bool,
+    ) {
+        let Some((last, leading)) = chords.split_last() else {
+            return;
+        };
+        let mut table = table.to_string();
+        for (i, chord) in leading.iter().enumerate() {
+            table = match self.table_switched_to(&table, *chord) {
+                Some(next) => next,
+                None => {
+                    let words: Vec<&str> = keys.split_whitespace().take(i + 1).collect();
+                    let next = format!("{table} {}", words.join(" "));
+                    self.bind(&table, *chord, Command::SwitchTable(next.clone()), false);
+                    next
+                }
+            };
+        }
+        self.bind(&table, *last, action, repeat);
+    }
+
+    fn table_switched_to(&self, table: &str, chord: egui::KeyboardShortcut) -> Option<String> {
+        self.bindings
+            .iter()
+            .find(|b| b.table == table && b.chord == chord)
+            .and_then(|b| match &b.action {
+                Command::SwitchTable(next) => Some(next.clone()),
+                Command::EnterPrefix => Some(Mode::Prefix.table_name().to_string()),
+                _ => None,
+            })
     }
 
-    match mode {
-        Mode::Normal => {
-            if modifiers.ctrl && key == egui::Key::B {
-                return Some(Action::EnterPrefix);
+    pub fn unbind(&mut self, table: &str, chord: egui::KeyboardShortcut) -> bool {
+        let before = self.bindings.len();
+        self.bindings
+            .retain(|b| !(b.table == table && b.chord == chord));
+        self.bindings.len() != before
+    }
+
+    /// Makes `chord` the only key that enters prefix mode.
+    pub fn set_prefix(&mut self, chord: egui::KeyboardShortcut) {
+        let root = Mode::Normal.table_name();
+        self.bindings
+            .retain(|b| !(b.table == root && b.action == Command::EnterPrefix));
+        self.bind(root, chord, Command::EnterPrefix, false);
+    }
+
+    pub fn prefix(&self) -> Option<egui::KeyboardShortcut> {
+        self.bindings
+            .iter()
+            .find(|b| b.table == Mode::Normal.table_name() && b.action == Command::EnterPrefix)
+            .map(|b| b.chord)
+    }
+
+    /// The binding for a key press in `table`. The table's own bindings win over global
+    /// ones, and exact modifier matches over ones that ignore an extra Shift or Alt.
+    pub fn lookup(
+        &self,
+        table: &str,
+        key: egui::Key,
+        modifiers: egui::Modifiers,
+    ) -> Option<&Binding> {
+        let candidates = || {
+            self.bindings
+                .iter()
+                .filter(move |b| b.chord.logical_key == key)
+        };
+        for table in [table, GLOBAL_TABLE] {
+            let in_table = || candidates().filter(move |b| b.table == table);
+            if let Some(b) = in_table()
+                .find(|b| modifiers.matches_exact(b.chord.modifiers))
+                .or_else(|| in_table().find(|b| modifiers.matches_logically(b.chord.modifiers)))
+            {
+                return Some(b);
             }
-            if modifiers.ctrl && key == egui::Key::Enter {
-                return Some(Action::SendEnter);
+        }
+        None
+    }
+
+    /// The keys that lead to `table` from the root table, e.g. `Ctrl+B, W`.
+    fn table_keys(&self, table: &str) -> Option<String> {
+        let mut keys = Vec::new();
+        let mut table = table.to_string();
+        // Bounded, in case tables switch to each other in a cycle.
+        for _ in 0..8 {
+            if table == Mode::Normal.table_name() {
+                keys.reverse();
+                return Some(keys.join(", "));
             }
-            None
+            let b = self.bindings.iter().find(|b| {
+                b.table != table
+                    && match &b.action {
+                        Command::SwitchTable(next) => *next == table,
+                        Command::EnterPrefix => table == Mode::Prefix.table_name(),
+                        _ => false,
+                    }
+            })?;
+            keys.push(format_chord(&b.chord));
+            table = b.table.clone();
+        }
+        None
+    }
+
+    /// The bindings of one table as `(key, description)`, for the which-key overlay.
+    pub fn continuations(&self, table: &str) -> Vec<(String, String)> {
+        self.bindings
+            .iter()
+            .filter(|b| b.table == table)
+            .map(|b| (format_chord(&b.chord), describe_binding(b)))
+            .collect()
+    }
+
+    /// Every binding as `(keys, description)`, grouped by table, for the shortcuts panel.
+    /// Key table bindings are shown after the keys that reach them, e.g. `Ctrl+B, S`.
+    pub fn describe(&self) -> Vec<(String, String)> {
+        let mut tables: Vec<&str> = vec![Mode::Normal.table_name(), GLOBAL_TABLE];
+        for b in &self.bindings {
+            if !tables.contains(&b.table.as_str()) {
+                tables.push(&b.table);
+            }
+        }
+        let mut rows = Vec::new();
+        for table in tables {
+            let lead = self.table_lead(table);
+            for b in self.bindings.iter().filter(|b| b.table == table) {
+                if matches!(b.action, Command::EnterPrefix | Command::SwitchTable(_)) {
+                    continue;
+                }
+                rows.push((
+                    format!("{lead}{}", format_chord(&b.chord)),
+                    describe_binding(b),
+                ));
+            }
+        }
+        rows
+    }
+
+    /// The keys that run `command` outside copy mode and the buffer picker, if any.
+    pub fn keys_for(&self, command: &Command) -> Option<String> {
+        let b = self.bindings.iter().find(|b| {
+            b.action == *command
+                && !matches!(
+                    Mode::from_table_name(&b.table),
+                    Some(Mode::Copy | Mode::CopySearch | Mode::BufferPicker)
+                )
+        })?;
+        Some(format!(
+            "{}{}",
+            self.table_lead(&b.table),
+            format_chord(&b.chord)
+        ))
+    }
+
+    /// What comes before a key of `table` when it is listed.
+    fn table_lead(&self, table: &str) -> String {
+        match Mode::from_table_name(table) {
+            Some(Mode::Copy) => "Copy mode ".to_string(),
+            Some(Mode::CopySearch) => "Copy search ".to_string(),
+            Some(Mode::BufferPicker) => "Buffers ".to_string(),
+            _ if table == GLOBAL_TABLE => String::new(),
+            _ => match self.table_keys(table) {
+                Some(keys) if keys.is_empty() => keys,
+                Some(keys) => format!("{keys}, "),
+                None => format!("[{table}] "),
+            },
+        }
+    }
+}
+
+fn describe_binding(b: &Binding) -> String {
+    if b.repeat {
+        format!("{} (repeats)", b.action.describe())
+    } else {
+        b.action.describe()
+    }
+}
+
+/// A key or a space-separated key sequence.
+pub fn parse_sequence(keys: &str) -> Result<Vec<egui::KeyboardShortcut>> {
+    let chords = keys
+        .split_whitespace()
+        .map(parse_chord)
+        .collect::<Result<Vec<_>>>()?;
+    if chords.is_empty() {
+        bail!("missing key");
+    }
+    Ok(chords)
+}
+
+/// `ORCHESTRATERM_KEYMAP`, else `keymap.conf` in the runtime directory.
+pub fn keymap_path() -> Result<PathBuf> {
+    if let Ok(explicit) = std::env::var("ORCHESTRATERM_KEYMAP") {
+        let trimmed = explicit.trim();
+        if !trimmed.is_empty() {
+            return Ok(PathBuf::from(trimmed));
         }
-        Mode::Prefix => match key {
-            egui::Key::S => Some(Action::SplitHorizontal),
-            egui::Key::V => Some(Action::SplitVertical),
-            egui::Key::X => Some(Action::ClosePane),
-            egui::Key::Z => Some(Action::ToggleZoom),
-            egui::Key::ArrowLeft | egui::Key::ArrowUp => Some(Action::FocusPrev),
-            egui::Key::ArrowRight | egui::Key::ArrowDown => Some(Action::FocusNext),
-            egui::Key::OpenBracket => Some(Action::EnterCopyMode),
-            egui::Key::Escape => Some(Action::ExitCopyMode),
-            _ => None,
-        },
-        Mode::Copy => match key {
-            egui::Key::ArrowUp => Some(Action::CopyMoveUp),
-            egui::Key::ArrowDown => Some(Action::CopyMoveDown),
-            egui::Key::ArrowLeft => Some(Action::CopyMoveLeft),
-            egui::Key::ArrowRight => Some(Action::CopyMoveRight),
-            egui::Key::Space => Some(Action::CopyStartSelection),
-            egui::Key::Enter => Some(Action::CopyCopySelection),
-            egui::Key::Slash => Some(Action::CopySearchStart),
-            egui::Key::Escape => Some(Action::ExitCopyMode),
-            _ => None,
-        },
-        Mode::CopySearch => match key {
-            egui::Key::Enter => Some(Action::CopySearchApply),
-            egui::Key::Escape => Some(Action::ExitCopyMode),
-            _ => None,
-        },
+    }
+    Ok(runtime_dir()?.join("keymap.conf"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::SplitAxis;
+
+    fn press(keymap: &Keymap, table: &str, chord: &str) -> Option<Command> {
+        let chord = parse_chord(chord).unwrap();
+        keymap
+            .lookup(table, chord.logical_key, chord.modifiers)
+            .map(|b| b.action.clone())
+    }
+
+    #[test]
+    fn chords_parse_in_tmux_notation() {
+        let chord = parse_chord("C-M-a").unwrap();
+        assert_eq!(chord.logical_key, egui::Key::A);
+        assert_eq!(
+            chord.modifiers,
+            egui::Modifiers::CTRL | egui::Modifiers::ALT
+        );
+        assert_eq!(parse_chord("Cmd--").unwrap().logical_key, egui::Key::Minus);
+        assert_eq!(parse_chord("S").unwrap().modifiers, egui::Modifiers::NONE);
+        assert_eq!(
+            parse_chord("Left").unwrap().logical_key,
+            egui::Key::ArrowLeft
+        );
+        assert!(parse_chord("C-Nope").is_err());
+    }
+
+    #[test]
+    fn defaults_match_the_documented_keys() {
+        let keymap = Keymap::defaults();
+        assert_eq!(press(&keymap, "root", "C-b"), Some(Command::EnterPrefix));
+        assert_eq!(press(&keymap, "root", "b"), None);
+        assert_eq!(
+            press(&keymap, "prefix", "s"),
+            Some(Command::SplitWindow {
+                axis: SplitAxis::Horizontal,
+                dir: None
+            })
+        );
+        assert_eq!(press(&keymap, "prefix", "C-s"), None);
+        assert_eq!(press(&keymap, "copy", "Cmd-o"), Some(Command::OpenFolder));
+        assert_eq!(press(&keymap, "root", "Cmd-S-="), Some(Command::ZoomIn));
+        assert_eq!(
+            press(&keymap, "copy", "Escape"),
+            Some(Command::ExitCopyMode)
+        );
+    }
+
+    #[test]
+    fn config_rebinds_prefix_and_commands() {
+        let mut keymap = Keymap::defaults();
+        let errors = keymap.apply_config(
+            "set -g prefix C-a  # screen habits\n\
+             bind | split-vertical\n\
+             bind -n F5 send-text \"make\\n\"\n\
+             bind -T copy k copy-up\n\
+             bind p paste-buffer buffer2\n\
+             unbind z\n\
+             bind q frobnicate\n\
+             unbind -T copy q\n",
+        );
+        assert_eq!(
+            errors,
+            vec![
+                "7: unknown command: frobnicate".to_string(),
+                "8: q is not bound".to_string()
+            ]
+        );
+        assert_eq!(press(&keymap, "root", "C-a"), Some(Command::EnterPrefix));
+        assert_eq!(press(&keymap, "root", "C-b"), None);
+        assert_eq!(
+            press(&keymap, "prefix", "|"),
+            Some(Command::SplitWindow {
+                axis: SplitAxis::Vertical,
+                dir: None
+            })
+        );
+        assert_eq!(
+            press(&keymap, "root", "F5"),
+            Some(Command::SendText("make\n".to_string()))
+        );
+        assert_eq!(press(&keymap, "copy", "k"), Some(Command::CopyMoveUp));
+        assert_eq!(
+            press(&keymap, "prefix", "p"),
+            Some(Command::PasteBuffer(Some("buffer2".to_string())))
+        );
+        assert_eq!(press(&keymap, "prefix", "z"), None);
+
+        let rows = keymap.describe();
+        assert!(rows.contains(&("Ctrl+A, |".to_string(), "Split vertically".to_string())));
+        assert!(rows.contains(&("F5".to_string(), "Send \"make\\n\"".to_string())));
+    }
+
+    #[test]
+    fn sequences_build_key_tables() {
+        let mut keymap = Keymap::defaults();
+        let errors = keymap.apply_config(
+            "bind \"w 1\" select-pane 1\n\
+             bind \"w 2\" select-pane 2\n\
+             bind -n M-f switch-table fonts\n\
+             bind -T fonts -r = font-zoom-in\n\
+             unbind \"w 2\"\n\
+             bind -x q cancel\n",
+        );
+        assert_eq!(errors, vec!["6: bind: unknown flag -x".to_string()]);
+        assert_eq!(
+            press(&keymap, "prefix", "w"),
+            Some(Command::SwitchTable("prefix w".to_string()))
+        );
+        assert_eq!(
+            press(&keymap, "prefix w", "1"),
+            Some(Command::SelectPane {
+                target: Some(1),
+                title: None
+            })
+        );
+        assert_eq!(press(&keymap, "prefix w", "2"), None);
+        let zoom = parse_chord("=").unwrap();
+        assert!(
+            keymap
+                .lookup("fonts", zoom.logical_key, zoom.modifiers)
+                .is_some_and(|b| b.repeat)
+        );
+        assert_eq!(
+            keymap.continuations("prefix w"),
+            vec![("1".to_string(), "Focus pane 1".to_string())]
+        );
+
+        let rows = keymap.describe();
+        assert!(rows.contains(&("Ctrl+B, W, 1".to_string(), "Focus pane 1".to_string())));
+        assert!(rows.contains(&(
+            "Alt+F, =".to_string(),
+            "Zoom terminal font in (repeats)".to_string()
+        )));
     }
 }
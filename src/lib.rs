@@ -1,9 +1,27 @@
+mod base64;
+pub mod bell;
+pub mod buffers;
+pub mod command;
 #[cfg(test)]
 mod compat;
+pub mod config;
 pub mod core;
+pub mod emoji;
+pub mod emulator;
 pub mod engine;
+pub mod fonts;
+pub mod graphics;
 pub mod gui;
+pub mod input;
 pub mod keymap;
+pub mod links;
+pub mod mux;
+pub mod palette;
 pub mod protocol;
+pub mod reflow;
+pub mod render;
 pub mod server;
+pub mod status;
 pub mod terminal;
+pub mod theme;
+pub mod width;
@@ -0,0 +1,344 @@
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use anyhow::{Context, Result};
+
+use crate::width::AMBIGUOUS_PAD;
+
+const URL_SCHEMES: [&str; 5] = ["https://", "http://", "file://", "ssh://", "ftp://"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinkTarget {
+    Url(String),
+    File {
+        path: String,
+        line: Option<u32>,
+        column: Option<u32>,
+    },
+}
+
+/// A link found in a rendered row; `start`/`end` are char indices (end exclusive).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkSpan {
+    pub start: usize,
+    pub end: usize,
+    pub target: LinkTarget,
+}
+
+/// Finds URLs and `path[:line[:col]]` references in one row of terminal text.
+pub fn detect(text: &str) -> Vec<LinkSpan> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = detect_urls(&chars);
+    let mut i = 0;
+    while i < chars.len() {
+        if !is_path_char(chars[i]) {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < chars.len() && (is_path_char(chars[i]) || chars[i] == ':') {
+            i += 1;
+        }
+        if out.iter().any(|s| start < s.end && i > s.start) {
+            continue;
+        }
+        let token: String = chars[start..i].iter().collect();
+        if let Some((target, len)) = parse_file_ref(&token) {
+            out.push(LinkSpan {
+                start,
+                end: start + len,
+                target,
+            });
+        }
+    }
+    out.sort_by_key(|s| s.start);
+    out
+}
+
+fn detect_urls(chars: &[char]) -> Vec<LinkSpan> {
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let rest: String = chars[i..chars.len().min(i + 8)].iter().collect();
+        let Some(scheme) = URL_SCHEMES.iter().find(|s| rest.starts_with(**s)) else {
+            i += 1;
+            continue;
+        };
+        let start = i;
+        let mut end = i + scheme.len();
+        while end < chars.len()
+            && !chars[end].is_whitespace()
+            && !matches!(chars[end], '<' | '>' | '"' | '\'' | '`')
+        {
+            end += 1;
+        }
+        // Trailing punctuation and unbalanced closers usually belong to the prose around a URL.
+        while end > start + scheme.len() {
+            let last = chars[end - 1];
+            let unbalanced = |open: char, close: char| {
+                last == close
+                    && chars[start..end].iter().filter(|c| **c == open).count()
+                        < chars[start..end].iter().filter(|c| **c == close).count()
+            };
+            if matches!(last, '.' | ',' | ';' | ':' | '!' | '?')
+                || unbalanced('(', ')')
+                || unbalanced('[', ']')
+                || unbalanced('{', '}')
+            {
+                end -= 1;
+            } else {
+                break;
+            }
+        }
+        if end > start + scheme.len() {
+            out.push(LinkSpan {
+                start,
+                end,
+                target: LinkTarget::Url(chars[start..end].iter().collect()),
+            });
+        }
+        i = end.max(i + 1);
+    }
+    out
+}
+
+fn is_path_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '/' | '.' | '_' | '-' | '~' | '+' | '@')
+}
+
+/// Parses `path`, `path:line` or `path:line:col` (a trailing `:` as printed by gcc is
+/// ignored) and returns the target plus the number of chars it covers.
+fn parse_file_ref(token: &str) -> Option<(LinkTarget, usize)> {
+    let mut parts = token.split(':');
+    let path = parts.next()?;
+    let line = parts.next().and_then(|p| p.parse::<u32>().ok());
+    let column = line.and(parts.next().and_then(|p| p.parse::<u32>().ok()));
+
+    let path = path.trim_end_matches(['.', ',']);
+    if path.is_empty() || path.chars().all(|c| c == '.' || c == '/') {
+        return None;
+    }
+    let name = path.rsplit('/').next().unwrap_or(path);
+    let has_dir = path.contains('/');
+    let has_ext = name
+        .rsplit_once('.')
+        .is_some_and(|(stem, ext)| !stem.is_empty() && !ext.is_empty());
+    let looks_like_path =
+        (has_dir && (has_ext || path.starts_with(['.', '~', '/']))) || (has_ext && line.is_some());
+    if !looks_like_path || path.chars().all(|c| c.is_ascii_digit() || c == '/') {
+        return None;
+    }
+
+    let mut len = path.chars().count();
+    if let Some(line) = line {
+        len += 1 + line.to_string().len();
+        if let Some(column) = column {
+            len += 1 + column.to_string().len();
+        }
+    }
+    Some((
+        LinkTarget::File {
+            path: path.to_string(),
+            line,
+            column,
+        },
+        len,
+    ))
+}
+
+/// Replaces a leading `~` with `$HOME`; other paths are returned as given.
+pub fn expand_home(path: &str) -> PathBuf {
+    let rest = match path.strip_prefix('~') {
+        Some("") => "",
+        Some(rest) if rest.starts_with('/') => &rest[1..],
+        _ => return PathBuf::from(path),
+    };
+    std::env::var("HOME")
+        .map(|home| PathBuf::from(home).join(rest))
+        .unwrap_or_else(|_| PathBuf::from(path))
+}
+
+impl LinkTarget {
+    /// Resolves `~/` and cwd-relative file paths; URLs are returned unchanged.
+    pub fn resolve(&self, cwd: Option<&Path>) -> Self {
+        let Self::File { path, line, column } = self else {
+            return self.clone();
+        };
+        let resolved = if path.starts_with("~/") {
+            expand_home(path)
+        } else if Path::new(path).is_absolute() {
+            PathBuf::from(path)
+        } else if let Some(cwd) = cwd {
+            cwd.join(path)
+        } else {
+            PathBuf::from(path)
+        };
+        Self::File {
+            path: resolved.to_string_lossy().to_string(),
+            line: *line,
+            column: *column,
+        }
+    }
+
+    /// Auto-detected file links are only offered when the file actually exists.
+    pub fn exists(&self) -> bool {
+        match self {
+            Self::Url(_) => true,
+            Self::File { path, .. } => Path::new(path).exists(),
+        }
+    }
+}
+
+/// Command templates used to open links; `{url}`, `{file}`, `{line}` and `{column}` are
+/// substituted per argument, so paths containing spaces stay a single argument.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkOpener {
+    pub url_command: String,
+    pub file_command: String,
+}
+
+impl Default for LinkOpener {
+    fn default() -> Self {
+        let url_default = if cfg!(target_os = "macos") {
+            "open {url}"
+        } else {
+            "xdg-open {url}"
+        };
+        Self {
+            url_command: std::env::var("ORCHESTRATERM_URL_OPENER")
+                .unwrap_or_else(|_| url_default.to_string()),
+            file_command: std::env::var("ORCHESTRATERM_FILE_OPENER")
+                .unwrap_or_else(|_| "code --goto {file}:{line}:{column}".to_string()),
+        }
+    }
+}
+
+impl LinkOpener {
+    pub fn argv(&self, target: &LinkTarget) -> Vec<String> {
+        let (template, url, file, line, column) = match target {
+            LinkTarget::Url(url) => {
+                let file = url.strip_prefix("file://").unwrap_or("").to_string();
+                (&self.url_command, url.clone(), file, 1, 1)
+            }
+            LinkTarget::File { path, line, column } => (
+                &self.file_command,
+                format!("file://{path}"),
+                path.clone(),
+                line.unwrap_or(1),
+                column.unwrap_or(1),
+            ),
+        };
+        template
+            .split_whitespace()
+            .map(|arg| {
+                arg.replace("{url}", &url)
+                    .replace("{file}", &file)
+                    .replace("{line}", &line.to_string())
+                    .replace("{column}", &column.to_string())
+            })
+            .collect()
+    }
+
+    pub fn open(&self, target: &LinkTarget) -> Result<()> {
+        let argv = self.argv(target);
+        let (program, args) = argv
+            .split_first()
+            .ok_or_else(|| anyhow::anyhow!("empty link opener command"))?;
+        Command::new(program)
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .with_context(|| format!("failed to run link opener: {program}"))?;
+        Ok(())
+    }
+}
+
+/// Text of one screen row plus the column each char starts in (wide cells span two).
+pub fn row_text(screen: &vt100::Screen, row: u16, cols: u16) -> (String, Vec<u16>) {
+    let mut text = String::new();
+    let mut columns = Vec::new();
+    for col in 0..cols {
+        let Some(cell) = screen.cell(row, col) else {
+            break;
+        };
+        if cell.is_wide_continuation() {
+            continue;
+        }
+        let contents = cell.contents();
+        if contents.is_empty() {
+            text.push(' ');
+            columns.push(col);
+            continue;
+        }
+        for ch in contents.chars().filter(|c| *c != AMBIGUOUS_PAD) {
+            text.push(ch);
+            columns.push(col);
+        }
+    }
+    (text, columns)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn urls_drop_trailing_punctuation() {
+        let spans = detect("see https://example.com/a_(b). and (http://x.io/y)");
+        let urls: Vec<_> = spans.iter().map(|s| s.target.clone()).collect();
+        assert_eq!(
+            urls,
+            vec![
+                LinkTarget::Url("https://example.com/a_(b)".to_string()),
+                LinkTarget::Url("http://x.io/y".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn compiler_locations_are_detected() {
+        let spans = detect("  --> src/gui.rs:646:21");
+        assert_eq!(spans.len(), 1);
+        assert_eq!(spans[0].start, 6);
+        assert_eq!(spans[0].end, 23);
+        assert_eq!(
+            spans[0].target,
+            LinkTarget::File {
+                path: "src/gui.rs".to_string(),
+                line: Some(646),
+                column: Some(21),
+            }
+        );
+        let gcc = detect("main.c:10:5: error: expected ';'");
+        assert!(matches!(
+            &gcc[0].target,
+            LinkTarget::File { path, line: Some(10), column: Some(5) } if path == "main.c"
+        ));
+    }
+
+    #[test]
+    fn plain_words_and_fractions_are_not_links() {
+        assert!(detect("ratio 1/2 done: ok").is_empty());
+        assert!(detect("version 1.2.3").is_empty());
+    }
+
+    #[test]
+    fn relative_paths_resolve_against_cwd_and_fill_templates() {
+        let target = LinkTarget::File {
+            path: "src/lib.rs".to_string(),
+            line: Some(3),
+            column: None,
+        }
+        .resolve(Some(Path::new("/work/my repo")));
+        let opener = LinkOpener {
+            url_command: "open {url}".to_string(),
+            file_command: "editor --goto {file}:{line}:{column}".to_string(),
+        };
+        assert_eq!(
+            opener.argv(&target),
+            vec!["editor", "--goto", "/work/my repo/src/lib.rs:3:1"]
+        );
+    }
+}
@@ -1,9 +1,10 @@
 use anyhow::Result;
 use clap::{Parser, Subcommand};
 use eframe::egui;
+use orchestraterm::command;
 use orchestraterm::engine::{PlanStatus, RecoveryPolicy, TeamDisplayMode, TeamMessagePriority};
 use orchestraterm::gui::OrchestraApp;
-use orchestraterm::protocol::ServerRequest;
+use orchestraterm::protocol::{ServerEvent, ServerRequest};
 use orchestraterm::server;
 
 #[derive(Debug, Parser)]
@@ -28,6 +29,15 @@ enum Command {
         #[command(subcommand)]
         command: TeamCmd,
     },
+    /// Run prompt commands (`split-window -h`, `select-layout tiled`, ...) in a running GUI.
+    #[command(name = "command")]
+    Run {
+        /// Only the GUI showing this session runs them.
+        #[arg(long)]
+        session: Option<String>,
+        #[arg(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+        words: Vec<String>,
+    },
 }
 
 #[derive(Debug, Subcommand)]
@@ -35,8 +45,16 @@ enum ServerCmd {
     Start,
     Ping,
     Sessions,
-    Create { name: String },
-    Attach { name: String },
+    Create {
+        name: String,
+    },
+    Attach {
+        name: String,
+    },
+    /// Print server events (bells, ...) as JSON lines until interrupted.
+    Events,
+    /// List the panes whose shells the server is running.
+    Panes,
 }
 
 #[derive(Debug, Subcommand)]
@@ -163,9 +181,28 @@ fn main() -> Result<()> {
         None | Some(Command::Gui) => run_gui(),
         Some(Command::Server { command }) => run_server_cli(command),
         Some(Command::Team { command }) => run_team_cli(command),
+        Some(Command::Run { session, words }) => run_command_cli(session, &words),
     }
 }
 
+/// Team commands go straight to the server; the rest are published for the GUI to run.
+fn run_command_cli(session: Option<String>, words: &[String]) -> Result<()> {
+    for parsed in command::parse_words(words)? {
+        let resp = match &parsed {
+            command::Command::Team(team) => server::send_request(&team.request())?,
+            _ => {
+                let event = ServerEvent::Command {
+                    session: session.clone(),
+                    command: parsed.line(),
+                };
+                server::send_request(&ServerRequest::Publish { event })?
+            }
+        };
+        println!("{}", resp.message);
+    }
+    Ok(())
+}
+
 fn run_gui() -> Result<()> {
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
@@ -178,10 +215,7 @@ fn run_gui() -> Result<()> {
     eframe::run_native(
         "OrchestraTerm",
         options,
-        Box::new(|cc| {
-            configure_fonts(&cc.egui_ctx);
-            Ok(Box::new(OrchestraApp::new()))
-        }),
+        Box::new(|cc| Ok(Box::new(OrchestraApp::new(&cc.egui_ctx)))),
     )
     .map_err(|e| anyhow::anyhow!("failed to launch GUI: {e}"))?;
 
@@ -213,6 +247,25 @@ fn run_server_cli(command: ServerCmd) -> Result<()> {
             println!("{}", resp.message);
             Ok(())
         }
+        ServerCmd::Events => server::subscribe(|event| {
+            println!("{}", serde_json::to_string(&event)?);
+            Ok(())
+        }),
+        ServerCmd::Panes => {
+            let resp = server::send_request(&ServerRequest::ListPanes)?;
+            for pane in resp.panes {
+                println!(
+                    "{}:{} {} {} ({} attached)",
+                    pane.session,
+                    pane.pane_id,
+                    pane.command.as_deref().unwrap_or("-"),
+                    pane.cwd
+                        .map_or("-".to_string(), |p| p.display().to_string()),
+                    pane.clients
+                );
+            }
+            Ok(())
+        }
     }
 }
 
@@ -496,42 +549,3 @@ fn parse_message_priority(v: &str) -> Result<TeamMessagePriority> {
         _ => Err(anyhow::anyhow!("invalid priority: {v}")),
     }
 }
-
-fn configure_fonts(ctx: &egui::Context) {
-    let mut fonts = egui::FontDefinitions::default();
-
-    // Keep ASCII/ANSI block rendering crisp with a true monospace primary face.
-    if let Ok(bytes) = std::fs::read("/System/Library/Fonts/Menlo.ttc") {
-        fonts
-            .font_data
-            .insert("menlo".to_owned(), egui::FontData::from_owned(bytes).into());
-        if let Some(mono) = fonts.families.get_mut(&egui::FontFamily::Monospace) {
-            mono.insert(0, "menlo".to_owned());
-        }
-    }
-
-    // Korean fallback: appended to avoid replacing monospace pixel-grid metrics.
-    for path in [
-        "/System/Library/Fonts/AppleSDGothicNeo.ttc",
-        "/System/Library/Fonts/AppleGothic.ttf",
-    ] {
-        if let Ok(bytes) = std::fs::read(path) {
-            fonts.font_data.insert(
-                "korean".to_owned(),
-                egui::FontData::from_owned(bytes).into(),
-            );
-            fonts
-                .families
-                .entry(egui::FontFamily::Proportional)
-                .or_default()
-                .push("korean".to_owned());
-            fonts
-                .families
-                .entry(egui::FontFamily::Monospace)
-                .or_default()
-                .push("korean".to_owned());
-            break;
-        }
-    }
-    ctx.set_fonts(fonts);
-}
@@ -0,0 +1,262 @@
+//! Panes hosted by the server, so shells keep running while no GUI shows them.
+//!
+//! Each pane has a PTY and an emulator of its own, which answers the application's
+//! queries and provides the snapshot a client rebuilds the screen from when it attaches.
+//! Output is handed to attached clients while the emulator is still locked, so the
+//! snapshot and the output after it neither overlap nor leave a gap.
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use anyhow::{Context, Result};
+
+use crate::base64;
+use crate::config::Config;
+use crate::core::SessionCore;
+use crate::emulator::Emulator;
+use crate::protocol::{HostedPaneInfo, PaneEvent, PaneInput, PaneSpawn};
+use crate::terminal::{self, PaneTerminal, SharedEmulator};
+use crate::theme::Theme;
+use crate::width::AmbiguousWidth;
+
+/// Every hosted pane, keyed by session and pane id, and the layouts GUIs left behind.
+#[derive(Default)]
+pub struct Mux {
+    panes: Mutex<BTreeMap<(String, usize), Arc<HostedPane>>>,
+    layouts: Mutex<BTreeMap<String, SessionCore>>,
+}
+
+pub struct HostedPane {
+    terminal: Mutex<PaneTerminal>,
+    emulator: SharedEmulator,
+    /// Attached clients; a sender whose client went away is dropped on the next chunk.
+    clients: Arc<Mutex<Vec<Sender<PaneEvent>>>>,
+    exited: Arc<AtomicBool>,
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+impl Mux {
+    /// The running pane, taking on `spawn`'s theme and ambiguous width, or a new one
+    /// started from `spawn` if there is none. With `respawn` a running pane is replaced
+    /// by a new one, and its shell ended.
+    pub fn pane(
+        &self,
+        session: &str,
+        pane_id: usize,
+        spawn: Option<&PaneSpawn>,
+        respawn: bool,
+    ) -> Result<Arc<HostedPane>> {
+        let mut panes = lock(&self.panes);
+        let key = (session.to_string(), pane_id);
+        if let Some(pane) = panes.get(&key)
+            && !respawn
+            && !pane.exited.load(Ordering::Relaxed)
+        {
+            if let Some(spawn) = spawn {
+                pane.set_appearance(spawn.theme.clone(), spawn.ambiguous_width);
+            }
+            return Ok(Arc::clone(pane));
+        }
+        let spawn = spawn.with_context(|| format!("no pane {pane_id} in session {session}"))?;
+        // Never a program named by the client: anyone who can reach the server could
+        // otherwise run anything as its user.
+        let shell = Config::load(None).0.shell_command();
+        let pane = HostedPane::spawn(spawn, &shell)?;
+        if let Some(old) = panes.insert(key, Arc::clone(&pane)) {
+            lock(&old.terminal).kill();
+        }
+        Ok(pane)
+    }
+
+    /// Ends the pane's shell and forgets it, so attaching again starts a new one.
+    pub fn kill(&self, session: &str, pane_id: usize) {
+        let pane = lock(&self.panes).remove(&(session.to_string(), pane_id));
+        if let Some(pane) = pane {
+            lock(&pane.terminal).kill();
+        }
+    }
+
+    /// Running panes, dropping those whose shell has exited.
+    pub fn list(&self) -> Vec<HostedPaneInfo> {
+        let mut panes = lock(&self.panes);
+        panes.retain(|_, pane| !pane.exited.load(Ordering::Relaxed));
+        panes
+            .iter()
+            .map(|((session, pane_id), pane)| {
+                let terminal = lock(&pane.terminal);
+                HostedPaneInfo {
+                    session: session.clone(),
+                    pane_id: *pane_id,
+                    command: terminal.foreground_command(),
+                    cwd: terminal.cwd(),
+                    clients: lock(&pane.clients).len(),
+                }
+            })
+            .collect()
+    }
+
+    pub fn save_layout(&self, layout: SessionCore) {
+        lock(&self.layouts).insert(layout.name.clone(), layout);
+    }
+
+    pub fn layout(&self, session: &str) -> Option<SessionCore> {
+        lock(&self.layouts).get(session).cloned()
+    }
+}
+
+impl HostedPane {
+    fn spawn(spec: &PaneSpawn, shell: &[String]) -> Result<Arc<Self>> {
+        let mut emulator = Emulator::new(spec.rows.max(1), spec.cols.max(2), spec.scrollback);
+        emulator.set_theme(spec.theme.clone());
+        emulator.set_ambiguous_width(spec.ambiguous_width);
+        let emulator = Arc::new(Mutex::new(emulator));
+        let clients = Arc::new(Mutex::new(Vec::<Sender<PaneEvent>>::new()));
+        let exited = Arc::new(AtomicBool::new(false));
+        let (forward_to, exit_flag) = (Arc::clone(&clients), Arc::clone(&exited));
+        let mut terminal = PaneTerminal::spawn(
+            shell,
+            spec.cwd.as_deref(),
+            Arc::clone(&emulator),
+            move |emulator, bytes| {
+                // Bells and clipboard requests reach the GUI through its own copy.
+                emulator.take_bells();
+                emulator.take_clipboard_requests();
+                let mut clients = lock(&forward_to);
+                if bytes.is_empty() {
+                    exit_flag.store(true, Ordering::Relaxed);
+                    for client in clients.drain(..) {
+                        let _ = client.send(PaneEvent::Exited);
+                    }
+                } else if !clients.is_empty() {
+                    let event = PaneEvent::Output {
+                        data: base64::encode(bytes),
+                    };
+                    clients.retain(|tx| tx.send(event.clone()).is_ok());
+                }
+            },
+        )?;
+        terminal.resize(spec.cols, spec.rows, (0, 0))?;
+        Ok(Arc::new(Self {
+            terminal: Mutex::new(terminal),
+            emulator,
+            clients,
+            exited,
+        }))
+    }
+
+    /// Adds a client. Its first event is [`PaneEvent::Attached`] with the current screen,
+    /// followed by the output from then on.
+    pub fn attach(&self) -> Receiver<PaneEvent> {
+        let (tx, rx) = mpsc::channel();
+        let mut emulator = terminal::lock(&self.emulator);
+        let (rows, cols) = emulator.screen().size();
+        let snapshot = base64::encode(&emulator.snapshot());
+        let _ = tx.send(PaneEvent::Attached {
+            rows,
+            cols,
+            snapshot,
+        });
+        if self.exited.load(Ordering::Relaxed) {
+            let _ = tx.send(PaneEvent::Exited);
+        } else {
+            lock(&self.clients).push(tx);
+        }
+        rx
+    }
+
+    /// Applies a client's input. [`PaneInput::Kill`] only ends the shell; [`Mux::kill`]
+    /// also forgets the pane.
+    pub fn apply(&self, input: PaneInput) -> Result<()> {
+        let mut terminal = lock(&self.terminal);
+        match input {
+            PaneInput::Input { data } => terminal.write_bytes(&base64::decode(data.as_bytes())),
+            PaneInput::Resize {
+                rows,
+                cols,
+                pixel_width,
+                pixel_height,
+            } => {
+                {
+                    let mut emulator = terminal::lock(&self.emulator);
+                    emulator.set_size(rows, cols);
+                    emulator.set_cell_pixels(pixel_width / cols.max(1), pixel_height / rows.max(1));
+                }
+                terminal.resize(cols, rows, (pixel_width, pixel_height))
+            }
+            PaneInput::Signal { signal } => terminal.signal(&signal),
+            PaneInput::Log { path } => terminal.set_log(path.as_deref()),
+            PaneInput::Appearance {
+                theme,
+                ambiguous_width,
+            } => {
+                self.set_appearance(theme, ambiguous_width);
+                Ok(())
+            }
+            PaneInput::Kill => {
+                terminal.kill();
+                Ok(())
+            }
+        }
+    }
+
+    fn set_appearance(&self, theme: Theme, ambiguous_width: AmbiguousWidth) {
+        let mut emulator = terminal::lock(&self.emulator);
+        emulator.set_theme(theme);
+        emulator.set_ambiguous_width(ambiguous_width);
+    }
+
+    /// What clients show about the pane besides its screen.
+    pub fn info(&self) -> PaneEvent {
+        let terminal = lock(&self.terminal);
+        PaneEvent::Info {
+            cwd: terminal.cwd(),
+            command: terminal.foreground_command(),
+            logging: terminal.is_logging(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::*;
+    use crate::theme::Rgb;
+
+    #[cfg(unix)]
+    #[test]
+    fn hosted_panes_answer_colour_queries_with_the_clients_theme() {
+        let spec = PaneSpawn {
+            cwd: None,
+            rows: 4,
+            cols: 60,
+            scrollback: 0,
+            theme: Theme {
+                background: Rgb(0x10, 0x20, 0x30),
+                ..Theme::default()
+            },
+            ambiguous_width: AmbiguousWidth::Narrow,
+        };
+        // Asks for the background, then shows its input (the reply) with escapes made
+        // visible.
+        let script = r"stty raw -echo; printf '\033]11;?\007'; exec cat -v";
+        let shell = ["sh", "-c", script].map(String::from);
+        let pane = HostedPane::spawn(&spec, &shell).unwrap();
+        let expected = "^[]11;rgb:1010/2020/3030";
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let contents = loop {
+            let contents = terminal::lock(&pane.emulator).screen().contents();
+            if contents.contains(expected) || Instant::now() > deadline {
+                break contents;
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        };
+        lock(&pane.terminal).kill();
+        assert!(contents.contains(expected), "{contents:?}");
+    }
+}
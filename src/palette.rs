@@ -0,0 +1,433 @@
+//! The Ctrl+Shift+P palette: every command, pane, session, window and team object in one
+//! fuzzy-searchable list.
+//!
+//! Matching is a case-insensitive subsequence search that favours word starts and runs of
+//! consecutive characters, so `spv` finds "Split vertically" and `fzi` ranks
+//! `font-zoom-in` above `fizzing`.
+
+use crate::command::{Command, SIMPLE_COMMANDS};
+use crate::core::{Layout, SplitAxis};
+use crate::engine::{EngineState, MemberStatus, TaskStatus};
+use crate::keymap::Keymap;
+
+/// Most results shown at once.
+pub const MAX_RESULTS: usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ItemKind {
+    Action,
+    Pane,
+    Session,
+    Window,
+    Member,
+    Task,
+}
+
+impl ItemKind {
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Action => "action",
+            Self::Pane => "pane",
+            Self::Session => "session",
+            Self::Window => "window",
+            Self::Member => "member",
+            Self::Task => "task",
+        }
+    }
+}
+
+/// What choosing an item does.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Target {
+    Command(Command),
+    /// Focus this pane of the current session.
+    Pane(usize),
+    /// Attach to this session.
+    Session(String),
+    Member {
+        team: String,
+        member: usize,
+    },
+    Task {
+        team: String,
+        task: usize,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Item {
+    pub kind: ItemKind,
+    /// Shown first and matched first.
+    pub label: String,
+    /// Shown dimmed after the label; matched when the label doesn't match.
+    pub detail: String,
+    pub target: Target,
+}
+
+/// A pane as the palette lists it; the GUI fills in what the shell is doing.
+#[derive(Debug, Clone, Default)]
+pub struct PaneInfo {
+    pub id: usize,
+    pub title: String,
+    pub cwd: Option<String>,
+    pub command: Option<String>,
+}
+
+/// Scores `text` against `query`, returning the score and the char indices that matched,
+/// or `None` if `query` is not a subsequence of `text`. Whitespace in `query` is ignored.
+pub fn fuzzy_match(query: &str, text: &str) -> Option<(i32, Vec<usize>)> {
+    let query: Vec<char> = query
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .flat_map(char::to_lowercase)
+        .collect();
+    let mut positions = Vec::with_capacity(query.len());
+    let mut score = 0;
+    let mut wanted = query.iter().peekable();
+    let mut previous: Option<char> = None;
+    let mut last_match: Option<usize> = None;
+    for (i, c) in text.chars().enumerate() {
+        let Some(&&q) = wanted.peek() else {
+            break;
+        };
+        if c.to_lowercase().eq(std::iter::once(q)) {
+            score += 1;
+            let word_start = previous.is_none_or(|p| !p.is_alphanumeric())
+                || (c.is_uppercase() && previous.is_some_and(char::is_lowercase));
+            if word_start {
+                score += 8;
+            }
+            match last_match {
+                Some(last) if last + 1 == i => score += 5,
+                Some(last) => score -= ((i - last - 1) as i32).min(3),
+                None => score -= (i as i32).min(3),
+            }
+            positions.push(i);
+            last_match = Some(i);
+            wanted.next();
+        }
+        previous = Some(c);
+    }
+    wanted.peek().is_none().then_some((score, positions))
+}
+
+/// Indices of the items matching `query`, best first, each with the label chars to
+/// highlight. An empty query keeps every item in its original order.
+pub fn search(items: &[Item], query: &str) -> Vec<(usize, Vec<usize>)> {
+    if query.trim().is_empty() {
+        return (0..items.len().min(MAX_RESULTS))
+            .map(|i| (i, Vec::new()))
+            .collect();
+    }
+    let mut matches: Vec<(i32, usize, Vec<usize>)> = items
+        .iter()
+        .enumerate()
+        .filter_map(|(i, item)| {
+            if let Some((score, positions)) = fuzzy_match(query, &item.label) {
+                return Some((score, i, positions));
+            }
+            let (score, _) = fuzzy_match(query, &format!("{} {}", item.label, item.detail))?;
+            // A detail-only match ranks below label matches of the same quality.
+            Some((score - 10, i, Vec::new()))
+        })
+        .collect();
+    matches.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+    matches.truncate(MAX_RESULTS);
+    matches
+        .into_iter()
+        .map(|(_, i, positions)| (i, positions))
+        .collect()
+}
+
+/// Commands worth running from the palette, with the keys bound to them. Copy-mode and
+/// buffer-picker commands only make sense inside those modes and are left out.
+pub fn action_items(keymap: &Keymap) -> Vec<Item> {
+    let mut commands: Vec<Command> = SIMPLE_COMMANDS
+        .iter()
+        .map(|(_, command)| command.clone())
+        .filter(|command| {
+            !matches!(
+                command,
+                Command::EnterPrefix
+                    | Command::ExitCopyMode
+                    | Command::CopyMoveUp
+                    | Command::CopyMoveDown
+                    | Command::CopyMoveLeft
+                    | Command::CopyMoveRight
+                    | Command::CopyStartSelection
+                    | Command::CopyCopySelection
+                    | Command::CopySearchStart
+                    | Command::CopySearchApply
+                    | Command::BufferPickerUp
+                    | Command::BufferPickerDown
+                    | Command::BufferPickerPaste
+                    | Command::BufferPickerDelete
+                    | Command::BufferPickerClose
+                    | Command::CommandPalette
+            )
+        })
+        .collect();
+    for axis in [SplitAxis::Horizontal, SplitAxis::Vertical] {
+        commands.push(Command::SplitWindow { axis, dir: None });
+    }
+    commands.push(Command::KillPane(None));
+    commands.push(Command::PasteBuffer(None));
+    commands.extend(Layout::all().iter().copied().map(Command::SelectLayout));
+
+    commands
+        .into_iter()
+        .map(|command| {
+            let detail = match keymap.keys_for(&command) {
+                Some(keys) => format!("{} · {keys}", command.line()),
+                None => command.line(),
+            };
+            Item {
+                kind: ItemKind::Action,
+                label: command.describe(),
+                detail,
+                target: Target::Command(command),
+            }
+        })
+        .collect()
+}
+
+pub fn pane_items(panes: &[PaneInfo]) -> Vec<Item> {
+    panes
+        .iter()
+        .map(|pane| {
+            let mut detail = format!("%{}", pane.id);
+            for part in [&pane.command, &pane.cwd].into_iter().flatten() {
+                detail.push_str(" · ");
+                detail.push_str(part);
+            }
+            Item {
+                kind: ItemKind::Pane,
+                label: pane.title.clone(),
+                detail,
+                target: Target::Pane(pane.id),
+            }
+        })
+        .collect()
+}
+
+/// Sessions and their windows. Windows of the current session jump to their active pane;
+/// the others attach to their session.
+pub fn session_items(state: &EngineState, current: &str) -> Vec<Item> {
+    let mut items = Vec::new();
+    for session in state.sessions.values() {
+        let attached = session.name == current;
+        items.push(Item {
+            kind: ItemKind::Session,
+            label: session.name.clone(),
+            detail: if attached {
+                "attached".to_string()
+            } else {
+                format!("{} windows", session.windows.len())
+            },
+            target: Target::Session(session.name.clone()),
+        });
+        for window in &session.windows {
+            items.push(Item {
+                kind: ItemKind::Window,
+                label: format!("{}:{}", session.name, window.title),
+                detail: format!("{} panes", window.panes.len()),
+                target: if attached {
+                    Target::Pane(window.active_pane)
+                } else {
+                    Target::Session(session.name.clone())
+                },
+            });
+        }
+    }
+    items
+}
+
+/// Active members and unfinished tasks of every team.
+pub fn team_items(state: &EngineState) -> Vec<Item> {
+    let mut items = Vec::new();
+    for team in state.teams.values() {
+        for member in &team.members {
+            if member.status == MemberStatus::Terminated {
+                continue;
+            }
+            let role = if member.is_lead { "lead" } else { "member" };
+            items.push(Item {
+                kind: ItemKind::Member,
+                label: format!("{}/{}", team.id, member.name),
+                detail: format!("#{} {role} · {}", member.id, member.model),
+                target: Target::Member {
+                    team: team.id.clone(),
+                    member: member.id,
+                },
+            });
+        }
+        for task in &team.tasks {
+            if task.status == TaskStatus::Done {
+                continue;
+            }
+            let assignee = task
+                .assignee
+                .and_then(|id| team.members.iter().find(|m| m.id == id))
+                .map_or("unassigned".to_string(), |m| m.name.clone());
+            items.push(Item {
+                kind: ItemKind::Task,
+                label: format!("{}#{} {}", team.id, task.id, task.title),
+                detail: format!("{:?} · {assignee}", task.status),
+                target: Target::Task {
+                    team: team.id.clone(),
+                    task: task.id,
+                },
+            });
+        }
+    }
+    items
+}
+
+/// The open palette: its query, the items it searches and the highlighted result.
+#[derive(Debug, Default)]
+pub struct Palette {
+    pub query: String,
+    pub items: Vec<Item>,
+    /// Index into the current results.
+    pub selected: usize,
+}
+
+impl Palette {
+    pub fn open(&mut self, items: Vec<Item>) {
+        self.query.clear();
+        self.items = items;
+        self.selected = 0;
+    }
+
+    pub fn results(&self) -> Vec<(usize, Vec<usize>)> {
+        search(&self.items, &self.query)
+    }
+
+    /// Moves the highlight by `delta` results, wrapping at either end.
+    pub fn move_selection(&mut self, delta: isize, result_count: usize) {
+        if result_count == 0 {
+            self.selected = 0;
+            return;
+        }
+        let count = result_count as isize;
+        self.selected = (self.selected as isize + delta).rem_euclid(count) as usize;
+    }
+
+    /// The highlighted item, taken out of the palette.
+    pub fn choose(&mut self) -> Option<Item> {
+        let (index, _) = self.results().into_iter().nth(self.selected)?;
+        let item = self.items.swap_remove(index);
+        self.items.clear();
+        Some(item)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::TeamDisplayMode;
+
+    fn labels(items: &[Item], query: &str) -> Vec<String> {
+        search(items, query)
+            .into_iter()
+            .map(|(i, _)| items[i].label.clone())
+            .collect()
+    }
+
+    #[test]
+    fn fuzzy_matching_prefers_word_starts_and_runs() {
+        assert_eq!(fuzzy_match("spv", "Split vertically").unwrap().1, [0, 1, 6]);
+        assert!(fuzzy_match("xyz", "Split vertically").is_none());
+        assert!(fuzzy_match("SPLIT", "split-window").is_some());
+        let (word_starts, _) = fuzzy_match("fzi", "font-zoom-in").unwrap();
+        let (scattered, _) = fuzzy_match("fzi", "fizzing").unwrap();
+        assert!(word_starts > scattered);
+        let (run, _) = fuzzy_match("zoom", "Toggle zoom").unwrap();
+        let (gaps, _) = fuzzy_match("zoom", "zero overflow on macOS").unwrap();
+        assert!(run > gaps);
+    }
+
+    #[test]
+    fn actions_list_bound_keys_and_rank_by_label() {
+        let items = action_items(&Keymap::defaults());
+        let split = items
+            .iter()
+            .find(|i| {
+                i.target
+                    == Target::Command(Command::SplitWindow {
+                        axis: SplitAxis::Vertical,
+                        dir: None,
+                    })
+            })
+            .unwrap();
+        assert_eq!(split.label, "Split vertically");
+        assert_eq!(split.detail, "split-window -h · Ctrl+B, V");
+        assert!(
+            !items
+                .iter()
+                .any(|i| i.target == Target::Command(Command::CopyMoveUp))
+        );
+        assert_eq!(labels(&items, "tiled")[0], "Layout tiled");
+        assert_eq!(labels(&items, "spv")[0], "Split vertically");
+        // `main-vertical` only appears in the detail, so it still matches.
+        assert_eq!(labels(&items, "main-vertical")[0], "Layout main-vertical");
+    }
+
+    #[test]
+    fn panes_sessions_and_team_objects_are_listed() {
+        let panes = pane_items(&[PaneInfo {
+            id: 2,
+            title: "build".into(),
+            cwd: Some("/src/app".into()),
+            command: Some("cargo".into()),
+        }]);
+        assert_eq!(panes[0].detail, "%2 · cargo · /src/app");
+        assert_eq!(labels(&panes, "cargo"), ["build"]);
+
+        let mut state = EngineState::default();
+        state.create_session("review");
+        let sessions = session_items(&state, "default");
+        assert_eq!(
+            sessions
+                .iter()
+                .map(|i| i.label.as_str())
+                .collect::<Vec<_>>(),
+            ["default", "default:Window 0", "review", "review:Window 0"]
+        );
+        assert_eq!(sessions[1].target, Target::Pane(0));
+        assert_eq!(sessions[3].target, Target::Session("review".into()));
+
+        state
+            .create_team("frontend", TeamDisplayMode::InProcess, false)
+            .unwrap();
+        state
+            .add_member("frontend", "alice", "gpt-5", false, true)
+            .unwrap();
+        state
+            .add_task("frontend", "Fix login form", Vec::new(), Vec::new())
+            .unwrap();
+        let team = team_items(&state);
+        assert_eq!(labels(&team, "alice"), ["frontend/alice"]);
+        assert_eq!(labels(&team, "login"), ["frontend#0 Fix login form"]);
+        assert_eq!(team[0].detail, "#0 lead · gpt-5");
+    }
+
+    #[test]
+    fn palette_selection_wraps_and_chooses() {
+        let mut palette = Palette::default();
+        palette.open(action_items(&Keymap::defaults()));
+        palette.query = "layout".into();
+        let count = palette.results().len();
+        assert_eq!(count, Layout::all().len());
+        palette.move_selection(-1, count);
+        assert_eq!(palette.selected, count - 1);
+        palette.move_selection(1, count);
+        assert_eq!(palette.selected, 0);
+        let chosen = palette.choose().unwrap();
+        assert!(matches!(
+            chosen.target,
+            Target::Command(Command::SelectLayout(_))
+        ));
+        assert!(palette.items.is_empty());
+    }
+}
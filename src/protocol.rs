@@ -1,9 +1,23 @@
+use std::path::PathBuf;
+
 use serde::{Deserialize, Serialize};
 
+use crate::core::SessionCore;
 use crate::engine::{
     AgentTeam, PlanStatus, RecoveryPolicy, TeamDisplayMode, TeamMessage, TeamMessagePriority,
     TeamTask, TeamUsage,
 };
+use crate::theme::Theme;
+use crate::width::AmbiguousWidth;
+
+/// A request as sent over the wire: the request's own fields plus `token`, the contents
+/// of the server's token file. Requests without the right token are refused.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Authenticated {
+    pub token: String,
+    #[serde(flatten)]
+    pub request: ServerRequest,
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
@@ -109,6 +123,129 @@ pub enum ServerRequest {
     TeamUsage {
         team_id: String,
     },
+    /// Keeps the connection open and streams every [`ServerEvent`] as a JSON line after
+    /// the initial response.
+    Subscribe,
+    /// Broadcasts an event to subscribers; sent by the GUI.
+    Publish {
+        event: ServerEvent,
+    },
+    /// Connects to a pane hosted by the server, starting it from `spawn` if it is not
+    /// running. After the initial response the connection carries [`PaneInput`] lines
+    /// from the client and [`PaneEvent`] lines from the server.
+    PaneAttach {
+        session: String,
+        pane_id: usize,
+        spawn: Option<PaneSpawn>,
+        /// Ends the running shell and starts a new one from `spawn` in the same step, so
+        /// the attach cannot reach the old shell while it is still exiting.
+        #[serde(default)]
+        respawn: bool,
+    },
+    /// Panes the server is hosting.
+    ListPanes,
+    /// Stores a GUI's layout so the next GUI attaching to the session gets it back.
+    SaveLayout {
+        layout: SessionCore,
+    },
+    /// Returns the stored layout of `session` in [`ServerResponse::layout`], if any.
+    LoadLayout {
+        session: String,
+    },
+}
+
+/// How to start a pane that is not running yet. The program is always the shell from the
+/// server's own config.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PaneSpawn {
+    pub cwd: Option<String>,
+    pub rows: u16,
+    pub cols: u16,
+    pub scrollback: usize,
+    /// What the client draws with; also applied when attaching to a running pane.
+    #[serde(default)]
+    pub theme: Theme,
+    #[serde(default)]
+    pub ambiguous_width: AmbiguousWidth,
+}
+
+/// Sent by a client on a `PaneAttach` connection.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PaneInput {
+    /// Bytes for the PTY, base64-encoded.
+    Input { data: String },
+    Resize {
+        rows: u16,
+        cols: u16,
+        pixel_width: u16,
+        pixel_height: u16,
+    },
+    /// A signal name (`INT`, `TERM`, ...) for the foreground process group.
+    Signal { signal: String },
+    /// Starts logging raw output to `path`, or stops with `None`.
+    Log { path: Option<PathBuf> },
+    /// The client's theme or ambiguous width changed. The server's emulator answers the
+    /// application's colour queries and cursor reports, so it must match what is drawn.
+    Appearance {
+        theme: Theme,
+        ambiguous_width: AmbiguousWidth,
+    },
+    /// Ends the pane's shell.
+    Kill,
+}
+
+/// Sent by the server on a `PaneAttach` connection.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PaneEvent {
+    /// First on every connection: the pane's size and base64 bytes that rebuild its
+    /// screen on a blank emulator of that size.
+    Attached {
+        rows: u16,
+        cols: u16,
+        snapshot: String,
+    },
+    /// PTY output, base64-encoded.
+    Output { data: String },
+    /// Sent when any of these change.
+    Info {
+        cwd: Option<PathBuf>,
+        command: Option<String>,
+        logging: bool,
+    },
+    /// The shell exited or was killed; the server closes the connection after this.
+    Exited,
+}
+
+/// A pane the server is hosting, as listed by `ListPanes`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HostedPaneInfo {
+    pub session: String,
+    pub pane_id: usize,
+    pub command: Option<String>,
+    pub cwd: Option<PathBuf>,
+    /// Clients currently attached.
+    pub clients: usize,
+}
+
+/// Something that happened in a GUI, pushed to `Subscribe` connections.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ServerEvent {
+    /// A pane rang the bell; `count` includes bells coalesced by rate limiting.
+    Bell {
+        session: String,
+        pane_id: usize,
+        pane_title: String,
+        count: usize,
+    },
+    /// Commands for GUIs to run, from `orchestraterm command`; `session` limits them to
+    /// the GUI showing that session.
+    Command {
+        session: Option<String>,
+        command: String,
+    },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -124,6 +261,10 @@ pub struct ServerResponse {
     pub messages: Vec<TeamMessage>,
     #[serde(default)]
     pub usage: Option<TeamUsage>,
+    #[serde(default)]
+    pub panes: Vec<HostedPaneInfo>,
+    #[serde(default)]
+    pub layout: Option<SessionCore>,
 }
 
 impl ServerResponse {
@@ -136,6 +277,8 @@ impl ServerResponse {
             tasks: Vec::new(),
             messages: Vec::new(),
             usage: None,
+            panes: Vec::new(),
+            layout: None,
         }
     }
 
@@ -148,6 +291,8 @@ impl ServerResponse {
             tasks: Vec::new(),
             messages: Vec::new(),
             usage: None,
+            panes: Vec::new(),
+            layout: None,
         }
     }
 }
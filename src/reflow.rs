@@ -0,0 +1,386 @@
+//! Rewrapping of soft-wrapped lines when a terminal changes size.
+//!
+//! `vt100` truncates rows on resize, so the emulator instead collects the logical lines
+//! (rows joined by their wrap flag) from scrollback and screen, and replays them into a
+//! parser of the new size, which wraps them again at the new width.
+
+use std::fmt::Write as _;
+
+use vt100::Color;
+
+/// Rendition of one cell, compared to decide when a new SGR sequence is needed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Pen {
+    fg: Color,
+    bg: Color,
+    bold: bool,
+    italic: bool,
+    underline: bool,
+    inverse: bool,
+}
+
+impl Pen {
+    const DEFAULT: Self = Self {
+        fg: Color::Default,
+        bg: Color::Default,
+        bold: false,
+        italic: false,
+        underline: false,
+        inverse: false,
+    };
+
+    fn of(cell: &vt100::Cell) -> Self {
+        Self {
+            fg: cell.fgcolor(),
+            bg: cell.bgcolor(),
+            bold: cell.bold(),
+            italic: cell.italic(),
+            underline: cell.underline(),
+            inverse: cell.inverse(),
+        }
+    }
+
+    fn write_sgr(&self, out: &mut String) {
+        out.push_str("\x1b[0");
+        for (on, code) in [
+            (self.bold, 1),
+            (self.italic, 3),
+            (self.underline, 4),
+            (self.inverse, 7),
+        ] {
+            if on {
+                let _ = write!(out, ";{code}");
+            }
+        }
+        for (color, base) in [(self.fg, 38), (self.bg, 48)] {
+            match color {
+                Color::Default => {}
+                Color::Idx(i) => {
+                    let _ = write!(out, ";{base};5;{i}");
+                }
+                Color::Rgb(r, g, b) => {
+                    let _ = write!(out, ";{base};2;{r};{g};{b}");
+                }
+            }
+        }
+        out.push('m');
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Cell {
+    /// End of the cell's text in [`Line::text`]; it starts where the previous cell ends.
+    end: usize,
+    width: u16,
+    pen: Pen,
+}
+
+/// A logical line: the cells of consecutive rows joined while the wrap flag is set.
+#[derive(Debug, Default)]
+struct Line {
+    text: String,
+    cells: Vec<Cell>,
+    /// Column offset of the cursor from the start of the line, if it sits on this line.
+    cursor: Option<usize>,
+}
+
+/// Where a line's cells land at a given width.
+struct Layout {
+    /// Rows occupied by the cells alone.
+    content_rows: usize,
+    /// Cursor position relative to the line's first row.
+    cursor: Option<(usize, u16)>,
+}
+
+impl Layout {
+    fn rows(&self) -> usize {
+        self.content_rows
+            .max(self.cursor.map_or(0, |(row, _)| row + 1))
+    }
+}
+
+impl Line {
+    fn width(&self) -> usize {
+        self.cells.iter().map(|c| usize::from(c.width)).sum()
+    }
+
+    fn is_blank(&self) -> bool {
+        self.text.bytes().all(|b| b == b' ') && self.cells.iter().all(|c| c.pen == Pen::DEFAULT)
+    }
+
+    fn push(&mut self, text: &str, width: u16, pen: Pen) {
+        self.text.push_str(text);
+        self.cells.push(Cell {
+            end: self.text.len(),
+            width,
+            pen,
+        });
+    }
+
+    fn push_blanks(&mut self, count: usize) {
+        for _ in 0..count {
+            self.push(" ", 1, Pen::DEFAULT);
+        }
+    }
+
+    /// Mirrors `vt100` wrapping: a character that does not fit moves to the next row,
+    /// and a wide character that would start in the last column is preceded by a blank.
+    fn layout(&self, cols: u16) -> Layout {
+        let place = |pos: &mut (usize, u16), width: u16| {
+            if width == 2 && pos.1 + 1 == cols {
+                pos.1 += 1;
+            }
+            if pos.1 + width > cols {
+                *pos = (pos.0 + 1, 0);
+            }
+            let at = *pos;
+            pos.1 += width;
+            at
+        };
+        let mut pos = (0, 0);
+        let mut offset = 0;
+        let mut cursor = None;
+        for cell in &self.cells {
+            let at = place(&mut pos, cell.width.min(cols));
+            if self
+                .cursor
+                .is_some_and(|c| (offset..offset + usize::from(cell.width)).contains(&c))
+            {
+                cursor = Some(at);
+            }
+            offset += usize::from(cell.width);
+        }
+        let content_rows = pos.0 + 1;
+        if let Some(target) = self.cursor
+            && cursor.is_none()
+        {
+            // Past the end of the text: walk blank columns up to the cursor.
+            let mut at = place(&mut pos, 1);
+            while offset < target {
+                offset += 1;
+                at = place(&mut pos, 1);
+            }
+            cursor = Some(at);
+        }
+        Layout {
+            content_rows,
+            cursor,
+        }
+    }
+
+    /// Bytes that print the line from column 0 on a terminal `cols` wide.
+    fn write_to(&self, out: &mut String, cols: u16) {
+        let mut pen = Pen::DEFAULT;
+        let mut col = 0u16;
+        let mut start = 0;
+        for cell in &self.cells {
+            if cell.width == 2 && col + 1 == cols {
+                if pen != Pen::DEFAULT {
+                    pen = Pen::DEFAULT;
+                    pen.write_sgr(out);
+                }
+                out.push(' ');
+                col = 0;
+            } else if col + cell.width > cols {
+                col = 0;
+            }
+            if cell.pen != pen {
+                pen = cell.pen;
+                pen.write_sgr(out);
+            }
+            out.push_str(&self.text[start..cell.end]);
+            start = cell.end;
+            col += cell.width;
+        }
+        if pen != Pen::DEFAULT {
+            out.push_str("\x1b[0m");
+        }
+    }
+}
+
+/// Replaces `parser` with one of the new size holding the same main screen and
+/// scrollback, rewrapped.
+///
+/// Scroll regions and the saved cursor are not carried over; full-screen programs that
+/// use them redraw on `SIGWINCH` anyway.
+pub fn resize(parser: &mut vt100::Parser, rows: u16, cols: u16, scrollback_len: usize) {
+    let state = Saved::take(parser);
+    let lines = collect_lines(parser);
+    replay(parser, &lines, &state, rows, cols, scrollback_len);
+}
+
+/// The main screen's scrollback and screen as plain text, one logical line per line.
+/// `parser` is only grown and scrolled back while the lines are read, then returned to
+/// its size and the live screen; nothing is replayed, so its state is left as it was.
+pub fn text(parser: &mut vt100::Parser) -> String {
+    let (rows, cols) = parser.screen().size();
+    let lines = collect_lines(parser);
+    // Shrinking drops only the blank rows growing added, and keeps the scroll region.
+    parser.set_size(rows, cols);
+    parser.set_scrollback(0);
+    let mut text = String::new();
+    for (i, line) in lines.iter().enumerate() {
+        if i > 0 {
+            text.push('\n');
+        }
+        text.push_str(line.text.trim_end());
+    }
+    text
+}
+
+/// Bytes that rebuild the main screen, its scrollback, title and modes on a fresh
+/// parser of the same size. `parser` is rebuilt at its own size afterwards.
+pub fn snapshot(parser: &mut vt100::Parser, scrollback_len: usize) -> Vec<u8> {
+    let (rows, cols) = parser.screen().size();
+    let state = Saved::take(parser);
+    let lines = collect_lines(parser);
+    let bytes = replay_bytes(&lines, &state, rows, cols);
+    let mut next = vt100::Parser::new(rows.max(1), cols.max(2), scrollback_len);
+    next.process(&bytes);
+    *parser = next;
+    bytes
+}
+
+/// What is restored after replaying the lines.
+struct Saved {
+    title: Vec<u8>,
+    modes: Vec<u8>,
+}
+
+impl Saved {
+    fn take(parser: &vt100::Parser) -> Self {
+        let screen = parser.screen();
+        let mut modes = screen.attributes_formatted();
+        modes.extend(screen.input_mode_formatted());
+        if screen.hide_cursor() {
+            modes.extend(b"\x1b[?25l");
+        }
+        Self {
+            title: screen.title_formatted(),
+            modes,
+        }
+    }
+}
+
+/// Replaces `parser` with one of the given size holding `lines`.
+fn replay(
+    parser: &mut vt100::Parser,
+    lines: &[Line],
+    saved: &Saved,
+    rows: u16,
+    cols: u16,
+    scrollback_len: usize,
+) {
+    let mut next = vt100::Parser::new(rows.max(1), cols.max(2), scrollback_len);
+    next.process(&replay_bytes(lines, saved, rows, cols));
+    *parser = next;
+}
+
+/// Bytes that print `lines` on a blank terminal of the given size and restore `saved`.
+fn replay_bytes(lines: &[Line], saved: &Saved, rows: u16, cols: u16) -> Vec<u8> {
+    let (rows, cols) = (rows.max(1), cols.max(2));
+    let mut out = String::new();
+    let mut total_rows = 0;
+    let mut cursor = (0, 0);
+    for (i, line) in lines.iter().enumerate() {
+        if i > 0 {
+            out.push_str("\r\n");
+        }
+        line.write_to(&mut out, cols);
+        let layout = line.layout(cols);
+        if let Some((row, col)) = layout.cursor {
+            cursor = (total_rows + row, col);
+        }
+        for _ in layout.content_rows..layout.rows() {
+            out.push_str("\r\n");
+        }
+        total_rows += layout.rows();
+    }
+
+    let top = total_rows.saturating_sub(usize::from(rows));
+    let cursor_row = (cursor.0 - top.min(cursor.0)).min(usize::from(rows - 1));
+    let cursor_col = cursor.1.min(cols - 1);
+    let _ = write!(out, "\x1b[{};{}H", cursor_row + 1, cursor_col + 1);
+    let mut bytes = saved.title.clone();
+    bytes.extend_from_slice(out.as_bytes());
+    bytes.extend_from_slice(&saved.modes);
+    bytes
+}
+
+/// Logical lines from the oldest scrollback row down to the cursor line and any text
+/// below it. Leaves `parser` taller than before, so it must be discarded afterwards.
+fn collect_lines(parser: &mut vt100::Parser) -> Vec<Line> {
+    let (screen_rows, cols) = parser.screen().size();
+    let (cursor_row, cursor_col) = parser.screen().cursor_position();
+    parser.set_scrollback(usize::MAX);
+    let mut history = parser.screen().scrollback();
+    if history > usize::from(screen_rows) {
+        // `vt100` can only scroll back as far as the screen is tall. Growing the screen
+        // appends blank rows below the cursor and keeps the wrap flags, as the width is
+        // unchanged.
+        let tall = history.min(usize::from(u16::MAX)) as u16;
+        parser.set_size(tall, cols);
+        history = usize::from(tall);
+    }
+    let total = history + usize::from(screen_rows);
+
+    let mut lines = Vec::new();
+    let mut line = Line::default();
+    for global in 0..total {
+        // Row 0 of the view scrolled back by `history - global` is scrollback row `global`,
+        // and the row below it is the next one even when that is on the live screen.
+        let row = if global < history {
+            parser.set_scrollback(history - global);
+            0
+        } else {
+            parser.set_scrollback(0);
+            (global - history) as u16
+        };
+        let screen = parser.screen();
+        if global == history + usize::from(cursor_row) {
+            line.cursor = Some(line.width() + usize::from(cursor_col));
+        }
+        // Blanks are only materialised once something follows them on the line.
+        let mut blanks = 0;
+        for col in 0..cols {
+            let Some(cell) = screen.cell(row, col) else {
+                break;
+            };
+            if cell.is_wide_continuation() {
+                continue;
+            }
+            let pen = Pen::of(cell);
+            let text = if cell.has_contents() {
+                cell.contents()
+            } else {
+                String::new()
+            };
+            if pen == Pen::DEFAULT && (text.is_empty() || text == " ") {
+                blanks += 1;
+                continue;
+            }
+            line.push_blanks(blanks);
+            blanks = 0;
+            let text = if text.is_empty() { " " } else { &text };
+            line.push(text, if cell.is_wide() { 2 } else { 1 }, pen);
+        }
+        if !screen.row_wrapped(row) || global + 1 == total {
+            lines.push(std::mem::take(&mut line));
+            continue;
+        }
+        if screen.cell(row + 1, 0).is_some_and(|c| c.is_wide()) {
+            // The blank left in the last column when a wide character wrapped is not text.
+            blanks = blanks.saturating_sub(1);
+        }
+        line.push_blanks(blanks);
+    }
+
+    let keep = lines
+        .iter()
+        .position(|l| l.cursor.is_some())
+        .map_or(1, |i| i + 1);
+    while lines.len() > keep && lines.last().is_some_and(Line::is_blank) {
+        lines.pop();
+    }
+    lines
+}
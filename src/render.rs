@@ -0,0 +1,705 @@
+//! Row-cached drawing of a terminal screen.
+//!
+//! Each row is reduced to merged background spans, underline spans, image and emoji runs
+//! and one text galley whose sections are runs of same-coloured cells. Rows are only rebuilt when
+//! their spans change, and not even compared while the emulator's generation stands still.
+
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+
+use eframe::egui;
+use eframe::epaint::text::{FontsView, LayoutJob, LayoutSection, TextFormat};
+
+use crate::emulator::CursorStyle;
+use crate::graphics::{self, Placement};
+use crate::terminal::{self, SharedEmulator};
+use crate::theme::{Rgb, Theme};
+use crate::width::{self, AMBIGUOUS_PAD};
+
+/// How the terminal font is scaled and spaced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderPreset {
+    Balanced,
+    Compact,
+    Pixel,
+}
+
+impl RenderPreset {
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Balanced => "Balanced",
+            Self::Compact => "Compact",
+            Self::Pixel => "Pixel",
+        }
+    }
+
+    /// Name used in the config file.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Balanced => "balanced",
+            Self::Compact => "compact",
+            Self::Pixel => "pixel",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::all().into_iter().find(|p| p.name() == name.trim())
+    }
+
+    pub fn all() -> [Self; 3] {
+        [Self::Balanced, Self::Compact, Self::Pixel]
+    }
+
+    /// Font scale relative to the configured size, and line height relative to the
+    /// font's own.
+    pub fn scale(self) -> (f32, f32) {
+        match self {
+            Self::Balanced => (1.0, 1.1),
+            Self::Compact => (0.9, 1.05),
+            Self::Pixel => (1.07, 1.15),
+        }
+    }
+}
+
+/// A run of cells `start..end` sharing one colour.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Span {
+    pub start: u16,
+    pub end: u16,
+    pub color: egui::Color32,
+}
+
+/// Consecutive cells laid out as one galley section: either single-codepoint cells of
+/// the same width, or one grapheme cluster, which may span any number of cells.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TextRun {
+    pub col: u16,
+    /// Cells per glyph: 1, or 2 for wide characters; a cluster's whole span.
+    pub width: u16,
+    pub glyphs: u16,
+    pub text: String,
+    pub color: egui::Color32,
+}
+
+/// Consecutive placeholder cells on one screen row showing the same image row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ImageRun {
+    pub col: u16,
+    pub len: u16,
+    /// Placement id, and image row and column of the first cell.
+    pub placeholder: (u32, u16, u16),
+}
+
+/// An emoji cluster drawn as an image from the colour emoji font.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct EmojiRun {
+    pub col: u16,
+    pub width: u16,
+    pub text: String,
+    /// Colour for the text fallback, should the font lack this cluster.
+    pub color: egui::Color32,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub struct RowSpans {
+    pub backgrounds: Vec<Span>,
+    pub underlines: Vec<Span>,
+    pub text: Vec<TextRun>,
+    pub images: Vec<ImageRun>,
+    pub emoji: Vec<EmojiRun>,
+}
+
+/// Cells a character at `col` covers: 2 if the next cell continues it.
+fn cell_width(screen: &vt100::Screen, row: u16, col: u16, cols: u16) -> u16 {
+    if col + 1 < cols
+        && screen
+            .cell(row, col + 1)
+            .is_some_and(|c| c.is_wide_continuation())
+    {
+        2
+    } else {
+        1
+    }
+}
+
+/// Reduces one screen row to drawing primitives, merging neighbouring cells that look
+/// alike. Blank cells join any text run as spaces; trailing blanks are dropped. Emoji go
+/// to their own runs when `color_emoji` is set.
+pub fn row_spans(
+    screen: &vt100::Screen,
+    row: u16,
+    cols: u16,
+    colors: &Theme,
+    color_emoji: bool,
+) -> RowSpans {
+    let mut spans = RowSpans::default();
+    let mut pending_blanks = 0u16;
+    let mut col = 0;
+    while col < cols {
+        let Some(cell) = screen.cell(row, col) else {
+            break;
+        };
+        if cell.is_wide_continuation() {
+            col += 1;
+            continue;
+        }
+        if let Some((id, prow, pcol)) = graphics::placeholder(cell) {
+            match spans.images.last_mut() {
+                Some(run)
+                    if run.col + run.len == col
+                        && run.placeholder == (id, prow, pcol.wrapping_sub(run.len)) =>
+                {
+                    run.len += 1;
+                }
+                _ => spans.images.push(ImageRun {
+                    col,
+                    len: 1,
+                    placeholder: (id, prow, pcol),
+                }),
+            }
+            pending_blanks = 0;
+            col += 1;
+            continue;
+        }
+
+        let mut width = cell_width(screen, row, col, cols);
+        let mut fg = vt_fg_to_egui(cell.fgcolor(), colors);
+        let mut bg = vt_bg_to_egui(cell.bgcolor(), colors);
+        if cell.inverse() {
+            std::mem::swap(&mut fg, &mut bg);
+        }
+        if cell.bgcolor() != vt100::Color::Default || cell.inverse() {
+            push_span(&mut spans.backgrounds, col, col + width, bg);
+        }
+        let mut content = cell.contents();
+        if content.is_empty() || content == " " || content.starts_with(AMBIGUOUS_PAD) {
+            pending_blanks += width;
+            col += width;
+            continue;
+        }
+        // The rest of a cluster whose codepoints took cells of their own, and the padding
+        // of an ambiguous-width character, are drawn as part of this cell.
+        while col + width < cols {
+            let next_col = col + width;
+            let Some(next) = screen.cell(row, next_col) else {
+                break;
+            };
+            let text = next.contents();
+            let pad = text.starts_with(AMBIGUOUS_PAD);
+            if !pad && !width::continues_cluster(&content, &text) {
+                break;
+            }
+            if !pad {
+                content.push_str(&text);
+            }
+            let next_width = cell_width(screen, row, next_col, cols);
+            if next.bgcolor() != vt100::Color::Default || next.inverse() {
+                let bg = if next.inverse() {
+                    vt_fg_to_egui(next.fgcolor(), colors)
+                } else {
+                    vt_bg_to_egui(next.bgcolor(), colors)
+                };
+                push_span(&mut spans.backgrounds, next_col, next_col + next_width, bg);
+            }
+            width += next_width;
+        }
+        if cell.underline() {
+            push_span(&mut spans.underlines, col, col + width, fg);
+        }
+
+        if color_emoji && width::is_emoji(&content) {
+            spans.emoji.push(EmojiRun {
+                col,
+                width,
+                text: content,
+                color: fg,
+            });
+            pending_blanks = 0;
+            col += width;
+            continue;
+        }
+
+        let single = content.chars().nth(1).is_none();
+        match spans.text.last_mut() {
+            Some(run)
+                if single
+                    && run.width == width
+                    && run.color == fg
+                    && (run.glyphs > 1 || run.text.chars().nth(1).is_none())
+                    && run.col + run.glyphs * width + pending_blanks == col
+                    && pending_blanks.is_multiple_of(width) =>
+            {
+                for _ in 0..pending_blanks / width {
+                    run.text.push(' ');
+                }
+                run.text.push_str(&content);
+                run.glyphs += 1 + pending_blanks / width;
+            }
+            _ => spans.text.push(TextRun {
+                col,
+                width,
+                glyphs: 1,
+                text: content,
+                color: fg,
+            }),
+        }
+        pending_blanks = 0;
+        col += width;
+    }
+    spans
+}
+
+fn push_span(spans: &mut Vec<Span>, start: u16, end: u16, color: egui::Color32) {
+    match spans.last_mut() {
+        Some(last) if last.end == start && last.color == color => last.end = end,
+        _ => spans.push(Span { start, end, color }),
+    }
+}
+
+/// Geometry every cached galley depends on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CellMetrics {
+    pub cell_w: f32,
+    pub cell_h: f32,
+    pub font_size: f32,
+    /// Whether emoji are drawn from the colour emoji font rather than the galley.
+    pub color_emoji: bool,
+}
+
+#[derive(Debug)]
+pub struct CachedRow {
+    hash: u64,
+    pub spans: RowSpans,
+    pub galley: Option<Arc<egui::Galley>>,
+}
+
+/// Per-pane cache of laid-out rows.
+#[derive(Debug, Default)]
+pub struct RowCache {
+    generation: Option<u64>,
+    metrics: Option<CellMetrics>,
+    cols: u16,
+    rows: Vec<CachedRow>,
+    rebuilt: usize,
+}
+
+impl RowCache {
+    /// Whether the cache already shows `generation` at this geometry.
+    pub fn is_current(
+        &self,
+        generation: u64,
+        metrics: CellMetrics,
+        (rows, cols): (u16, u16),
+    ) -> bool {
+        self.metrics == Some(metrics)
+            && self.cols == cols
+            && self.generation == Some(generation)
+            && self.rows.len() == usize::from(rows)
+    }
+
+    /// Brings the cache up to date with `screen`, laying out only rows whose spans
+    /// changed. Nothing is compared when `generation` and the geometry are unchanged.
+    pub fn update(
+        &mut self,
+        fonts: &mut FontsView<'_>,
+        screen: &vt100::Screen,
+        generation: u64,
+        colors: &Theme,
+        metrics: CellMetrics,
+        (rows, cols): (u16, u16),
+    ) {
+        if self.is_current(generation, metrics, (rows, cols)) {
+            self.rebuilt = 0;
+            return;
+        }
+        let spans = (0..rows)
+            .map(|row| row_spans(screen, row, cols, colors, metrics.color_emoji))
+            .collect();
+        self.update_spans(fonts, spans, generation, metrics, cols);
+    }
+
+    /// [`Self::update`] from spans already taken from the screen, so rows are laid out
+    /// without holding the emulator.
+    pub fn update_spans(
+        &mut self,
+        fonts: &mut FontsView<'_>,
+        spans: Vec<RowSpans>,
+        generation: u64,
+        metrics: CellMetrics,
+        cols: u16,
+    ) {
+        self.rebuilt = 0;
+        if self.metrics != Some(metrics) || self.cols != cols {
+            self.rows.clear();
+        }
+        self.generation = Some(generation);
+        self.metrics = Some(metrics);
+        self.cols = cols;
+        self.rows.truncate(spans.len());
+
+        for (index, spans) in spans.into_iter().enumerate() {
+            let mut hasher = DefaultHasher::new();
+            spans.hash(&mut hasher);
+            let hash = hasher.finish();
+            if self.rows.get(index).is_some_and(|r| r.hash == hash) {
+                continue;
+            }
+            let galley = layout_row(fonts, &spans.text, metrics);
+            let cached = CachedRow {
+                hash,
+                spans,
+                galley,
+            };
+            if index < self.rows.len() {
+                self.rows[index] = cached;
+            } else {
+                self.rows.push(cached);
+            }
+            self.rebuilt += 1;
+        }
+    }
+
+    pub fn rows(&self) -> &[CachedRow] {
+        &self.rows
+    }
+
+    /// Rows laid out again by the last [`Self::update`].
+    pub fn rebuilt(&self) -> usize {
+        self.rebuilt
+    }
+}
+
+/// What drawing a pane needs from its emulator, copied out under a short lock so rows are
+/// laid out and painted while the reader thread keeps parsing.
+#[derive(Debug, Default)]
+pub struct PaneView {
+    /// Every row's spans and the generation they show, until laid out by the row cache.
+    pub spans: Option<(u64, Vec<RowSpans>)>,
+    pub cursor: (u16, u16),
+    pub cursor_style: CursorStyle,
+    pub hide_cursor: bool,
+    /// Column the cursor box starts at and the cells it covers: a wide character under
+    /// the cursor gets a box over both its cells.
+    pub cursor_cells: (u16, u16),
+    /// Placements shown on the rows, by id.
+    pub placements: HashMap<u32, Placement>,
+}
+
+impl PaneView {
+    /// Copies out whatever `cache` does not show yet. Unless `wait`, a pane whose reader
+    /// thread holds the emulator keeps its last view and `false` comes back at once.
+    pub fn capture(
+        &mut self,
+        emulator: &SharedEmulator,
+        cache: &RowCache,
+        colors: &Theme,
+        metrics: CellMetrics,
+        (rows, cols): (u16, u16),
+        wait: bool,
+    ) -> bool {
+        let emulator = if wait {
+            terminal::lock(emulator)
+        } else {
+            match terminal::try_lock(emulator) {
+                Some(emulator) => emulator,
+                None => return false,
+            }
+        };
+        let generation = emulator.generation();
+        if cache.is_current(generation, metrics, (rows, cols)) {
+            return true;
+        }
+        let screen = emulator.screen();
+        let spans: Vec<RowSpans> = (0..rows)
+            .map(|row| row_spans(screen, row, cols, colors, metrics.color_emoji))
+            .collect();
+        self.placements = spans
+            .iter()
+            .flat_map(|row| &row.images)
+            .filter_map(|run| {
+                let id = run.placeholder.0;
+                Some((id, emulator.placement(id)?.clone()))
+            })
+            .collect();
+        let (row, col) = screen.cursor_position();
+        let continues = |col: u16| {
+            screen
+                .cell(row, col)
+                .is_some_and(|c| c.is_wide_continuation())
+        };
+        let start = if col > 0 && continues(col) {
+            col - 1
+        } else {
+            col
+        };
+        let width = if start + 1 < cols && continues(start + 1) {
+            2
+        } else {
+            1
+        };
+        self.cursor = (row, col);
+        self.cursor_cells = (start, width);
+        self.cursor_style = emulator.cursor_style();
+        self.hide_cursor = screen.hide_cursor();
+        self.spans = Some((generation, spans));
+        true
+    }
+}
+
+/// One galley for the row. Each section holds glyphs of one natural width, spaced so
+/// every glyph advances exactly its cells, and its leading space re-aligns it to its
+/// starting column, so glyphs from fallback fonts cannot push the rest of the row.
+fn layout_row(
+    fonts: &mut FontsView<'_>,
+    runs: &[TextRun],
+    metrics: CellMetrics,
+) -> Option<Arc<egui::Galley>> {
+    if runs.is_empty() {
+        return None;
+    }
+    let font_id = egui::FontId::monospace(metrics.font_size);
+    let mut job = LayoutJob::default();
+    job.wrap.max_width = f32::INFINITY;
+    let mut cursor_x = 0.0;
+    let mut push =
+        |job: &mut LayoutJob, text: &str, start_x: f32, extra: f32, end_x: f32, color| {
+            let begin = job.text.len();
+            job.text.push_str(text);
+            job.sections.push(LayoutSection {
+                leading_space: start_x - cursor_x,
+                byte_range: begin..job.text.len(),
+                format: TextFormat {
+                    font_id: font_id.clone(),
+                    extra_letter_spacing: extra,
+                    line_height: Some(metrics.cell_h),
+                    color,
+                    ..Default::default()
+                },
+            });
+            cursor_x = end_x;
+        };
+    for run in runs {
+        let start_x = f32::from(run.col) * metrics.cell_w;
+        if run.glyphs == 1 {
+            // A single character or cluster: its marks must not be spread apart, and it
+            // is centred in its cells when the font draws it narrower than them.
+            let advance: f32 = run
+                .text
+                .chars()
+                .map(|c| fonts.glyph_width(&font_id, c))
+                .sum();
+            let span = f32::from(run.width) * metrics.cell_w;
+            let x = start_x + ((span - advance) / 2.0).max(0.0);
+            push(&mut job, &run.text, x, 0.0, x + advance, run.color);
+            continue;
+        }
+        let step = f32::from(run.width) * metrics.cell_w;
+        let mut section: Option<(usize, usize, f32)> = None;
+        for (i, (offset, c)) in run.text.char_indices().enumerate() {
+            let advance = fonts.glyph_width(&font_id, c);
+            if let Some((begin, first, width)) = section {
+                if width == advance {
+                    continue;
+                }
+                let glyphs = (i - first) as f32;
+                let x = start_x + first as f32 * step;
+                push(
+                    &mut job,
+                    &run.text[begin..offset],
+                    x,
+                    step - width,
+                    x + (glyphs - 1.0) * step + width,
+                    run.color,
+                );
+            }
+            section = Some((offset, i, advance));
+        }
+        if let Some((begin, first, width)) = section {
+            let glyphs = f32::from(run.glyphs) - first as f32;
+            let x = start_x + first as f32 * step;
+            push(
+                &mut job,
+                &run.text[begin..],
+                x,
+                step - width,
+                x + (glyphs - 1.0) * step + width,
+                run.color,
+            );
+        }
+    }
+    Some(fonts.layout_job(job))
+}
+
+pub fn rgb_to_egui(Rgb(r, g, b): Rgb) -> egui::Color32 {
+    egui::Color32::from_rgb(r, g, b)
+}
+
+fn vt_fg_to_egui(color: vt100::Color, theme: &Theme) -> egui::Color32 {
+    match color {
+        vt100::Color::Default => rgb_to_egui(theme.foreground),
+        vt100::Color::Rgb(r, g, b) => egui::Color32::from_rgb(r, g, b),
+        vt100::Color::Idx(idx) => rgb_to_egui(theme.ansi256(idx)),
+    }
+}
+
+fn vt_bg_to_egui(color: vt100::Color, theme: &Theme) -> egui::Color32 {
+    match color {
+        vt100::Color::Default => rgb_to_egui(theme.background),
+        vt100::Color::Rgb(r, g, b) => egui::Color32::from_rgb(r, g, b),
+        vt100::Color::Idx(idx) => rgb_to_egui(theme.ansi256(idx)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spans(bytes: &[u8], cols: u16) -> RowSpans {
+        let mut parser = vt100::Parser::new(2, cols, 0);
+        parser.process(bytes);
+        row_spans(parser.screen(), 0, cols, &Theme::default(), true)
+    }
+
+    #[test]
+    fn same_colour_cells_merge_across_blanks() {
+        let row = spans(b"ab cd\x1b[31mef\x1b[0m   ", 20);
+        assert_eq!(row.text.len(), 2);
+        assert_eq!((row.text[0].col, row.text[0].glyphs), (0, 5));
+        assert_eq!(row.text[0].text, "ab cd");
+        assert_eq!((row.text[1].col, row.text[1].text.as_str()), (5, "ef"));
+        assert!(row.backgrounds.is_empty());
+    }
+
+    #[test]
+    fn wide_characters_form_their_own_runs() {
+        let row = spans("a한글b".as_bytes(), 20);
+        let runs: Vec<_> = row
+            .text
+            .iter()
+            .map(|r| (r.col, r.width, r.text.as_str()))
+            .collect();
+        assert_eq!(runs, [(0, 1, "a"), (1, 2, "한글"), (5, 1, "b")]);
+    }
+
+    #[test]
+    fn backgrounds_and_underlines_merge() {
+        let row = spans(b"\x1b[44mab  \x1b[4mcd\x1b[0m", 20);
+        assert_eq!(row.backgrounds.len(), 1);
+        assert_eq!((row.backgrounds[0].start, row.backgrounds[0].end), (0, 6));
+        assert_eq!(row.underlines.len(), 1);
+        assert_eq!((row.underlines[0].start, row.underlines[0].end), (4, 6));
+    }
+
+    #[test]
+    fn cache_lays_out_only_changed_rows() {
+        let ctx = egui::Context::default();
+        let mut parser = vt100::Parser::new(4, 20, 0);
+        parser.process(b"one\r\ntwo\r\nthree");
+        let metrics = CellMetrics {
+            cell_w: 9.0,
+            cell_h: 18.0,
+            font_size: 14.0,
+            color_emoji: false,
+        };
+        let mut cache = RowCache::default();
+        let update = |cache: &mut RowCache, screen: &vt100::Screen, generation| {
+            let _ = ctx.run(egui::RawInput::default(), |ctx| {
+                ctx.fonts_mut(|fonts| {
+                    cache.update(
+                        fonts,
+                        screen,
+                        generation,
+                        &Theme::default(),
+                        metrics,
+                        (4, 20),
+                    );
+                });
+            });
+            cache.rebuilt()
+        };
+        assert_eq!(update(&mut cache, parser.screen(), 1), 4);
+        assert_eq!(update(&mut cache, parser.screen(), 1), 0);
+        parser.process(b"\x1b[2;1Htoo");
+        assert_eq!(update(&mut cache, parser.screen(), 2), 1);
+        assert!(cache.rows()[1].galley.is_some());
+        assert!(cache.rows()[3].galley.is_none());
+    }
+
+    #[test]
+    fn a_busy_pane_keeps_its_last_view() {
+        let emulator: SharedEmulator = Arc::new(std::sync::Mutex::new(
+            crate::emulator::Emulator::new(4, 20, 0),
+        ));
+        terminal::lock(&emulator).process(b"ab\x1b[6 q");
+        let metrics = CellMetrics {
+            cell_w: 9.0,
+            cell_h: 18.0,
+            font_size: 14.0,
+            color_emoji: false,
+        };
+        let cache = RowCache::default();
+        let mut view = PaneView::default();
+        let capture = |view: &mut PaneView| {
+            view.capture(
+                &emulator,
+                &cache,
+                &Theme::default(),
+                metrics,
+                (4, 20),
+                false,
+            )
+        };
+        assert!(capture(&mut view));
+        assert_eq!(view.cursor, (0, 2));
+        assert_eq!(view.spans.as_ref().map(|(_, rows)| rows.len()), Some(4));
+
+        // The reader thread is mid-parse: the frame is drawn from the last view at once.
+        let (locked_tx, locked_rx) = std::sync::mpsc::channel();
+        let (release_tx, release_rx) = std::sync::mpsc::channel::<()>();
+        let reader = {
+            let emulator = Arc::clone(&emulator);
+            std::thread::spawn(move || {
+                let mut emulator = terminal::lock(&emulator);
+                locked_tx.send(()).unwrap();
+                release_rx.recv().unwrap();
+                emulator.process(b"cd");
+            })
+        };
+        locked_rx.recv().unwrap();
+        view.spans = None;
+        assert!(!capture(&mut view));
+        assert_eq!(view.cursor, (0, 2));
+        assert!(view.spans.is_none());
+        release_tx.send(()).unwrap();
+        reader.join().unwrap();
+
+        assert!(capture(&mut view));
+        assert_eq!(view.cursor, (0, 4));
+    }
+
+    #[test]
+    fn combining_clusters_stay_alone() {
+        let row = spans("xe\u{301}y".as_bytes(), 20);
+        let texts: Vec<_> = row.text.iter().map(|r| r.text.as_str()).collect();
+        assert_eq!(texts, ["x", "e\u{301}", "y"]);
+    }
+
+    #[test]
+    fn clusters_span_all_their_cells() {
+        let mut parser = vt100::Parser::new(2, 20, 0);
+        parser.process("a👨\u{200D}👩b".as_bytes());
+        let row = row_spans(parser.screen(), 0, 20, &Theme::default(), false);
+        assert!(row.emoji.is_empty());
+        let runs: Vec<_> = row
+            .text
+            .iter()
+            .map(|r| (r.col, r.width, r.glyphs, r.text.as_str()))
+            .collect();
+        assert_eq!(
+            runs,
+            [(0, 1, 1, "a"), (1, 4, 1, "👨\u{200D}👩"), (5, 1, 1, "b")]
+        );
+    }
+}
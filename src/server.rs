@@ -1,38 +1,254 @@
-use std::io::{BufRead, BufReader, Write};
-use std::net::{TcpListener, TcpStream};
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
-use anyhow::{Context, Result};
+use anyhow::{Context, Result, bail};
+use serde::Serialize;
 
+use crate::config::{self, Config};
 use crate::engine::EngineState;
-use crate::protocol::{ServerRequest, ServerResponse};
+use crate::mux::{HostedPane, Mux};
+use crate::protocol::{
+    Authenticated, PaneEvent, PaneInput, PaneSpawn, ServerEvent, ServerRequest, ServerResponse,
+};
+
+/// How often attached clients are told about a pane's directory and command.
+const PANE_INFO_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Open `Subscribe` connections; senders whose connection closed are dropped on the
+/// next broadcast.
+type Subscribers = Arc<Mutex<Vec<Sender<ServerEvent>>>>;
+
+/// Name of the file, next to the user config, holding the token every request carries.
+const TOKEN_FILE: &str = "server-token";
 
 pub fn server_addr() -> String {
-    std::env::var("ORCHESTRATERM_SERVER_ADDR").unwrap_or_else(|_| "127.0.0.1:7899".to_string())
+    Config::load(None).0.server_address
+}
+
+/// The token file. Only its owner can read it, so only they can talk to the server even
+/// though any local user can connect to the port.
+pub fn token_path() -> Result<PathBuf> {
+    let config = config::config_path().with_context(|| "no config directory (HOME is unset)")?;
+    Ok(config.with_file_name(TOKEN_FILE))
+}
+
+/// The token clients must send, made on first use and kept across server restarts. A
+/// file others could read is replaced.
+fn server_token() -> Result<String> {
+    let path = token_path()?;
+    if let Ok(meta) = fs::metadata(&path)
+        && is_private(&meta)
+        && let Ok(token) = fs::read_to_string(&path)
+        && !token.trim().is_empty()
+    {
+        return Ok(token.trim().to_string());
+    }
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("failed to create {}", dir.display()))?;
+    }
+    let _ = fs::remove_file(&path);
+    let token = random_token()?;
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options
+        .open(&path)
+        .with_context(|| format!("failed to create {}", path.display()))?;
+    file.write_all(token.as_bytes())?;
+    Ok(token)
+}
+
+#[cfg(unix)]
+fn is_private(meta: &fs::Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    meta.is_file() && meta.mode() & 0o077 == 0
+}
+
+#[cfg(not(unix))]
+fn is_private(meta: &fs::Metadata) -> bool {
+    meta.is_file()
+}
+
+/// 32 random bytes as hex.
+#[cfg(unix)]
+fn random_token() -> Result<String> {
+    let mut bytes = [0u8; 32];
+    fs::File::open("/dev/urandom")
+        .and_then(|mut random| random.read_exact(&mut bytes))
+        .with_context(|| "failed to read /dev/urandom")?;
+    Ok(bytes.iter().map(|b| format!("{b:02x}")).collect())
+}
+
+/// Four randomly keyed hashes as hex.
+#[cfg(not(unix))]
+fn random_token() -> Result<String> {
+    use std::hash::{BuildHasher, RandomState};
+    Ok((0..4)
+        .map(|i| format!("{:016x}", RandomState::new().hash_one(i)))
+        .collect())
+}
+
+/// Compares every byte, so the time taken does not tell how much of a guess was right.
+fn token_matches(given: &str, token: &str) -> bool {
+    given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
 }
 
 pub fn run_server() -> Result<()> {
     let listener = TcpListener::bind(server_addr()).with_context(|| "failed to bind tcp server")?;
+    let token: Arc<str> = server_token()?.into();
 
     let state = Arc::new(Mutex::new(EngineState::load_or_default()));
     if let Ok(st) = state.lock() {
         let _ = st.save();
     }
+    let subscribers = Subscribers::default();
+    let mux = Arc::new(Mux::default());
 
     for stream in listener.incoming() {
         let Ok(stream) = stream else {
             continue;
         };
         let state = state.clone();
+        let subscribers = subscribers.clone();
+        let mux = mux.clone();
+        let token = token.clone();
         std::thread::spawn(move || {
-            let _ = handle_client(stream, state);
+            let _ = handle_client(stream, &token, state, subscribers, mux);
         });
     }
 
     Ok(())
 }
 
-fn handle_client(stream: TcpStream, state: Arc<Mutex<EngineState>>) -> Result<()> {
+fn write_json(writer: &mut impl Write, value: &impl Serialize) -> Result<()> {
+    let raw = serde_json::to_string(value)?;
+    writer.write_all(raw.as_bytes())?;
+    writer.write_all(b"\n")?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Sends `event` to every live subscriber and returns how many received it.
+fn broadcast(subscribers: &Subscribers, event: &ServerEvent) -> usize {
+    let Ok(mut subscribers) = subscribers.lock() else {
+        return 0;
+    };
+    subscribers.retain(|tx| tx.send(event.clone()).is_ok());
+    subscribers.len()
+}
+
+/// Serves a `Subscribe` connection until the client goes away.
+fn stream_events(writer: &mut TcpStream, subscribers: &Subscribers) -> Result<()> {
+    let (tx, rx) = mpsc::channel();
+    subscribers
+        .lock()
+        .map_err(|_| anyhow::anyhow!("subscriber lock poisoned"))?
+        .push(tx);
+    write_json(writer, &ServerResponse::ok("subscribed"))?;
+    for event in rx {
+        write_json(writer, &event)?;
+    }
+    Ok(())
+}
+
+/// Serves a `PaneAttach` connection: client input is applied on this thread while
+/// another forwards the pane's events, until either side goes away.
+fn serve_pane(
+    reader: BufReader<TcpStream>,
+    mut writer: TcpStream,
+    mux: &Mux,
+    session: &str,
+    pane_id: usize,
+    spawn: Option<PaneSpawn>,
+    respawn: bool,
+) -> Result<()> {
+    let pane = match mux.pane(session, pane_id, spawn.as_ref(), respawn) {
+        Ok(pane) => pane,
+        Err(err) => return write_json(&mut writer, &ServerResponse::err(format!("{err:#}"))),
+    };
+    write_json(&mut writer, &ServerResponse::ok("attached"))?;
+    let events = pane.attach();
+    let closed = Arc::new(AtomicBool::new(false));
+    let forward = {
+        let (pane, closed) = (Arc::clone(&pane), Arc::clone(&closed));
+        let mut writer = writer
+            .try_clone()
+            .with_context(|| "failed to clone stream")?;
+        std::thread::spawn(move || forward_pane_events(&mut writer, &pane, &events, &closed))
+    };
+    for line in reader.lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        let Ok(input) = serde_json::from_str::<PaneInput>(line.trim()) else {
+            continue;
+        };
+        if input == PaneInput::Kill {
+            mux.kill(session, pane_id);
+            break;
+        }
+        let _ = pane.apply(input);
+    }
+    closed.store(true, Ordering::Relaxed);
+    let _ = writer.shutdown(Shutdown::Read);
+    let _ = forward.join();
+    Ok(())
+}
+
+/// Writes `events` to a client, with an [`PaneEvent::Info`] whenever it changes, until
+/// the pane exits or `closed` is set.
+fn forward_pane_events(
+    writer: &mut TcpStream,
+    pane: &HostedPane,
+    events: &Receiver<PaneEvent>,
+    closed: &AtomicBool,
+) -> Result<()> {
+    let mut info = None;
+    let mut checked: Option<Instant> = None;
+    while !closed.load(Ordering::Relaxed) {
+        match events.recv_timeout(PANE_INFO_INTERVAL) {
+            Ok(event) => {
+                write_json(writer, &event)?;
+                if event == PaneEvent::Exited {
+                    break;
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+        if checked.is_none_or(|at| at.elapsed() >= PANE_INFO_INTERVAL) {
+            checked = Some(Instant::now());
+            let current = pane.info();
+            if info.as_ref() != Some(&current) {
+                write_json(writer, &current)?;
+                info = Some(current);
+            }
+        }
+    }
+    let _ = writer.shutdown(Shutdown::Both);
+    Ok(())
+}
+
+fn handle_client(
+    stream: TcpStream,
+    token: &str,
+    state: Arc<Mutex<EngineState>>,
+    subscribers: Subscribers,
+    mux: Arc<Mux>,
+) -> Result<()> {
     let mut writer = stream
         .try_clone()
         .with_context(|| "failed to clone stream")?;
@@ -47,17 +263,56 @@ fn handle_client(stream: TcpStream, state: Arc<Mutex<EngineState>>) -> Result<()
             break;
         }
 
-        let req: ServerRequest = match serde_json::from_str(line.trim()) {
-            Ok(req) => req,
+        let req = match serde_json::from_str::<Authenticated>(line.trim()) {
+            Ok(req) if token_matches(&req.token, token) => req.request,
+            Ok(_) => {
+                return write_json(&mut writer, &ServerResponse::err("invalid token"));
+            }
             Err(err) => {
-                let raw =
-                    serde_json::to_string(&ServerResponse::err(format!("invalid request: {err}")))?;
-                writer.write_all(raw.as_bytes())?;
-                writer.write_all(b"\n")?;
-                writer.flush()?;
+                write_json(
+                    &mut writer,
+                    &ServerResponse::err(format!("invalid request: {err}")),
+                )?;
                 continue;
             }
         };
+        // Events bypass the engine state.
+        let req = match req {
+            ServerRequest::Subscribe => return stream_events(&mut writer, &subscribers),
+            ServerRequest::Publish { event } => {
+                let delivered = broadcast(&subscribers, &event);
+                write_json(
+                    &mut writer,
+                    &ServerResponse::ok(format!("delivered to {delivered} subscribers")),
+                )?;
+                continue;
+            }
+            // Hosted panes and layouts live in the mux, not the saved state.
+            ServerRequest::PaneAttach {
+                session,
+                pane_id,
+                spawn,
+                respawn,
+            } => return serve_pane(reader, writer, &mux, &session, pane_id, spawn, respawn),
+            ServerRequest::ListPanes => {
+                let mut r = ServerResponse::ok("ok");
+                r.panes = mux.list();
+                write_json(&mut writer, &r)?;
+                continue;
+            }
+            ServerRequest::SaveLayout { layout } => {
+                mux.save_layout(layout);
+                write_json(&mut writer, &ServerResponse::ok("layout saved"))?;
+                continue;
+            }
+            ServerRequest::LoadLayout { session } => {
+                let mut r = ServerResponse::ok("ok");
+                r.layout = mux.layout(&session);
+                write_json(&mut writer, &r)?;
+                continue;
+            }
+            req => req,
+        };
 
         let mut guard = state
             .lock()
@@ -253,6 +508,14 @@ fn handle_client(stream: TcpStream, state: Arc<Mutex<EngineState>>) -> Result<()
                 }
                 Err(err) => ServerResponse::err(err.to_string()),
             },
+            ServerRequest::Subscribe
+            | ServerRequest::Publish { .. }
+            | ServerRequest::PaneAttach { .. }
+            | ServerRequest::ListPanes
+            | ServerRequest::SaveLayout { .. }
+            | ServerRequest::LoadLayout { .. } => {
+                unreachable!("handled before taking the state lock")
+            }
         };
 
         let _ = guard.save();
@@ -264,27 +527,150 @@ fn handle_client(stream: TcpStream, state: Arc<Mutex<EngineState>>) -> Result<()
             resp.messages = Vec::new();
         }
 
-        let raw = serde_json::to_string(&resp)?;
-        writer.write_all(raw.as_bytes())?;
-        writer.write_all(b"\n")?;
-        writer.flush()?;
+        write_json(&mut writer, &resp)?;
     }
 
     Ok(())
 }
 
-pub fn send_request(req: &ServerRequest) -> Result<ServerResponse> {
+/// Connects to the server and sends `request` with the token from the token file.
+fn connect(request: &ServerRequest) -> Result<TcpStream> {
+    let path = token_path()?;
+    let token = fs::read_to_string(&path)
+        .with_context(|| format!("failed to read server token {}", path.display()))?;
     let mut stream =
         TcpStream::connect(server_addr()).with_context(|| "failed to connect server")?;
+    stream.set_nodelay(true)?;
+    write_json(
+        &mut stream,
+        &Authenticated {
+            token: token.trim().to_string(),
+            request: request.clone(),
+        },
+    )?;
+    Ok(stream)
+}
 
-    let raw = serde_json::to_string(req)?;
-    stream.write_all(raw.as_bytes())?;
-    stream.write_all(b"\n")?;
-    stream.flush()?;
-
+pub fn send_request(req: &ServerRequest) -> Result<ServerResponse> {
+    let stream = connect(req)?;
     let mut reader = BufReader::new(stream);
     let mut line = String::new();
     reader.read_line(&mut line)?;
     let resp = serde_json::from_str(line.trim())?;
     Ok(resp)
 }
+
+/// Starts `orchestraterm server start` in the background unless a server already
+/// answers, and waits for it to come up. The server gets a process group of its own so
+/// it outlives the terminal or GUI that started it.
+pub fn ensure_running() -> Result<()> {
+    if send_request(&ServerRequest::Ping).is_ok() {
+        return Ok(());
+    }
+    let exe = std::env::current_exe().with_context(|| "failed to locate executable")?;
+    let mut command = std::process::Command::new(exe);
+    command
+        .args(["server", "start"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(&mut command, 0);
+    command.spawn().with_context(|| "failed to start server")?;
+    for _ in 0..40 {
+        std::thread::sleep(Duration::from_millis(50));
+        if send_request(&ServerRequest::Ping).is_ok() {
+            return Ok(());
+        }
+    }
+    bail!("server did not come up at {}", server_addr())
+}
+
+/// Opens a `PaneAttach` connection and returns its two halves, the reader positioned at
+/// the first [`PaneEvent`].
+pub fn attach_pane(
+    session: &str,
+    pane_id: usize,
+    spawn: Option<PaneSpawn>,
+    respawn: bool,
+) -> Result<(BufReader<TcpStream>, TcpStream)> {
+    let stream = connect(&ServerRequest::PaneAttach {
+        session: session.to_string(),
+        pane_id,
+        spawn,
+        respawn,
+    })?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let resp: ServerResponse = serde_json::from_str(line.trim())?;
+    if !resp.ok {
+        bail!("attach failed: {}", resp.message);
+    }
+    Ok((reader, stream))
+}
+
+/// Subscribes to the server's event stream and calls `on_event` for each event until the
+/// server closes the connection or `on_event` fails.
+pub fn subscribe(mut on_event: impl FnMut(ServerEvent) -> Result<()>) -> Result<()> {
+    let stream = connect(&ServerRequest::Subscribe)?;
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let resp: ServerResponse = serde_json::from_str(line.trim())?;
+    if !resp.ok {
+        anyhow::bail!("subscribe failed: {}", resp.message);
+    }
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+        let event = serde_json::from_str(line.trim())
+            .with_context(|| format!("invalid event: {}", line.trim()))?;
+        on_event(event)?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests_without_the_token_are_refused() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let state = Arc::new(Mutex::new(EngineState::default()));
+            let _ = handle_client(
+                stream,
+                "secret",
+                state,
+                Subscribers::default(),
+                Arc::default(),
+            );
+        });
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut ask = |line: &str| {
+            // Once the server has hung up these may fail, which reads as no response.
+            let _ = stream.write_all(format!("{line}\n").as_bytes());
+            let mut response = String::new();
+            let _ = reader.read_line(&mut response);
+            serde_json::from_str::<ServerResponse>(&response).ok()
+        };
+
+        let listed = ask(r#"{"type":"list_panes","token":"secret"}"#).unwrap();
+        assert!(listed.ok, "{}", listed.message);
+        let missing = ask(r#"{"type":"list_panes"}"#).unwrap();
+        assert!(!missing.ok && missing.message.contains("token"));
+        let wrong = ask(
+            r#"{"type":"pane_attach","session":"s","pane_id":1,"spawn":null,"token":"secreT"}"#,
+        )
+        .unwrap();
+        assert_eq!((wrong.ok, wrong.message.as_str()), (false, "invalid token"));
+        // The connection is closed after a wrong token.
+        assert!(ask(r#"{"type":"list_panes","token":"secret"}"#).is_none());
+    }
+}
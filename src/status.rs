@@ -0,0 +1,661 @@
+//! The status line: left, centre and right templates in a tmux-like format language.
+//!
+//! - `#{name}` inserts a variable (`session`, `pane_title`, `active_tasks`, ...)
+//! - `#{?name,then,else}` picks a branch on whether the variable is set (non-empty and
+//!   not `0`); branches may hold any format, and `else` may be left out
+//! - `#[fg=red,bg=#202020,bold]` changes the style until the next `#[...]`; `#[default]`
+//!   resets it
+//! - `#(command)` inserts the first line of a shell command's output, refreshed every
+//!   `status.interval` seconds; a command that runs past [`COMMAND_TIMEOUT`] is killed
+//! - `##` is a literal `#`
+
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::time::Duration;
+
+use anyhow::{Context, Result, bail};
+
+use crate::engine::EngineState;
+use crate::theme::{Rgb, Theme};
+
+pub const DEFAULT_LEFT: &str =
+    "#{?mode,#[fg=yellow,bold]#{mode}#[default] ,}#[fg=green,bold]#{session}#[default] #{window}";
+pub const DEFAULT_CENTRE: &str = "#{pane_title}#{?command, · #{command},}#{?zoomed, (zoomed),}";
+pub const DEFAULT_RIGHT: &str = "#{?team,#[fg=cyan]#{team}#[default] #{active_tasks} active#{?unread, · #[fg=yellow]#{unread} unread#[default],} · $#{cost} · ,}#{time}";
+
+/// The `[status]` section of the config.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatusConfig {
+    pub left: Template,
+    pub centre: Template,
+    pub right: Template,
+    /// How often `#(command)`s rerun and team counts are reread.
+    pub interval: Duration,
+}
+
+impl Default for StatusConfig {
+    fn default() -> Self {
+        Self {
+            left: Template::parse(DEFAULT_LEFT).expect("default status-left"),
+            centre: Template::parse(DEFAULT_CENTRE).expect("default status-centre"),
+            right: Template::parse(DEFAULT_RIGHT).expect("default status-right"),
+            interval: Duration::from_secs(5),
+        }
+    }
+}
+
+impl StatusConfig {
+    pub fn commands(&self) -> Vec<String> {
+        let mut commands = self.left.commands();
+        commands.extend(self.centre.commands());
+        commands.extend(self.right.commands());
+        commands.dedup();
+        commands
+    }
+
+    /// Whether any of the templates reads `var`, so costly variables are only looked up
+    /// when shown.
+    pub fn uses(&self, var: &str) -> bool {
+        [&self.left, &self.centre, &self.right]
+            .iter()
+            .any(|template| template.uses(var))
+    }
+}
+
+/// Variables a template may use, for validation and documentation.
+pub const VARIABLES: &[&str] = &[
+    "session",
+    "window",
+    "mode",
+    "pane_id",
+    "pane_title",
+    "panes",
+    "zoomed",
+    "cwd",
+    "command",
+    "workspace",
+    "team",
+    "active_tasks",
+    "unread",
+    "cost",
+    "tokens",
+    "time",
+    "date",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    /// An entry of the theme's 16-colour palette.
+    Palette(u8),
+    Rgb(Rgb),
+}
+
+impl Color {
+    fn parse(name: &str) -> Option<Self> {
+        const NAMES: [&str; 8] = [
+            "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+        ];
+        if let Some(i) = NAMES.iter().position(|n| *n == name) {
+            return Some(Self::Palette(i as u8));
+        }
+        if let Some(i) = name
+            .strip_prefix("bright")
+            .and_then(|n| NAMES.iter().position(|c| *c == n))
+        {
+            return Some(Self::Palette(i as u8 + 8));
+        }
+        if let Some(i) = name
+            .strip_prefix("colour")
+            .or_else(|| name.strip_prefix("color"))
+        {
+            return i.parse().ok().filter(|i| *i < 16).map(Self::Palette);
+        }
+        Rgb::parse(name).map(Self::Rgb)
+    }
+
+    pub fn resolve(self, theme: &Theme) -> Rgb {
+        match self {
+            Self::Palette(i) => theme.palette[usize::from(i)],
+            Self::Rgb(rgb) => rgb,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Style {
+    pub fg: Option<Color>,
+    pub bg: Option<Color>,
+    pub bold: bool,
+}
+
+impl Style {
+    /// Applies a `#[...]` body: comma-separated `fg=`, `bg=`, `bold`, `nobold` and
+    /// `default`.
+    fn apply(&mut self, spec: &str) -> Result<()> {
+        for part in spec.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            match part.split_once('=') {
+                Some(("fg", color)) => self.fg = Some(parse_color(color)?),
+                Some(("bg", color)) => self.bg = Some(parse_color(color)?),
+                None if part == "bold" => self.bold = true,
+                None if part == "nobold" => self.bold = false,
+                None if part == "default" => *self = Self::default(),
+                _ => bail!("unknown style: {part}"),
+            }
+        }
+        Ok(())
+    }
+}
+
+fn parse_color(name: &str) -> Result<Color> {
+    Color::parse(name.trim()).with_context(|| format!("unknown colour: {name}"))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Text(String),
+    Var(String),
+    Cond {
+        var: String,
+        then: Vec<Node>,
+        otherwise: Vec<Node>,
+    },
+    Style(String),
+    Shell(String),
+}
+
+/// A parsed template.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Template(Vec<Node>);
+
+impl Template {
+    pub fn parse(text: &str) -> Result<Self> {
+        let chars: Vec<char> = text.chars().collect();
+        let (nodes, rest) = parse_nodes(&chars, false)?;
+        if !rest.is_empty() {
+            bail!("unexpected {}", rest.iter().collect::<String>());
+        }
+        let template = Self(nodes);
+        template.validate()?;
+        Ok(template)
+    }
+
+    fn validate(&self) -> Result<()> {
+        fn check(nodes: &[Node]) -> Result<()> {
+            for node in nodes {
+                match node {
+                    Node::Var(var) => check_var(var)?,
+                    Node::Cond {
+                        var,
+                        then,
+                        otherwise,
+                    } => {
+                        check_var(var)?;
+                        check(then)?;
+                        check(otherwise)?;
+                    }
+                    Node::Style(spec) => Style::default().apply(spec)?,
+                    Node::Text(_) | Node::Shell(_) => {}
+                }
+            }
+            Ok(())
+        }
+        fn check_var(var: &str) -> Result<()> {
+            if !VARIABLES.contains(&var) {
+                bail!("unknown variable: {var}");
+            }
+            Ok(())
+        }
+        check(&self.0)
+    }
+
+    /// Whether `#{var}` or `#{?var,...}` appears anywhere in the template.
+    pub fn uses(&self, var: &str) -> bool {
+        fn find(nodes: &[Node], var: &str) -> bool {
+            nodes.iter().any(|node| match node {
+                Node::Var(name) => name == var,
+                Node::Cond {
+                    var: name,
+                    then,
+                    otherwise,
+                } => name == var || find(then, var) || find(otherwise, var),
+                _ => false,
+            })
+        }
+        find(&self.0, var)
+    }
+
+    /// The `#(command)`s in the template, wherever they appear.
+    pub fn commands(&self) -> Vec<String> {
+        fn collect(nodes: &[Node], out: &mut Vec<String>) {
+            for node in nodes {
+                match node {
+                    Node::Shell(command) => out.push(command.clone()),
+                    Node::Cond {
+                        then, otherwise, ..
+                    } => {
+                        collect(then, out);
+                        collect(otherwise, out);
+                    }
+                    _ => {}
+                }
+            }
+        }
+        let mut out = Vec::new();
+        collect(&self.0, &mut out);
+        out
+    }
+
+    /// Expands the template into styled spans. Commands that have not produced output
+    /// yet expand to nothing.
+    pub fn render(
+        &self,
+        vars: &HashMap<&str, String>,
+        shell_output: &HashMap<String, String>,
+    ) -> Vec<(String, Style)> {
+        fn walk(
+            nodes: &[Node],
+            vars: &HashMap<&str, String>,
+            shell_output: &HashMap<String, String>,
+            style: &mut Style,
+            out: &mut Vec<(String, Style)>,
+        ) {
+            for node in nodes {
+                let text = match node {
+                    Node::Text(text) => text.clone(),
+                    Node::Var(var) => vars.get(var.as_str()).cloned().unwrap_or_default(),
+                    Node::Shell(command) => shell_output.get(command).cloned().unwrap_or_default(),
+                    Node::Style(spec) => {
+                        // Validated when parsed.
+                        let _ = style.apply(spec);
+                        continue;
+                    }
+                    Node::Cond {
+                        var,
+                        then,
+                        otherwise,
+                    } => {
+                        let set = vars
+                            .get(var.as_str())
+                            .is_some_and(|v| !v.is_empty() && v != "0");
+                        let branch = if set { then } else { otherwise };
+                        walk(branch, vars, shell_output, style, out);
+                        continue;
+                    }
+                };
+                match out.last_mut() {
+                    Some((last, last_style)) if *last_style == *style => last.push_str(&text),
+                    _ if text.is_empty() => {}
+                    _ => out.push((text, *style)),
+                }
+            }
+        }
+        let mut out = Vec::new();
+        walk(&self.0, vars, shell_output, &mut Style::default(), &mut out);
+        out
+    }
+}
+
+/// Parses until the end of input or, inside a conditional, an unescaped `,` or `}`,
+/// returning the nodes and the unparsed rest.
+fn parse_nodes(mut chars: &[char], in_branch: bool) -> Result<(Vec<Node>, &[char])> {
+    let mut nodes = Vec::new();
+    let mut text = String::new();
+    loop {
+        match chars {
+            [] => break,
+            [',' | '}', ..] if in_branch => break,
+            ['#', '#' | ',' | '}', rest @ ..] => {
+                text.push(chars[1]);
+                chars = rest;
+            }
+            ['#', '{', '?', rest @ ..] => {
+                let end = rest
+                    .iter()
+                    .position(|c| *c == ',')
+                    .context("expected , after the condition")?;
+                let var: String = rest[..end].iter().collect();
+                let (then, after) = parse_nodes(&rest[end + 1..], true)?;
+                let (otherwise, after) = match after {
+                    [',', rest @ ..] => parse_nodes(rest, true)?,
+                    _ => (Vec::new(), after),
+                };
+                let ['}', rest @ ..] = after else {
+                    bail!("unterminated #{{?{var},...}}");
+                };
+                flush(&mut text, &mut nodes);
+                nodes.push(Node::Cond {
+                    var: var.trim().to_string(),
+                    then,
+                    otherwise,
+                });
+                chars = rest;
+            }
+            ['#', open @ ('{' | '[' | '('), rest @ ..] => {
+                let close = match open {
+                    '{' => '}',
+                    '[' => ']',
+                    _ => ')',
+                };
+                let end = rest
+                    .iter()
+                    .position(|c| *c == close)
+                    .with_context(|| format!("unterminated #{open}"))?;
+                let body: String = rest[..end].iter().collect();
+                flush(&mut text, &mut nodes);
+                nodes.push(match open {
+                    '{' => Node::Var(body.trim().to_string()),
+                    '[' => Node::Style(body),
+                    _ => Node::Shell(body),
+                });
+                chars = &rest[end + 1..];
+            }
+            [c, rest @ ..] => {
+                text.push(*c);
+                chars = rest;
+            }
+        }
+    }
+    flush(&mut text, &mut nodes);
+    Ok((nodes, chars))
+}
+
+fn flush(text: &mut String, nodes: &mut Vec<Node>) {
+    if !text.is_empty() {
+        nodes.push(Node::Text(std::mem::take(text)));
+    }
+}
+
+/// The team variables for `team`: the team of the focused pane's member if its title
+/// names one, else the first team.
+pub fn team_vars(state: &EngineState, pane_title: &str, vars: &mut HashMap<&str, String>) {
+    let found = state.teams.values().find_map(|team| {
+        let member = team
+            .members
+            .iter()
+            .find(|m| m.name.eq_ignore_ascii_case(pane_title))?;
+        Some((team, Some(member.id)))
+    });
+    let Some((team, viewer)) = found.or_else(|| state.teams.values().next().map(|t| (t, None)))
+    else {
+        return;
+    };
+    // A member's unread messages, or messages nobody has read yet.
+    let unread = team
+        .messages
+        .iter()
+        .filter(|m| match viewer {
+            Some(id) => m.to_member.is_none_or(|to| to == id) && !m.read_by.contains(&id),
+            None => m.read_by.is_empty(),
+        })
+        .count();
+    let usage = state.team_usage(&team.id).unwrap_or_default();
+    vars.insert("team", team.id.clone());
+    vars.insert("active_tasks", usage.active_tasks.to_string());
+    vars.insert("unread", unread.to_string());
+    vars.insert("cost", format!("{:.2}", usage.cost_usd));
+    vars.insert(
+        "tokens",
+        (usage.input_tokens + usage.output_tokens).to_string(),
+    );
+}
+
+/// `path` with the home directory shown as `~`.
+pub fn abbreviate_home(path: &Path) -> String {
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+    match home
+        .as_deref()
+        .and_then(|home| path.strip_prefix(home).ok())
+    {
+        Some(rest) if rest.as_os_str().is_empty() => "~".to_string(),
+        Some(rest) => format!("~/{}", rest.display()),
+        None => path.display().to_string(),
+    }
+}
+
+/// How long a `#(command)` may run before it is killed.
+pub const COMMAND_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long a killed `#(command)`'s output may take to close.
+const KILL_GRACE: Duration = Duration::from_millis(500);
+
+/// Shown for a `#(command)` that ran over or could not start and has no earlier output.
+pub const COMMAND_FAILED: &str = "#(?)";
+
+/// The first line of `sh -c command`'s output, or `None` if it could not start or did not
+/// finish within `timeout`.
+///
+/// The command runs in a process group of its own, and the whole group is killed when it
+/// is done with, so pipelines and background jobs do not outlive it.
+pub fn run_command(command: &str, timeout: Duration) -> Option<String> {
+    let mut command_line = Command::new("sh");
+    command_line
+        .args(["-c", command])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null());
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(&mut command_line, 0);
+    let mut child = command_line.spawn().ok()?;
+    let mut stdout = child.stdout.take()?;
+    // Read on another thread, so the deadline holds however long the output stays open.
+    let (tx, rx) = mpsc::channel();
+    let reader = std::thread::spawn(move || {
+        let mut out = Vec::new();
+        let _ = stdout.read_to_end(&mut out);
+        let _ = tx.send(out);
+    });
+    let out = rx.recv_timeout(timeout).ok();
+    #[cfg(unix)]
+    let _ = Command::new("kill")
+        .args(["-s", "KILL", "--", &format!("-{}", child.id())])
+        .stderr(Stdio::null())
+        .status();
+    let _ = child.kill();
+    let _ = child.wait();
+    // With the group gone the pipe closes and the reader finishes. Only a process that
+    // left the group can still hold it open; that reader is left behind rather than
+    // stalling the status line.
+    if out.is_some() || rx.recv_timeout(KILL_GRACE).is_ok() {
+        let _ = reader.join();
+    }
+    let out = out?;
+    let text = String::from_utf8_lossy(&out);
+    Some(text.lines().next().unwrap_or_default().trim().to_string())
+}
+
+/// The local offset from UTC in seconds, as reported by `date +%z`; zero if unknown.
+pub fn utc_offset() -> i64 {
+    Command::new("date")
+        .arg("+%z")
+        .output()
+        .ok()
+        .and_then(|out| parse_utc_offset(String::from_utf8_lossy(&out.stdout).trim()))
+        .unwrap_or(0)
+}
+
+/// Parses `+0900` or `-0330`.
+fn parse_utc_offset(text: &str) -> Option<i64> {
+    let (sign, digits) = match text.split_at_checked(1)? {
+        ("+", digits) => (1, digits),
+        ("-", digits) => (-1, digits),
+        _ => return None,
+    };
+    if digits.len() != 4 || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let hours: i64 = digits[..2].parse().ok()?;
+    let minutes: i64 = digits[2..].parse().ok()?;
+    Some(sign * (hours * 3600 + minutes * 60))
+}
+
+/// `YYYY-MM-DD` and `HH:MM` for a Unix time shifted by `offset` seconds.
+pub fn date_and_time(unix_secs: i64, offset: i64) -> (String, String) {
+    let secs = unix_secs + offset;
+    let days = secs.div_euclid(86_400);
+    let of_day = secs.rem_euclid(86_400);
+    // Howard Hinnant's civil_from_days.
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (
+        format!("{year:04}-{month:02}-{day:02}"),
+        format!("{:02}:{:02}", of_day / 3600, of_day % 3600 / 60),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{TeamDisplayMode, TeamMessagePriority};
+
+    fn render(template: &str, vars: &[(&'static str, &str)]) -> Vec<(String, Style)> {
+        let vars = vars.iter().map(|(k, v)| (*k, v.to_string())).collect();
+        let shell = HashMap::from([("whoami".to_string(), "dev".to_string())]);
+        Template::parse(template).unwrap().render(&vars, &shell)
+    }
+
+    fn text(template: &str, vars: &[(&'static str, &str)]) -> String {
+        render(template, vars).into_iter().map(|(t, _)| t).collect()
+    }
+
+    #[test]
+    fn expands_variables_conditionals_and_commands() {
+        let vars = [("session", "main"), ("unread", "0"), ("team", "core")];
+        assert_eq!(text("[#{session}] ##1", &vars), "[main] #1");
+        assert_eq!(text("#{?team,team #{team},solo}", &vars), "team core");
+        assert_eq!(text("#{?unread,#{unread} new,none}", &vars), "none");
+        assert_eq!(text("#{?cwd,in #{cwd}}!", &vars), "!");
+        assert_eq!(text("a#{?team,x#,y}b", &vars), "ax,yb");
+        assert_eq!(text("#(whoami)@#(hostname)", &vars), "dev@");
+        assert_eq!(
+            Template::parse("#(whoami) #{?team,#(date)}")
+                .unwrap()
+                .commands(),
+            ["whoami", "date"]
+        );
+        let template = Template::parse("#{session} #{?team,#{?zoomed,#{cwd}}}").unwrap();
+        assert!(template.uses("session") && template.uses("zoomed") && template.uses("cwd"));
+        assert!(!template.uses("command"));
+    }
+
+    #[test]
+    fn styles_split_spans() {
+        let spans = render("#[fg=red,bold]A#[bg=#102030]B#[default]C", &[]);
+        let red = Style {
+            fg: Some(Color::Palette(1)),
+            bg: None,
+            bold: true,
+        };
+        assert_eq!(
+            spans,
+            [
+                ("A".to_string(), red),
+                (
+                    "B".to_string(),
+                    Style {
+                        bg: Some(Color::Rgb(Rgb(0x10, 0x20, 0x30))),
+                        ..red
+                    }
+                ),
+                ("C".to_string(), Style::default()),
+            ]
+        );
+        assert_eq!(Color::parse("brightblue"), Some(Color::Palette(12)));
+        assert_eq!(Color::parse("colour3"), Some(Color::Palette(3)));
+    }
+
+    #[test]
+    fn rejects_bad_templates() {
+        let error = |t| format!("{:#}", Template::parse(t).unwrap_err());
+        assert_eq!(error("#{sesion}"), "unknown variable: sesion");
+        assert_eq!(error("#{session"), "unterminated #{");
+        assert_eq!(error("#{?team,a"), "unterminated #{?team,...}");
+        assert_eq!(error("#[fg=mauve]"), "unknown colour: mauve");
+        assert_eq!(error("#[blink]"), "unknown style: blink");
+        for default in [DEFAULT_LEFT, DEFAULT_CENTRE, DEFAULT_RIGHT] {
+            Template::parse(default).unwrap();
+        }
+    }
+
+    #[test]
+    fn team_variables_follow_the_focused_member() {
+        let mut state = EngineState::default();
+        state
+            .create_team("core", TeamDisplayMode::InProcess, false)
+            .unwrap();
+        state
+            .add_member("core", "lead", "gpt-5", false, true)
+            .unwrap();
+        state
+            .add_member("core", "bob", "gpt-5", false, false)
+            .unwrap();
+        state.add_task("core", "a", Vec::new(), Vec::new()).unwrap();
+        state.claim_task("core", 1, 0).unwrap();
+        state
+            .post_message("core", None, Some(1), "hi", TeamMessagePriority::Normal)
+            .unwrap();
+
+        let mut vars = HashMap::new();
+        team_vars(&state, "Bob", &mut vars);
+        assert_eq!(vars["team"], "core");
+        assert_eq!(vars["active_tasks"], "1");
+        assert_eq!(vars["unread"], "1");
+        assert_eq!(vars["cost"], "0.00");
+        state.mark_message_read("core", 1, 0).unwrap();
+        team_vars(&state, "Bob", &mut vars);
+        assert_eq!(vars["unread"], "0");
+    }
+
+    #[test]
+    fn clock_uses_the_local_offset() {
+        assert_eq!(parse_utc_offset("+0900"), Some(9 * 3600));
+        assert_eq!(parse_utc_offset("-0330"), Some(-(3 * 3600 + 30 * 60)));
+        assert_eq!(parse_utc_offset("UTC"), None);
+        // 2024-02-29 23:30 UTC.
+        let t = 1_709_249_400;
+        assert_eq!(
+            date_and_time(t, 0),
+            ("2024-02-29".to_string(), "23:30".to_string())
+        );
+        assert_eq!(
+            date_and_time(t, 9 * 3600),
+            ("2024-03-01".to_string(), "08:30".to_string())
+        );
+    }
+
+    #[test]
+    fn commands_that_run_over_are_killed() {
+        let timeout = Duration::from_millis(300);
+        assert_eq!(
+            run_command("echo one; echo two", timeout).as_deref(),
+            Some("one")
+        );
+        let started = std::time::Instant::now();
+        assert_eq!(run_command("sleep 5 & wait", timeout), None);
+        assert!(started.elapsed() < Duration::from_secs(2));
+
+        // The pipeline's processes go with the shell.
+        assert_eq!(run_command("sleep 7.25 | cat", timeout), None);
+        let running = || {
+            let procs = std::fs::read_dir("/proc").into_iter().flatten().flatten();
+            procs
+                .filter_map(|entry| std::fs::read(entry.path().join("cmdline")).ok())
+                .any(|cmdline| cmdline.starts_with(b"sleep\x007.25"))
+        };
+        // SIGKILL takes effect asynchronously.
+        let started = std::time::Instant::now();
+        while running() && started.elapsed() < Duration::from_secs(2) {
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        assert!(!running());
+    }
+}
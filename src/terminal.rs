@@ -1,18 +1,83 @@
-use std::io::{Read, Write};
-use std::sync::{Arc, Mutex, mpsc};
+use std::fs::File;
+use std::io::{BufRead, Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, TryLockError};
 
-use anyhow::{Context, Result};
+use anyhow::{Context, Result, bail};
 use portable_pty::{CommandBuilder, MasterPty, PtySize, native_pty_system};
 
+use crate::base64;
+use crate::emulator::Emulator;
+use crate::protocol::{PaneEvent, PaneInput, PaneSpawn};
+use crate::server;
+use crate::theme::Theme;
+use crate::width::AmbiguousWidth;
+
+/// A pane's emulator, fed by its reader thread and drawn by the GUI.
+pub type SharedEmulator = Arc<Mutex<Emulator>>;
+
+/// Output is parsed in slices this big, unlocking the emulator in between, so a pane
+/// streaming output never keeps it from the GUI for long.
+const PARSE_SLICE: usize = 4 * 1024;
+
+/// Locks `emulator`, carrying on with its state if the other side panicked.
+pub fn lock(emulator: &SharedEmulator) -> MutexGuard<'_, Emulator> {
+    emulator.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// [`lock`] without waiting: `None` while another thread holds the emulator.
+pub fn try_lock(emulator: &SharedEmulator) -> Option<MutexGuard<'_, Emulator>> {
+    match emulator.try_lock() {
+        Ok(emulator) => Some(emulator),
+        Err(TryLockError::Poisoned(poisoned)) => Some(poisoned.into_inner()),
+        Err(TryLockError::WouldBlock) => None,
+    }
+}
+
+/// A pane's shell: a PTY of this process, or one hosted by the server and reached over
+/// a `PaneAttach` connection.
 pub struct PaneTerminal {
+    backend: Backend,
+}
+
+enum Backend {
+    Local(LocalPty),
+    Remote(RemotePty),
+}
+
+struct LocalPty {
     writer: Arc<Mutex<Box<dyn Write + Send>>>,
     master: Arc<Mutex<Box<dyn MasterPty + Send>>>,
-    pub output_rx: mpsc::Receiver<Vec<u8>>,
     child: Box<dyn portable_pty::Child + Send>,
+    /// Where the reader thread copies raw output while logging.
+    log: Arc<Mutex<Option<File>>>,
+}
+
+struct RemotePty {
+    stream: Mutex<TcpStream>,
+    /// What the server last reported about the pane.
+    info: Arc<Mutex<RemoteInfo>>,
+}
+
+#[derive(Default)]
+struct RemoteInfo {
+    cwd: Option<PathBuf>,
+    command: Option<String>,
+    logging: bool,
 }
 
 impl PaneTerminal {
-    pub fn spawn(initial_dir: Option<&str>) -> Result<Self> {
+    /// Starts `shell` (the program, then its arguments). Its output is parsed into
+    /// `emulator` on a reader thread, which also answers the emulator's queries.
+    /// `on_output` runs with the emulator still locked after each chunk is applied, and
+    /// with an empty chunk when the PTY closes, so the caller can redraw or forward it.
+    pub fn spawn(
+        shell: &[String],
+        initial_dir: Option<&str>,
+        emulator: SharedEmulator,
+        on_output: impl Fn(&mut Emulator, &[u8]) + Send + 'static,
+    ) -> Result<Self> {
         let pty_system = native_pty_system();
         let pair = pty_system
             .openpty(PtySize {
@@ -23,8 +88,9 @@ impl PaneTerminal {
             })
             .with_context(|| "failed to open pty")?;
 
-        let mut cmd = CommandBuilder::new("/bin/zsh");
-        cmd.arg("-i");
+        let (program, args) = shell.split_first().context("no shell configured")?;
+        let mut cmd = CommandBuilder::new(program);
+        cmd.args(args);
         cmd.env("TERM", "xterm-256color");
         cmd.env("COLORTERM", "truecolor");
         cmd.env("TERM_PROGRAM", "OrchestraTerm");
@@ -45,26 +111,51 @@ impl PaneTerminal {
             .with_context(|| "failed to take pty writer")?;
 
         let master = Arc::new(Mutex::new(pair.master));
-        let (tx, rx) = mpsc::channel::<Vec<u8>>();
+        let writer = Arc::new(Mutex::new(writer));
+        let reply_writer = Arc::clone(&writer);
+        let log = Arc::new(Mutex::new(None::<File>));
+        let reader_log = Arc::clone(&log);
 
         std::thread::spawn(move || {
-            let mut buf = [0_u8; 8192];
+            let mut buf = vec![0_u8; 64 * 1024];
             loop {
                 match reader.read(&mut buf) {
                     Ok(0) => break,
                     Ok(n) => {
-                        let _ = tx.send(buf[..n].to_vec());
+                        let mut replies = Vec::new();
+                        for slice in buf[..n].chunks(PARSE_SLICE) {
+                            let mut emulator = lock(&emulator);
+                            emulator.process(slice);
+                            on_output(&mut emulator, slice);
+                            replies.extend(emulator.take_responses());
+                        }
+                        // Written outside the lock: a child that stops reading must not
+                        // stall the GUI.
+                        if !replies.is_empty()
+                            && let Ok(mut writer) = reply_writer.lock()
+                        {
+                            let _ = writer.write_all(&replies).and_then(|()| writer.flush());
+                        }
+                        if let Ok(mut log) = reader_log.lock()
+                            && let Some(file) = log.as_mut()
+                            && file.write_all(&buf[..n]).is_err()
+                        {
+                            *log = None;
+                        }
                     }
                     Err(_) => break,
                 }
             }
+            on_output(&mut lock(&emulator), &[]);
         });
 
         let mut terminal = Self {
-            writer: Arc::new(Mutex::new(writer)),
-            master,
-            output_rx: rx,
-            child,
+            backend: Backend::Local(LocalPty {
+                writer,
+                master,
+                child,
+                log,
+            }),
         };
 
         terminal.send_line("clear")?;
@@ -75,6 +166,84 @@ impl PaneTerminal {
         Ok(terminal)
     }
 
+    /// Attaches to pane `pane_id` of `session` in the server, which starts it from
+    /// `spawn` if it is not running. `emulator` mirrors the server's copy: it is brought
+    /// to the pane's current screen, then fed its output with queries left to the
+    /// server. `on_output` is called as for [`Self::spawn`], including when the server
+    /// ends the connection.
+    ///
+    /// Dropping the terminal only detaches; [`Self::kill`] ends the shell.
+    pub fn attach(
+        session: &str,
+        pane_id: usize,
+        spawn: Option<PaneSpawn>,
+        respawn: bool,
+        emulator: SharedEmulator,
+        on_output: impl Fn(&mut Emulator, &[u8]) + Send + 'static,
+    ) -> Result<Self> {
+        let (mut reader, stream) = server::attach_pane(session, pane_id, spawn, respawn)?;
+        let info = Arc::new(Mutex::new(RemoteInfo::default()));
+        let reader_info = Arc::clone(&info);
+
+        std::thread::spawn(move || {
+            let mut line = String::new();
+            loop {
+                line.clear();
+                if !matches!(reader.read_line(&mut line), Ok(n) if n > 0) {
+                    break;
+                }
+                let Ok(event) = serde_json::from_str::<PaneEvent>(line.trim()) else {
+                    continue;
+                };
+                let (size, data) = match event {
+                    PaneEvent::Attached {
+                        rows,
+                        cols,
+                        snapshot,
+                    } => (Some((rows, cols)), snapshot),
+                    PaneEvent::Output { data } => (None, data),
+                    PaneEvent::Info {
+                        cwd,
+                        command,
+                        logging,
+                    } => {
+                        if let Ok(mut info) = reader_info.lock() {
+                            *info = RemoteInfo {
+                                cwd,
+                                command,
+                                logging,
+                            };
+                        }
+                        continue;
+                    }
+                    PaneEvent::Exited => break,
+                };
+                let bytes = base64::decode(data.as_bytes());
+                if let Some((rows, cols)) = size {
+                    lock(&emulator).set_size(rows, cols);
+                }
+                for slice in bytes.chunks(PARSE_SLICE) {
+                    let mut emulator = lock(&emulator);
+                    emulator.process_mirrored(slice);
+                    on_output(&mut emulator, slice);
+                }
+            }
+            on_output(&mut lock(&emulator), &[]);
+        });
+
+        Ok(Self {
+            backend: Backend::Remote(RemotePty {
+                stream: Mutex::new(stream),
+                info,
+            }),
+        })
+    }
+
+    /// Whether the shell is hosted by the server.
+    pub fn is_remote(&self) -> bool {
+        matches!(self.backend, Backend::Remote(_))
+    }
+
     pub fn send_line(&mut self, text: &str) -> Result<()> {
         self.write_bytes(text.as_bytes())?;
         self.write_bytes(b"\n")?;
@@ -82,7 +251,15 @@ impl PaneTerminal {
     }
 
     pub fn write_bytes(&mut self, data: &[u8]) -> Result<()> {
-        let mut guard = self
+        let pty = match &self.backend {
+            Backend::Local(pty) => pty,
+            Backend::Remote(remote) => {
+                return remote.send(&PaneInput::Input {
+                    data: base64::encode(data),
+                });
+            }
+        };
+        let mut guard = pty
             .writer
             .lock()
             .map_err(|_| anyhow::anyhow!("failed to lock pty writer"))?;
@@ -95,8 +272,21 @@ impl PaneTerminal {
         Ok(())
     }
 
-    pub fn resize(&mut self, cols: u16, rows: u16) -> Result<()> {
-        let guard = self
+    /// Resizes the PTY; `pixel_size` is the text area in device pixels, which image
+    /// tools read through `TIOCGWINSZ`.
+    pub fn resize(&mut self, cols: u16, rows: u16, pixel_size: (u16, u16)) -> Result<()> {
+        let pty = match &self.backend {
+            Backend::Local(pty) => pty,
+            Backend::Remote(remote) => {
+                return remote.send(&PaneInput::Resize {
+                    rows,
+                    cols,
+                    pixel_width: pixel_size.0,
+                    pixel_height: pixel_size.1,
+                });
+            }
+        };
+        let guard = pty
             .master
             .lock()
             .map_err(|_| anyhow::anyhow!("failed to lock pty master"))?;
@@ -104,24 +294,214 @@ impl PaneTerminal {
             .resize(PtySize {
                 rows,
                 cols,
-                pixel_width: 0,
-                pixel_height: 0,
+                pixel_width: pixel_size.0,
+                pixel_height: pixel_size.1,
             })
             .with_context(|| "failed to resize pty")?;
         Ok(())
     }
 
+    /// Current directory of the shell where the platform exposes it (`/proc` on Linux).
+    pub fn cwd(&self) -> Option<PathBuf> {
+        match &self.backend {
+            Backend::Local(pty) => {
+                let pid = pty.child.process_id()?;
+                std::fs::read_link(format!("/proc/{pid}/cwd")).ok()
+            }
+            Backend::Remote(remote) => remote.info.lock().ok()?.cwd.clone(),
+        }
+    }
+
+    /// Name of the process in the terminal's foreground, e.g. `vim` while it runs and the
+    /// shell otherwise, where the platform exposes it.
+    pub fn foreground_command(&self) -> Option<String> {
+        match &self.backend {
+            Backend::Local(pty) => pty.foreground_command(),
+            Backend::Remote(remote) => remote.info.lock().ok()?.command.clone(),
+        }
+    }
+
+    /// Tells a server-hosted pane what the GUI draws with, as the server's emulator is the
+    /// one answering the application's queries. Local panes share the GUI's emulator.
+    pub fn set_appearance(&self, theme: &Theme, ambiguous_width: AmbiguousWidth) -> Result<()> {
+        match &self.backend {
+            Backend::Local(_) => Ok(()),
+            Backend::Remote(remote) => remote.send(&PaneInput::Appearance {
+                theme: theme.clone(),
+                ambiguous_width,
+            }),
+        }
+    }
+
+    /// Appends the pane's raw output from now on to `path`; `None` stops logging.
+    pub fn set_log(&mut self, path: Option<&Path>) -> Result<()> {
+        let pty = match &self.backend {
+            Backend::Local(pty) => pty,
+            Backend::Remote(remote) => {
+                remote.send(&PaneInput::Log {
+                    path: path.map(Path::to_path_buf),
+                })?;
+                if let Ok(mut info) = remote.info.lock() {
+                    info.logging = path.is_some();
+                }
+                return Ok(());
+            }
+        };
+        let file = match path {
+            Some(path) => Some(
+                File::options()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .with_context(|| format!("failed to open log: {}", path.display()))?,
+            ),
+            None => None,
+        };
+        *pty.log
+            .lock()
+            .map_err(|_| anyhow::anyhow!("failed to lock pane log"))? = file;
+        Ok(())
+    }
+
+    pub fn is_logging(&self) -> bool {
+        match &self.backend {
+            Backend::Local(pty) => pty.log.lock().is_ok_and(|log| log.is_some()),
+            Backend::Remote(remote) => remote.info.lock().is_ok_and(|info| info.logging),
+        }
+    }
+
+    /// Sends `signal` (`INT`, `TERM`, ...) to the terminal's foreground process group.
+    pub fn signal(&self, signal: &str) -> Result<()> {
+        match &self.backend {
+            Backend::Local(pty) => pty.signal(signal),
+            Backend::Remote(remote) => remote.send(&PaneInput::Signal {
+                signal: signal.to_string(),
+            }),
+        }
+    }
+
+    /// Ends the shell, wherever it runs.
     pub fn kill(&mut self) {
-        let _ = self.child.kill();
+        match &mut self.backend {
+            Backend::Local(pty) => {
+                let _ = pty.child.kill();
+            }
+            Backend::Remote(remote) => {
+                let _ = remote.send(&PaneInput::Kill);
+            }
+        }
     }
 }
 
 impl Drop for PaneTerminal {
+    /// Local shells go with their pane; server-hosted ones keep running detached.
     fn drop(&mut self) {
-        self.kill();
+        match &mut self.backend {
+            Backend::Local(pty) => {
+                let _ = pty.child.kill();
+            }
+            Backend::Remote(remote) => {
+                if let Ok(stream) = remote.stream.lock() {
+                    let _ = stream.shutdown(Shutdown::Both);
+                }
+            }
+        }
+    }
+}
+
+impl LocalPty {
+    #[cfg(unix)]
+    fn foreground_command(&self) -> Option<String> {
+        let pid = self.master.lock().ok()?.process_group_leader()?;
+        let name = std::fs::read_to_string(format!("/proc/{pid}/comm")).ok()?;
+        Some(name.trim_end().to_string())
+    }
+
+    #[cfg(not(unix))]
+    fn foreground_command(&self) -> Option<String> {
+        None
+    }
+
+    #[cfg(unix)]
+    fn signal(&self, signal: &str) -> Result<()> {
+        let pgid = self
+            .master
+            .lock()
+            .ok()
+            .and_then(|m| m.process_group_leader())
+            .context("no foreground process")?;
+        let status = std::process::Command::new("kill")
+            .args(["-s", signal, "--", &format!("-{pgid}")])
+            .status()
+            .with_context(|| "failed to run kill")?;
+        if !status.success() {
+            bail!("kill -s {signal} -{pgid} failed");
+        }
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    fn signal(&self, _signal: &str) -> Result<()> {
+        bail!("signals are not supported on this platform")
     }
 }
 
-fn shell_quote(input: &str) -> String {
+impl RemotePty {
+    fn send(&self, input: &PaneInput) -> Result<()> {
+        let mut stream = self
+            .stream
+            .lock()
+            .map_err(|_| anyhow::anyhow!("failed to lock server connection"))?;
+        let raw = serde_json::to_string(input)?;
+        stream
+            .write_all(raw.as_bytes())
+            .and_then(|()| stream.write_all(b"\n"))
+            .with_context(|| "failed to write to server")?;
+        Ok(())
+    }
+}
+
+/// Quotes `input` as one shell word.
+pub fn shell_quote(input: &str) -> String {
     format!("'{}'", input.replace('\'', "'\"'\"'"))
 }
+
+/// Dropped paths as shell words to type at the prompt, each relative to `cwd` when
+/// given and inside it, followed by a space.
+pub fn quote_paths(paths: &[PathBuf], cwd: Option<&Path>) -> String {
+    let mut text = String::new();
+    for path in paths {
+        let relative = cwd.and_then(|cwd| path.strip_prefix(cwd).ok());
+        let shown = match relative {
+            Some(rest) if rest.as_os_str().is_empty() => ".".to_string(),
+            Some(rest) => rest.to_string_lossy().to_string(),
+            None => path.to_string_lossy().to_string(),
+        };
+        text.push_str(&shell_quote(&shown));
+        text.push(' ');
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dropped_paths_are_quoted_and_optionally_relative() {
+        let paths = [
+            PathBuf::from("/work/src/main.rs"),
+            PathBuf::from("/work/it's here"),
+            PathBuf::from("/work"),
+            PathBuf::from("/elsewhere/x"),
+        ];
+        assert_eq!(
+            quote_paths(&paths, None),
+            "'/work/src/main.rs' '/work/it'\"'\"'s here' '/work' '/elsewhere/x' "
+        );
+        assert_eq!(
+            quote_paths(&paths, Some(Path::new("/work"))),
+            "'src/main.rs' 'it'\"'\"'s here' '.' '/elsewhere/x' "
+        );
+    }
+}
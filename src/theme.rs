@@ -0,0 +1,461 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::engine::runtime_dir;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rgb(pub u8, pub u8, pub u8);
+
+impl Rgb {
+    /// Accepts `#rrggbb`, `#rgb`, `0xrrggbb` and X11 `rgb:r/g/b` specs (1-4 hex digits each).
+    pub fn parse(spec: &str) -> Option<Self> {
+        let spec = spec.trim().trim_matches(|c| c == '\'' || c == '"');
+        if let Some(rest) = spec.strip_prefix("rgb:") {
+            let parts: Vec<&str> = rest.split('/').collect();
+            if parts.len() != 3 {
+                return None;
+            }
+            let mut out = [0_u8; 3];
+            for (slot, part) in out.iter_mut().zip(parts) {
+                if part.is_empty() || part.len() > 4 {
+                    return None;
+                }
+                let value = u32::from_str_radix(part, 16).ok()?;
+                let max = (1_u32 << (4 * part.len())) - 1;
+                *slot = ((value * 255 + max / 2) / max) as u8;
+            }
+            return Some(Self(out[0], out[1], out[2]));
+        }
+        let hex = spec.strip_prefix('#').or_else(|| spec.strip_prefix("0x"))?;
+        match hex.len() {
+            6 => {
+                let v = u32::from_str_radix(hex, 16).ok()?;
+                Some(Self((v >> 16) as u8, (v >> 8) as u8, v as u8))
+            }
+            3 => {
+                let v = u16::from_str_radix(hex, 16).ok()?;
+                let expand = |n: u16| (n as u8 & 0xf) * 17;
+                Some(Self(expand(v >> 8), expand(v >> 4), expand(v)))
+            }
+            _ => None,
+        }
+    }
+
+    /// X11 colour spec as used in OSC 4/10/11/12 replies.
+    pub fn to_x11_spec(self) -> String {
+        format!(
+            "rgb:{:02x}{:02x}/{:02x}{:02x}/{:02x}{:02x}",
+            self.0, self.0, self.1, self.1, self.2, self.2
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Theme {
+    pub name: String,
+    pub foreground: Rgb,
+    pub background: Rgb,
+    pub cursor: Rgb,
+    pub selection: Rgb,
+    pub palette: [Rgb; 16],
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            name: "OrchestraTerm Dark".to_string(),
+            foreground: Rgb(210, 220, 230),
+            background: Rgb(22, 25, 29),
+            cursor: Rgb(120, 220, 160),
+            selection: Rgb(52, 84, 122),
+            palette: [
+                Rgb(0, 0, 0),
+                Rgb(205, 49, 49),
+                Rgb(13, 188, 121),
+                Rgb(229, 229, 16),
+                Rgb(36, 114, 200),
+                Rgb(188, 63, 188),
+                Rgb(17, 168, 205),
+                Rgb(229, 229, 229),
+                Rgb(102, 102, 102),
+                Rgb(241, 76, 76),
+                Rgb(35, 209, 139),
+                Rgb(245, 245, 67),
+                Rgb(59, 142, 234),
+                Rgb(214, 112, 214),
+                Rgb(41, 184, 219),
+                Rgb(255, 255, 255),
+            ],
+        }
+    }
+}
+
+const ANSI_NAMES: [&str; 8] = [
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+];
+
+impl Theme {
+    pub fn builtin() -> Vec<Self> {
+        let solarized = Self {
+            name: "Solarized Dark".to_string(),
+            foreground: Rgb(131, 148, 150),
+            background: Rgb(0, 43, 54),
+            cursor: Rgb(147, 161, 161),
+            selection: Rgb(7, 54, 66),
+            palette: [
+                Rgb(7, 54, 66),
+                Rgb(220, 50, 47),
+                Rgb(133, 153, 0),
+                Rgb(181, 137, 0),
+                Rgb(38, 139, 210),
+                Rgb(211, 54, 130),
+                Rgb(42, 161, 152),
+                Rgb(238, 232, 213),
+                Rgb(0, 43, 54),
+                Rgb(203, 75, 22),
+                Rgb(88, 110, 117),
+                Rgb(101, 123, 131),
+                Rgb(131, 148, 150),
+                Rgb(108, 113, 196),
+                Rgb(147, 161, 161),
+                Rgb(253, 246, 227),
+            ],
+        };
+        let light = Self {
+            name: "Paper Light".to_string(),
+            foreground: Rgb(40, 42, 46),
+            background: Rgb(250, 250, 247),
+            cursor: Rgb(30, 120, 80),
+            selection: Rgb(200, 220, 245),
+            palette: [
+                Rgb(0, 0, 0),
+                Rgb(180, 30, 30),
+                Rgb(20, 130, 60),
+                Rgb(150, 110, 0),
+                Rgb(30, 80, 180),
+                Rgb(150, 40, 150),
+                Rgb(20, 120, 140),
+                Rgb(190, 190, 190),
+                Rgb(100, 100, 100),
+                Rgb(220, 50, 50),
+                Rgb(40, 160, 80),
+                Rgb(190, 150, 0),
+                Rgb(50, 110, 220),
+                Rgb(190, 70, 190),
+                Rgb(30, 150, 170),
+                Rgb(60, 60, 60),
+            ],
+        };
+        vec![Self::default(), solarized, light]
+    }
+
+    /// Loads a theme file, picking the importer from the extension.
+    pub fn load(path: &Path) -> Result<Self> {
+        let raw = fs::read_to_string(path)
+            .with_context(|| format!("failed to read theme: {}", path.display()))?;
+        let name = path
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_else(|| "Custom".to_string());
+        let ext = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_ascii_lowercase())
+            .unwrap_or_default();
+        if ext == "itermcolors" {
+            Self::from_itermcolors(&name, &raw)
+        } else {
+            Self::from_key_value(&name, &raw)
+        }
+    }
+
+    /// Imports Kitty-style `color0 #000000` lines and Alacritty-style
+    /// `[colors.normal]` / `black = '#000000'` tables.
+    pub fn from_key_value(name: &str, raw: &str) -> Result<Self> {
+        let mut theme = Self {
+            name: name.to_string(),
+            ..Self::default()
+        };
+        let mut section = String::new();
+        let mut matched = 0;
+
+        for line in raw.lines() {
+            // `#` starts both comments and hex colours, so only whole-line comments are skipped;
+            // trailing comments fall away when the value is cut at the first whitespace.
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with("//") {
+                continue;
+            }
+            if line.starts_with('[') && line.ends_with(']') {
+                section = line[1..line.len() - 1].trim().to_ascii_lowercase();
+                continue;
+            }
+            let (key, value) = match line.split_once(['=', ':']) {
+                Some((k, v)) if !k.trim().contains(' ') => (k.trim(), v.trim()),
+                _ => match line.split_once(char::is_whitespace) {
+                    Some((k, v)) => (k.trim(), v.trim()),
+                    None => continue,
+                },
+            };
+            let value = value
+                .split_whitespace()
+                .next()
+                .unwrap_or("")
+                .trim_end_matches(',');
+            let Some(color) = Rgb::parse(value) else {
+                continue;
+            };
+            let key = key
+                .trim_matches(|c| c == '\'' || c == '"')
+                .to_ascii_lowercase();
+            if theme.assign(&section, &key, color) {
+                matched += 1;
+            }
+        }
+
+        if matched == 0 {
+            anyhow::bail!("no colours recognised in theme: {name}");
+        }
+        Ok(theme)
+    }
+
+    fn assign(&mut self, section: &str, key: &str, color: Rgb) -> bool {
+        let section = section.strip_prefix("colors.").unwrap_or(section);
+        if let Some(idx) = key
+            .strip_prefix("color")
+            .and_then(|n| n.parse::<usize>().ok())
+        {
+            if idx < 16 {
+                self.palette[idx] = color;
+                return true;
+            }
+            return false;
+        }
+        if let Some(pos) = ANSI_NAMES.iter().position(|n| *n == key) {
+            let idx = match section {
+                "normal" => pos,
+                "bright" => pos + 8,
+                _ => return false,
+            };
+            self.palette[idx] = color;
+            return true;
+        }
+        let slot = match (section, key) {
+            ("selection", "background") | (_, "selection_background" | "selection") => {
+                &mut self.selection
+            }
+            ("cursor", "text") => return false,
+            (_, "foreground") => &mut self.foreground,
+            (_, "background") => &mut self.background,
+            (_, "cursor" | "cursor_color") => &mut self.cursor,
+            _ => return false,
+        };
+        *slot = color;
+        true
+    }
+
+    /// Imports an iTerm2 `.itermcolors` property list.
+    pub fn from_itermcolors(name: &str, raw: &str) -> Result<Self> {
+        let mut theme = Self {
+            name: name.to_string(),
+            ..Self::default()
+        };
+        let mut matched = 0;
+        let mut rest = raw;
+
+        while let Some(start) = rest.find("<key>") {
+            rest = &rest[start + 5..];
+            let Some(end) = rest.find("</key>") else {
+                break;
+            };
+            let key = rest[..end].trim().to_string();
+            rest = &rest[end + 6..];
+            let Some(dict_start) = rest.find("<dict>") else {
+                break;
+            };
+            let Some(dict_end) = rest.find("</dict>") else {
+                break;
+            };
+            if dict_start > dict_end {
+                continue;
+            }
+            let dict = &rest[dict_start + 6..dict_end];
+            rest = &rest[dict_end + 7..];
+
+            let components = plist_components(dict);
+            let Some(color) = components else {
+                continue;
+            };
+            let slot = if let Some(n) = key
+                .strip_prefix("Ansi ")
+                .and_then(|k| k.strip_suffix(" Color"))
+                .and_then(|n| n.parse::<usize>().ok())
+            {
+                theme.palette.get_mut(n)
+            } else {
+                match key.as_str() {
+                    "Foreground Color" => Some(&mut theme.foreground),
+                    "Background Color" => Some(&mut theme.background),
+                    "Cursor Color" => Some(&mut theme.cursor),
+                    "Selection Color" => Some(&mut theme.selection),
+                    _ => None,
+                }
+            };
+            if let Some(slot) = slot {
+                *slot = color;
+                matched += 1;
+            }
+        }
+
+        if matched == 0 {
+            anyhow::bail!("no colours recognised in itermcolors: {name}");
+        }
+        Ok(theme)
+    }
+
+    /// Maps an xterm 256-colour index through this theme's 16-colour base palette.
+    pub fn ansi256(&self, i: u8) -> Rgb {
+        if i < 16 {
+            return self.palette[usize::from(i)];
+        }
+        if (16..=231).contains(&i) {
+            let idx = i - 16;
+            let map = |v: u8| if v == 0 { 0 } else { 55 + v * 40 };
+            return Rgb(map(idx / 36), map((idx % 36) / 6), map(idx % 6));
+        }
+        let gray = 8 + (i - 232) * 10;
+        Rgb(gray, gray, gray)
+    }
+
+    /// Returns this theme with application-set OSC overrides applied on top.
+    pub fn with_overrides(&self, overrides: &ColorOverrides) -> Self {
+        let mut out = self.clone();
+        for (idx, color) in &overrides.palette {
+            if let Some(slot) = out.palette.get_mut(usize::from(*idx)) {
+                *slot = *color;
+            }
+        }
+        if let Some(c) = overrides.foreground {
+            out.foreground = c;
+        }
+        if let Some(c) = overrides.background {
+            out.background = c;
+        }
+        if let Some(c) = overrides.cursor {
+            out.cursor = c;
+        }
+        out
+    }
+
+    /// Built-in themes followed by every loadable file in `themes_dir()`.
+    pub fn discover() -> Vec<Self> {
+        let mut themes = Self::builtin();
+        let Ok(dir) = themes_dir() else {
+            return themes;
+        };
+        let Ok(entries) = fs::read_dir(&dir) else {
+            return themes;
+        };
+        let mut paths: Vec<PathBuf> = entries.flatten().map(|e| e.path()).collect();
+        paths.sort();
+        for path in paths {
+            if let Ok(theme) = Self::load(&path) {
+                themes.retain(|t| t.name != theme.name);
+                themes.push(theme);
+            }
+        }
+        themes
+    }
+}
+
+/// Colours set by applications through OSC 4/10/11/12; reset by OSC 104/110/111/112.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ColorOverrides {
+    pub palette: BTreeMap<u8, Rgb>,
+    pub foreground: Option<Rgb>,
+    pub background: Option<Rgb>,
+    pub cursor: Option<Rgb>,
+}
+
+fn plist_components(dict: &str) -> Option<Rgb> {
+    let mut rgb = [None::<f32>; 3];
+    let mut rest = dict;
+    while let Some(start) = rest.find("<key>") {
+        rest = &rest[start + 5..];
+        let end = rest.find("</key>")?;
+        let key = rest[..end].trim().to_string();
+        rest = &rest[end + 6..];
+        let open = rest.find('>')?;
+        let close = rest.find("</")?;
+        if open >= close {
+            continue;
+        }
+        let value = rest[open + 1..close].trim().parse::<f32>().ok();
+        let slot = match key.as_str() {
+            "Red Component" => 0,
+            "Green Component" => 1,
+            "Blue Component" => 2,
+            _ => continue,
+        };
+        rgb[slot] = value;
+    }
+    let to_byte = |v: f32| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+    Some(Rgb(to_byte(rgb[0]?), to_byte(rgb[1]?), to_byte(rgb[2]?)))
+}
+
+pub fn themes_dir() -> Result<PathBuf> {
+    Ok(runtime_dir()?.join("themes"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_hex_and_x11_specs() {
+        assert_eq!(Rgb::parse("#1d1f21"), Some(Rgb(0x1d, 0x1f, 0x21)));
+        assert_eq!(Rgb::parse("0xffffff"), Some(Rgb(255, 255, 255)));
+        assert_eq!(Rgb::parse("rgb:ffff/0000/8080"), Some(Rgb(255, 0, 128)));
+        assert_eq!(Rgb::parse("rgb:f/0/8"), Some(Rgb(255, 0, 136)));
+        assert_eq!(Rgb(255, 0, 128).to_x11_spec(), "rgb:ffff/0000/8080");
+    }
+
+    #[test]
+    fn kitty_and_alacritty_key_values_are_imported() {
+        let kitty = "foreground #c5c8c6\nbackground #1d1f21\ncolor1 #cc6666\ncursor #aeafad\n";
+        let theme = Theme::from_key_value("kitty", kitty).unwrap();
+        assert_eq!(theme.foreground, Rgb(0xc5, 0xc8, 0xc6));
+        assert_eq!(theme.palette[1], Rgb(0xcc, 0x66, 0x66));
+        assert_eq!(theme.cursor, Rgb(0xae, 0xaf, 0xad));
+
+        let alacritty =
+            "[colors.primary]\nbackground = '#101010'\n\n[colors.bright]\nred = '#ff0000'\n";
+        let theme = Theme::from_key_value("alacritty", alacritty).unwrap();
+        assert_eq!(theme.background, Rgb(0x10, 0x10, 0x10));
+        assert_eq!(theme.palette[9], Rgb(255, 0, 0));
+    }
+
+    #[test]
+    fn itermcolors_plist_is_imported() {
+        let plist = r#"<plist><dict>
+            <key>Ansi 2 Color</key>
+            <dict>
+                <key>Blue Component</key><real>0.0</real>
+                <key>Green Component</key><real>1</real>
+                <key>Red Component</key><real>0.5</real>
+            </dict>
+            <key>Background Color</key>
+            <dict>
+                <key>Blue Component</key><real>0.1</real>
+                <key>Green Component</key><real>0.1</real>
+                <key>Red Component</key><real>0.1</real>
+            </dict>
+        </dict></plist>"#;
+        let theme = Theme::from_itermcolors("iterm", plist).unwrap();
+        assert_eq!(theme.palette[2], Rgb(128, 255, 0));
+        assert_eq!(theme.background, Rgb(26, 26, 26));
+    }
+}
@@ -0,0 +1,123 @@
+//! Character widths beyond the per-codepoint ones `vt100` assigns.
+//!
+//! Cells are still given out one codepoint at a time with the `wcwidth` widths shells use
+//! to place the cursor, so the grid and the shell agree. A grapheme cluster whose
+//! codepoints landed in neighbouring cells (ZWJ sequences, flags, spacing marks) is then
+//! drawn once across all of them. East Asian ambiguous-width characters can be made two
+//! cells wide: each is followed by an [`AMBIGUOUS_PAD`] cell that renders as part of it.
+
+use serde::{Deserialize, Serialize};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthChar;
+
+/// Fills the second cell of an ambiguous-width character under [`AmbiguousWidth::Wide`].
+/// Private use, so `vt100` gives it one cell; never shown or copied.
+pub const AMBIGUOUS_PAD: char = '\u{10EEED}';
+
+/// How many cells East Asian ambiguous-width characters (`○`, `※`, `°`, box
+/// drawing, ...) take. Must match the shell's locale, or line editing drifts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AmbiguousWidth {
+    Narrow,
+    Wide,
+}
+
+impl Default for AmbiguousWidth {
+    /// `ORCHESTRATERM_AMBIGUOUS_WIDTH` (`1`/`narrow` or `2`/`wide`), else narrow.
+    fn default() -> Self {
+        std::env::var("ORCHESTRATERM_AMBIGUOUS_WIDTH")
+            .ok()
+            .and_then(|v| Self::parse(&v))
+            .unwrap_or(Self::Narrow)
+    }
+}
+
+impl AmbiguousWidth {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim() {
+            "1" | "narrow" => Some(Self::Narrow),
+            "2" | "wide" => Some(Self::Wide),
+            _ => None,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Narrow => "Narrow (1 cell)",
+            Self::Wide => "Wide (2 cells)",
+        }
+    }
+
+    pub fn all() -> [Self; 2] {
+        [Self::Narrow, Self::Wide]
+    }
+}
+
+/// Whether `c` is one cell wide normally and two in East Asian contexts. Private use
+/// characters are left narrow: they are mostly icon font glyphs drawn one cell wide.
+pub fn is_ambiguous(c: char) -> bool {
+    let private = matches!(u32::from(c), 0xE000..=0xF8FF | 0xF0000..=0x10FFFD);
+    !private && c.width() == Some(1) && c.width_cjk() == Some(2)
+}
+
+/// Whether the text of the next cell continues the cluster `cluster`, e.g. the second
+/// regional indicator of a flag or the emoji after a zero-width joiner.
+pub fn continues_cluster(cluster: &str, next: &str) -> bool {
+    // Nothing in ASCII extends a cluster except LF after CR, which never reaches a cell.
+    if next.chars().next().is_none_or(|c| c.is_ascii()) {
+        return false;
+    }
+    let joined = format!("{cluster}{next}");
+    joined.graphemes(true).nth(1).is_none()
+}
+
+/// Whether a cluster is best drawn from a colour emoji font: it has an emoji presentation
+/// by default or asks for one with VS16, or it is a flag.
+pub fn is_emoji(cluster: &str) -> bool {
+    cluster.chars().any(|c| match u32::from(c) {
+        0xFE0F => true,
+        0x1F000..=0x1FAFF => true,
+        0x2300..=0x2BFF => c.width() == Some(2),
+        _ => false,
+    })
+}
+
+/// `text` without ambiguous-width padding, for copying and searching.
+pub fn strip_pads(text: &str) -> String {
+    text.chars().filter(|c| *c != AMBIGUOUS_PAD).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ambiguous_characters_exclude_private_use() {
+        assert!(is_ambiguous('○'));
+        assert!(is_ambiguous('※'));
+        assert!(!is_ambiguous('α'));
+        assert!(!is_ambiguous('a'));
+        assert!(!is_ambiguous('한'));
+        assert!(!is_ambiguous('\u{E0B0}'));
+        assert!(!is_ambiguous(AMBIGUOUS_PAD));
+    }
+
+    #[test]
+    fn clusters_continue_across_cells() {
+        assert!(continues_cluster("🇰", "🇷"));
+        assert!(!continues_cluster("🇰🇷", "🇯"));
+        assert!(continues_cluster("👨\u{200D}", "👩"));
+        assert!(!continues_cluster("👨", "👩"));
+        assert!(!continues_cluster("e", "x"));
+    }
+
+    #[test]
+    fn emoji_presentation_is_detected() {
+        assert!(is_emoji("😀"));
+        assert!(is_emoji("❤\u{FE0F}"));
+        assert!(!is_emoji("❤"));
+        assert!(is_emoji("⌚"));
+        assert!(!is_emoji("한"));
+    }
+}